@@ -0,0 +1,134 @@
+// Chunking, hashing, indexing and search over generated corpora.
+//
+//   cargo bench                                    # every group
+//   cargo bench -- search                          # one group
+//   CONTEXT_RAG_BENCH_FILES=100,2000 cargo bench   # other corpus sizes
+//
+// To check a change for regressions, save a baseline before it and compare
+// after: `cargo bench -- --save-baseline main`, then `cargo bench -- --baseline main`.
+
+use context_rag_indexer::indexer::{chunk_content, ContentHash, ContextRagIndexer, ContextRagSearcher, IndexConfig};
+use context_rag_indexer::test_utils::{self, Words};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+// Files per generated corpus unless CONTEXT_RAG_BENCH_FILES says otherwise
+const DEFAULT_CORPUS_FILES: &[usize] = &[50, 500];
+const STORAGE_DIR: &str = ".bench-index";
+
+const QUERIES: &[&str] = &["shard commit", "vector fusion ranking", "configHandler", "boilerplate license comment"];
+
+fn corpus_sizes() -> Vec<usize> {
+    std::env::var("CONTEXT_RAG_BENCH_FILES")
+        .ok()
+        .map(|sizes| sizes.split(',').filter_map(|size| size.trim().parse().ok()).collect())
+        .unwrap_or_else(|| DEFAULT_CORPUS_FILES.to_vec())
+}
+
+// A fresh directory of `files` generated files
+fn generate_corpus(files: usize) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("context-rag-bench-{}-{}", std::process::id(), files));
+    let _ = fs::remove_dir_all(&root);
+    test_utils::write_corpus(&root, &test_utils::generate_corpus(files as u64, files)).unwrap();
+    root
+}
+
+fn corpus_config(root: &Path) -> IndexConfig {
+    IndexConfig {
+        include: vec!["*.md".to_string(), "*.rs".to_string()],
+        exclude: vec![STORAGE_DIR.to_string()],
+        storage_path: root.join(STORAGE_DIR).to_string_lossy().to_string(),
+        model: String::new(),
+        code_model: None,
+        tokenizer: None,
+        deterministic: false,
+        boilerplate_threshold: None,
+        strip_license_headers: false,
+        extract_comments: false,
+        shards: None,
+        max_ann_memory: None,
+        content_hash: None,
+        blocklist: Default::default(),
+        max_file_size: None,
+        aliases: Default::default(),
+        prune_dirs: Vec::new(),
+        max_depth: None,
+        root: None,
+    }
+}
+
+// The indexer walks the working directory, so runs happen inside the corpus
+fn index_corpus(root: &Path, config: &IndexConfig) {
+    let previous = std::env::current_dir().unwrap();
+    std::env::set_current_dir(root).unwrap();
+    let mut indexer = ContextRagIndexer::for_config(config).unwrap();
+    indexer.index_directory(config).unwrap();
+    std::env::set_current_dir(previous).unwrap();
+}
+
+fn chunking(c: &mut Criterion) {
+    let mut group = c.benchmark_group("chunking");
+    for kb in [4, 64, 1024] {
+        let text = test_utils::markdown(&mut Words::new(kb as u64), kb * 1024);
+        group.throughput(Throughput::Bytes(text.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(format!("{}KB", kb)), &text, |b, text| {
+            b.iter(|| chunk_content(text))
+        });
+    }
+    group.finish();
+}
+
+fn hashing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hashing");
+    for kb in [4, 1024, 16 * 1024] {
+        let bytes = test_utils::markdown(&mut Words::new(kb as u64), kb * 1024).into_bytes();
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        for hash in [ContentHash::Blake3, ContentHash::Sha256] {
+            group.bench_with_input(BenchmarkId::new(format!("{:?}", hash), format!("{}KB", kb)), &bytes, |b, bytes| {
+                b.iter(|| hash.hash(bytes))
+            });
+        }
+    }
+    group.finish();
+}
+
+fn indexing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("indexing");
+    group.sample_size(10).measurement_time(Duration::from_secs(20));
+    for files in corpus_sizes() {
+        let root = generate_corpus(files);
+        let config = corpus_config(&root);
+        group.throughput(Throughput::Elements(files as u64));
+        group.bench_function(BenchmarkId::from_parameter(files), |b| b.iter(|| index_corpus(&root, &config)));
+        let _ = fs::remove_dir_all(&root);
+    }
+    group.finish();
+}
+
+fn search(c: &mut Criterion) {
+    let mut group = c.benchmark_group("search");
+    for files in corpus_sizes() {
+        let root = generate_corpus(files);
+        let config = corpus_config(&root);
+        index_corpus(&root, &config);
+        let searcher = ContextRagSearcher::open(&config.storage_path).unwrap();
+
+        group.bench_function(BenchmarkId::new("keyword", files), |b| {
+            b.iter(|| QUERIES.iter().map(|query| searcher.search(query, 10).unwrap().len()).sum::<usize>())
+        });
+        group.bench_function(BenchmarkId::new("identifier", files), |b| {
+            b.iter(|| QUERIES.iter().map(|query| searcher.identifier_search(query, 10).unwrap().len()).sum::<usize>())
+        });
+        group.bench_function(BenchmarkId::new("fuzzy", files), |b| {
+            b.iter(|| QUERIES.iter().map(|query| searcher.fuzzy_search(query, 10).unwrap().len()).sum::<usize>())
+        });
+        drop(searcher);
+        let _ = fs::remove_dir_all(&root);
+    }
+    group.finish();
+}
+
+criterion_group!(benches, chunking, hashing, indexing, search);
+criterion_main!(benches);
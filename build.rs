@@ -0,0 +1,9 @@
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=proto/context_rag.proto");
+
+    // The gRPC service is feature-gated; protoc is only needed when it is enabled
+    #[cfg(feature = "grpc")]
+    tonic_prost_build::compile_protos("proto/context_rag.proto")?;
+
+    Ok(())
+}
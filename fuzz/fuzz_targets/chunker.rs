@@ -0,0 +1,23 @@
+#![no_main]
+
+// Everything between reading a file and storing its chunks: license
+// stripping, chunking and comment extraction over arbitrary text, including
+// huge lines, zero-width and combining characters, and lone CRs
+use context_rag_indexer::indexer::chunk_content;
+use context_rag_indexer::indexer::comments::extract_comments;
+use context_rag_indexer::indexer::license::strip_license_header;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|content: &str| {
+    let stripped = strip_license_header(content);
+    let content = stripped.as_deref().unwrap_or(content);
+
+    // Every file gets at least one chunk, even an empty one
+    let chunks = chunk_content(content);
+    assert!(!chunks.is_empty());
+    for chunk in &chunks {
+        for language in ["rust", "python", "javascript", "markdown", "unknown"] {
+            extract_comments(chunk, language);
+        }
+    }
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+// .context-rag.toml is hand-edited, so any text must parse or fail cleanly,
+// and a parsed config must turn into an index config
+use context_rag_indexer::config::ProjectConfig;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    if let Ok(config) = ProjectConfig::parse(input) {
+        let _ = config.index_config();
+    }
+});
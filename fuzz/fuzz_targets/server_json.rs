@@ -0,0 +1,9 @@
+#![no_main]
+
+// Requests arriving on the Unix socket and HTTP transports
+use context_rag_indexer::server::ServerEnvelope;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    let _ = serde_json::from_str::<ServerEnvelope>(input);
+});
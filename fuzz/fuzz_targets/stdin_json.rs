@@ -0,0 +1,17 @@
+#![no_main]
+
+// The Node layer pipes chunk and text batches through stdin; malformed or
+// odd JSON must come back as an error, never a panic
+use context_rag_indexer::embedding::{embed_chunks_request, embed_ndjson, embed_texts_request, EmbedMode};
+use context_rag_indexer::quantize::Quantization;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|input: &str| {
+    if let Ok(response) = embed_chunks_request("intfloat/multilingual-e5-small", input, EmbedMode::Document, None, true) {
+        serde_json::to_string(&response).unwrap();
+    }
+    if let Ok(response) = embed_texts_request(input, Some(Quantization::Binary)) {
+        serde_json::to_string(&response).unwrap();
+    }
+    let _ = embed_ndjson("intfloat/multilingual-e5-small", input.as_bytes(), std::io::sink(), EmbedMode::Query, Some(Quantization::Int8), false);
+});
@@ -0,0 +1,97 @@
+use crate::cache;
+use crate::embedding::{self, embed_documents};
+use crate::indexer::context::estimate_tokens;
+use crate::models::TokenCounter;
+use crate::profile;
+use serde::Serialize;
+use std::time::Instant;
+
+// Words synthetic chunks are drawn from: prose and identifiers, so tokenizers
+// split them about as often as they would a real tree
+const WORDS: &[&str] = &[
+    "the", "index", "search", "returns", "chunks", "ranked", "by", "score", "when", "a", "query", "matches", "vector", "model",
+    "embedding", "config", "fn", "let", "struct", "impl", "Result", "String", "Vec<f32>", "self", "storage_path", "batch_size",
+    "commit", "segment", "merge", "tokenizer", "overlap", "retry", "timeout", "handler", "request", "response", "async", "await",
+    "error", "cache", "shard", "reader", "writer", "pipeline", "throughput", "latency", "memory", "thread", "pool", "worker",
+];
+// About what the chunker emits, which caps chunks at 1000 characters
+const CHUNK_CHARS: usize = 800;
+
+// One timed run over the corpus. Peak memory is heap the run added on top of
+// what was live before it, read from the counting allocator; zero in
+// binaries that don't install it.
+#[derive(Serialize, Debug, Clone)]
+pub struct BenchRun {
+    pub engine: String,
+    pub batch_size: usize,
+    pub threads: usize,
+    pub chunks: usize,
+    pub tokens: usize,
+    pub elapsed_ms: u128,
+    pub chunks_per_sec: f64,
+    pub tokens_per_sec: f64,
+    pub peak_memory_bytes: u64,
+}
+
+// `count` chunks of made-up text, the same for a seed every run
+pub fn synthetic_chunks(count: usize, seed: u64) -> Vec<String> {
+    // xorshift64; zero would stay zero
+    let mut state = seed | 1;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    (0..count)
+        .map(|_| {
+            let mut chunk = String::with_capacity(CHUNK_CHARS + 16);
+            while chunk.len() < CHUNK_CHARS {
+                chunk.push_str(WORDS[next() as usize % WORDS.len()]);
+                chunk.push(if next() % 12 == 0 { '\n' } else { ' ' });
+            }
+            chunk
+        })
+        .collect()
+}
+
+// Embeds `chunks` with every engine, batch size and thread count combination
+// in turn. The embedding cache is switched off so each run reaches the
+// engine, and a warm-up embed keeps model loading out of the timings.
+pub fn run_bench(model: &str, chunks: &[String], engines: &[String], batch_sizes: &[usize], threads: &[usize]) -> Result<Vec<BenchRun>, String> {
+    let tokens: usize = match TokenCounter::for_model(model) {
+        Some(counter) => chunks.iter().map(|chunk| counter.fit(chunk).map(|fit| fit.tokens)).sum::<Result<_, _>>()?,
+        None => chunks.iter().map(|chunk| estimate_tokens(chunk)).sum(),
+    };
+    cache::set_cache_dir(None);
+
+    let mut runs = Vec::new();
+    for engine in engines {
+        embedding::set_engine(engine)?;
+        for &batch_size in batch_sizes {
+            for &thread_count in threads {
+                embedding::set_batch_size(batch_size);
+                embedding::set_threads(thread_count);
+                embed_documents(model, &chunks[..1.min(chunks.len())])?;
+
+                let baseline = profile::reset_peak();
+                let started = Instant::now();
+                embed_documents(model, chunks)?;
+                let elapsed = started.elapsed();
+                let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+                runs.push(BenchRun {
+                    engine: engine.clone(),
+                    batch_size,
+                    threads: thread_count,
+                    chunks: chunks.len(),
+                    tokens,
+                    elapsed_ms: elapsed.as_millis(),
+                    chunks_per_sec: chunks.len() as f64 / seconds,
+                    tokens_per_sec: tokens as f64 / seconds,
+                    peak_memory_bytes: profile::peak_bytes().saturating_sub(baseline),
+                });
+            }
+        }
+    }
+    Ok(runs)
+}
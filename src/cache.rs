@@ -0,0 +1,172 @@
+use crate::embedding::Pooling;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+const DEFAULT_CACHE_DIR: &str = ".cache/context-rag/embeddings";
+
+// Where embeddings are kept by content; None (the library's default) turns
+// the cache off
+static CACHE_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+pub fn set_cache_dir(dir: Option<PathBuf>) {
+    *CACHE_DIR.lock().unwrap_or_else(|e| e.into_inner()) = dir;
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+    CACHE_DIR.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+pub fn default_cache_dir() -> PathBuf {
+    std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default().join(DEFAULT_CACHE_DIR)
+}
+
+// Vectors an engine produced for a model, one file each under
+// <dir>/<model>/<2 hex>/<sha256>.f32 as little-endian floats. The hash
+// covers the engine, the model file when one was given (by path, size and
+// modification time), requested dimensions, pooling and normalization along
+// with the model and text, so vectors made one way never answer for other
+// settings.
+// Reads and writes are best effort: a missing or damaged entry is embedded
+// again, a failed write only costs the next run the same work.
+pub struct EmbeddingCache {
+    dir: PathBuf,
+    key: String,
+}
+
+impl EmbeddingCache {
+    pub fn new(dir: &Path, engine: &str, model: &str, model_file: Option<&Path>, dimensions: Option<usize>, pooling: Option<Pooling>, normalized: bool) -> Self {
+        // Settings left at their defaults hash as they did before they existed
+        let mut key = format!("{}\0{}\0{}\0", engine, model, dimensions.unwrap_or(0));
+        if let Some(file) = model_file {
+            let file = fs::canonicalize(file).unwrap_or_else(|_| file.to_path_buf());
+            let metadata = fs::metadata(&file).ok();
+            let modified = metadata.as_ref().and_then(|metadata| metadata.modified().ok()).and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok());
+            key.push_str(&format!(
+                "{}\0{}\0{}\0",
+                file.display(),
+                metadata.map_or(0, |metadata| metadata.len()),
+                modified.map_or(0, |modified| modified.as_nanos())
+            ));
+        }
+        if let Some(pooling) = pooling {
+            key.push_str(&format!("{:?}\0", pooling));
+        }
+        if !normalized {
+            key.push_str("raw\0");
+        }
+        EmbeddingCache { dir: dir.join(model_dir_name(model)), key }
+    }
+
+    pub fn get(&self, text: &str) -> Option<Vec<f32>> {
+        let bytes = fs::read(self.entry(text)).ok()?;
+        if bytes.is_empty() || bytes.len() % 4 != 0 {
+            return None;
+        }
+        Some(bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect())
+    }
+
+    pub fn put(&self, text: &str, embedding: &[f32]) {
+        let _ = self.write(text, embedding);
+    }
+
+    // `put` for callers that need to know the entry was written
+    pub fn write(&self, text: &str, embedding: &[f32]) -> io::Result<()> {
+        let path = self.entry(text);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let bytes: Vec<u8> = embedding.iter().flat_map(|value| value.to_le_bytes()).collect();
+        // Renamed into place so a concurrent reader never sees half a vector
+        let partial = path.with_extension(format!("{}.tmp", std::process::id()));
+        let written = fs::write(&partial, bytes).and_then(|_| fs::rename(&partial, &path));
+        if written.is_err() {
+            let _ = fs::remove_file(&partial);
+        }
+        written
+    }
+
+    fn entry(&self, text: &str) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(self.key.as_bytes());
+        hasher.update(text.as_bytes());
+        let hash = hex::encode(hasher.finalize());
+        self.dir.join(&hash[..2]).join(format!("{}.f32", hash))
+    }
+}
+
+// Model names can be paths or carry an owner (BAAI/bge-small-en-v1.5)
+fn model_dir_name(model: &str) -> String {
+    model.replace(['/', '\\', ':'], "--")
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ClearedCache {
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+// Deletes the cached embeddings of `model`, or of every model. Only what the
+// cache writes is removed: a directory holding anything else is refused
+// untouched, so a mistyped --cache-dir can't take other files with it.
+pub fn clear_cache(dir: &Path, model: Option<&str>) -> io::Result<ClearedCache> {
+    let (root, depth) = match model {
+        Some(model) => (dir.join(model_dir_name(model)), 2usize),
+        None => (dir.to_path_buf(), 3),
+    };
+    let mut cleared = ClearedCache::default();
+    if !root.exists() {
+        return Ok(cleared);
+    }
+
+    let mut entries = Vec::new();
+    let mut directories = Vec::new();
+    for entry in walkdir::WalkDir::new(&root).min_depth(1).contents_first(true) {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy();
+        // Entries sit `depth` levels down, under a model directory (when
+        // clearing them all) and a two-hex-digit one
+        let expected = match depth.checked_sub(entry.depth()) {
+            Some(0) => entry.file_type().is_file() && is_entry_name(&name),
+            Some(1) => entry.file_type().is_dir() && name.len() == 2 && name.bytes().all(|b| b.is_ascii_hexdigit()),
+            Some(_) => entry.file_type().is_dir(),
+            None => false,
+        };
+        if !expected {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a cached embedding; refusing to clear {}", entry.path().display(), root.display()),
+            ));
+        }
+        if entry.file_type().is_dir() {
+            directories.push(entry.into_path());
+        } else {
+            entries.push((entry.metadata()?.len(), entry.into_path()));
+        }
+    }
+
+    for (bytes, path) in entries {
+        fs::remove_file(path)?;
+        cleared.entries += 1;
+        cleared.bytes += bytes;
+    }
+    // Deepest first, as the walk listed them
+    for directory in directories {
+        fs::remove_dir(directory)?;
+    }
+    if model.is_some() {
+        fs::remove_dir(&root)?;
+    }
+    Ok(cleared)
+}
+
+// <sha256>.f32, or a write interrupted before its rename (<sha256>.<pid>.tmp)
+fn is_entry_name(name: &str) -> bool {
+    let (hash, rest) = name.split_at(name.len().min(64));
+    hash.len() == 64
+        && hash.bytes().all(|b| b.is_ascii_hexdigit())
+        && (rest == ".f32" || rest.strip_prefix('.').and_then(|rest| rest.strip_suffix(".tmp")).is_some_and(|pid| !pid.is_empty() && pid.bytes().all(|b| b.is_ascii_digit())))
+}
@@ -0,0 +1,241 @@
+use crate::embedding::{batch_size, device, fall_back_to_cpu, note_device_used, ComputeDevice, EmbeddingEngine, preset_for_model, Pooling};
+use crate::models::{embed_batched, load_tokenizer, model_dir, pool, score_batched};
+use candle_core::{DType, Device, IndexOp, Module, Tensor};
+use candle_nn::{linear, Linear, VarBuilder};
+use candle_transformers::models::bert::{BertModel, Config};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokenizers::Tokenizer;
+
+// A BERT-family checkout (config.json, tokenizer.json, model.safetensors)
+// run on --device; forward passes only read the weights, so no lock is needed
+struct CandleModel {
+    model: BertModel,
+    tokenizer: Tokenizer,
+    pooling: Pooling,
+    device: Device,
+    compute: ComputeDevice,
+}
+
+// A BertForSequenceClassification checkout (ms-marco cross-encoders and
+// the like): the encoder with its pooler and a classifier on top, scoring a
+// query and a text read together
+struct CrossEncoder {
+    model: BertModel,
+    pooler: Linear,
+    classifier: Linear,
+    tokenizer: Tokenizer,
+    device: Device,
+    compute: ComputeDevice,
+}
+
+static MODELS: Mutex<BTreeMap<String, Arc<CandleModel>>> = Mutex::new(BTreeMap::new());
+static CROSS_ENCODERS: Mutex<BTreeMap<String, Arc<CrossEncoder>>> = Mutex::new(BTreeMap::new());
+
+// Registered as `candle`; texts go through the model --batch-size at a
+// time, loading it on first use
+pub struct CandleEngine;
+
+impl EmbeddingEngine for CandleEngine {
+    fn embed_batch(&self, model: &str, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+        let loaded = load(model)?;
+        note_device_used(loaded.compute);
+        loaded.embed(texts).map_err(|e| format!("Candle embedding with {} failed: {}", model, e))
+    }
+
+    fn splits_long_inputs(&self) -> bool {
+        true
+    }
+
+    // Forward passes only read the weights
+    fn parallel_on_cpu(&self) -> bool {
+        device() == ComputeDevice::Cpu
+    }
+
+    fn score_pairs(&self, model: &str, query: &str, texts: &[&str]) -> Result<Vec<f32>, String> {
+        let loaded = load_cross_encoder(model)?;
+        note_device_used(loaded.compute);
+        loaded.score(query, texts).map_err(|e| format!("Candle reranking with {} failed: {}", model, e))
+    }
+}
+
+fn load(model: &str) -> Result<Arc<CandleModel>, String> {
+    let mut models = MODELS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(loaded) = models.get(model) {
+        return Ok(loaded.clone());
+    }
+    let dir = model_dir(model);
+    let loaded = Arc::new(CandleModel::open(model, &dir).map_err(|e| format!("Failed to load {} from {}: {}", model, dir.display(), e))?);
+    models.insert(model.to_string(), loaded.clone());
+    Ok(loaded)
+}
+
+fn load_cross_encoder(model: &str) -> Result<Arc<CrossEncoder>, String> {
+    let mut models = CROSS_ENCODERS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(loaded) = models.get(model) {
+        return Ok(loaded.clone());
+    }
+    let dir = model_dir(model);
+    let loaded = Arc::new(CrossEncoder::open(&dir).map_err(|e| format!("Failed to load {} from {}: {}", model, dir.display(), e))?);
+    models.insert(model.to_string(), loaded.clone());
+    Ok(loaded)
+}
+
+impl CandleModel {
+    fn open(model: &str, dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let config: Config = serde_json::from_str(&fs::read_to_string(dir.join("config.json")).map_err(|e| format!("config.json: {}", e))?)
+            .map_err(|e| format!("config.json: {}", e))?;
+        let weights = dir.join("model.safetensors");
+        if !weights.is_file() {
+            return Err("no model.safetensors".into());
+        }
+        // Safety: the weights are mapped read-only and not expected to
+        // change while the process runs, same as the index's own mmaps
+        let (device, compute) = candle_device();
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[weights], DType::F32, &device)? };
+        Ok(CandleModel {
+            model: BertModel::load(vb, &config)?,
+            tokenizer: load_tokenizer(dir)?,
+            pooling: preset_for_model(model).map_or(Pooling::Mean, |preset| preset.pooling),
+            device,
+            compute,
+        })
+    }
+
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        embed_batched(&self.tokenizer, texts, batch_size(), |batch| {
+            let rows = |values: &[u32]| Tensor::from_slice(values, (batch.rows, batch.len), &self.device);
+            // [rows, tokens, hidden]
+            let states = self.model.forward(&rows(&batch.ids)?, &rows(&batch.type_ids)?, Some(&rows(&batch.mask)?))?.to_vec3::<f32>()?;
+            Ok(states.into_iter().enumerate().map(|(row, states)| pool(states, &batch.row_mask(row), self.pooling)).collect())
+        })
+    }
+}
+
+impl CrossEncoder {
+    fn open(dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let json = fs::read_to_string(dir.join("config.json")).map_err(|e| format!("config.json: {}", e))?;
+        let config: Config = serde_json::from_str(&json).map_err(|e| format!("config.json: {}", e))?;
+        let labels = serde_json::from_str::<serde_json::Value>(&json)?["id2label"].as_object().map_or(1, |labels| labels.len().max(1));
+        let weights = dir.join("model.safetensors");
+        if !weights.is_file() {
+            return Err("no model.safetensors".into());
+        }
+        let (device, compute) = candle_device();
+        // Safety: as for embedding models
+        let vb = unsafe { VarBuilder::from_mmaped_safetensors(&[weights], DType::F32, &device)? };
+        // The encoder's weights sit under config.json's model_type (`bert.`)
+        let prefix = config.model_type.clone().unwrap_or_else(|| "bert".to_string());
+        Ok(CrossEncoder {
+            model: BertModel::load(vb.clone(), &config)?,
+            pooler: linear(config.hidden_size, config.hidden_size, vb.pp(format!("{}.pooler.dense", prefix)))?,
+            classifier: linear(config.hidden_size, labels, vb.pp("classifier"))?,
+            tokenizer: load_tokenizer(dir)?,
+            device,
+            compute,
+        })
+    }
+
+    // The classifier's logit, or with several labels the last one's (the
+    // "relevant" class of two-way classifiers)
+    fn score(&self, query: &str, texts: &[&str]) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        score_batched(&self.tokenizer, query, texts, batch_size(), |batch| {
+            let rows = |values: &[u32]| Tensor::from_slice(values, (batch.rows, batch.len), &self.device);
+            let states = self.model.forward(&rows(&batch.ids)?, &rows(&batch.type_ids)?, Some(&rows(&batch.mask)?))?;
+            // [rows, hidden] from each row's [CLS] state
+            let pooled = self.pooler.forward(&states.i((.., 0))?)?.tanh()?;
+            let logits = self.classifier.forward(&pooled)?.to_vec2::<f32>()?;
+            Ok(logits.into_iter().map(|row| row.last().copied().unwrap_or_default()).collect())
+        })
+    }
+}
+
+// The first GPU of the kind --device asks for, or the CPU when there is none
+// or this build can't drive it
+pub(crate) fn candle_device() -> (Device, ComputeDevice) {
+    let found = match device() {
+        ComputeDevice::Cpu => return (Device::Cpu, ComputeDevice::Cpu),
+        ComputeDevice::Cuda => Device::new_cuda(0),
+        ComputeDevice::Metal => Device::new_metal(0),
+    };
+    match found {
+        Ok(found) => (found, device()),
+        Err(e) => (Device::Cpu, fall_back_to_cpu(device(), e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{write_tokenizer, TempDir};
+    use candle_nn::VarMap;
+
+    // A one-layer BERT with random weights over test_utils' vocabulary: its
+    // vectors mean nothing, but loading, masking and batching are the real ones
+    fn tiny_bert(dir: &Path, classifier: bool) {
+        let config = serde_json::json!({
+            "vocab_size": 40, "hidden_size": 8, "num_hidden_layers": 1, "num_attention_heads": 2, "intermediate_size": 16,
+            "hidden_act": "gelu", "hidden_dropout_prob": 0.0, "max_position_embeddings": 32, "type_vocab_size": 2,
+            "initializer_range": 0.02, "layer_norm_eps": 1e-12, "pad_token_id": 0, "classifier_dropout": null, "model_type": "bert",
+        });
+        fs::write(dir.join("config.json"), config.to_string()).unwrap();
+        let vars = VarMap::new();
+        let vb = VarBuilder::from_varmap(&vars, DType::F32, &Device::Cpu);
+        BertModel::load(vb.clone(), &serde_json::from_value(config).unwrap()).unwrap();
+        if classifier {
+            linear(8, 8, vb.pp("bert.pooler.dense")).unwrap();
+            linear(8, 1, vb.pp("classifier")).unwrap();
+        }
+        vars.save(dir.join("model.safetensors")).unwrap();
+        write_tokenizer(dir).unwrap();
+    }
+
+    fn close(a: &[f32], b: &[f32]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4)
+    }
+
+    // Padding a text out to its batch's longest doesn't move its vector
+    #[test]
+    fn batched_texts_embed_as_they_do_alone() {
+        let dir = TempDir::new("candle-embed").unwrap();
+        tiny_bert(dir.path(), false);
+        let model = CandleModel::open("tiny", dir.path()).unwrap();
+
+        let texts = ["index", "chunk vector query shard segment token", "unlisted words"];
+        let batched = model.embed(&texts).unwrap();
+        assert_eq!(batched.len(), texts.len());
+        for (text, vector) in texts.iter().zip(&batched) {
+            assert_eq!(vector.len(), 8);
+            assert!((vector.iter().map(|x| x * x).sum::<f32>().sqrt() - 1.0).abs() < 1e-4);
+            assert!(close(vector, &model.embed(&[text]).unwrap()[0]), "{}", text);
+        }
+        assert!(!close(&batched[0], &batched[1]));
+    }
+
+    #[test]
+    fn cross_encoders_score_each_pair() {
+        let dir = TempDir::new("candle-rerank").unwrap();
+        tiny_bert(dir.path(), true);
+        let model = CrossEncoder::open(dir.path()).unwrap();
+
+        let texts = ["search ranking", "chunk", "fusion of keyword and vector scores"];
+        let scores = model.score("ranking", &texts).unwrap();
+        assert_eq!(scores.len(), texts.len());
+        for (text, score) in texts.iter().zip(&scores) {
+            assert!(score.is_finite());
+            assert!((score - model.score("ranking", &[text]).unwrap()[0]).abs() < 1e-4, "{}", text);
+        }
+    }
+
+    #[test]
+    fn checkouts_without_weights_are_refused() {
+        let dir = TempDir::new("candle-missing").unwrap();
+        tiny_bert(dir.path(), false);
+        fs::remove_file(dir.join("model.safetensors")).unwrap();
+        let error = CandleModel::open("tiny", dir.path()).err().unwrap();
+        assert!(error.to_string().contains("no model.safetensors"));
+        assert!(CrossEncoder::open(dir.path()).is_err());
+    }
+}
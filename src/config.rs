@@ -0,0 +1,335 @@
+use crate::embedding;
+use crate::indexer::context::DEFAULT_CONTEXT_TOKENS;
+use crate::indexer::presets::{index_preset, BINARY_EXCLUDES};
+use crate::indexer::{Blocklist, ContentHash, FallbackLadder, FusionWeights, IndexConfig, PathAliases};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+pub const CONFIG_FILE: &str = ".context-rag.toml";
+pub const DEFAULT_STORAGE_PATH: &str = ".context-rag/index";
+pub const DEFAULT_MODEL: &str = "sentence-transformers/all-MiniLM-L6-v2";
+
+// Project configuration read from `.context-rag.toml`
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ProjectConfig {
+    #[serde(default)]
+    pub index: IndexSection,
+    #[serde(default)]
+    pub embedder: EmbedderSection,
+    #[serde(default)]
+    pub search: SearchSection,
+    #[serde(default)]
+    pub context: ContextSection,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexSection {
+    // "node", "rust", "python" or "monorepo": bundled include, exclude and
+    // prune_dirs lists, which the ones below extend, plus a file size limit
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    // ":memory:" keeps the index in RAM for the life of the process
+    #[serde(default = "default_storage_path", alias = "storage")]
+    pub storage_path: String,
+    // "default", "cjk" for Chinese/Japanese/Korean docs, or "jieba"
+    #[serde(default)]
+    pub tokenizer: Option<String>,
+    #[serde(default)]
+    pub deterministic: bool,
+    // Fraction of files a line must appear in to be stripped as boilerplate
+    // (license headers, generated banners); unset keeps every line
+    #[serde(default)]
+    pub boilerplate_threshold: Option<f32>,
+    // Drop leading license comments (SPDX, copyright blocks) before chunking
+    #[serde(default)]
+    pub strip_license_headers: bool,
+    // Index code comments and docstrings in a field of their own, which
+    // `search --comment-boost` can weight up for natural-language queries
+    #[serde(default)]
+    pub extract_comments: bool,
+    // Split the index into this many shards by path hash, for repositories
+    // with millions of chunks; fixed when the index is first built
+    #[serde(default)]
+    pub shards: Option<usize>,
+    // Hybrid searches score vector stores bigger than this ("512MB") off
+    // disk instead of loading them, so large indexes don't swap
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_ann_memory: Option<ByteSize>,
+    // "blake3" (the default for new indexes) or "sha256" for snapshots that
+    // get signed; changing it takes effect on the next full index run
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<ContentHash>,
+    // `paths` globs (`**/secrets/**`) and `terms` no search may return,
+    // enforced by the index itself whatever filters a caller passes
+    #[serde(default, skip_serializing_if = "Blocklist::is_empty")]
+    pub blocklist: Blocklist,
+    // Leave out files bigger than this ("1MB"), such as generated dumps and
+    // vendored bundles; unset indexes files of any size
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_file_size: Option<ByteSize>,
+    // `"node_modules/@org/pkg" = "packages/pkg"`: files under the first are
+    // indexed and reported as the second, the include and exclude patterns
+    // being matched against that. Skipped where the second already exists.
+    #[serde(default, skip_serializing_if = "PathAliases::is_empty")]
+    pub aliases: PathAliases,
+    // Directory names such as "node_modules" or "target" the walk skips
+    // wherever they appear, without reading what's inside
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prune_dirs: Vec<String>,
+    // How many levels below the project root files are indexed: 1 keeps
+    // only the root's own files; unset walks the whole tree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<usize>,
+}
+
+impl Default for IndexSection {
+    fn default() -> Self {
+        IndexSection {
+            preset: None,
+            include: vec!["*.md".to_string(), "docs/".to_string()],
+            exclude: vec![".git/".to_string(), "node_modules/".to_string(), "target/".to_string()],
+            storage_path: default_storage_path(),
+            tokenizer: None,
+            deterministic: false,
+            boilerplate_threshold: None,
+            strip_license_headers: false,
+            extract_comments: false,
+            shards: None,
+            max_ann_memory: None,
+            content_hash: None,
+            blocklist: Blocklist::default(),
+            max_file_size: None,
+            aliases: PathAliases::default(),
+            prune_dirs: Vec::new(),
+            max_depth: None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EmbedderSection {
+    #[serde(default = "default_model")]
+    pub model: String,
+    // "fast", "quality", "multilingual" or "code"; sets the model
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    // Embeds source chunks, by detected language; prose stays on `model`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_model: Option<String>,
+}
+
+impl Default for EmbedderSection {
+    fn default() -> Self {
+        EmbedderSection { model: default_model(), preset: None, code_model: None }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct SearchSection {
+    // Hybrid channel weights; `tune fusion` writes these from an eval set
+    #[serde(default)]
+    pub fusion: FusionWeights,
+    // Keyword hits under this calibrated confidence (0-1) are dropped
+    #[serde(default)]
+    pub min_confidence: f32,
+    // Looser searches tried when a keyword search comes back (nearly) empty
+    #[serde(default)]
+    pub fallback: FallbackLadder,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContextSection {
+    // Files, or `path#chunk` chunks, always put ahead of retrieved results
+    #[serde(default)]
+    pub pinned: Vec<String>,
+    // Estimated tokens an assembled context may hold, pins included
+    #[serde(default = "default_context_tokens")]
+    pub max_tokens: usize,
+}
+
+impl Default for ContextSection {
+    fn default() -> Self {
+        ContextSection { pinned: Vec::new(), max_tokens: default_context_tokens() }
+    }
+}
+
+// A byte count, written as a number or with a KB, MB or GB suffix
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteSize(pub u64);
+
+impl std::str::FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let value = value.trim();
+        let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+        let (number, unit) = value.split_at(split);
+        let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+            "" | "B" => 1,
+            "KB" => 1 << 10,
+            "MB" => 1 << 20,
+            "GB" => 1 << 30,
+            _ => return Err(format!("Invalid size '{}' (expected e.g. 512MB)", value)),
+        };
+        let number: u64 = number.parse().map_err(|_| format!("Invalid size '{}' (expected e.g. 512MB)", value))?;
+        Ok(ByteSize(number * multiplier))
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Bytes(u64),
+            Text(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Bytes(bytes) => Ok(ByteSize(bytes)),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+fn default_storage_path() -> String {
+    DEFAULT_STORAGE_PATH.to_string()
+}
+
+fn default_context_tokens() -> usize {
+    DEFAULT_CONTEXT_TOKENS
+}
+
+fn default_model() -> String {
+    DEFAULT_MODEL.to_string()
+}
+
+impl EmbedderSection {
+    // A preset decides the model; naming a different model as well is a mistake
+    fn resolve_preset(&mut self) -> Result<(), String> {
+        let Some(name) = &self.preset else {
+            return Ok(());
+        };
+        let preset = embedding::preset(name)?;
+        if self.model != DEFAULT_MODEL && self.model != preset.model {
+            return Err(format!(
+                "[embedder] sets both model = \"{}\" and preset = \"{}\" ({}); keep one",
+                self.model, name, preset.model
+            ));
+        }
+        self.model = preset.model.to_string();
+        Ok(())
+    }
+}
+
+impl IndexSection {
+    // The preset's lists go first and the config's own entries after them;
+    // settings the config leaves unset take the preset's
+    fn resolve_preset(&mut self) -> Result<(), String> {
+        let Some(name) = &self.preset else {
+            return Ok(());
+        };
+        let preset = index_preset(name)?;
+        extend(&mut self.include, preset.include);
+        extend(&mut self.exclude, preset.exclude);
+        extend(&mut self.exclude, BINARY_EXCLUDES);
+        extend(&mut self.prune_dirs, preset.prune_dirs);
+        self.max_file_size = self.max_file_size.or(Some(ByteSize(preset.max_file_size)));
+        self.extract_comments |= preset.extract_comments;
+        Ok(())
+    }
+}
+
+fn extend(entries: &mut Vec<String>, preset: &[&str]) {
+    let own = std::mem::take(entries);
+    *entries = preset.iter().map(|entry| entry.to_string()).collect();
+    for entry in own {
+        if !entries.contains(&entry) {
+            entries.push(entry);
+        }
+    }
+}
+
+impl ProjectConfig {
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(path)?;
+        Ok(Self::parse(&content).map_err(|e| format!("Invalid {}: {}", path.display(), e))?)
+    }
+
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let mut config: ProjectConfig = toml::from_str(content).map_err(|e| e.to_string())?;
+        config.index.resolve_preset()?;
+        config.embedder.resolve_preset()?;
+        Ok(config)
+    }
+
+    // Missing config is not an error; the defaults index docs and markdown
+    pub fn load_or_default(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        if path.exists() {
+            Self::load(path)
+        } else {
+            Ok(ProjectConfig::default())
+        }
+    }
+
+    // Rewrites [search.fusion] in place, keeping the rest of the file and its
+    // comments as they are; creates the file if there is none yet
+    pub fn write_fusion_weights(path: &Path, weights: &FusionWeights) -> Result<(), Box<dyn std::error::Error>> {
+        let content = if path.exists() { fs::read_to_string(path)? } else { String::new() };
+        let mut document: toml_edit::DocumentMut = content
+            .parse()
+            .map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+
+        let search = document
+            .entry("search")
+            .or_insert_with(toml_edit::table)
+            .as_table_mut()
+            .ok_or_else(|| format!("[search] in {} is not a table", path.display()))?;
+        search.set_implicit(true);
+        let fusion = search
+            .entry("fusion")
+            .or_insert_with(toml_edit::table)
+            .as_table_mut()
+            .ok_or_else(|| format!("[search.fusion] in {} is not a table", path.display()))?;
+        for (key, value) in [("keyword", weights.keyword), ("identifier", weights.identifier), ("vector", weights.vector)] {
+            fusion[key] = toml_edit::value(value as f64);
+        }
+
+        fs::write(path, document.to_string())?;
+        Ok(())
+    }
+
+    pub fn index_config(&self) -> IndexConfig {
+        IndexConfig {
+            include: self.index.include.clone(),
+            exclude: self.index.exclude.clone(),
+            storage_path: self.index.storage_path.clone(),
+            model: self.embedder.model.clone(),
+            code_model: self.embedder.code_model.clone(),
+            tokenizer: self.index.tokenizer.clone(),
+            deterministic: self.index.deterministic,
+            boilerplate_threshold: self.index.boilerplate_threshold,
+            strip_license_headers: self.index.strip_license_headers,
+            extract_comments: self.index.extract_comments,
+            shards: self.index.shards,
+            max_ann_memory: self.index.max_ann_memory.map(|size| size.0),
+            content_hash: self.index.content_hash,
+            blocklist: self.index.blocklist.clone(),
+            max_file_size: self.index.max_file_size.map(|size| size.0),
+            aliases: self.index.aliases.clone(),
+            prune_dirs: self.index.prune_dirs.clone(),
+            max_depth: self.index.max_depth,
+            root: None,
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use crate::embedding::{self, EmbedMode};
+use crate::quantize::Quantization;
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+
+// A long-lived embedder for callers that would otherwise spawn the binary
+// per request and pay for loading the model every time. The model is loaded
+// once up front, then each input line is a request and each output line its
+// answer, in order:
+//
+//   {"chunks": [...]}   the chunks with embeddings, as the one-shot stdin
+//                       protocol answers; embedded as documents
+//   {"text": "..."}     one vector, as `--text` prints it; a query
+//   {"exit": true}      answered with {"exit": true}, then the daemon stops
+//
+// `"mode"` embeds either as queries or documents instead, and an `"id"` is
+// echoed back for matching answers to requests. A failed request is answered
+// with `{"error": ...}` and the daemon carries on.
+#[derive(Debug, Clone)]
+pub struct DaemonOptions {
+    pub model: String,
+    // Overrides each request's default; a request's own "mode" wins
+    pub mode: Option<EmbedMode>,
+    pub quantization: Option<Quantization>,
+    pub sparse: bool,
+}
+
+// Loads the model by embedding a throwaway text, so the first request isn't
+// the slow one
+pub fn warm_up(model: &str) -> Result<(), String> {
+    embedding::embed_query(model, "warm-up").map(drop)
+}
+
+// The answer to one request line, and whether it asked the daemon to stop
+pub fn handle_line(line: &str, options: &DaemonOptions) -> (Value, bool) {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return (json!({ "error": format!("Invalid request: {}", e) }), false),
+    };
+    let exit = request["exit"].as_bool() == Some(true);
+    let mut response = match respond(line, &request, options) {
+        Ok(response) => response,
+        Err(e) => json!({ "error": e }),
+    };
+    if let Some(id) = request.get("id") {
+        response["id"] = id.clone();
+    }
+    (response, exit)
+}
+
+fn respond(line: &str, request: &Value, options: &DaemonOptions) -> Result<Value, String> {
+    let mode = |default| -> Result<EmbedMode, String> {
+        match request["mode"].as_str() {
+            Some(mode) => mode.parse(),
+            None => Ok(options.mode.unwrap_or(default)),
+        }
+    };
+    if request["exit"].as_bool() == Some(true) {
+        Ok(json!({ "exit": true }))
+    } else if let Some(text) = request["text"].as_str() {
+        embedding::embed_text_request(&options.model, text, mode(EmbedMode::Query)?, options.quantization, options.sparse)
+    } else if request.get("chunks").is_some() {
+        embedding::embed_chunks_request(&options.model, line, mode(EmbedMode::Document)?, options.quantization, options.sparse)
+    } else {
+        Err("Expected a 'chunks' array, a 'text' or 'exit'".to_string())
+    }
+}
+
+// Answers requests from `input` until it ends or one asks to exit; returns
+// whether one did
+pub fn serve_lines(input: impl BufRead, mut output: impl Write, options: &DaemonOptions) -> std::io::Result<bool> {
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (response, exit) = handle_line(&line, options);
+        writeln!(output, "{}", response)?;
+        output.flush()?;
+        if exit {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// The same protocol on a Unix socket, one thread per connection, until any
+// connection asks to exit; the socket file is removed on the way out
+#[cfg(unix)]
+pub fn serve_socket(socket_path: &str, mode: u32, options: DaemonOptions) -> std::io::Result<()> {
+    use std::io::BufReader;
+    use std::os::unix::net::UnixStream;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    let listener = crate::server::unix::bind(socket_path, mode)?;
+    let exiting = Arc::new(AtomicBool::new(false));
+    let options = Arc::new(options);
+
+    for stream in listener.incoming() {
+        if exiting.load(Ordering::Relaxed) {
+            break;
+        }
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        let (exiting, options, socket_path) = (exiting.clone(), options.clone(), socket_path.to_string());
+        std::thread::spawn(move || {
+            let served = stream.try_clone().and_then(|writer| serve_lines(BufReader::new(stream), writer, &options));
+            match served {
+                Ok(true) => {
+                    exiting.store(true, Ordering::Relaxed);
+                    // Wakes the accept loop so it sees the flag
+                    let _ = UnixStream::connect(&socket_path);
+                }
+                Ok(false) => {}
+                Err(e) => eprintln!("Connection error: {}", e),
+            }
+        });
+    }
+
+    std::fs::remove_file(socket_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_answered_in_order_until_one_exits() {
+        let options = DaemonOptions { model: "mock-model".to_string(), mode: None, quantization: None, sparse: false };
+        let input = [
+            r#"{"id": 1, "text": "signing keys"}"#,
+            "",
+            r#"{"id": "two", "chunks": [{"content": "signing keys", "file_path": "a.md"}], "mode": "query"}"#,
+            "not json",
+            r#"{"id": 3, "text": "signing keys", "mode": "sideways"}"#,
+            r#"{"id": 4}"#,
+            r#"{"exit": true, "id": 5}"#,
+            r#"{"id": 6, "text": "never read"}"#,
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        assert!(serve_lines(input.as_bytes(), &mut output, &options).unwrap());
+
+        let answers: Vec<Value> = String::from_utf8(output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(answers.len(), 6);
+        let query = embedding::embed_query("mock-model", "signing keys").unwrap();
+        let vector = |value: &Value| serde_json::from_value::<Vec<f32>>(value.clone()).unwrap();
+        assert_eq!(answers[0]["id"], 1);
+        assert_eq!(vector(&answers[0]["embedding"]), query);
+        assert_eq!(answers[1]["id"], "two");
+        assert_eq!(vector(&answers[1]["chunks"][0]["embedding"]), query);
+        assert!(answers[2]["error"].as_str().unwrap().starts_with("Invalid request"));
+        assert_eq!(answers[3]["id"], 3);
+        assert!(answers[3]["error"].is_string());
+        assert_eq!(answers[4]["error"], "Expected a 'chunks' array, a 'text' or 'exit'");
+        assert_eq!(answers[5], json!({ "exit": true, "id": 5 }));
+    }
+}
@@ -0,0 +1,879 @@
+use crate::cache::{cache_dir, EmbeddingCache};
+use crate::config::DEFAULT_MODEL;
+use crate::indexer::sparse::SparseVector;
+use crate::models::TokenCounter;
+use crate::quantize::{embedding_json, Quantization};
+use crate::recording::{self, RecordingEngine};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+
+// Where vectors come from. Engines are registered by name and one is picked
+// per process, so stored vectors and query vectors agree. Built in: `mock`
+// hashes text into stable stand-in vectors, `onnx` runs the model through
+// ONNX Runtime, `candle` runs BERT-family models in pure Rust and `gguf` runs
+// quantized GGUF files on the CPU (each built with the feature of the same
+// name); `openai` calls a remote OpenAI-compatible `/embeddings` endpoint and
+// `ollama` a local Ollama server.
+pub trait EmbeddingEngine: Send + Sync {
+    // One vector per text, in order, finished with `unit_length`
+    fn embed_batch(&self, model: &str, texts: &[&str]) -> Result<Vec<Vec<f32>>, String>;
+
+    // Whether the engine tokenizes texts itself and splits those over the
+    // model's limit into windows; others get texts cut at the limit first
+    fn splits_long_inputs(&self) -> bool {
+        false
+    }
+
+    // Whether concurrent `embed_batch` calls each keep a CPU core busy, so
+    // that --threads can split texts across them
+    fn parallel_on_cpu(&self) -> bool {
+        false
+    }
+
+    // How relevant each text is to `query`, by a cross-encoder `model` that
+    // reads the two together; higher is better, on the model's own scale
+    fn score_pairs(&self, model: &str, _query: &str, _texts: &[&str]) -> Result<Vec<f32>, String> {
+        Err(format!("The {} engine can't rerank with {}; use --engine candle or onnx", engine_name(), model))
+    }
+}
+
+pub struct MockEngine;
+
+impl EmbeddingEngine for MockEngine {
+    fn embed_batch(&self, _model: &str, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+        mock_call()?;
+        Ok(texts.iter().map(|text| generate_mock_embedding(text)).collect())
+    }
+
+    fn parallel_on_cpu(&self) -> bool {
+        true
+    }
+
+    fn score_pairs(&self, _model: &str, query: &str, texts: &[&str]) -> Result<Vec<f32>, String> {
+        mock_call()?;
+        Ok(texts.iter().map(|text| crate::rerank::mock_score(query, text)).collect())
+    }
+}
+
+// Knobs for testing callers' timeouts and retries against the mock engine:
+// a delay before every call, the share of calls that fail, and a seed that
+// changes every vector and picks which calls fail. The nth call fails or not
+// the same way every run; with --threads above 1, which batch is the nth
+// call is up to the scheduler.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MockSettings {
+    pub latency: Duration,
+    pub failure_rate: f64,
+    pub seed: u64,
+}
+
+impl MockSettings {
+    pub const DEFAULT: MockSettings = MockSettings { latency: Duration::ZERO, failure_rate: 0.0, seed: 0 };
+
+    // CONTEXT_RAG_MOCK_LATENCY_MS, CONTEXT_RAG_MOCK_FAILURE_RATE and
+    // CONTEXT_RAG_MOCK_SEED, for callers that can't pass flags
+    pub fn from_env() -> Result<Self, String> {
+        let mut settings = MockSettings::DEFAULT;
+        if let Some(ms) = env_var("CONTEXT_RAG_MOCK_LATENCY_MS")? {
+            settings.latency = Duration::from_millis(ms);
+        }
+        if let Some(rate) = env_var("CONTEXT_RAG_MOCK_FAILURE_RATE")? {
+            settings.failure_rate = rate;
+        }
+        if let Some(seed) = env_var("CONTEXT_RAG_MOCK_SEED")? {
+            settings.seed = seed;
+        }
+        Ok(settings)
+    }
+}
+
+fn env_var<T: std::str::FromStr>(name: &str) -> Result<Option<T>, String> {
+    match std::env::var(name) {
+        Ok(value) => value.trim().parse().map(Some).map_err(|_| format!("Invalid {}: '{}'", name, value)),
+        Err(_) => Ok(None),
+    }
+}
+
+pub fn set_mock_settings(settings: MockSettings) -> Result<(), String> {
+    if !(0.0..=1.0).contains(&settings.failure_rate) {
+        return Err(format!("Mock failure rate {} is not between 0 and 1", settings.failure_rate));
+    }
+    *MOCK.lock().unwrap_or_else(|e| e.into_inner()) = settings;
+    MOCK_CALLS.store(0, Ordering::Relaxed);
+    Ok(())
+}
+
+pub fn mock_settings() -> MockSettings {
+    *MOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Waits out the latency, then fails if this call's draw falls under the
+// failure rate
+fn mock_call() -> Result<(), String> {
+    let settings = mock_settings();
+    if !settings.latency.is_zero() {
+        std::thread::sleep(settings.latency);
+    }
+    let call = MOCK_CALLS.fetch_add(1, Ordering::Relaxed);
+    if settings.failure_rate > 0.0 && seeded_draw(settings.seed, call) < settings.failure_rate {
+        return Err(format!("Injected mock failure on call {} (failure rate {})", call, settings.failure_rate));
+    }
+    Ok(())
+}
+
+// Uniform in [0, 1) and fixed for a seed and call number
+fn seeded_draw(seed: u64, call: u64) -> f64 {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    let mut hasher = DefaultHasher::new();
+    (seed, call).hash(&mut hasher);
+    (hasher.finish() >> 11) as f64 / (1u64 << 53) as f64
+}
+
+// Engines behind a feature this build lacks, named in the error for them
+const FEATURE_ENGINES: [&str; 3] = ["onnx", "candle", "gguf"];
+
+static ENGINES: LazyLock<Mutex<BTreeMap<String, Arc<dyn EmbeddingEngine>>>> = LazyLock::new(|| {
+    let mut engines: BTreeMap<String, Arc<dyn EmbeddingEngine>> = BTreeMap::new();
+    engines.insert("mock".to_string(), Arc::new(MockEngine));
+    #[cfg(feature = "onnx")]
+    engines.insert("onnx".to_string(), Arc::new(crate::onnx::OnnxEngine));
+    #[cfg(feature = "candle")]
+    engines.insert("candle".to_string(), Arc::new(crate::candle::CandleEngine));
+    #[cfg(feature = "gguf")]
+    engines.insert("gguf".to_string(), Arc::new(crate::gguf::GgufEngine));
+    engines.insert("openai".to_string(), Arc::new(crate::openai::OpenAiEngine));
+    engines.insert("ollama".to_string(), Arc::new(crate::ollama::OllamaEngine));
+    Mutex::new(engines)
+});
+// The selected engine and its name; mock until one is set, with a warning
+// since its vectors only look like the model's
+static ENGINE: Mutex<Option<(String, Arc<dyn EmbeddingEngine>)>> = Mutex::new(None);
+static WARNED_UNSET_ENGINE: AtomicBool = AtomicBool::new(false);
+const UNSET_ENGINE_WARNING: &str = "no embedding engine selected, so vectors are hash-based mock stand-ins rather than the model's; \
+pass --engine onnx, candle, gguf, openai or ollama, or --engine mock to use them on purpose";
+static MOCK: Mutex<MockSettings> = Mutex::new(MockSettings::DEFAULT);
+// Calls the mock engine has taken since its settings were set
+static MOCK_CALLS: AtomicU64 = AtomicU64::new(0);
+// The model file for engines that load a single file rather than a checkout
+static MODEL_PATH: Mutex<Option<PathBuf>> = Mutex::new(None);
+// Texts per forward pass for engines that run the model locally
+static BATCH_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_BATCH_SIZE);
+
+// Where local engines run the model, and where the last of them ended up
+static DEVICE: Mutex<ComputeDevice> = Mutex::new(ComputeDevice::Cpu);
+static DEVICE_USED: Mutex<Option<ComputeDevice>> = Mutex::new(None);
+// Leading components kept of every vector, 0 to keep them all
+static DIMENSIONS: AtomicUsize = AtomicUsize::new(0);
+// Whether vectors are made unit length; off for callers scoring raw
+// magnitudes themselves
+static NORMALIZE: AtomicBool = AtomicBool::new(true);
+// Pooling forced on every local model instead of the one it was trained with
+static POOLING: Mutex<Option<Pooling>> = Mutex::new(None);
+// Threads embedding at once on the CPU, and the pool kept for them
+static THREADS: AtomicUsize = AtomicUsize::new(1);
+static POOL: Mutex<Option<Arc<rayon::ThreadPool>>> = Mutex::new(None);
+static ONNX_TUNING: Mutex<OnnxTuning> = Mutex::new(OnnxTuning::DEFAULT);
+
+pub const DEFAULT_BATCH_SIZE: usize = 32;
+
+// Makes `engine` selectable as `name`, replacing any engine of that name
+pub fn register_engine(name: &str, engine: Arc<dyn EmbeddingEngine>) {
+    ENGINES.lock().unwrap_or_else(|e| e.into_inner()).insert(name.to_string(), engine);
+}
+
+pub fn engine_names() -> Vec<String> {
+    ENGINES.lock().unwrap_or_else(|e| e.into_inner()).keys().cloned().collect()
+}
+
+pub fn set_engine(name: &str) -> Result<(), String> {
+    let engine = ENGINES.lock().unwrap_or_else(|e| e.into_inner()).get(name).cloned();
+    let engine = match engine {
+        Some(engine) => engine,
+        None if FEATURE_ENGINES.contains(&name) => return Err(format!("The {0} engine needs a build with the `{0}` feature", name)),
+        None => return Err(format!("Unknown engine '{}' (expected one of: {})", name, engine_names().join(", "))),
+    };
+    *ENGINE.lock().unwrap_or_else(|e| e.into_inner()) = Some((name.to_string(), engine));
+    Ok(())
+}
+
+// The selected engine, behind a recording or replay when one is set
+pub fn engine() -> Arc<dyn EmbeddingEngine> {
+    let selected = ENGINE.lock().unwrap_or_else(|e| e.into_inner()).as_ref().map(|(_, engine)| engine.clone());
+    let engine = selected.unwrap_or_else(|| {
+        if !WARNED_UNSET_ENGINE.swap(true, Ordering::Relaxed) {
+            eprintln!("Warning: {}", UNSET_ENGINE_WARNING);
+        }
+        Arc::new(MockEngine)
+    });
+    match recording::recording() {
+        Some(recording) => Arc::new(RecordingEngine { inner: engine, engine: engine_name(), recording }),
+        None => engine,
+    }
+}
+
+pub fn engine_name() -> String {
+    ENGINE.lock().unwrap_or_else(|e| e.into_inner()).as_ref().map_or_else(|| "mock".to_string(), |(name, _)| name.clone())
+}
+
+// Set while the mock engine stands in for one nobody chose
+pub fn engine_warning() -> Option<&'static str> {
+    ENGINE.lock().unwrap_or_else(|e| e.into_inner()).is_none().then_some(UNSET_ENGINE_WARNING)
+}
+
+// Names the engine behind a response's vectors, and carries the warning
+// when they are mock vectors nobody asked for
+pub fn with_engine(mut response: Value) -> Value {
+    response["engine"] = json!(engine_name());
+    if let Some(warning) = engine_warning() {
+        response["warning"] = json!(warning);
+    }
+    response
+}
+
+pub fn set_model_path(path: Option<PathBuf>) {
+    *MODEL_PATH.lock().unwrap_or_else(|e| e.into_inner()) = path;
+}
+
+pub fn model_path() -> Option<PathBuf> {
+    MODEL_PATH.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+pub fn set_batch_size(size: usize) {
+    BATCH_SIZE.store(size.max(1), Ordering::Relaxed);
+}
+
+pub fn batch_size() -> usize {
+    BATCH_SIZE.load(Ordering::Relaxed)
+}
+
+pub fn set_normalize(normalize: bool) {
+    NORMALIZE.store(normalize, Ordering::Relaxed);
+}
+
+pub fn normalizes() -> bool {
+    NORMALIZE.load(Ordering::Relaxed)
+}
+
+pub fn set_pooling(pooling: Option<Pooling>) {
+    *POOLING.lock().unwrap_or_else(|e| e.into_inner()) = pooling;
+}
+
+pub fn pooling() -> Option<Pooling> {
+    *POOLING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn set_threads(threads: usize) {
+    THREADS.store(threads.max(1), Ordering::Relaxed);
+    *POOL.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+pub fn threads() -> usize {
+    THREADS.load(Ordering::Relaxed)
+}
+
+fn thread_pool() -> Result<Arc<rayon::ThreadPool>, String> {
+    let mut pool = POOL.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(pool) = pool.as_ref() {
+        return Ok(pool.clone());
+    }
+    let built = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads())
+        .thread_name(|index| format!("embed-{}", index))
+        .build()
+        .map_err(|e| format!("Failed to start {} embedding threads: {}", threads(), e))?;
+    Ok(pool.insert(Arc::new(built)).clone())
+}
+
+// How the onnx engine sets up ONNX Runtime sessions; None leaves a setting
+// to --threads or to ONNX Runtime, whose defaults size thread pools by the
+// host's cores rather than a container's CPU quota
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OnnxTuning {
+    // Threads inside each operator; overrides --threads for onnx
+    pub intra_threads: Option<usize>,
+    // Threads running independent operators at once; more than one switches
+    // the session to parallel execution
+    pub inter_threads: Option<usize>,
+    pub optimization: Option<GraphOptimization>,
+}
+
+impl OnnxTuning {
+    pub const DEFAULT: OnnxTuning = OnnxTuning { intra_threads: None, inter_threads: None, optimization: None };
+
+    // CONTEXT_RAG_ONNX_INTRA_THREADS, CONTEXT_RAG_ONNX_INTER_THREADS and
+    // CONTEXT_RAG_ONNX_OPTIMIZATION, for CI runners set up by environment
+    pub fn from_env() -> Result<Self, String> {
+        Ok(OnnxTuning {
+            intra_threads: env_var("CONTEXT_RAG_ONNX_INTRA_THREADS")?,
+            inter_threads: env_var("CONTEXT_RAG_ONNX_INTER_THREADS")?,
+            optimization: env_var("CONTEXT_RAG_ONNX_OPTIMIZATION")?,
+        })
+    }
+}
+
+// ONNX Runtime's graph optimization levels, from none to every rewrite
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GraphOptimization {
+    Disable,
+    Basic,
+    Extended,
+    All,
+}
+
+impl std::str::FromStr for GraphOptimization {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "disable" => Ok(GraphOptimization::Disable),
+            "basic" => Ok(GraphOptimization::Basic),
+            "extended" => Ok(GraphOptimization::Extended),
+            "all" => Ok(GraphOptimization::All),
+            other => Err(format!("Unknown graph optimization level '{}' (expected disable, basic, extended or all)", other)),
+        }
+    }
+}
+
+// Applies to models the onnx engine loads afterwards
+pub fn set_onnx_tuning(tuning: OnnxTuning) {
+    *ONNX_TUNING.lock().unwrap_or_else(|e| e.into_inner()) = tuning;
+}
+
+pub fn onnx_tuning() -> OnnxTuning {
+    *ONNX_TUNING.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ComputeDevice {
+    Cpu,
+    Cuda,
+    Metal,
+}
+
+impl std::str::FromStr for ComputeDevice {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "cpu" => Ok(ComputeDevice::Cpu),
+            "cuda" => Ok(ComputeDevice::Cuda),
+            "metal" => Ok(ComputeDevice::Metal),
+            other => Err(format!("Unknown device '{}' (expected cpu, cuda or metal)", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for ComputeDevice {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            ComputeDevice::Cpu => "CPU",
+            ComputeDevice::Cuda => "CUDA",
+            ComputeDevice::Metal => "Metal",
+        })
+    }
+}
+
+// Asked of the onnx, candle and gguf engines; one that can't get the device
+// says why on stderr and runs on the CPU
+pub fn set_device(device: ComputeDevice) {
+    *DEVICE.lock().unwrap_or_else(|e| e.into_inner()) = device;
+}
+
+pub fn device() -> ComputeDevice {
+    *DEVICE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// Recorded by local engines on every batch, so responses can say what the
+// vectors were computed on; None when no local engine has run
+pub fn note_device_used(device: ComputeDevice) {
+    *DEVICE_USED.lock().unwrap_or_else(|e| e.into_inner()) = Some(device);
+}
+
+pub fn device_used() -> Option<ComputeDevice> {
+    *DEVICE_USED.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// A device that couldn't be had, and the CPU taking over
+pub fn fall_back_to_cpu(device: ComputeDevice, reason: impl std::fmt::Display) -> ComputeDevice {
+    eprintln!("{} is unavailable ({}); running on the CPU", device, reason);
+    ComputeDevice::Cpu
+}
+
+// Matryoshka truncation: models trained for it (text-embedding-3, nomic,
+// mxbai, ...) front-load what matters, so the first `dimensions` components,
+// made unit length again (unless --no-normalize), make a smaller vector
+// that still works
+pub fn set_dimensions(dimensions: Option<usize>) {
+    DIMENSIONS.store(dimensions.unwrap_or_default(), Ordering::Relaxed);
+}
+
+pub fn dimensions() -> Option<usize> {
+    Some(DIMENSIONS.load(Ordering::Relaxed)).filter(|&dimensions| dimensions > 0)
+}
+
+pub fn shorten(embedding: &mut Vec<f32>) {
+    if let Some(dimensions) = dimensions().filter(|&dimensions| dimensions < embedding.len()) {
+        embedding.truncate(dimensions);
+        unit_length(embedding);
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Pooling {
+    Mean,
+    Cls,
+    // Each dimension's largest value over the tokens
+    Max,
+}
+
+impl std::str::FromStr for Pooling {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "mean" => Ok(Pooling::Mean),
+            "cls" => Ok(Pooling::Cls),
+            "max" => Ok(Pooling::Max),
+            other => Err(format!("Unknown pooling '{}' (expected mean, cls or max)", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for Pooling {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Pooling::Mean => "mean",
+            Pooling::Cls => "cls",
+            Pooling::Max => "max",
+        })
+    }
+}
+
+// A vetted model with the settings it was trained with. Asymmetric models
+// expect queries and documents to carry different prefixes; embedding either
+// side without them quietly costs recall.
+#[derive(Serialize, Debug, Clone, Copy)]
+pub struct ModelPreset {
+    pub name: &'static str,
+    pub model: &'static str,
+    pub dimensions: usize,
+    pub query_prefix: &'static str,
+    pub document_prefix: &'static str,
+    pub pooling: Pooling,
+}
+
+pub const PRESETS: &[ModelPreset] = &[
+    ModelPreset {
+        name: "fast",
+        model: "sentence-transformers/all-MiniLM-L6-v2",
+        dimensions: 384,
+        query_prefix: "",
+        document_prefix: "",
+        pooling: Pooling::Mean,
+    },
+    ModelPreset {
+        name: "quality",
+        model: "BAAI/bge-base-en-v1.5",
+        dimensions: 768,
+        query_prefix: "Represent this sentence for searching relevant passages: ",
+        document_prefix: "",
+        pooling: Pooling::Cls,
+    },
+    ModelPreset {
+        name: "multilingual",
+        model: "intfloat/multilingual-e5-small",
+        dimensions: 384,
+        query_prefix: "query: ",
+        document_prefix: "passage: ",
+        pooling: Pooling::Mean,
+    },
+    ModelPreset {
+        name: "code",
+        model: "jinaai/jina-embeddings-v2-base-code",
+        dimensions: 768,
+        query_prefix: "",
+        document_prefix: "",
+        pooling: Pooling::Mean,
+    },
+];
+
+pub fn preset(name: &str) -> Result<&'static ModelPreset, String> {
+    PRESETS.iter().find(|p| p.name == name).ok_or_else(|| {
+        let names: Vec<_> = PRESETS.iter().map(|p| p.name).collect();
+        format!("Unknown model preset '{}' (expected one of: {})", name, names.join(", "))
+    })
+}
+
+// Settings for a model named directly rather than through its preset
+pub fn preset_for_model(model: &str) -> Option<&'static ModelPreset> {
+    PRESETS.iter().find(|p| p.model == model)
+}
+
+// Which side of a retrieval pair a text is; asymmetric models embed each
+// with its own instruction prefix
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbedMode {
+    Query,
+    Document,
+}
+
+impl std::str::FromStr for EmbedMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "query" => Ok(EmbedMode::Query),
+            "document" => Ok(EmbedMode::Document),
+            other => Err(format!("Unknown mode '{}' (expected query or document)", other)),
+        }
+    }
+}
+
+const BGE_EN_QUERY_PREFIX: &str = "Represent this sentence for searching relevant passages: ";
+const BGE_ZH_QUERY_PREFIX: &str = "为这个句子生成表示以用于检索相关文章：";
+
+// What goes before a `mode` text for `model`: its preset's prefix, or for
+// models named directly (local paths, GGUF files, Ollama tags) the one its
+// family was trained with. e5 models want "query: " and "passage: ", bge's
+// English and Chinese models an instruction before queries only; bge-m3 and
+// e5-mistral, which take free-form instructions, get none.
+pub fn instruction_prefix(model: &str, mode: EmbedMode) -> &'static str {
+    let (query, document) = match preset_for_model(model) {
+        Some(preset) => (preset.query_prefix, preset.document_prefix),
+        None => {
+            let name = model.rsplit(['/', '\\']).next().unwrap_or(model).to_lowercase();
+            if name.contains("e5-") && !name.contains("mistral") {
+                ("query: ", "passage: ")
+            } else if name.starts_with("bge-") && name.contains("-en") {
+                (BGE_EN_QUERY_PREFIX, "")
+            } else if name.starts_with("bge-") && name.contains("-zh") {
+                (BGE_ZH_QUERY_PREFIX, "")
+            } else {
+                ("", "")
+            }
+        }
+    };
+    match mode {
+        EmbedMode::Query => query,
+        EmbedMode::Document => document,
+    }
+}
+
+pub fn embed_query(model: &str, text: &str) -> Result<Vec<f32>, String> {
+    embed(model, &format!("{}{}", instruction_prefix(model, EmbedMode::Query), text))
+}
+
+pub fn embed_document(model: &str, text: &str) -> Result<Vec<f32>, String> {
+    embed(model, &format!("{}{}", instruction_prefix(model, EmbedMode::Document), text))
+}
+
+// `embed_document` for many texts at once, which remote engines send in
+// batches instead of one request per text
+pub fn embed_documents(model: &str, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+    embed_as(model, texts, EmbedMode::Document)
+}
+
+pub fn embed_as(model: &str, texts: &[String], mode: EmbedMode) -> Result<Vec<Vec<f32>>, String> {
+    match instruction_prefix(model, mode) {
+        "" => embed_batch(model, texts),
+        prefix => embed_batch(model, &texts.iter().map(|text| format!("{}{}", prefix, text)).collect::<Vec<_>>()),
+    }
+}
+
+fn embed_batch(model: &str, texts: &[String]) -> Result<Vec<Vec<f32>>, String> {
+    let engine = engine();
+    let texts: Vec<&str> = texts.iter().map(String::as_str).collect();
+    let texts = fit_to_model(engine.as_ref(), model, texts)?;
+    // Mock vectors cost less to compute than to read back, and a recording
+    // has to see every vector the engine returns
+    let cache = match (cache_dir(), engine_name()) {
+        (Some(dir), name) if name != "mock" && recording::recording().is_none() => Some(EmbeddingCache::new(&dir, &name, model, model_path().as_deref(), dimensions(), pooling(), normalizes())),
+        _ => None,
+    };
+    let mut embeddings: Vec<Option<Vec<f32>>> = match &cache {
+        Some(cache) => texts.iter().map(|text| cache.get(text)).collect(),
+        None => vec![None; texts.len()],
+    };
+
+    let missing: Vec<&str> = texts.iter().zip(&embeddings).filter(|(_, cached)| cached.is_none()).map(|(text, _)| *text).collect();
+    let mut computed = run_engine(engine.as_ref(), model, &missing)?.into_iter();
+    for (text, embedding) in texts.iter().zip(embeddings.iter_mut()).filter(|(_, cached)| cached.is_none()) {
+        let fresh = computed.next().ok_or_else(|| format!("The engine returned too few embeddings for {}", model))?;
+        if let Some(cache) = &cache {
+            cache.put(text, &fresh);
+        }
+        *embedding = Some(fresh);
+    }
+
+    let mut embeddings: Vec<Vec<f32>> = embeddings.into_iter().flatten().collect();
+    embeddings.iter_mut().for_each(shorten);
+    Ok(embeddings)
+}
+
+fn run_engine(engine: &dyn EmbeddingEngine, model: &str, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+    match threads() {
+        _ if texts.is_empty() => Ok(Vec::new()),
+        threads if threads > 1 && texts.len() > 1 && engine.parallel_on_cpu() => {
+            // Contiguous runs, one per thread but no smaller than a batch, so
+            // every call still fills its forward passes
+            let run = texts.len().div_ceil(threads).max(batch_size());
+            let runs = thread_pool()?.install(|| texts.par_chunks(run).map(|run| engine.embed_batch(model, run)).collect::<Result<Vec<_>, _>>())?;
+            Ok(runs.into_iter().flatten().collect())
+        }
+        _ => engine.embed_batch(model, texts),
+    }
+}
+
+fn embed(model: &str, text: &str) -> Result<Vec<f32>, String> {
+    let engine = engine();
+    let mut embedding = engine
+        .embed_batch(model, &fit_to_model(engine.as_ref(), model, vec![text])?)?
+        .pop()
+        .ok_or_else(|| format!("The engine returned no embedding for {}", model))?;
+    shorten(&mut embedding);
+    Ok(embedding)
+}
+
+// Texts cut at the model's token limit for an engine that would otherwise
+// get them whole, when the model's tokenizer is at hand
+fn fit_to_model<'a>(engine: &dyn EmbeddingEngine, model: &str, mut texts: Vec<&'a str>) -> Result<Vec<&'a str>, String> {
+    if engine.splits_long_inputs() {
+        return Ok(texts);
+    }
+    if let Some(counter) = TokenCounter::for_model(model) {
+        for text in &mut texts {
+            if let Some(cut) = counter.fit(text)?.cut {
+                *text = &text[..cut];
+            }
+        }
+    }
+    Ok(texts)
+}
+
+// A text's length in the model's own tokens, special tokens included, and
+// whether the engine only saw part of it
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenCount {
+    pub tokens: usize,
+    pub truncated: bool,
+}
+
+// One count per text, or None when the model has no tokenizer.json to count
+// with (remote model names, GGUF files)
+pub fn count_tokens(model: &str, texts: &[String]) -> Result<Option<Vec<TokenCount>>, String> {
+    let Some(counter) = TokenCounter::for_model(model) else {
+        return Ok(None);
+    };
+    let splits = engine().splits_long_inputs();
+    texts
+        .iter()
+        .map(|text| {
+            let fit = counter.fit(text)?;
+            Ok(TokenCount { tokens: fit.tokens, truncated: fit.cut.is_some() && !splits })
+        })
+        .collect::<Result<_, String>>()
+        .map(Some)
+}
+
+// What `--text` prints: one text's vector, embedded as a query unless `mode`
+// says it is a document, with its term weights when `sparse` asks for them
+pub fn embed_text_request(model: &str, text: &str, mode: EmbedMode, quantization: Option<Quantization>, sparse: bool) -> Result<Value, String> {
+    let embedding = match mode {
+        EmbedMode::Query => embed_query(model, text),
+        EmbedMode::Document => embed_document(model, text),
+    }?;
+
+    let mut response = with_engine(json!({
+        "embedding": embedding_json(&embedding, quantization),
+        "model": model,
+        "dimensions": embedding.len(),
+        "device": device_used()
+    }));
+    if sparse {
+        response["sparse"] = json!(SparseVector::encode(text));
+    }
+    Ok(response)
+}
+
+// The stdin protocol the Node layer speaks: `{"chunks": [{"content", "file_path",
+// "chunk_index"}]}` in, the same chunks with embeddings out. Missing fields
+// default rather than fail, as the Node side has always relied on. With the
+// model's tokenizer.json at hand each chunk also gets `token_count` and
+// whether it was `truncated` to fit the model. `quantization` swaps each
+// float array for a compact QuantizedVector, and `sparse` adds each chunk's
+// term weights as a SparseVector for hybrid stores. Chunks are embedded as
+// documents unless `mode` says they are queries.
+pub fn embed_chunks_request(model: &str, input: &str, mode: EmbedMode, quantization: Option<Quantization>, sparse: bool) -> Result<Value, String> {
+    let input_data: Value = serde_json::from_str(input).map_err(|e| e.to_string())?;
+    let chunks = input_data["chunks"].as_array().ok_or("Missing 'chunks' array in input")?;
+
+    let (chunk_embeddings, dimensions) = embed_chunk_values(model, chunks, mode, quantization, sparse)?;
+
+    Ok(with_engine(json!({
+        "chunks": chunk_embeddings,
+        "model": model,
+        "dimensions": dimensions,
+        "device": device_used()
+    })))
+}
+
+// The streaming form of the same protocol, for inputs too big to hold at
+// once: one chunk object per input line, one embedded chunk per output line
+// in the same order, --batch-size times --threads lines in memory at a time.
+// Blank lines are skipped; returns the chunks written.
+pub fn embed_ndjson(
+    model: &str,
+    input: impl BufRead,
+    mut output: impl Write,
+    mode: EmbedMode,
+    quantization: Option<Quantization>,
+    sparse: bool,
+) -> Result<usize, String> {
+    let batch = batch_size() * threads();
+    let mut chunks = Vec::with_capacity(batch);
+    let mut written = 0;
+    let mut flush = |chunks: &mut Vec<Value>| -> Result<(), String> {
+        for chunk in embed_chunk_values(model, chunks, mode, quantization, sparse)?.0 {
+            serde_json::to_writer(&mut output, &chunk).map_err(|e| e.to_string())?;
+            output.write_all(b"\n").map_err(|e| e.to_string())?;
+        }
+        output.flush().map_err(|e| e.to_string())?;
+        written += chunks.len();
+        chunks.clear();
+        Ok(())
+    };
+    for (number, line) in input.lines().enumerate() {
+        let line = line.map_err(|e| e.to_string())?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        chunks.push(serde_json::from_str(&line).map_err(|e| format!("Line {}: {}", number + 1, e))?);
+        if chunks.len() == batch {
+            flush(&mut chunks)?;
+        }
+    }
+    flush(&mut chunks)?;
+    Ok(written)
+}
+
+// The chunks with their embeddings, and the vectors' length
+fn embed_chunk_values(
+    model: &str,
+    chunks: &[Value],
+    mode: EmbedMode,
+    quantization: Option<Quantization>,
+    sparse: bool,
+) -> Result<(Vec<Value>, Option<usize>), String> {
+    let contents: Vec<String> = chunks.iter().map(|chunk| chunk["content"].as_str().unwrap_or("").to_string()).collect();
+    let token_counts = count_tokens(model, &contents)?;
+    let embeddings = embed_as(model, &contents, mode)?;
+    let dimensions = effective_dimensions(&embeddings);
+    let chunks = chunks
+        .iter()
+        .zip(contents.iter().zip(embeddings))
+        .enumerate()
+        .map(|(position, (chunk, (content, embedding)))| {
+            let mut out = json!({
+                "content": content,
+                "embedding": embedding_json(&embedding, quantization),
+                "file_path": chunk.get("file_path").unwrap_or(&json!("")),
+                "chunk_index": chunk.get("chunk_index").unwrap_or(&json!(0))
+            });
+            if let Some(count) = token_counts.as_ref().map(|counts| counts[position]) {
+                out["token_count"] = json!(count.tokens);
+                out["truncated"] = json!(count.truncated);
+            }
+            if sparse {
+                out["sparse"] = json!(SparseVector::encode(content));
+            }
+            out
+        })
+        .collect();
+    Ok((chunks, dimensions))
+}
+
+// Legacy stdin protocol: `{"texts": [...]}` in, one embedding per text out
+pub fn embed_texts_request(input: &str, quantization: Option<Quantization>) -> Result<Value, String> {
+    let input_data: Value = serde_json::from_str(input).map_err(|e| e.to_string())?;
+    let texts = input_data["texts"].as_array().ok_or("Missing 'texts' array in input")?;
+
+    let texts: Vec<String> = texts.iter().map(|text| text.as_str().unwrap_or("").to_string()).collect();
+    let embeddings = embed_batch(DEFAULT_MODEL, &texts)?;
+
+    Ok(with_engine(json!({
+        "model": DEFAULT_MODEL,
+        "dimensions": effective_dimensions(&embeddings),
+        "embeddings": embeddings.iter().map(|embedding| embedding_json(embedding, quantization)).collect::<Vec<_>>(),
+        "device": device_used()
+    })))
+}
+
+// The length of the vectors actually returned, after any --dimensions
+// truncation; --dimensions itself when there were none to measure
+pub fn effective_dimensions(embeddings: &[Vec<f32>]) -> Option<usize> {
+    embeddings.first().map(Vec::len).or_else(dimensions)
+}
+
+pub fn generate_mock_embedding(text: &str) -> Vec<f32> {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+    
+    // Create a deterministic but varied embedding based on text content;
+    // seed 0 keeps the vectors from before there was a seed
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    let seed = mock_settings().seed;
+    if seed != 0 {
+        seed.hash(&mut hasher);
+    }
+    let base_hash = hasher.finish();
+    
+    let mut embedding = Vec::with_capacity(384);
+    
+    // Generate 384-dimensional vector with values between -1 and 1
+    for i in 0..384 {
+        let mut hasher = DefaultHasher::new();
+        (base_hash.wrapping_add(i as u64)).hash(&mut hasher);
+        let hash_val = hasher.finish();
+        
+        // Convert to float between -1 and 1
+        let normalized = (hash_val as f64 / u64::MAX as f64) * 2.0 - 1.0;
+        embedding.push(normalized as f32);
+    }
+    
+    // Normalize the vector to unit length (like real embeddings)
+    normalize(&mut embedding);
+    
+    embedding
+}
+
+// Stored and query vectors are compared by dot product, so every engine
+// hands back vectors through `unit_length`
+pub fn normalize(embedding: &mut [f32]) {
+    let magnitude: f32 = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if magnitude > 0.0 {
+        for value in embedding {
+            *value /= magnitude;
+        }
+    }
+}
+
+// What engines finish each vector with: unit length, or left as the model
+// produced it under --no-normalize
+pub fn unit_length(embedding: &mut [f32]) {
+    if normalizes() {
+        normalize(embedding);
+    }
+}
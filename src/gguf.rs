@@ -0,0 +1,232 @@
+use crate::candle::candle_device;
+use crate::embedding::{batch_size, device, note_device_used, ComputeDevice, EmbeddingEngine, model_path, preset_for_model, Pooling};
+use crate::models::{embed_batched, model_dir, pool};
+use candle_core::quantized::gguf_file::{Content, Value};
+use candle_core::{Device, Module, Tensor};
+use candle_nn::LayerNorm;
+use candle_transformers::quantized_nn::{layer_norm, linear, Embedding, Linear};
+use candle_transformers::quantized_var_builder::VarBuilder;
+use serde_json::{json, Map};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use tokenizers::{Tokenizer, TruncationParams};
+
+// llama.cpp stores BERT's WordPiece vocabulary with word-initial pieces
+// marked by a leading U+2581 and continuation pieces bare; `##` undoes that
+const WORD_START: char = '\u{2581}';
+
+// A quantized BERT GGUF file (bge, MiniLM, ...) as converted by llama.cpp,
+// run on --device; weights stay quantized in memory and are only expanded
+// one matmul at a time. nomic-bert and the other BERT variants llama.cpp
+// converts use rotary positions and gated feed-forwards this doesn't
+// implement, so they are refused on load.
+struct GgufModel {
+    embeddings: Embeddings,
+    layers: Vec<Layer>,
+    tokenizer: Tokenizer,
+    pooling: Pooling,
+    heads: usize,
+    device: Device,
+    compute: ComputeDevice,
+}
+
+struct Embeddings {
+    tokens: Embedding,
+    positions: Embedding,
+    token_types: Option<Embedding>,
+    norm: LayerNorm,
+}
+
+struct Layer {
+    query: Linear,
+    key: Linear,
+    value: Linear,
+    output: Linear,
+    output_norm: LayerNorm,
+    up: Linear,
+    down: Linear,
+    layer_norm: LayerNorm,
+}
+
+static MODELS: Mutex<BTreeMap<PathBuf, Arc<GgufModel>>> = Mutex::new(BTreeMap::new());
+
+// Registered as `gguf`; texts go through the file given with --model-path
+// --batch-size at a time, loading it on first use
+pub struct GgufEngine;
+
+impl EmbeddingEngine for GgufEngine {
+    fn embed_batch(&self, model: &str, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+        let loaded = load(model)?;
+        note_device_used(loaded.compute);
+        loaded.embed(texts).map_err(|e| format!("GGUF embedding with {} failed: {}", model, e))
+    }
+
+    fn splits_long_inputs(&self) -> bool {
+        true
+    }
+
+    // Forward passes only read the weights
+    fn parallel_on_cpu(&self) -> bool {
+        device() == ComputeDevice::Cpu
+    }
+}
+
+fn load(model: &str) -> Result<Arc<GgufModel>, String> {
+    let path = model_path().unwrap_or_else(|| model_dir(model));
+    if !path.is_file() {
+        return Err(format!("No GGUF file at {}; pass one with --model-path", path.display()));
+    }
+    let mut models = MODELS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(loaded) = models.get(&path) {
+        return Ok(loaded.clone());
+    }
+    let loaded = Arc::new(GgufModel::open(model, &path).map_err(|e| format!("Failed to load {}: {}", path.display(), e))?);
+    models.insert(path, loaded.clone());
+    Ok(loaded)
+}
+
+impl GgufModel {
+    fn open(model: &str, path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let content = Content::read(&mut File::open(path)?)?;
+        let metadata = |key: &str| content.metadata.get(key).ok_or_else(|| format!("no {} in the GGUF metadata", key));
+        let architecture = metadata("general.architecture")?.to_string()?;
+        if architecture != "bert" {
+            return Err(format!("unsupported architecture '{}': the gguf engine only runs bert GGUF files; use an ONNX export with --engine onnx, or --engine ollama", architecture).into());
+        }
+        let size = |key: &str| -> Result<usize, Box<dyn std::error::Error>> { Ok(metadata(&format!("bert.{}", key))?.to_u32()? as usize) };
+        let hidden = size("embedding_length")?;
+        let heads = size("attention.head_count")?;
+        let feed_forward = size("feed_forward_length")?;
+        let context = size("context_length")?;
+        let eps = metadata("bert.attention.layer_norm_epsilon")?.to_f32()? as f64;
+        // llama.cpp's pooling types: 1 is mean, 2 is CLS
+        let pooling = match content.metadata.get("bert.pooling_type").map(Value::to_u32) {
+            Some(Ok(2)) => Pooling::Cls,
+            Some(Ok(1)) => Pooling::Mean,
+            _ => preset_for_model(model).map_or(Pooling::Mean, |preset| preset.pooling),
+        };
+        let tokenizer = tokenizer(&content, context)?;
+        let vocab = tokenizer.get_vocab_size(false);
+
+        let (device, compute) = candle_device();
+        let vb = VarBuilder::from_gguf(path, &device)?;
+        let type_count = vb.get_no_shape("token_types.weight").map_or(0, |types| types.shape().dims()[0]);
+        let embeddings = Embeddings {
+            tokens: Embedding::new(vocab, hidden, vb.pp("token_embd"))?,
+            positions: Embedding::new(context, hidden, vb.pp("position_embd"))?,
+            token_types: match type_count {
+                0 => None,
+                count => Some(Embedding::new(count, hidden, vb.pp("token_types"))?),
+            },
+            norm: layer_norm(hidden, eps, vb.pp("token_embd_norm"))?,
+        };
+        let layers = (0..size("block_count")?)
+            .map(|index| {
+                let vb = vb.pp(format!("blk.{}", index));
+                Ok(Layer {
+                    query: linear(hidden, hidden, vb.pp("attn_q"))?,
+                    key: linear(hidden, hidden, vb.pp("attn_k"))?,
+                    value: linear(hidden, hidden, vb.pp("attn_v"))?,
+                    output: linear(hidden, hidden, vb.pp("attn_output"))?,
+                    output_norm: layer_norm(hidden, eps, vb.pp("attn_output_norm"))?,
+                    up: linear(hidden, feed_forward, vb.pp("ffn_up"))?,
+                    down: linear(feed_forward, hidden, vb.pp("ffn_down"))?,
+                    layer_norm: layer_norm(hidden, eps, vb.pp("layer_output_norm"))?,
+                })
+            })
+            .collect::<candle_core::Result<_>>()?;
+        Ok(GgufModel { embeddings, layers, tokenizer, pooling, heads, device, compute })
+    }
+
+    fn embed(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        embed_batched(&self.tokenizer, texts, batch_size(), |batch| {
+            let rows = |values: &[u32]| Tensor::from_slice(values, (batch.rows, batch.len), &self.device);
+            let positions = Tensor::arange(0, batch.len as u32, &self.device)?;
+            let mut states = self.embeddings.tokens.forward(&rows(&batch.ids)?)?.broadcast_add(&self.embeddings.positions.forward(&positions)?)?;
+            if let Some(token_types) = &self.embeddings.token_types {
+                states = states.add(&token_types.forward(&rows(&batch.type_ids)?)?)?;
+            }
+            // Padding gets a large negative score so attention skips it;
+            // [rows, 1, 1, tokens] to broadcast over heads and queries
+            let mask: Vec<f32> = batch.mask.iter().map(|&m| if m == 0 { f32::MIN } else { 0.0 }).collect();
+            let mask = Tensor::from_vec(mask, (batch.rows, 1, 1, batch.len), &self.device)?;
+            // [rows, tokens, hidden]
+            let mut states = self.embeddings.norm.forward(&states)?;
+            for layer in &self.layers {
+                states = layer.forward(&states, &mask, self.heads)?;
+            }
+            Ok(states.to_vec3::<f32>()?.into_iter().enumerate().map(|(row, states)| pool(states, &batch.row_mask(row), self.pooling)).collect())
+        })
+    }
+}
+
+impl Layer {
+    fn forward(&self, states: &Tensor, mask: &Tensor, heads: usize) -> candle_core::Result<Tensor> {
+        let (batch, tokens, hidden) = states.dims3()?;
+        let head_size = hidden / heads;
+        let split = |projection: &Linear| projection.forward(states)?.reshape((batch, tokens, heads, head_size))?.transpose(1, 2)?.contiguous();
+        let (query, key, value) = (split(&self.query)?, split(&self.key)?, split(&self.value)?);
+        let scores = (query.matmul(&key.t()?)? / (head_size as f64).sqrt())?.broadcast_add(mask)?;
+        let attended = candle_nn::ops::softmax_last_dim(&scores)?.matmul(&value)?.transpose(1, 2)?.reshape((batch, tokens, hidden))?;
+        let states = self.output_norm.forward(&(states + self.output.forward(&attended)?)?)?;
+        let feed_forward = self.down.forward(&self.up.forward(&states)?.gelu_erf()?)?;
+        self.layer_norm.forward(&(states + feed_forward)?)
+    }
+}
+
+// The WordPiece tokenizer the file was converted from, rebuilt from its
+// `tokenizer.ggml.*` metadata, truncating to the context length
+fn tokenizer(content: &Content, context: usize) -> Result<Tokenizer, Box<dyn std::error::Error>> {
+    let metadata = |key: &str| content.metadata.get(key).ok_or_else(|| format!("no {} in the GGUF metadata", key));
+    let tokens = metadata("tokenizer.ggml.tokens")?
+        .to_vec()?
+        .iter()
+        .map(|token| {
+            let token = token.to_string()?;
+            Ok(match token.strip_prefix(WORD_START) {
+                Some(word) => word.to_string(),
+                None if token.starts_with('[') && token.ends_with(']') => token.clone(),
+                None => format!("##{}", token),
+            })
+        })
+        .collect::<candle_core::Result<Vec<_>>>()?;
+    let special = |key: &str| -> Result<(String, u32), Box<dyn std::error::Error>> {
+        let id = metadata(key)?.to_u32()?;
+        let token = tokens.get(id as usize).ok_or_else(|| format!("{} {} is out of range", key, id))?;
+        Ok((token.clone(), id))
+    };
+    let (cls, cls_id) = special("tokenizer.ggml.cls_token_id").or_else(|_| special("tokenizer.ggml.bos_token_id"))?;
+    // sic: llama.cpp's key name
+    let (sep, sep_id) = special("tokenizer.ggml.seperator_token_id").or_else(|_| special("tokenizer.ggml.eos_token_id"))?;
+    let (unk, _) = special("tokenizer.ggml.unknown_token_id")?;
+    let vocab: Map<_, _> = tokens.iter().enumerate().map(|(id, token)| (token.clone(), json!(id))).collect();
+
+    let piece = |kind: &str, id: &str| json!({ kind: { "id": id, "type_id": 0 } });
+    let definition = json!({
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": { "type": "BertNormalizer", "clean_text": true, "handle_chinese_chars": true, "strip_accents": null, "lowercase": true },
+        "pre_tokenizer": { "type": "BertPreTokenizer" },
+        "post_processor": {
+            "type": "TemplateProcessing",
+            "single": [piece("SpecialToken", &cls), piece("Sequence", "A"), piece("SpecialToken", &sep)],
+            "pair": [piece("SpecialToken", &cls), piece("Sequence", "A"), piece("SpecialToken", &sep), piece("Sequence", "B"), piece("SpecialToken", &sep)],
+            "special_tokens": {
+                cls.clone(): { "id": cls, "ids": [cls_id], "tokens": [cls] },
+                sep.clone(): { "id": sep, "ids": [sep_id], "tokens": [sep] },
+            },
+        },
+        "decoder": null,
+        "model": { "type": "WordPiece", "unk_token": unk, "continuing_subword_prefix": "##", "max_input_chars_per_word": 100, "vocab": vocab },
+    });
+    let mut tokenizer = Tokenizer::from_str(&definition.to_string()).map_err(|e| e.to_string())?;
+    tokenizer
+        .with_truncation(Some(TruncationParams { max_length: context, ..Default::default() }))
+        .map_err(|e| e.to_string())?;
+    Ok(tokenizer)
+}
@@ -0,0 +1,117 @@
+use super::memory;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const ALIASES_FILE: &str = "aliases.json";
+
+// Directories whose files are really somewhere else, such as a workspace
+// package symlinked or vendored into node_modules: `node_modules/@org/pkg`
+// to `packages/pkg`. Their chunks are stored, and so reported, under the
+// canonical path. Saved with the index like the blocklist, so refreshes
+// opened by path can still find files that only exist under an alias.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(transparent)]
+pub struct PathAliases(pub BTreeMap<String, String>);
+
+impl PathAliases {
+    pub fn load(storage_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if memory::is_memory_storage(storage_path) {
+            return Ok(memory::aliases(storage_path));
+        }
+        let path = Path::new(storage_path).join(ALIASES_FILE);
+        if !path.exists() {
+            return Ok(PathAliases::default());
+        }
+        let aliases: PathAliases = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+        aliases.validate()?;
+        Ok(aliases)
+    }
+
+    pub fn save(&self, storage_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.validate()?;
+        if memory::is_memory_storage(storage_path) {
+            memory::set_aliases(storage_path, self);
+            return Ok(());
+        }
+        let path = Path::new(storage_path).join(ALIASES_FILE);
+        if self.is_empty() {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        super::vectors::write_atomically(&path, &serde_json::to_vec(self)?)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        for (alias, canonical) in self.pairs() {
+            if alias.is_empty() || canonical.is_empty() {
+                return Err(format!("Invalid path alias '{}' = '{}': neither side may be the project root", alias, canonical));
+            }
+            if within(&canonical, &alias) || within(&alias, &canonical) {
+                return Err(format!("Invalid path alias '{}' = '{}': one contains the other", alias, canonical));
+            }
+        }
+        Ok(())
+    }
+
+    // Both sides relative to the project root, without "./" or a trailing
+    // slash, longest alias first so nested ones win
+    fn pairs(&self) -> Vec<(String, String)> {
+        let mut pairs: Vec<_> = self.0.iter().map(|(alias, canonical)| (normalize(alias), normalize(canonical))).collect();
+        pairs.sort_by_key(|(alias, _)| std::cmp::Reverse(alias.len()));
+        pairs
+    }
+
+    // The stored path a file under an alias goes by; None for files no
+    // alias covers
+    pub fn canonical(&self, stored: &str) -> Option<String> {
+        let relative = stored.strip_prefix("./").unwrap_or(stored);
+        self.pairs().into_iter().find_map(|(alias, canonical)| {
+            let rest = relative.strip_prefix(alias.as_str()).filter(|rest| rest.is_empty() || rest.starts_with('/'))?;
+            Some(format!("./{}{}", canonical, rest))
+        })
+    }
+
+    // Where the file stored as `stored` can be read, under `root` or else
+    // the working directory: there if it exists, otherwise under the first
+    // alias that has it
+    pub fn source(&self, root: Option<&Path>, stored: &str) -> PathBuf {
+        let under_root = |path: String| root.map_or_else(|| PathBuf::from(&path), |root| root.join(&path));
+        let path = under_root(stored.to_string());
+        if path.exists() {
+            return path;
+        }
+        let relative = stored.strip_prefix("./").unwrap_or(stored);
+        self.pairs()
+            .into_iter()
+            .filter_map(|(alias, canonical)| {
+                let rest = relative.strip_prefix(canonical.as_str()).filter(|rest| rest.is_empty() || rest.starts_with('/'))?;
+                Some(under_root(format!("./{}{}", alias, rest)))
+            })
+            .find(|aliased| aliased.exists())
+            .unwrap_or(path)
+    }
+
+    // Whether an alias lies below `directory`, so a walk has to enter it
+    pub fn leads_to_alias(&self, directory: &str) -> bool {
+        let directory = normalize(directory);
+        self.pairs().iter().any(|(alias, _)| matches!(directory.as_str(), "" | ".") || alias.starts_with(&format!("{}/", directory)))
+    }
+}
+
+fn normalize(path: &str) -> String {
+    let path = super::forward_slashes(path);
+    path.trim_start_matches("./").trim_matches('/').to_string()
+}
+
+fn within(path: &str, directory: &str) -> bool {
+    path == directory || path.starts_with(&format!("{}/", directory))
+}
@@ -0,0 +1,67 @@
+use super::cjk::CjkBigramTokenizer;
+use tantivy::tokenizer::{LowerCaser, NgramTokenizer, RemoveLongFilter, SimpleTokenizer, TextAnalyzer, WhitespaceTokenizer};
+use tantivy::Index;
+
+// Lowercased, stemmed words; what plain queries match against
+pub const STEMMED: &str = "en_stem";
+// Lowercased words without stemming
+pub const EXACT: &str = "default";
+// Words exactly as written, so `Index` and `index` stay distinct
+pub const CASED: &str = "cased";
+// Lowercased character trigrams for literal substring lookups
+pub const TRIGRAM: &str = "trigram";
+// Overlapping CJK bigrams plus lowercased words for everything else
+pub const CJK_BIGRAM: &str = "cjk_bigram";
+// Pre-weighted terms from the sparse encoder, taken as they are
+pub const SPARSE: &str = "sparse";
+#[cfg(feature = "cjk-jieba")]
+pub const JIEBA: &str = "jieba";
+
+// Maps the `tokenizer` config value to the analyzer used for chunk content
+pub fn content_analyzer(tokenizer: Option<&str>) -> Result<&'static str, String> {
+    match tokenizer.unwrap_or("default") {
+        "default" => Ok(STEMMED),
+        "cjk" => Ok(CJK_BIGRAM),
+        #[cfg(feature = "cjk-jieba")]
+        "jieba" => Ok(JIEBA),
+        #[cfg(not(feature = "cjk-jieba"))]
+        "jieba" => Err("The jieba tokenizer needs a build with the `cjk-jieba` feature".to_string()),
+        other => Err(format!("Unknown tokenizer '{}' (expected default, cjk or jieba)", other)),
+    }
+}
+
+// Custom analyzers must be registered on every handle that writes or queries
+// the index, since tantivy doesn't persist them with the index
+pub fn register_tokenizers(index: &Index) -> tantivy::Result<()> {
+    let tokenizers = index.tokenizers();
+
+    tokenizers.register(
+        CASED,
+        TextAnalyzer::builder(SimpleTokenizer::default())
+            .filter(RemoveLongFilter::limit(40))
+            .build(),
+    );
+    tokenizers.register(
+        TRIGRAM,
+        TextAnalyzer::builder(NgramTokenizer::all_ngrams(3, 3)?)
+            .filter(LowerCaser)
+            .build(),
+    );
+    tokenizers.register(
+        CJK_BIGRAM,
+        TextAnalyzer::builder(CjkBigramTokenizer)
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser)
+            .build(),
+    );
+    tokenizers.register(SPARSE, TextAnalyzer::builder(WhitespaceTokenizer::default()).build());
+    #[cfg(feature = "cjk-jieba")]
+    tokenizers.register(
+        JIEBA,
+        TextAnalyzer::builder(tantivy_jieba::JiebaTokenizer {})
+            .filter(RemoveLongFilter::limit(40))
+            .filter(LowerCaser)
+            .build(),
+    );
+    Ok(())
+}
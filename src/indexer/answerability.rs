@@ -0,0 +1,55 @@
+use super::{sparse, SearchHit};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+// Only the top of the ranking is what a client would put in front of a model
+const ASSESSED_HITS: usize = 5;
+// Below either threshold the context probably doesn't answer the query
+const MIN_COVERAGE: f32 = 0.6;
+const MIN_SIMILARITY: f32 = 0.3;
+
+// Rough estimate of whether the retrieved context answers the query, so
+// agents can widen the search instead of guessing from weak matches
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Answerability {
+    // 0..1; term coverage, averaged with vector similarity when there is one
+    pub score: f32,
+    // Fraction of the query's terms that appear somewhere in the top hits
+    pub term_coverage: f32,
+    // Best query/chunk vector similarity, for searches that rank by vectors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<f32>,
+    pub answerable: bool,
+}
+
+pub fn assess_answerability(query: &str, hits: &[SearchHit], similarity: Option<f32>) -> Answerability {
+    let query_terms: Vec<String> = sparse::encode(query).into_iter().map(|(term, _)| term).collect();
+    let context_terms: HashSet<String> = hits
+        .iter()
+        .take(ASSESSED_HITS)
+        .flat_map(|hit| sparse::encode(&hit.content))
+        .map(|(term, _)| term)
+        .collect();
+
+    let term_coverage = if hits.is_empty() {
+        0.0
+    } else if query_terms.is_empty() {
+        // Nothing to check terms against, e.g. a query of stopwords only
+        1.0
+    } else {
+        query_terms.iter().filter(|term| context_terms.contains(*term)).count() as f32 / query_terms.len() as f32
+    };
+
+    let score = match similarity {
+        Some(similarity) => (term_coverage + similarity.clamp(0.0, 1.0)) / 2.0,
+        None => term_coverage,
+    };
+    Answerability {
+        score,
+        term_coverage,
+        similarity,
+        answerable: !hits.is_empty()
+            && term_coverage >= MIN_COVERAGE
+            && similarity.is_none_or(|similarity| similarity >= MIN_SIMILARITY),
+    }
+}
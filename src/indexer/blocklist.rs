@@ -0,0 +1,158 @@
+use super::memory;
+use super::SearchHit;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use tantivy::query::{PhraseQuery, Query, RegexQuery, TermQuery};
+use tantivy::schema::{Field, IndexRecordOption};
+use tantivy::tokenizer::TextAnalyzer;
+use tantivy::Term;
+
+const BLOCKLIST_FILE: &str = "blocklist.json";
+
+// Paths and terms no search of the index may return, whatever the caller
+// asks for. Saved with the index on every run, so searchers opened by path
+// enforce it without seeing the config; in-memory indexes keep it in RAM.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Blocklist {
+    // Globs over paths relative to the project root: `*` stays within one
+    // directory, `**` spans any number. Patterns without a `/` match the
+    // file name at any depth.
+    #[serde(default)]
+    pub paths: Vec<String>,
+    // Whole words or phrases, matched case-insensitively
+    #[serde(default)]
+    pub terms: Vec<String>,
+}
+
+impl Blocklist {
+    pub fn load(storage_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        if memory::is_memory_storage(storage_path) {
+            return Ok(memory::blocklist(storage_path));
+        }
+        let path = Path::new(storage_path).join(BLOCKLIST_FILE);
+        if !path.exists() {
+            return Ok(Blocklist::default());
+        }
+        let blocklist: Blocklist = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+        blocklist.validate()?;
+        Ok(blocklist)
+    }
+
+    pub fn save(&self, storage_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.validate()?;
+        if memory::is_memory_storage(storage_path) {
+            memory::set_blocklist(storage_path, self);
+            return Ok(());
+        }
+        let path = Path::new(storage_path).join(BLOCKLIST_FILE);
+        if self.is_empty() {
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+        super::vectors::write_atomically(&path, &serde_json::to_vec(self)?)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty() && self.terms.is_empty()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        self.matcher().map(|_| ())
+    }
+
+    // The same rules as `exclusions`, compiled once for checking chunks read
+    // straight from the doc store instead of through a query
+    pub(super) fn matcher(&self) -> Result<BlockMatcher, String> {
+        let paths = self
+            .paths
+            .iter()
+            .map(|pattern| {
+                Regex::new(&format!("^{}$", path_pattern(pattern))).map_err(|e| format!("Invalid blocked path {}: {}", pattern, e))
+            })
+            .collect::<Result<_, _>>()?;
+        // Words split the way the `content_exact` tokenizer splits them, so
+        // `deprecated` blocks `deprecated_api` in both places
+        let terms = self
+            .terms
+            .iter()
+            .filter_map(|term| {
+                let words: Vec<String> = term.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()).map(regex::escape).collect();
+                (!words.is_empty()).then(|| format!(r"(?i)(^|[^\p{{L}}\p{{N}}]){}($|[^\p{{L}}\p{{N}}])", words.join(r"[^\p{L}\p{N}]+")))
+            })
+            .map(|pattern| Regex::new(&pattern).map_err(|e| e.to_string()))
+            .collect::<Result<_, _>>()?;
+        Ok(BlockMatcher { paths, terms })
+    }
+
+    // One query per rule, each to be excluded from every search. Terms go
+    // through `content_exact`'s unstemmed analyzer, so they block whole
+    // words only.
+    pub(super) fn exclusions(
+        &self,
+        path_key_field: Field,
+        content_exact_field: Field,
+        analyzer: &mut TextAnalyzer,
+    ) -> tantivy::Result<Vec<Box<dyn Query>>> {
+        let mut queries: Vec<Box<dyn Query>> = Vec::new();
+        for pattern in &self.paths {
+            queries.push(Box::new(RegexQuery::from_pattern(&path_pattern(pattern), path_key_field)?));
+        }
+        for term in &self.terms {
+            let mut terms = Vec::new();
+            let mut stream = analyzer.token_stream(term);
+            while let Some(token) = stream.next() {
+                terms.push(Term::from_field_text(content_exact_field, &token.text));
+            }
+            match terms.len() {
+                0 => {}
+                1 => queries.push(Box::new(TermQuery::new(terms.remove(0), IndexRecordOption::Basic))),
+                _ => queries.push(Box::new(PhraseQuery::new(terms))),
+            }
+        }
+        Ok(queries)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(super) struct BlockMatcher {
+    paths: Vec<Regex>,
+    terms: Vec<Regex>,
+}
+
+impl BlockMatcher {
+    pub(super) fn blocks(&self, hit: &SearchHit) -> bool {
+        self.paths.iter().any(|regex| regex.is_match(&hit.file_path)) || self.terms.iter().any(|regex| regex.is_match(&hit.content))
+    }
+}
+
+// Glob to an unanchored regex over stored paths, which start with "./"
+fn path_pattern(glob: &str) -> String {
+    let glob = glob.trim_start_matches("./").trim_start_matches('/');
+    let mut pattern = String::from(r"(\./)?");
+    if !glob.contains('/') {
+        pattern.push_str("(.*/)?");
+    }
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    pattern.push_str("(.*/)?");
+                } else {
+                    pattern.push_str(".*");
+                }
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern
+}
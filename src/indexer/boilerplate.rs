@@ -0,0 +1,90 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+// Written next to the index so single-file refreshes strip the same lines
+const BOILERPLATE_FILE: &str = "boilerplate.json";
+// Shorter lines are braces and keywords, not banners
+const MIN_LINE_CHARS: usize = 20;
+// Below this many files a shared line is as likely to be a shared idiom
+const MIN_FILES: usize = 10;
+
+// Lines found in an extreme fraction of files (license headers, generated
+// banners), dropped from content before chunking so they don't dominate
+// BM25 for the common words in them
+#[derive(Debug, Default)]
+pub struct Boilerplate {
+    lines: HashSet<String>,
+}
+
+impl Boilerplate {
+    // `threshold` is the fraction of files a line has to appear in
+    pub fn detect<'a, I>(files: I, threshold: f32) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut document_frequency: HashMap<&str, usize> = HashMap::new();
+        let mut file_count = 0;
+        for content in files {
+            file_count += 1;
+            let distinct: HashSet<&str> = content.lines().map(str::trim).filter(|line| line.chars().count() >= MIN_LINE_CHARS).collect();
+            for line in distinct {
+                *document_frequency.entry(line).or_default() += 1;
+            }
+        }
+
+        if file_count < MIN_FILES {
+            return Boilerplate::default();
+        }
+        let cutoff = (threshold * file_count as f32).ceil().max(2.0) as usize;
+        Boilerplate {
+            lines: document_frequency
+                .into_iter()
+                .filter(|(_, count)| *count >= cutoff)
+                .map(|(line, _)| line.to_string())
+                .collect(),
+        }
+    }
+
+    pub fn load(storage_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = Path::new(storage_path).join(BOILERPLATE_FILE);
+        if !path.exists() {
+            return Ok(Boilerplate::default());
+        }
+        let lines: Vec<String> = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+        Ok(Boilerplate { lines: lines.into_iter().collect() })
+    }
+
+    // Sorted, so deterministic runs write identical files; an empty set
+    // removes the file
+    pub fn save(&self, storage_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let path = Path::new(storage_path).join(BOILERPLATE_FILE);
+        if self.lines.is_empty() {
+            if path.exists() {
+                fs::remove_file(&path)?;
+            }
+            return Ok(());
+        }
+        let sorted: BTreeSet<&String> = self.lines.iter().collect();
+        fs::write(&path, serde_json::to_vec_pretty(&sorted)?)?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn strip<'a>(&self, content: &'a str) -> Cow<'a, str> {
+        if self.lines.is_empty() || !content.lines().any(|line| self.lines.contains(line.trim())) {
+            return Cow::Borrowed(content);
+        }
+        let kept: Vec<&str> = content.lines().filter(|line| !self.lines.contains(line.trim())).collect();
+        Cow::Owned(kept.join("\n"))
+    }
+}
@@ -0,0 +1,83 @@
+use super::{memory, sparse, vectors::write_atomically, ContextRagSearcher, SearchHit};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+// Rebuilt whenever a commit moves the index's opstamp
+const CALIBRATION_FILE: &str = "calibration.json";
+// Chunks sampled, spread evenly over the index, to build the distribution
+const SAMPLE_CHUNKS: usize = 64;
+// Terms per sampled query, the heaviest in the chunk
+const QUERY_TERMS: usize = 3;
+
+// BM25 scores mean nothing across indexes, so each index keeps the top
+// scores of queries drawn from its own chunks. A hit's confidence is where
+// its score falls in that distribution: 0.5 matches a typical query for
+// content that is known to be there.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Calibration {
+    pub opstamp: u64,
+    // Sorted ascending
+    pub scores: Vec<f32>,
+}
+
+impl Calibration {
+    pub fn load_or_build(storage_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let searcher = ContextRagSearcher::open(storage_path)?;
+        let opstamp = searcher.opstamp()?;
+        if memory::is_memory_storage(storage_path) {
+            return Self::build(&searcher, opstamp);
+        }
+        let path = Path::new(storage_path).join(CALIBRATION_FILE);
+        if let Ok(content) = fs::read_to_string(&path) {
+            if let Ok(calibration) = serde_json::from_str::<Calibration>(&content) {
+                if calibration.opstamp == opstamp {
+                    return Ok(calibration);
+                }
+            }
+        }
+
+        let calibration = Self::build(&searcher, opstamp)?;
+        // Best effort; a read-only index just recalibrates next time
+        let _ = write_atomically(&path, &serde_json::to_vec(&calibration)?);
+        Ok(calibration)
+    }
+
+    fn build(searcher: &ContextRagSearcher, opstamp: u64) -> Result<Self, Box<dyn std::error::Error>> {
+        let chunks = searcher.all_chunks()?;
+        let step = (chunks.len() / SAMPLE_CHUNKS).max(1);
+        let mut scores = Vec::new();
+        for chunk in chunks.iter().step_by(step).take(SAMPLE_CHUNKS) {
+            let mut terms = sparse::encode(&chunk.content);
+            terms.sort_by(|a, b| b.1.total_cmp(&a.1));
+            let query: Vec<&str> = terms.iter().take(QUERY_TERMS).map(|(term, _)| term.as_str()).collect();
+            if query.is_empty() {
+                continue;
+            }
+            if let Some(top) = searcher.search(&query.join(" "), 1)?.first() {
+                scores.push(top.score);
+            }
+        }
+        scores.sort_by(f32::total_cmp);
+        Ok(Calibration { opstamp, scores })
+    }
+
+    // Fraction of calibration queries whose best score this one reaches
+    pub fn confidence(&self, score: f32) -> f32 {
+        if self.scores.is_empty() {
+            return 0.0;
+        }
+        self.scores.partition_point(|sample| *sample <= score) as f32 / self.scores.len() as f32
+    }
+
+    // Sets each hit's confidence and drops those under `min_confidence`;
+    // returns how many were dropped
+    pub fn apply(&self, hits: &mut Vec<SearchHit>, min_confidence: f32) -> usize {
+        for hit in hits.iter_mut() {
+            hit.confidence = Some(self.confidence(hit.score));
+        }
+        let before = hits.len();
+        hits.retain(|hit| hit.confidence.unwrap_or(0.0) >= min_confidence);
+        before - hits.len()
+    }
+}
@@ -0,0 +1,77 @@
+use tantivy::tokenizer::{PreTokenizedStream, PreTokenizedString, Token, Tokenizer};
+
+// Dictionary-free tokenizer for Chinese, Japanese and Korean: runs of CJK
+// characters become overlapping bigrams ("全文检索" -> 全文, 文检, 检索), the
+// same scheme Lucene's CJK analyzer uses. Other text is split into words.
+#[derive(Clone, Default)]
+pub struct CjkBigramTokenizer;
+
+impl Tokenizer for CjkBigramTokenizer {
+    type TokenStream<'a> = PreTokenizedStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> Self::TokenStream<'a> {
+        PreTokenizedStream::from(PreTokenizedString {
+            text: String::new(),
+            tokens: tokenize(text),
+        })
+    }
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut push = |from: usize, to: usize| {
+        tokens.push(Token {
+            offset_from: from,
+            offset_to: to,
+            position: tokens.len(),
+            text: text[from..to].to_string(),
+            position_length: 1,
+        });
+    };
+
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let end_of = |i: usize| chars.get(i).map_or(text.len(), |(offset, _)| *offset);
+
+    let mut i = 0;
+    while i < chars.len() {
+        let (start, c) = chars[i];
+        if is_cjk(c) {
+            let mut run_end = i;
+            while run_end < chars.len() && is_cjk(chars[run_end].1) {
+                run_end += 1;
+            }
+            if run_end - i == 1 {
+                push(start, end_of(i + 1));
+            } else {
+                for pair in chars[i..run_end].windows(2) {
+                    let (second, c) = pair[1];
+                    push(pair[0].0, second + c.len_utf8());
+                }
+            }
+            i = run_end;
+        } else if c.is_alphanumeric() {
+            let mut word_end = i;
+            while word_end < chars.len() && chars[word_end].1.is_alphanumeric() && !is_cjk(chars[word_end].1) {
+                word_end += 1;
+            }
+            push(start, end_of(word_end));
+            i = word_end;
+        } else {
+            i += 1;
+        }
+    }
+
+    tokens
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}'     // Hiragana, Katakana
+        | '\u{3400}'..='\u{4DBF}'   // CJK Extension A
+        | '\u{4E00}'..='\u{9FFF}'   // CJK Unified Ideographs
+        | '\u{AC00}'..='\u{D7AF}'   // Hangul syllables
+        | '\u{F900}'..='\u{FAFF}'   // CJK Compatibility Ideographs
+        | '\u{FF66}'..='\u{FF9F}'   // Half-width Katakana
+        | '\u{20000}'..='\u{2A6DF}' // CJK Extension B
+    )
+}
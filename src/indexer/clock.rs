@@ -0,0 +1,82 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Where the indexer gets the current time and files' modification times,
+// both in seconds since the epoch. One clock per process, the system's until
+// another is set, so tests and snapshot builds can pin every timestamp.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> i64;
+
+    fn modified(&self, path: &Path) -> io::Result<i64>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> i64 {
+        seconds(SystemTime::now())
+    }
+
+    fn modified(&self, path: &Path) -> io::Result<i64> {
+        Ok(seconds(fs::metadata(path)?.modified()?))
+    }
+}
+
+// The same instant for every call, and for every file unless `modified` is
+// left unset, in which case files keep their real times
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FixedClock {
+    pub now: i64,
+    pub modified: Option<i64>,
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> i64 {
+        self.now
+    }
+
+    fn modified(&self, path: &Path) -> io::Result<i64> {
+        match self.modified {
+            Some(modified) => Ok(modified),
+            None => SystemClock.modified(path),
+        }
+    }
+}
+
+static CLOCK: Mutex<Option<Arc<dyn Clock>>> = Mutex::new(None);
+
+// Replaces the process's clock; None goes back to the system's
+pub fn set_clock(clock: Option<Arc<dyn Clock>>) {
+    *CLOCK.lock().unwrap_or_else(|e| e.into_inner()) = clock;
+}
+
+pub fn clock() -> Arc<dyn Clock> {
+    CLOCK.lock().unwrap_or_else(|e| e.into_inner()).clone().unwrap_or_else(|| Arc::new(SystemClock))
+}
+
+pub fn now() -> i64 {
+    clock().now()
+}
+
+// A file's modification time as the index records it. Filesystems that lose
+// times report the epoch or earlier, and skewed clocks future dates; both are
+// pulled into [0, now], reading as long untouched and as just written
+// instead of failing the run or sorting ahead of every real edit.
+pub fn modified_time(path: &Path) -> io::Result<i64> {
+    modified_time_with(clock().as_ref(), path)
+}
+
+// `modified_time` read off `clock` rather than the process's
+pub fn modified_time_with(clock: &dyn Clock, path: &Path) -> io::Result<i64> {
+    Ok(clock.modified(path)?.clamp(0, clock.now().max(0)))
+}
+
+fn seconds(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
+}
@@ -0,0 +1,86 @@
+// Comment syntax per language; `None` for languages without that form
+struct Syntax {
+    line: &'static [&'static str],
+    block: Option<(&'static str, &'static str)>,
+    docstrings: bool,
+}
+
+const C_LIKE: Syntax = Syntax { line: &["//"], block: Some(("/*", "*/")), docstrings: false };
+const HASH: Syntax = Syntax { line: &["#"], block: None, docstrings: false };
+const PYTHON: Syntax = Syntax { line: &["#"], block: None, docstrings: true };
+const PHP: Syntax = Syntax { line: &["//", "#"], block: Some(("/*", "*/")), docstrings: false };
+const SQL: Syntax = Syntax { line: &["--"], block: Some(("/*", "*/")), docstrings: false };
+
+fn syntax(language: &str) -> Option<&'static Syntax> {
+    match language {
+        "rust" | "javascript" | "typescript" | "go" | "java" | "kotlin" | "csharp" | "c" | "cpp" | "swift" => Some(&C_LIKE),
+        "python" => Some(&PYTHON),
+        "ruby" | "shell" => Some(&HASH),
+        "php" => Some(&PHP),
+        "sql" => Some(&SQL),
+        _ => None,
+    }
+}
+
+// The comments and docstrings of a code chunk, one per line with their
+// markers removed; empty for prose and unknown languages. Trailing comments
+// after code only count when whitespace precedes the marker, which keeps
+// `http://` in string literals out.
+pub fn extract_comments(chunk: &str, language: &str) -> String {
+    let Some(syntax) = syntax(language) else {
+        return String::new();
+    };
+
+    let mut comments: Vec<&str> = Vec::new();
+    let mut closing: Option<&str> = None;
+
+    for line in chunk.lines() {
+        let trimmed = line.trim();
+
+        if let Some(close) = closing {
+            match trimmed.find(close) {
+                Some(end) => {
+                    comments.push(&trimmed[..end]);
+                    closing = None;
+                }
+                None => comments.push(trimmed),
+            }
+            continue;
+        }
+
+        let opener = syntax
+            .block
+            .filter(|(open, _)| trimmed.starts_with(open))
+            .or_else(|| syntax.docstrings.then_some(("\"\"\"", "\"\"\"")).filter(|(open, _)| trimmed.starts_with(open)));
+        if let Some((open, close)) = opener {
+            let body = &trimmed[open.len()..];
+            match body.find(close) {
+                Some(end) => comments.push(&body[..end]),
+                None => {
+                    comments.push(body);
+                    closing = Some(close);
+                }
+            }
+            continue;
+        }
+
+        if let Some(marker) = syntax.line.iter().find(|marker| trimmed.starts_with(*marker)) {
+            comments.push(trimmed.trim_start_matches(marker).trim_start_matches(['/', '!', '#']));
+            continue;
+        }
+
+        for marker in syntax.line {
+            if let Some(at) = line.find(&format!(" {}", marker)) {
+                comments.push(&line[at + 1 + marker.len()..]);
+                break;
+            }
+        }
+    }
+
+    comments
+        .into_iter()
+        .map(|comment| comment.trim().trim_start_matches('*').trim())
+        .filter(|comment| !comment.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -0,0 +1,97 @@
+use super::{parse_chunk_id, relative_to_root, ContextRagSearcher, SearchHit};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+// Same estimate the Node side truncates with
+const CHARS_PER_TOKEN: usize = 4;
+pub const DEFAULT_CONTEXT_TOKENS: usize = 2000;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ContextChunk {
+    #[serde(flatten)]
+    pub hit: SearchHit,
+    pub pinned: bool,
+    pub tokens: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AssembledContext {
+    pub query: String,
+    // Pinned chunks first, in config order, then search hits by rank
+    pub chunks: Vec<ContextChunk>,
+    pub tokens: usize,
+    pub max_tokens: usize,
+    // Chunks left out because they didn't fit the budget
+    pub dropped: usize,
+    // Pins that matched nothing in the index
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub missing_pins: Vec<String>,
+}
+
+pub fn estimate_tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN)
+}
+
+// Context for `query` within `max_tokens`: every chunk of each pinned file
+// (or just `path#chunk` for a pinned chunk) goes in ahead of the search hits.
+// Chunks that don't fit are skipped, so smaller ones after them still can.
+pub fn assemble_context(
+    storage_path: &str,
+    query: &str,
+    limit: usize,
+    max_tokens: usize,
+    pins: &[String],
+) -> Result<AssembledContext, Box<dyn std::error::Error>> {
+    let searcher = ContextRagSearcher::open(storage_path)?;
+
+    let mut pinned = Vec::new();
+    let mut missing_pins = Vec::new();
+    if !pins.is_empty() {
+        let chunks = searcher.all_chunks()?;
+        for pin in pins {
+            let (path, chunk_index) = match parse_chunk_id(pin) {
+                Some((path, chunk_index)) => (path, Some(chunk_index)),
+                None => (pin.as_str(), None),
+            };
+            let root = relative_to_root(path);
+            let mut matched: Vec<&SearchHit> = chunks
+                .iter()
+                .filter(|chunk| Path::new(&chunk.file_path) == Path::new(&root))
+                .filter(|chunk| chunk_index.is_none_or(|index| chunk.chunk_index == index))
+                .collect();
+            if matched.is_empty() {
+                missing_pins.push(pin.clone());
+            }
+            matched.sort_by_key(|chunk| chunk.chunk_index);
+            pinned.extend(matched.into_iter().cloned());
+        }
+    }
+
+    let hits = searcher.search(query, limit)?;
+    let mut context = AssembledContext {
+        query: query.to_string(),
+        chunks: Vec::new(),
+        tokens: 0,
+        max_tokens,
+        dropped: 0,
+        missing_pins,
+    };
+    let candidates = pinned.into_iter().map(|hit| (hit, true)).chain(hits.into_iter().map(|hit| (hit, false)));
+    for (hit, is_pinned) in candidates {
+        let duplicate = context
+            .chunks
+            .iter()
+            .any(|chunk| chunk.hit.file_path == hit.file_path && chunk.hit.chunk_index == hit.chunk_index);
+        if duplicate {
+            continue;
+        }
+        let tokens = estimate_tokens(&hit.content);
+        if context.tokens + tokens > max_tokens {
+            context.dropped += 1;
+            continue;
+        }
+        context.tokens += tokens;
+        context.chunks.push(ContextChunk { hit, pinned: is_pinned, tokens });
+    }
+    Ok(context)
+}
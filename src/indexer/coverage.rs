@@ -0,0 +1,219 @@
+use super::file_content::read_file;
+use super::ignore;
+use super::{forward_slashes, prunes_directory, skip_reason, stored_path, walked_path, ContextRagSearcher, IndexConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+use walkdir::WalkDir;
+
+// Why a file in the working tree has no chunks in the index
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Uncovered {
+    // Matched an exclude pattern
+    Excluded,
+    // Matched no include pattern
+    NotIncluded,
+    // Not valid UTF-8, so it can't be stored as it is named
+    NonUtf8Path,
+    // Under one of [index] prune_dirs
+    Pruned,
+    // Deeper than [index] max_depth
+    TooDeep,
+    // Bigger than [index] max_file_size
+    TooLarge,
+    // Not UTF-8 text
+    Binary,
+    // Nothing but whitespace, so no chunks
+    Empty,
+    // Marked `context-rag: ignore` near the top
+    Ignored,
+    Unreadable,
+    // Matched by the config but added or changed since the last index run
+    NotIndexed,
+}
+
+impl std::fmt::Display for Uncovered {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Uncovered::Excluded => "excluded",
+            Uncovered::NotIncluded => "not included",
+            Uncovered::NonUtf8Path => "non-UTF-8 path",
+            Uncovered::Pruned => "pruned directory",
+            Uncovered::TooDeep => "too deep",
+            Uncovered::TooLarge => "too large",
+            Uncovered::Binary => "binary",
+            Uncovered::Empty => "empty",
+            Uncovered::Ignored => "ignored by marker",
+            Uncovered::Unreadable => "unreadable",
+            Uncovered::NotIndexed => "not indexed yet",
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct Coverage {
+    pub files: usize,
+    pub indexed: usize,
+    // Files left out, by reason
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub uncovered: BTreeMap<Uncovered, usize>,
+}
+
+impl Coverage {
+    fn add(&mut self, reason: Option<Uncovered>) {
+        self.files += 1;
+        match reason {
+            Some(reason) => *self.uncovered.entry(reason).or_default() += 1,
+            None => self.indexed += 1,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DirectoryCoverage {
+    // Relative to the project root, "." for the root itself
+    pub path: String,
+    // Files in this directory and everything below it
+    #[serde(flatten)]
+    pub coverage: Coverage,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ExtensionCoverage {
+    // Without the dot; empty for files without an extension
+    pub extension: String,
+    #[serde(flatten)]
+    pub coverage: Coverage,
+}
+
+// The working tree against the index: every directory and extension with how
+// many of its files made it in, and why the rest didn't
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CoverageReport {
+    #[serde(flatten)]
+    pub total: Coverage,
+    // Sorted by path, so parents come before their children
+    pub directories: Vec<DirectoryCoverage>,
+    // Most uncovered files first
+    pub extensions: Vec<ExtensionCoverage>,
+    // Directories an exclude pattern, prune_dirs or max_depth drops whole
+    // (.git/, target/, ...); their files are not walked or counted
+    pub excluded_directories: Vec<String>,
+}
+
+pub fn coverage_report(config: &IndexConfig) -> Result<CoverageReport, Box<dyn std::error::Error>> {
+    let indexed: BTreeSet<String> = ContextRagSearcher::open(&config.storage_path)?.file_states()?.into_keys().collect();
+
+    let mut total = Coverage::default();
+    let mut directories: BTreeMap<String, Coverage> = BTreeMap::new();
+    let mut extensions: BTreeMap<String, Coverage> = BTreeMap::new();
+    let mut excluded_directories = Vec::new();
+
+    let mut walker = WalkDir::new(config.root.as_deref().unwrap_or(Path::new("."))).sort_by_file_name().into_iter();
+    while let Some(entry) = walker.next() {
+        let Ok(entry) = entry else { continue };
+        let path = &*walked_path(entry.path(), config);
+        if entry.file_type().is_dir() {
+            // Every file below a directory matching an exclude pattern
+            // matches it too, so there's no need to walk in; likewise for
+            // pruned directories and ones at the depth limit
+            if entry.depth() > 0 && (excluded_directory(path, config) || prunes_directory(path, config)) {
+                excluded_directories.push(relative(path));
+                walker.skip_current_dir();
+            }
+            continue;
+        }
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        // Files under an alias are judged by the path they're indexed as
+        let stored = stored_path(path).map(|stored| config.aliases.canonical(&stored).unwrap_or(stored));
+        let reason = match skip_reason(stored.as_deref().map_or(path, Path::new), config) {
+            Some(reason) => Some(reason),
+            None if stored.is_some_and(|stored| indexed.contains(&stored)) => None,
+            None => Some(unindexed_reason(entry.path())),
+        };
+
+        total.add(reason);
+        let extension = path.extension().map(|extension| extension.to_string_lossy().into_owned()).unwrap_or_default();
+        extensions.entry(extension).or_default().add(reason);
+        for directory in path.ancestors().skip(1) {
+            if directory.as_os_str().is_empty() {
+                break;
+            }
+            directories.entry(relative(directory)).or_default().add(reason);
+        }
+    }
+
+    let mut extensions: Vec<_> = extensions.into_iter().map(|(extension, coverage)| ExtensionCoverage { extension, coverage }).collect();
+    extensions.sort_by_key(|extension| std::cmp::Reverse(extension.coverage.files - extension.coverage.indexed));
+    Ok(CoverageReport {
+        total,
+        directories: directories.into_iter().map(|(path, coverage)| DirectoryCoverage { path, coverage }).collect(),
+        extensions,
+        excluded_directories,
+    })
+}
+
+pub(super) fn excluded_directory(path: &Path, config: &IndexConfig) -> bool {
+    let directory = format!("{}/", relative(path));
+    config.exclude.iter().any(|pattern| directory.contains(forward_slashes(pattern).as_ref()))
+}
+
+// A file the config matches but the index lacks: either the indexer skipped
+// it on reading, or it is newer than the last run
+fn unindexed_reason(path: &Path) -> Uncovered {
+    match read_file(path) {
+        Err(_) => Uncovered::Unreadable,
+        Ok(content) => match content.text() {
+            Err(_) => Uncovered::Binary,
+            Ok(text) if text.trim().is_empty() => Uncovered::Empty,
+            Ok(text) if ignore::ignores_file(text) => Uncovered::Ignored,
+            Ok(_) => Uncovered::NotIndexed,
+        },
+    }
+}
+
+// Like stored paths but without the "./", for reports and exclude patterns
+fn relative(path: &Path) -> String {
+    match path.strip_prefix(".") {
+        Ok(stripped) if !stripped.as_os_str().is_empty() => stored_path(stripped).unwrap_or_else(|| stripped.to_string_lossy().into_owned()),
+        _ => ".".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProjectConfig;
+    use crate::indexer::ContextRagIndexer;
+    use crate::test_utils::TempDir;
+    use std::fs;
+
+    #[test]
+    fn files_left_out_are_counted_by_directory_and_reason() {
+        let dir = TempDir::new("coverage").unwrap();
+        let source = dir.join("source");
+        for (file, content) in [("docs/guide.md", "signing keys"), ("docs/logo.png", "png"), ("target/out.md", "built")] {
+            let path = source.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let mut config = ProjectConfig::parse("[index]\ninclude = [\"*.md\"]\nprune_dirs = [\"target\"]\n").unwrap().index_config();
+        config.storage_path = dir.join("index").to_string_lossy().into_owned();
+        config.root = Some(source.clone());
+        ContextRagIndexer::for_config(&config).unwrap().index_directory(&config).unwrap();
+        fs::write(source.join("docs/later.md"), "written after the index run").unwrap();
+
+        let report = coverage_report(&config).unwrap();
+        assert_eq!((report.total.files, report.total.indexed), (3, 1));
+        let reasons: Vec<_> = report.total.uncovered.into_iter().collect();
+        assert_eq!(reasons, [(Uncovered::NotIncluded, 1), (Uncovered::NotIndexed, 1)]);
+        let paths: Vec<_> = report.directories.iter().map(|directory| (directory.path.as_str(), directory.coverage.files)).collect();
+        assert_eq!(paths, [(".", 3), ("docs", 3)]);
+        assert_eq!(report.excluded_directories, ["target"]);
+        assert_eq!(report.extensions[0].extension, "md");
+    }
+}
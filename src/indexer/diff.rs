@@ -0,0 +1,143 @@
+use super::{ChunkAudit, ContextRagSearcher};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileDelta {
+    pub file_path: String,
+    pub chunks_before: usize,
+    pub chunks_after: usize,
+}
+
+// What changed between two snapshots, each either an index directory or a
+// JSON-lines file written by `export`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotDiff {
+    pub added: Vec<FileDelta>,
+    pub removed: Vec<FileDelta>,
+    pub changed: Vec<FileDelta>,
+    pub unchanged: usize,
+    pub chunks_before: usize,
+    pub chunks_after: usize,
+}
+
+#[derive(Default)]
+struct FileSnapshot {
+    file_hash: String,
+    chunks: usize,
+}
+
+pub fn diff_snapshots(before: &str, after: &str) -> Result<SnapshotDiff, Box<dyn std::error::Error>> {
+    let before = load_snapshot(before)?;
+    let after = load_snapshot(after)?;
+
+    let mut diff = SnapshotDiff {
+        added: Vec::new(),
+        removed: Vec::new(),
+        changed: Vec::new(),
+        unchanged: 0,
+        chunks_before: before.values().map(|f| f.chunks).sum(),
+        chunks_after: after.values().map(|f| f.chunks).sum(),
+    };
+
+    for (file_path, old) in &before {
+        let new = after.get(file_path);
+        let delta = FileDelta {
+            file_path: file_path.clone(),
+            chunks_before: old.chunks,
+            chunks_after: new.map_or(0, |f| f.chunks),
+        };
+
+        match new {
+            None => diff.removed.push(delta),
+            Some(new) if new.file_hash != old.file_hash || new.chunks != old.chunks => diff.changed.push(delta),
+            Some(_) => diff.unchanged += 1,
+        }
+    }
+
+    for (file_path, new) in &after {
+        if !before.contains_key(file_path) {
+            diff.added.push(FileDelta {
+                file_path: file_path.clone(),
+                chunks_before: 0,
+                chunks_after: new.chunks,
+            });
+        }
+    }
+
+    Ok(diff)
+}
+
+fn load_snapshot(path: &str) -> Result<BTreeMap<String, FileSnapshot>, Box<dyn std::error::Error>> {
+    let audits = if Path::new(path).is_dir() {
+        ContextRagSearcher::open(path)?.audit_all()?
+    } else {
+        let mut audits = Vec::new();
+        for (line_number, line) in BufReader::new(fs::File::open(path)?).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let audit: ChunkAudit = serde_json::from_str(&line)
+                .map_err(|e| format!("{}:{}: not an export line: {}", path, line_number + 1, e))?;
+            audits.push(audit);
+        }
+        audits
+    };
+
+    let mut files: BTreeMap<String, FileSnapshot> = BTreeMap::new();
+    for audit in audits {
+        let file = files.entry(audit.chunk.file_path).or_default();
+        file.file_hash = audit.file_hash;
+        file.chunks += 1;
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProjectConfig;
+    use crate::indexer::export::export_chunks;
+    use crate::indexer::ContextRagIndexer;
+    use crate::test_utils::TempDir;
+
+    fn index(dir: &TempDir, storage: &str) -> String {
+        let mut config = ProjectConfig::parse("[index]\ninclude = [\"*.md\"]\n").unwrap().index_config();
+        config.storage_path = dir.join(storage).to_string_lossy().into_owned();
+        config.root = Some(dir.join("source"));
+        ContextRagIndexer::for_config(&config).unwrap().index_directory(&config).unwrap();
+        config.storage_path
+    }
+
+    #[test]
+    fn exports_and_indexes_diff_by_file() {
+        let dir = TempDir::new("diff").unwrap();
+        fs::create_dir_all(dir.join("source")).unwrap();
+        for (file, content) in [("kept.md", "unchanged notes"), ("edited.md", "first draft"), ("removed.md", "old notes")] {
+            fs::write(dir.join("source").join(file), content).unwrap();
+        }
+        let before = index(&dir, "before");
+        let exported = dir.join("before.jsonl");
+        export_chunks(&before, fs::File::create(&exported).unwrap()).unwrap();
+
+        fs::write(dir.join("source/edited.md"), "second draft").unwrap();
+        fs::remove_file(dir.join("source/removed.md")).unwrap();
+        fs::write(dir.join("source/added.md"), "new notes").unwrap();
+        let after = index(&dir, "after");
+
+        let diff = diff_snapshots(exported.to_str().unwrap(), &after).unwrap();
+        let paths = |deltas: &[FileDelta]| deltas.iter().map(|delta| delta.file_path.clone()).collect::<Vec<_>>();
+        assert_eq!(paths(&diff.added), ["./added.md"]);
+        assert_eq!(paths(&diff.removed), ["./removed.md"]);
+        assert_eq!(paths(&diff.changed), ["./edited.md"]);
+        assert_eq!((diff.unchanged, diff.chunks_before, diff.chunks_after), (1, 3, 3));
+
+        fs::write(&exported, "{\"not\": \"an export\"}\n").unwrap();
+        let error = diff_snapshots(exported.to_str().unwrap(), &after).err().unwrap().to_string();
+        assert!(error.contains("before.jsonl:1: not an export line"), "{}", error);
+    }
+}
@@ -0,0 +1,49 @@
+use super::SearchHit;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+// One line of an eval set: a query and the files or chunks that answer it,
+// e.g. {"query": "where are vectors saved", "relevant": ["src/indexer/vectors.rs", "docs/storage.md#2"]}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EvalCase {
+    pub query: String,
+    pub relevant: Vec<String>,
+}
+
+// Reads a JSONL eval set, skipping blank lines
+pub fn load_eval_set(path: &str) -> Result<Vec<EvalCase>, Box<dyn std::error::Error>> {
+    let content = fs::read_to_string(path).map_err(|e| format!("Failed to read eval set {}: {}", path, e))?;
+    let cases = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| serde_json::from_str(line).map_err(|e| format!("{}:{}: {}", path, n + 1, e)))
+        .collect::<Result<Vec<EvalCase>, String>>()?;
+    if cases.is_empty() {
+        return Err(format!("Eval set {} has no cases", path).into());
+    }
+    Ok(cases)
+}
+
+impl EvalCase {
+    // A `path#chunk` entry names one chunk; a bare path accepts any of its chunks
+    pub fn is_relevant(&self, hit: &SearchHit) -> bool {
+        let path = normalize(&hit.file_path);
+        self.relevant.iter().any(|entry| match entry.rsplit_once('#') {
+            Some((file, chunk)) if chunk.parse() == Ok(hit.chunk_index) => normalize(file) == path,
+            Some(_) => false,
+            None => normalize(entry) == path,
+        })
+    }
+
+    // 1 / rank of the first relevant hit, or 0 when none is returned
+    pub fn reciprocal_rank(&self, hits: &[SearchHit]) -> f32 {
+        hits.iter()
+            .position(|hit| self.is_relevant(hit))
+            .map_or(0.0, |rank| 1.0 / (rank + 1) as f32)
+    }
+}
+
+fn normalize(path: &str) -> &str {
+    path.strip_prefix("./").unwrap_or(path)
+}
@@ -0,0 +1,149 @@
+use super::eval::EvalCase;
+use super::hybrid::hybrid_search;
+use super::ContextRagSearcher;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+// Ranks searched per query for hard negatives: chunks the current pipeline
+// puts near the answer without being it
+const MINING_DEPTH: usize = 30;
+
+// sentence-transformers' (anchor, positive, negative) triplet columns
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TrainingTriplet {
+    pub anchor: String,
+    pub positive: String,
+    pub negative: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TrainingExport {
+    pub triplets: usize,
+    pub queries: usize,
+    // Queries whose relevant entries matched no indexed chunk
+    pub unmatched: Vec<String>,
+}
+
+// Writes every chunk as one JSON line, sorted by path and chunk index.
+// Indexes built in deterministic mode export byte-identical output.
+pub fn export_chunks<W: Write>(storage_path: &str, mut out: W) -> Result<usize, Box<dyn std::error::Error>> {
+    let audits = ContextRagSearcher::open(storage_path)?.audit_all()?;
+
+    for audit in &audits {
+        serde_json::to_writer(&mut out, audit)?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+
+    Ok(audits.len())
+}
+
+// Mines training triplets from an eval set and writes them as JSON lines.
+// The positive is the best-ranked relevant chunk (or the first relevant one
+// in the index when search misses it entirely); negatives are the highest
+// ranked hits that aren't relevant.
+pub fn export_training_pairs<W: Write>(
+    storage_path: &str,
+    cases: &[EvalCase],
+    negatives_per_query: usize,
+    mut out: W,
+) -> Result<TrainingExport, Box<dyn std::error::Error>> {
+    let chunks = ContextRagSearcher::open(storage_path)?.audit_all()?;
+    let mut export = TrainingExport::default();
+
+    for case in cases {
+        let hits = hybrid_search(storage_path, &case.query, MINING_DEPTH)?.hits;
+        let positive = hits
+            .iter()
+            .find(|hit| case.is_relevant(hit))
+            .or_else(|| chunks.iter().map(|audit| &audit.chunk).find(|chunk| case.is_relevant(chunk)));
+        let Some(positive) = positive else {
+            export.unmatched.push(case.query.clone());
+            continue;
+        };
+
+        let negatives = hits
+            .iter()
+            .filter(|hit| !case.is_relevant(hit) && hit.content != positive.content)
+            .take(negatives_per_query);
+        for negative in negatives {
+            let triplet = TrainingTriplet {
+                anchor: case.query.clone(),
+                positive: positive.content.clone(),
+                negative: negative.content.clone(),
+            };
+            serde_json::to_writer(&mut out, &triplet)?;
+            out.write_all(b"\n")?;
+            export.triplets += 1;
+        }
+        export.queries += 1;
+    }
+    out.flush()?;
+
+    Ok(export)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProjectConfig;
+    use crate::indexer::ContextRagIndexer;
+    use crate::test_utils::TempDir;
+    use std::fs;
+
+    fn index(dir: &TempDir, files: &[(&str, &str)], storage: &str) -> String {
+        for (file, content) in files {
+            let path = dir.join("source").join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        let mut config = ProjectConfig::parse("[index]\ninclude = [\"*.md\"]\ndeterministic = true\n").unwrap().index_config();
+        config.storage_path = dir.join(storage).to_string_lossy().into_owned();
+        config.root = Some(dir.join("source"));
+        ContextRagIndexer::for_config(&config).unwrap().index_directory(&config).unwrap();
+        config.storage_path
+    }
+
+    #[test]
+    fn deterministic_indexes_export_identically() {
+        let dir = TempDir::new("export").unwrap();
+        let files = [("b.md", "second file"), ("a/c.md", "nested file"), ("a.md", "first file")];
+        let (mut first, mut second) = (Vec::new(), Vec::new());
+        assert_eq!(export_chunks(&index(&dir, &files, "first"), &mut first).unwrap(), 3);
+        assert_eq!(export_chunks(&index(&dir, &files, "second"), &mut second).unwrap(), 3);
+        assert_eq!(first, second);
+
+        let paths: Vec<String> = String::from_utf8(first)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<crate::indexer::ChunkAudit>(line).unwrap().chunk.file_path)
+            .collect();
+        assert_eq!(paths, ["./a.md", "./a/c.md", "./b.md"]);
+    }
+
+    #[test]
+    fn hard_negatives_are_the_top_hits_that_are_not_relevant() {
+        let dir = TempDir::new("training-pairs").unwrap();
+        let files = [
+            ("rotate.md", "rotate the signing keys every quarter"),
+            ("vault.md", "signing keys live in the vault"),
+            ("release.md", "signing off the release"),
+            ("zebra.md", "zebras have stripes"),
+        ];
+        let storage = index(&dir, &files, "index");
+        let case = |query: &str, relevant: &str| EvalCase { query: query.to_string(), relevant: vec![relevant.to_string()] };
+        let cases = [case("rotate signing keys", "rotate.md"), case("signing keys", "zebra.md#0"), case("signing", "missing.md")];
+
+        let mut out = Vec::new();
+        let export = export_training_pairs(&storage, &cases, 1, &mut out).unwrap();
+        assert_eq!((export.triplets, export.queries), (2, 2));
+        assert_eq!(export.unmatched, ["signing"]);
+
+        let triplets: Vec<TrainingTriplet> = String::from_utf8(out).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(triplets[0].positive, "rotate the signing keys every quarter");
+        assert!(triplets[0].negative.contains("signing") && !triplets[0].negative.contains("rotate"));
+        // Search never reaches the zebras, so the relevant chunk stands in
+        assert_eq!(triplets[1].positive, "zebras have stripes");
+        assert!(triplets[1].negative.contains("signing keys"));
+    }
+}
@@ -0,0 +1,164 @@
+use super::hybrid::hybrid_channels;
+use super::{sparse, Calibration, ContextRagSearcher, FusionWeights, SearchHit, SearchOptions};
+use serde::{Deserialize, Serialize};
+
+// Query terms kept by the keyword extraction rung, the heaviest first
+const EXTRACTED_KEYWORDS: usize = 5;
+
+// One step of the fallback ladder, each looser than a plain keyword search
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FallbackRung {
+    // Default matching options and no confidence cutoff
+    Relax,
+    // Terms match within one edit
+    Fuzzy,
+    // Vector similarity alone; empty until vectors are backfilled
+    Vector,
+    // The query's heaviest content words, compound identifiers split
+    Keywords,
+}
+
+impl std::str::FromStr for FallbackRung {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "relax" => Ok(FallbackRung::Relax),
+            "fuzzy" => Ok(FallbackRung::Fuzzy),
+            "vector" => Ok(FallbackRung::Vector),
+            "keywords" => Ok(FallbackRung::Keywords),
+            other => Err(format!("Unknown fallback '{}' (expected relax, fuzzy, vector or keywords)", other)),
+        }
+    }
+}
+
+impl std::fmt::Display for FallbackRung {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            FallbackRung::Relax => "relax",
+            FallbackRung::Fuzzy => "fuzzy",
+            FallbackRung::Vector => "vector",
+            FallbackRung::Keywords => "keywords",
+        };
+        f.write_str(name)
+    }
+}
+
+// Rungs tried in order while a search has fewer than `min_results`
+// acceptable hits; no rungs turns fallback off
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct FallbackLadder {
+    #[serde(default)]
+    pub rungs: Vec<FallbackRung>,
+    #[serde(default = "default_min_results")]
+    pub min_results: usize,
+}
+
+impl Default for FallbackLadder {
+    fn default() -> Self {
+        FallbackLadder { rungs: Vec::new(), min_results: default_min_results() }
+    }
+}
+
+fn default_min_results() -> usize {
+    1
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FallbackSearch {
+    pub hits: Vec<SearchHit>,
+    // Hits of the original query dropped by the confidence cutoff
+    pub below_confidence: usize,
+    // Rung that produced `hits`; none when the query did on its own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rung: Option<FallbackRung>,
+    // Rungs run before one produced enough hits or the ladder ran out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tried: Vec<FallbackRung>,
+}
+
+// Keyword search that walks down `ladder` until a rung has enough hits. If
+// none does, the rung with the most hits wins, ties going to the earlier one.
+pub fn search_with_fallback(
+    storage_path: &str,
+    query: &str,
+    limit: usize,
+    options: SearchOptions,
+    min_confidence: f32,
+    ladder: &FallbackLadder,
+) -> Result<FallbackSearch, Box<dyn std::error::Error>> {
+    FallbackSearcher::open(storage_path)?.search(query, limit, options, min_confidence, ladder)
+}
+
+// An index and its calibration opened once for any number of fallback
+// searches, e.g. a stream of queries from a script
+pub struct FallbackSearcher {
+    storage_path: String,
+    searcher: ContextRagSearcher,
+    calibration: Calibration,
+}
+
+impl FallbackSearcher {
+    pub fn open(storage_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let searcher = ContextRagSearcher::open(storage_path)?;
+        let calibration = {
+            let _span = crate::profile::span("calibration");
+            Calibration::load_or_build(storage_path)?
+        };
+        Ok(FallbackSearcher { storage_path: storage_path.to_string(), searcher, calibration })
+    }
+
+    pub fn searcher(&self) -> &ContextRagSearcher {
+        &self.searcher
+    }
+
+    pub fn search(
+        &self,
+        query: &str,
+        limit: usize,
+        options: SearchOptions,
+        min_confidence: f32,
+        ladder: &FallbackLadder,
+    ) -> Result<FallbackSearch, Box<dyn std::error::Error>> {
+        let (storage_path, searcher, calibration) = (self.storage_path.as_str(), &self.searcher, &self.calibration);
+        let mut hits = searcher.search_with_options(query, limit, options)?;
+        let below_confidence = calibration.apply(&mut hits, min_confidence);
+
+        let mut result = FallbackSearch { hits, below_confidence, rung: None, tried: Vec::new() };
+        for &rung in &ladder.rungs {
+            if result.hits.len() >= ladder.min_results {
+                break;
+            }
+            result.tried.push(rung);
+            let _span = crate::profile::span("fallback");
+            let hits = match rung {
+                FallbackRung::Relax => {
+                    let mut hits = searcher.search(query, limit)?;
+                    calibration.apply(&mut hits, 0.0);
+                    hits
+                }
+                FallbackRung::Fuzzy => searcher.fuzzy_search(query, limit)?,
+                FallbackRung::Vector => {
+                    let vector_only = FusionWeights { keyword: 0.0, identifier: 0.0, vector: 1.0 };
+                    hybrid_channels(storage_path, query, limit)?.fuse(&vector_only, limit)
+                }
+                FallbackRung::Keywords => {
+                    let mut terms = sparse::encode(query);
+                    terms.sort_by(|a, b| b.1.total_cmp(&a.1));
+                    let keywords: Vec<&str> = terms.iter().take(EXTRACTED_KEYWORDS).map(|(term, _)| term.as_str()).collect();
+                    if keywords.is_empty() {
+                        Vec::new()
+                    } else {
+                        searcher.search(&keywords.join(" "), limit)?
+                    }
+                }
+            };
+            if hits.len() > result.hits.len() {
+                result.hits = hits;
+                result.rung = Some(rung);
+            }
+        }
+        Ok(result)
+    }
+}
@@ -0,0 +1,19 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// A file's bytes, read in one go. Working-tree files get truncated and
+// rewritten while the indexer runs, which a memory map would turn into a
+// SIGBUS, so they are read rather than mapped.
+pub struct FileContent(Vec<u8>);
+
+impl FileContent {
+    // Validated in place, so large files aren't copied a second time
+    pub fn text(&self) -> io::Result<&str> {
+        std::str::from_utf8(&self.0).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+pub fn read_file(path: &Path) -> io::Result<FileContent> {
+    fs::read(path).map(FileContent)
+}
@@ -0,0 +1,54 @@
+use super::shards::SHARDS_FILE;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+// Records which hash an index's file and chunk hashes were made with
+const CONTENT_HASH_FILE: &str = "content-hash";
+// Inputs at least this big are hashed across threads
+const PARALLEL_HASH_BYTES: usize = 1 << 20;
+
+// Hash for file and chunk contents. Both are 256-bit hex digests; BLAKE3 is
+// much faster, SHA-256 is there for snapshots that get signed. Indexes
+// built before BLAKE3 existed record nothing and stay on SHA-256.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentHash {
+    #[default]
+    Blake3,
+    Sha256,
+}
+
+impl ContentHash {
+    pub fn hash(&self, content: &[u8]) -> String {
+        match self {
+            ContentHash::Blake3 if content.len() >= PARALLEL_HASH_BYTES => {
+                blake3::Hasher::new().update_rayon(content).finalize().to_hex().to_string()
+            }
+            ContentHash::Blake3 => blake3::hash(content).to_hex().to_string(),
+            ContentHash::Sha256 => {
+                use sha2::{Digest, Sha256};
+                hex::encode(Sha256::digest(content))
+            }
+        }
+    }
+
+    // The hash an existing index was built with; new indexes get the default
+    pub fn load(storage_path: &str) -> Self {
+        let storage = Path::new(storage_path);
+        match fs::read_to_string(storage.join(CONTENT_HASH_FILE)) {
+            Ok(name) if name.trim() == "sha256" => ContentHash::Sha256,
+            Ok(_) => ContentHash::Blake3,
+            Err(_) if storage.join("meta.json").exists() || storage.join(SHARDS_FILE).exists() => ContentHash::Sha256,
+            Err(_) => ContentHash::default(),
+        }
+    }
+
+    pub fn save(&self, storage_path: &str) -> std::io::Result<()> {
+        let name = match self {
+            ContentHash::Blake3 => "blake3",
+            ContentHash::Sha256 => "sha256",
+        };
+        fs::write(Path::new(storage_path).join(CONTENT_HASH_FILE), name)
+    }
+}
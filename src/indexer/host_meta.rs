@@ -0,0 +1,44 @@
+use super::{memory, vectors::write_atomically};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+const HOST_META_FILE: &str = "host-meta.json";
+
+// Key-value pairs a host application keeps with the index (the last synced
+// commit, the model it embedded with, ...), so they are copied, moved and
+// deleted along with the data instead of drifting in a file of their own.
+// Index runs leave them alone.
+pub fn index_meta(storage_path: &str, key: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    Ok(load(storage_path)?.remove(key))
+}
+
+// Sets `key`, or removes it when `value` is None
+pub fn set_index_meta(storage_path: &str, key: &str, value: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut meta = load(storage_path)?;
+    match value {
+        Some(value) => meta.insert(key.to_string(), value.to_string()),
+        None => meta.remove(key),
+    };
+    if memory::is_memory_storage(storage_path) {
+        memory::set_host_meta(storage_path, meta);
+        return Ok(());
+    }
+    write_atomically(&Path::new(storage_path).join(HOST_META_FILE), &serde_json::to_vec_pretty(&meta)?)
+}
+
+fn load(storage_path: &str) -> Result<BTreeMap<String, String>, Box<dyn std::error::Error>> {
+    if memory::is_memory_storage(storage_path) {
+        memory::open(storage_path)?;
+        return Ok(memory::host_meta(storage_path));
+    }
+    if !Path::new(storage_path).is_dir() {
+        return Err(format!("No index at {}", storage_path).into());
+    }
+    let path = Path::new(storage_path).join(HOST_META_FILE);
+    match fs::read_to_string(&path) {
+        Ok(content) => Ok(serde_json::from_str(&content).map_err(|e| format!("Invalid {}: {}", path.display(), e))?),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+        Err(e) => Err(e.into()),
+    }
+}
@@ -0,0 +1,227 @@
+use super::answerability::{assess_answerability, Answerability};
+use super::language::is_code;
+use super::vectors::{store_path, VectorScores};
+use super::{ContextRagSearcher, SearchHit, VectorStore};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// Each channel contributes this many candidates per requested result
+pub(super) const CANDIDATE_FACTOR: usize = 4;
+// Standard reciprocal rank fusion constant
+const RRF_K: f32 = 60.0;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct HybridSearch {
+    pub hits: Vec<SearchHit>,
+    // Model whose vectors were used, if any
+    pub model: Option<String>,
+    // Model used for source chunks when code routing is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_model: Option<String>,
+    // "hybrid", or "keyword" when no vectors exist yet
+    pub mode: String,
+    // Fraction of chunks that have a vector in the active namespace
+    pub vector_coverage: f32,
+    pub answerability: Answerability,
+}
+
+// Relative weight of each channel in fusion; only the ratios matter. Code
+// chunks routed to a code model share the vector weight.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct FusionWeights {
+    #[serde(default = "unit_weight")]
+    pub keyword: f32,
+    #[serde(default = "unit_weight")]
+    pub identifier: f32,
+    #[serde(default = "unit_weight")]
+    pub vector: f32,
+}
+
+impl Default for FusionWeights {
+    fn default() -> Self {
+        FusionWeights { keyword: 1.0, identifier: 1.0, vector: 1.0 }
+    }
+}
+
+fn unit_weight() -> f32 {
+    1.0
+}
+
+// One query's ranking from each channel, before fusion
+#[derive(Debug)]
+pub struct Channels {
+    pub keyword: Vec<SearchHit>,
+    pub identifier: Vec<SearchHit>,
+    pub vector: Vec<SearchHit>,
+    pub code: Vec<SearchHit>,
+    pub model: Option<String>,
+    pub code_model: Option<String>,
+    pub vector_coverage: f32,
+}
+
+impl Channels {
+    pub fn fuse(&self, weights: &FusionWeights, limit: usize) -> Vec<SearchHit> {
+        let _span = crate::profile::span("fuse");
+        fuse_weighted(
+            &[
+                (weights.keyword, &self.keyword),
+                (weights.identifier, &self.identifier),
+                (weights.vector, &self.vector),
+                (weights.vector, &self.code),
+            ],
+            limit,
+        )
+    }
+
+    fn into_search(self, query: &str, weights: &FusionWeights, limit: usize) -> HybridSearch {
+        let hits = self.fuse(weights, limit);
+        // Fusion scores are ranks, so similarity comes from the vector channels
+        let similarity = self.vector.iter().chain(&self.code).map(|hit| hit.score).reduce(f32::max);
+        HybridSearch {
+            answerability: assess_answerability(query, &hits, similarity),
+            hits,
+            mode: if self.model.is_some() { "hybrid" } else { "keyword" }.to_string(),
+            model: self.model,
+            code_model: self.code_model,
+            vector_coverage: self.vector_coverage,
+        }
+    }
+}
+
+// Fuses BM25, literal identifier matches and vector rankings. Chunks still
+// waiting for their vectors can only rank through the first two, so results
+// degrade to keyword-only while a backfill is catching up.
+pub fn hybrid_search(storage_path: &str, query: &str, limit: usize) -> Result<HybridSearch, Box<dyn std::error::Error>> {
+    hybrid_search_weighted(storage_path, query, limit, &FusionWeights::default())
+}
+
+pub fn hybrid_search_weighted(
+    storage_path: &str,
+    query: &str,
+    limit: usize,
+    weights: &FusionWeights,
+) -> Result<HybridSearch, Box<dyn std::error::Error>> {
+    Ok(hybrid_channels(storage_path, query, limit * CANDIDATE_FACTOR)?.into_search(query, weights, limit))
+}
+
+// Ranks with one model's namespace instead of the active one, e.g. to compare
+// a candidate model against the current one before re-embedding
+pub fn hybrid_search_model(
+    storage_path: &str,
+    model: &str,
+    query: &str,
+    limit: usize,
+    weights: &FusionWeights,
+) -> Result<HybridSearch, Box<dyn std::error::Error>> {
+    let namespace = VectorStore::namespace(model);
+    if !store_path(storage_path, &namespace).exists() {
+        return Err(format!("No vectors for {}; run `context-rag-embedder backfill --model {}` first", model, model).into());
+    }
+    Ok(channels_with(storage_path, Some(namespace), query, limit * CANDIDATE_FACTOR)?.into_search(query, weights, limit))
+}
+
+// Per-channel rankings against the active namespace, `candidates` deep
+pub fn hybrid_channels(storage_path: &str, query: &str, candidates: usize) -> Result<Channels, Box<dyn std::error::Error>> {
+    channels_with(storage_path, VectorStore::active_namespace(storage_path), query, candidates)
+}
+
+fn channels_with(
+    storage_path: &str,
+    namespace: Option<String>,
+    query: &str,
+    candidates: usize,
+) -> Result<Channels, Box<dyn std::error::Error>> {
+    let searcher = ContextRagSearcher::open(storage_path)?;
+
+    let keyword = searcher.search(query, candidates)?;
+    let identifier = searcher.identifier_search(query, candidates)?;
+
+    let vectors = crate::profile::span("vectors");
+    let scores = match namespace {
+        Some(namespace) => VectorScores::load(storage_path, &namespace, query)?,
+        None => None,
+    };
+    let Some(scores) = scores.filter(|s| !s.is_empty()) else {
+        return Ok(Channels {
+            keyword,
+            identifier,
+            vector: Vec::new(),
+            code: Vec::new(),
+            model: None,
+            code_model: None,
+            vector_coverage: 0.0,
+        });
+    };
+
+    // Routed code chunks live in another model's space, so they rank in a
+    // channel of their own and only meet prose chunks through fusion
+    let mut embedded = 0;
+    let mut vector_hits = Vec::new();
+    let mut code_hits = Vec::new();
+    for mut chunk in searcher.all_chunks()? {
+        let Some(score) = scores.score_for(&chunk) else {
+            continue;
+        };
+        embedded += 1;
+        chunk.score = score;
+        if scores.code_model.is_some() && is_code(&chunk.language) {
+            code_hits.push(chunk);
+        } else {
+            vector_hits.push(chunk);
+        }
+    }
+    let total = searcher.num_chunks() as usize;
+    for hits in [&mut vector_hits, &mut code_hits] {
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(candidates);
+    }
+    drop(vectors);
+
+    Ok(Channels {
+        keyword,
+        identifier,
+        vector: vector_hits,
+        code: code_hits,
+        model: Some(scores.model),
+        code_model: scores.code_model,
+        vector_coverage: if total == 0 { 0.0 } else { embedded as f32 / total as f32 },
+    })
+}
+
+// Reciprocal rank fusion; the fused score replaces the channel scores
+pub fn fuse(rankings: &[Vec<SearchHit>], limit: usize) -> Vec<SearchHit> {
+    let weighted: Vec<(f32, &Vec<SearchHit>)> = rankings.iter().map(|ranking| (1.0, ranking)).collect();
+    fuse_weighted(&weighted, limit)
+}
+
+// Reciprocal rank fusion with each ranking's contributions scaled by its weight
+pub fn fuse_weighted(rankings: &[(f32, &Vec<SearchHit>)], limit: usize) -> Vec<SearchHit> {
+    let mut fused: HashMap<(String, u64), SearchHit> = HashMap::new();
+
+    for (weight, ranking) in rankings {
+        if *weight <= 0.0 {
+            continue;
+        }
+        for (rank, hit) in ranking.iter().enumerate() {
+            let contribution = weight / (RRF_K + rank as f32 + 1.0);
+            fused
+                .entry((hit.file_path.clone(), hit.chunk_index))
+                .and_modify(|existing| existing.score += contribution)
+                .or_insert_with(|| SearchHit {
+                    score: contribution,
+                    ..hit.clone()
+                });
+        }
+    }
+
+    let mut hits: Vec<SearchHit> = fused.into_values().collect();
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.file_path.cmp(&b.file_path)));
+    hits.truncate(limit);
+    hits
+}
+
+// Stored vectors are unit length, so this is cosine similarity; raw dot
+// product for an index built with --no-normalize
+pub(super) fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
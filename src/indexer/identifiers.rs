@@ -0,0 +1,82 @@
+use super::SearchHit;
+
+// Query words worth matching literally: anything with code-ish punctuation,
+// digits or inner capitals, or the whole query when it is a single word.
+// Plain prose words are left to BM25 and vectors.
+pub fn identifier_terms(query: &str) -> Vec<String> {
+    let words: Vec<&str> = query
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| matches!(c, '"' | '\'' | '`' | ',' | '(' | ')' | '?')))
+        .filter(|word| word.chars().count() >= 3)
+        .collect();
+
+    let single = words.len() == 1;
+    let mut terms: Vec<String> = words
+        .into_iter()
+        .filter(|word| single || looks_like_identifier(word))
+        .map(|word| word.to_lowercase())
+        .collect();
+    terms.dedup();
+    terms
+}
+
+// Path matches weigh double; longer literals are more specific
+pub fn identifier_score(hit: &SearchHit, terms: &[String]) -> f32 {
+    let path = hit.file_path.to_lowercase();
+    let content = hit.content.to_lowercase();
+
+    terms
+        .iter()
+        .map(|term| {
+            let weight = term.chars().count() as f32;
+            if path.contains(term.as_str()) {
+                2.0 * weight
+            } else if content.contains(term.as_str()) {
+                weight
+            } else {
+                0.0
+            }
+        })
+        .sum()
+}
+
+pub fn trigrams(term: &str) -> Vec<String> {
+    let chars: Vec<char> = term.chars().collect();
+    let mut grams: Vec<String> = chars.windows(3).map(|w| w.iter().collect()).collect();
+    grams.sort();
+    grams.dedup();
+    grams
+}
+
+// Literals every match of `pattern` must start with, when the set is small
+// and each is long enough to narrow candidates by trigrams. `None` means the
+// regex has to be checked against every chunk.
+pub fn regex_prefix_literals(pattern: &str) -> Option<Vec<String>> {
+    use regex_syntax::hir::literal::{ExtractKind, Extractor};
+
+    const MAX_LITERALS: usize = 16;
+
+    let hir = regex_syntax::parse(pattern).ok()?;
+    let mut extractor = Extractor::new();
+    extractor.kind(ExtractKind::Prefix).limit_total(256);
+    let literals = extractor.extract(&hir);
+
+    let literals = literals.literals()?;
+    if literals.is_empty() || literals.len() > MAX_LITERALS {
+        return None;
+    }
+
+    literals
+        .iter()
+        .map(|literal| {
+            let text = String::from_utf8(literal.as_bytes().to_vec()).ok()?;
+            (text.chars().count() >= 3).then(|| text.to_lowercase())
+        })
+        .collect()
+}
+
+fn looks_like_identifier(word: &str) -> bool {
+    word.contains(['_', '.', ':', '/', '-', '#'])
+        || word.chars().any(|c| c.is_ascii_digit())
+        || word.chars().skip(1).any(|c| c.is_uppercase())
+}
@@ -0,0 +1,53 @@
+use std::borrow::Cow;
+
+// Markers authors put in a file to keep it, or parts of it, out of the
+// index. Each must be a line of its own, optionally inside a comment
+// (`# context-rag: ignore`, `<!-- rag:ignore -->`), so code and docs that
+// merely mention them are indexed as usual.
+const FILE_MARKER: &str = "context-rag: ignore";
+// How far down a file the whole-file marker is looked for
+const FILE_MARKER_LINES: usize = 5;
+// Lines from one of these to the next end marker are dropped, markers
+// included; a region left open runs to the end of the file. Fences around
+// embedded data tables and generated blocks keep the rest of the file.
+const REGION_STARTS: &[&str] = &["rag:ignore", "rag:ignore-start", "rag:begin-ignore"];
+const REGION_ENDS: &[&str] = &["/rag:ignore", "rag:ignore-end", "rag:end-ignore"];
+
+const COMMENT_OPENERS: &[&str] = &["<!--", "/*", "//", "#", "--", ";;", "{-", "*"];
+const COMMENT_CLOSERS: &[&str] = &["-->", "*/", "-}"];
+
+pub fn ignores_file(content: &str) -> bool {
+    content.lines().take(FILE_MARKER_LINES).any(|line| marker(line) == FILE_MARKER)
+}
+
+// The content without its ignored regions, borrowed when it has none
+pub fn strip_ignored_regions(content: &str) -> Cow<'_, str> {
+    if !content.lines().any(|line| REGION_STARTS.contains(&marker(line))) {
+        return Cow::Borrowed(content);
+    }
+    let mut ignoring = false;
+    let mut kept = Vec::new();
+    for line in content.lines() {
+        let marker = marker(line);
+        if ignoring {
+            ignoring = !REGION_ENDS.contains(&marker);
+        } else if REGION_STARTS.contains(&marker) {
+            ignoring = true;
+        } else {
+            kept.push(line);
+        }
+    }
+    Cow::Owned(kept.join("\n"))
+}
+
+// A line's text inside any comment delimiters around it
+fn marker(line: &str) -> &str {
+    let mut text = line.trim();
+    if let Some(opener) = COMMENT_OPENERS.iter().find(|opener| text.starts_with(*opener)) {
+        text = text[opener.len()..].trim_start();
+    }
+    if let Some(closer) = COMMENT_CLOSERS.iter().find(|closer| text.ends_with(*closer)) {
+        text = text[..text.len() - closer.len()].trim_end();
+    }
+    text
+}
@@ -0,0 +1,77 @@
+use super::language::is_code;
+use super::{parse_chunk_id, relative_to_root, ChunkAudit, ContextRagSearcher, VectorStore};
+use serde::{Deserialize, Serialize};
+
+// Everything the index holds about one chunk, for debugging why it ranks
+// (or doesn't) the way it does
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChunkInspection {
+    // Content, metadata and provenance, as `audit` prints them
+    #[serde(flatten)]
+    pub audit: ChunkAudit,
+    // From the active vector store; None until the chunk is embedded
+    pub vector: Option<VectorSummary>,
+    // Normally one live copy; more mean deletes not yet merged away
+    pub segments: Vec<SegmentCopy>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VectorSummary {
+    pub model: String,
+    pub dimensions: usize,
+    // Stored vectors are unit length; anything else points at a bad write
+    // or an engine that skipped normalizing, unless --no-normalize built it
+    pub norm: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SegmentCopy {
+    pub shard: usize,
+    // The segment's id, as in its file names under the index directory
+    pub segment: String,
+    pub doc: u32,
+    pub deleted: bool,
+}
+
+// `file_path` may leave off the leading "./" stored paths carry
+pub fn inspect_chunk(storage_path: &str, file_path: &str, chunk_index: u64) -> Result<Option<ChunkInspection>, Box<dyn std::error::Error>> {
+    let file_path = relative_to_root(file_path);
+    let searcher = ContextRagSearcher::open(storage_path)?;
+    let Some(audit) = searcher.audit_chunk(&file_path, chunk_index)? else {
+        return Ok(None);
+    };
+    let segments = searcher.chunk_segments(&file_path, chunk_index)?;
+
+    let vector = VectorStore::load_active(storage_path)?.and_then(|store| {
+        let vector = store.vector_for(&audit.chunk)?;
+        let model = match &store.code_model {
+            Some(code_model) if is_code(&audit.chunk.language) => code_model.clone(),
+            _ => store.model.clone(),
+        };
+        Some(VectorSummary { model, dimensions: vector.len(), norm: vector.iter().map(|x| x * x).sum::<f32>().sqrt() })
+    });
+
+    Ok(Some(ChunkInspection { audit, vector, segments }))
+}
+
+// The stored vector of every chunk id (`path#index`), in order, for callers
+// doing their own similarity math; None for chunks the index lacks or hasn't
+// embedded yet. The active store is read once for all of them.
+pub fn chunk_vectors(storage_path: &str, ids: &[String]) -> Result<Vec<Option<Vec<f32>>>, Box<dyn std::error::Error>> {
+    let chunks = ids
+        .iter()
+        .map(|id| parse_chunk_id(id).ok_or_else(|| format!("Chunk id must look like <path>#<index>, got '{}'", id)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let Some(store) = VectorStore::load_active(storage_path)? else {
+        return Ok(vec![None; ids.len()]);
+    };
+    let searcher = ContextRagSearcher::open(storage_path)?;
+    chunks
+        .into_iter()
+        .map(|(file_path, chunk_index)| {
+            let file_path = relative_to_root(file_path);
+            let audit = searcher.audit_chunk(&file_path, chunk_index)?;
+            Ok(audit.and_then(|audit| store.vector_for(&audit.chunk).map(<[f32]>::to_vec)))
+        })
+        .collect()
+}
@@ -0,0 +1,42 @@
+use std::path::Path;
+
+// Languages tagged on chunks at index time, by file extension
+const LANGUAGES: &[(&str, &[&str])] = &[
+    ("rust", &["rs"]),
+    ("javascript", &["js", "jsx", "mjs", "cjs"]),
+    ("typescript", &["ts", "tsx"]),
+    ("python", &["py"]),
+    ("go", &["go"]),
+    ("java", &["java"]),
+    ("kotlin", &["kt"]),
+    ("ruby", &["rb"]),
+    ("php", &["php"]),
+    ("csharp", &["cs"]),
+    ("c", &["c", "h"]),
+    ("cpp", &["cc", "cpp", "hpp"]),
+    ("swift", &["swift"]),
+    ("shell", &["sh", "bash", "zsh"]),
+    ("sql", &["sql"]),
+    ("markdown", &["md", "markdown", "mdx"]),
+    ("restructuredtext", &["rst"]),
+    ("text", &["txt"]),
+];
+
+const PROSE: &[&str] = &["markdown", "restructuredtext", "text"];
+
+// Empty when the extension isn't recognised
+pub fn detect_language(path: &Path) -> &'static str {
+    let Some(ext) = path.extension().and_then(|e| e.to_str()) else {
+        return "";
+    };
+    let ext = ext.to_ascii_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|(_, extensions)| extensions.contains(&ext.as_str()))
+        .map_or("", |(language, _)| language)
+}
+
+// Untagged chunks count as prose, so routing only moves chunks we recognise
+pub fn is_code(language: &str) -> bool {
+    !language.is_empty() && !PROSE.contains(&language)
+}
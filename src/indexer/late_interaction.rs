@@ -0,0 +1,133 @@
+use super::hybrid::{dot, hybrid_search};
+use super::vectors::{store_path, write_atomically};
+use super::{assess_answerability, BackfillResult, ContextRagSearcher, HybridSearch, SearchHit, VectorStore};
+use crate::embedding::generate_mock_embedding;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+// Bounds the per-chunk cost: every stored token is a full vector
+const MAX_TOKENS: usize = 256;
+// Hybrid candidates rescored per requested result
+const RESCORE_FACTOR: usize = 4;
+
+// Per-token vectors for one model, keyed by chunk content hash like the
+// chunk-level store. Roughly MAX_TOKENS times its size, hence opt-in.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TokenVectorStore {
+    pub model: String,
+    pub chunks: HashMap<String, Vec<Vec<f32>>>,
+}
+
+impl TokenVectorStore {
+    pub fn load(storage_path: &str, model: &str) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let path = store_path(storage_path, &namespace(model));
+        if !path.exists() {
+            return Ok(None);
+        }
+        let store = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("Invalid token vector store {}: {}", path.display(), e))?;
+        Ok(Some(store))
+    }
+
+    pub fn save(&self, storage_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        write_atomically(&store_path(storage_path, &namespace(&self.model)), &serde_json::to_vec(self)?)
+    }
+}
+
+// Stores token vectors for every indexed chunk that lacks them and drops
+// those of chunks that are gone
+pub fn backfill_token_vectors<F>(storage_path: &str, model: &str, mut on_progress: F) -> Result<BackfillResult, Box<dyn std::error::Error>>
+where
+    F: FnMut(usize, usize),
+{
+    let start_time = std::time::Instant::now();
+    let mut store = TokenVectorStore::load(storage_path, model)?.unwrap_or_else(|| TokenVectorStore {
+        model: model.to_string(),
+        ..Default::default()
+    });
+
+    let chunks = ContextRagSearcher::open(storage_path)?.all_chunks()?;
+    let live: HashSet<&str> = chunks.iter().map(|c| c.chunk_hash.as_str()).collect();
+    store.chunks.retain(|hash, _| live.contains(hash.as_str()));
+
+    let missing: Vec<_> = chunks.iter().filter(|c| !store.chunks.contains_key(&c.chunk_hash)).collect();
+    let mut embedded = 0;
+    for chunk in &missing {
+        if store.chunks.contains_key(&chunk.chunk_hash) {
+            continue;
+        }
+        store.chunks.insert(chunk.chunk_hash.clone(), token_vectors(&chunk.content));
+        embedded += 1;
+        on_progress(embedded, missing.len());
+    }
+    store.save(storage_path)?;
+
+    Ok(BackfillResult {
+        model: store.model,
+        code_model: None,
+        embedded,
+        reused: chunks.len() - missing.len(),
+        total_chunks: chunks.len(),
+        failed: None,
+        processing_time_ms: start_time.elapsed().as_millis(),
+    })
+}
+
+// Hybrid search, then MaxSim rescoring of the top candidates against their
+// token vectors. Candidates without token vectors keep their fused order
+// after the rescored ones.
+pub fn late_interaction_search(storage_path: &str, query: &str, limit: usize) -> Result<HybridSearch, Box<dyn std::error::Error>> {
+    let mut result = hybrid_search(storage_path, query, limit * RESCORE_FACTOR)?;
+    let Some(model) = result.model.clone() else {
+        result.hits.truncate(limit);
+        result.answerability = assess_answerability(query, &result.hits, None);
+        return Ok(result);
+    };
+    let store = TokenVectorStore::load(storage_path, &model)?.ok_or_else(|| {
+        format!("No token vectors for {}; run `context-rag-embedder backfill --late-interaction` first", model)
+    })?;
+
+    let query_tokens = token_vectors(query);
+    let (mut rescored, rest): (Vec<SearchHit>, Vec<SearchHit>) = result
+        .hits
+        .into_iter()
+        .partition(|hit| store.chunks.contains_key(&hit.chunk_hash));
+    for hit in &mut rescored {
+        hit.score = max_sim(&query_tokens, &store.chunks[&hit.chunk_hash]);
+    }
+    rescored.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    result.hits = rescored.into_iter().chain(rest).take(limit).collect();
+    result.answerability = assess_answerability(query, &result.hits, result.answerability.similarity);
+    result.mode = "late-interaction".to_string();
+    Ok(result)
+}
+
+fn namespace(model: &str) -> String {
+    format!("{}.tokens", VectorStore::namespace(model))
+}
+
+// Repeated tokens add nothing to MaxSim, so each is stored once
+fn token_vectors(text: &str) -> Vec<Vec<f32>> {
+    let mut seen = HashSet::new();
+    text.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+        .filter(|token| seen.insert(token.clone()))
+        .take(MAX_TOKENS)
+        .map(|token| generate_mock_embedding(&token))
+        .collect()
+}
+
+// Mean over query tokens of their best match among the chunk's tokens
+fn max_sim(query: &[Vec<f32>], chunk: &[Vec<f32>]) -> f32 {
+    if query.is_empty() {
+        return 0.0;
+    }
+    let total: f32 = query
+        .iter()
+        .map(|q| chunk.iter().map(|t| dot(q, t)).fold(f32::NEG_INFINITY, f32::max).max(0.0))
+        .sum();
+    total / query.len() as f32
+}
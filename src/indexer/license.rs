@@ -0,0 +1,63 @@
+// Case-insensitive phrases that mark a leading comment as a license header
+const MARKERS: &[&str] = &[
+    "spdx-license-identifier",
+    "copyright",
+    "licensed under",
+    "permission is hereby granted",
+    "all rights reserved",
+    "gnu general public license",
+    "apache license",
+    "mit license",
+    "this source code form is subject to",
+];
+
+// Line comment prefixes, plus `#` lines that are directives rather than comments
+const LINE_COMMENTS: &[&str] = &["//", "#", "--", ";;"];
+const NOT_COMMENTS: &[&str] = &["#!", "#[", "#include", "#define", "#if", "#pragma"];
+const BLOCK_COMMENTS: &[(&str, &str)] = &[("/*", "*/"), ("<!--", "-->"), ("\"\"\"", "\"\"\""), ("{-", "-}")];
+
+// The file without its leading license comment, or `None` when it doesn't
+// start with one. A shebang line is kept.
+pub fn strip_license_header(content: &str) -> Option<String> {
+    let lines: Vec<&str> = content.lines().collect();
+    let shebang = lines.first().filter(|line| line.starts_with("#!") && !line.starts_with("#!["));
+    let mut start = shebang.map_or(0, |_| 1);
+    while start < lines.len() && lines[start].trim().is_empty() {
+        start += 1;
+    }
+
+    let end = header_end(&lines, start)?;
+    let header = lines[start..end].join("\n").to_lowercase();
+    if !MARKERS.iter().any(|marker| header.contains(marker)) {
+        return None;
+    }
+
+    let mut rest = end;
+    while rest < lines.len() && lines[rest].trim().is_empty() {
+        rest += 1;
+    }
+    let kept: Vec<&str> = shebang.into_iter().chain(&lines[rest..]).copied().collect();
+    Some(kept.join("\n"))
+}
+
+// One past the last line of the comment block starting at `start`
+fn header_end(lines: &[&str], start: usize) -> Option<usize> {
+    let first = lines.get(start)?.trim_start();
+
+    if let Some((open, close)) = BLOCK_COMMENTS.iter().find(|(open, _)| first.starts_with(open)) {
+        if first[open.len()..].contains(close) {
+            return Some(start + 1);
+        }
+        return lines[start + 1..].iter().position(|line| line.contains(close)).map(|offset| start + offset + 2);
+    }
+
+    let is_line_comment = |line: &str| {
+        let line = line.trim_start();
+        LINE_COMMENTS.iter().any(|prefix| line.starts_with(prefix)) && !NOT_COMMENTS.iter().any(|prefix| line.starts_with(prefix))
+    };
+    if !is_line_comment(first) {
+        return None;
+    }
+    let len = lines[start..].iter().take_while(|line| is_line_comment(line)).count();
+    Some(start + len)
+}
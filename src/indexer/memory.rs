@@ -0,0 +1,71 @@
+use super::{Blocklist, PathAliases};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tantivy::schema::Schema;
+use tantivy::Index;
+
+// Storage path for an index kept in RAM for the life of the process.
+// `:memory:` alone names one shared index; `:memory:<name>` keeps several
+// apart, e.g. one per review session.
+pub const MEMORY_STORAGE: &str = ":memory:";
+
+// Indexes share their RAM directory between clones, so handing out clones
+// lets indexers and searchers opened by path see the same documents
+static INDEXES: Mutex<BTreeMap<String, Index>> = Mutex::new(BTreeMap::new());
+// What a disk index keeps in blocklist.json
+static BLOCKLISTS: Mutex<BTreeMap<String, Blocklist>> = Mutex::new(BTreeMap::new());
+// What a disk index keeps in aliases.json
+static ALIASES: Mutex<BTreeMap<String, PathAliases>> = Mutex::new(BTreeMap::new());
+// What a disk index keeps in host-meta.json
+static HOST_META: Mutex<BTreeMap<String, BTreeMap<String, String>>> = Mutex::new(BTreeMap::new());
+
+pub fn is_memory_storage(storage_path: &str) -> bool {
+    storage_path.starts_with(MEMORY_STORAGE)
+}
+
+// The index at `storage_path`, created with `schema` if there is none yet
+pub(super) fn open_or_create(storage_path: &str, schema: impl FnOnce() -> Schema) -> Index {
+    let mut indexes = INDEXES.lock().unwrap_or_else(|e| e.into_inner());
+    indexes
+        .entry(storage_path.to_string())
+        .or_insert_with(|| Index::create_in_ram(schema()))
+        .clone()
+}
+
+pub fn open(storage_path: &str) -> Result<Index, Box<dyn std::error::Error>> {
+    let indexes = INDEXES.lock().unwrap_or_else(|e| e.into_inner());
+    let index = indexes.get(storage_path).ok_or_else(|| format!("No in-memory index at {}; index into it first", storage_path))?;
+    Ok(index.clone())
+}
+
+pub(super) fn blocklist(storage_path: &str) -> Blocklist {
+    BLOCKLISTS.lock().unwrap_or_else(|e| e.into_inner()).get(storage_path).cloned().unwrap_or_default()
+}
+
+pub(super) fn set_blocklist(storage_path: &str, blocklist: &Blocklist) {
+    BLOCKLISTS.lock().unwrap_or_else(|e| e.into_inner()).insert(storage_path.to_string(), blocklist.clone());
+}
+
+pub(super) fn aliases(storage_path: &str) -> PathAliases {
+    ALIASES.lock().unwrap_or_else(|e| e.into_inner()).get(storage_path).cloned().unwrap_or_default()
+}
+
+pub(super) fn set_aliases(storage_path: &str, aliases: &PathAliases) {
+    ALIASES.lock().unwrap_or_else(|e| e.into_inner()).insert(storage_path.to_string(), aliases.clone());
+}
+
+pub(super) fn host_meta(storage_path: &str) -> BTreeMap<String, String> {
+    HOST_META.lock().unwrap_or_else(|e| e.into_inner()).get(storage_path).cloned().unwrap_or_default()
+}
+
+pub(super) fn set_host_meta(storage_path: &str, meta: BTreeMap<String, String>) {
+    HOST_META.lock().unwrap_or_else(|e| e.into_inner()).insert(storage_path.to_string(), meta);
+}
+
+// Frees the index once its last searcher is gone; false if there was none
+pub fn drop_index(storage_path: &str) -> bool {
+    BLOCKLISTS.lock().unwrap_or_else(|e| e.into_inner()).remove(storage_path);
+    ALIASES.lock().unwrap_or_else(|e| e.into_inner()).remove(storage_path);
+    HOST_META.lock().unwrap_or_else(|e| e.into_inner()).remove(storage_path);
+    INDEXES.lock().unwrap_or_else(|e| e.into_inner()).remove(storage_path).is_some()
+}
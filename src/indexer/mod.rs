@@ -1,17 +1,163 @@
 use neon::prelude::*;
+use neon::types::buffer::TypedArray;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use tantivy::directory::MmapDirectory;
+use tantivy::indexer::NoMergePolicy;
 use tantivy::schema::*;
-use tantivy::{doc, Index, IndexWriter, ReloadPolicy};
+use tantivy::{doc, Index, IndexSettings, IndexWriter};
+use shards::ShardManifest;
 use walkdir::WalkDir;
+use boilerplate::Boilerplate;
+use file_content::read_file;
+use clock::modified_time;
+use crate::quantize::QuantizedVector;
+
+pub mod aliases;
+pub mod analyzers;
+pub mod answerability;
+pub mod blocklist;
+pub mod boilerplate;
+pub mod calibration;
+pub mod cjk;
+pub mod clock;
+pub mod comments;
+pub mod context;
+pub mod coverage;
+pub mod diff;
+pub mod eval;
+pub mod export;
+pub mod fallback;
+mod file_content;
+pub mod hashing;
+pub mod host_meta;
+pub mod hybrid;
+pub mod identifiers;
+pub mod ignore;
+pub mod inspect;
+pub mod language;
+pub mod license;
+pub mod memory;
+#[cfg(feature = "late-interaction")]
+pub mod late_interaction;
+pub mod presets;
+pub mod preview;
+mod priority;
+pub mod projection;
+pub mod provenance;
+mod read_ahead;
+pub mod refresh;
+pub mod review;
+pub mod search;
+pub mod shards;
+pub mod sparse;
+pub mod stats;
+pub mod status;
+pub mod terms;
+pub mod test_code;
+pub mod tune;
+pub mod vectors;
+
+pub use aliases::PathAliases;
+pub use answerability::{assess_answerability, Answerability};
+pub use blocklist::Blocklist;
+pub use calibration::Calibration;
+pub use clock::{set_clock, Clock, FixedClock, SystemClock};
+pub use context::{assemble_context, AssembledContext, ContextChunk};
+pub use coverage::{coverage_report, Coverage, CoverageReport, DirectoryCoverage, ExtensionCoverage, Uncovered};
+pub use diff::{diff_snapshots, FileDelta, SnapshotDiff};
+pub use export::{export_chunks, export_training_pairs, TrainingExport, TrainingTriplet};
+pub use eval::{load_eval_set, EvalCase};
+pub use fallback::{search_with_fallback, FallbackLadder, FallbackRung, FallbackSearch, FallbackSearcher};
+pub use hashing::ContentHash;
+pub use host_meta::{index_meta, set_index_meta};
+pub use inspect::{chunk_vectors, inspect_chunk, ChunkInspection, SegmentCopy, VectorSummary};
+pub use memory::{is_memory_storage, MEMORY_STORAGE};
+pub use hybrid::{hybrid_search, hybrid_search_model, hybrid_search_weighted, FusionWeights, HybridSearch};
+#[cfg(feature = "late-interaction")]
+pub use late_interaction::{backfill_token_vectors, late_interaction_search, TokenVectorStore};
+pub use projection::{project_chunks, ProjectedChunk, Projection, ProjectionMethod};
+pub use preview::{preview_chunks, ChunkPreview, FilePreview};
+pub use provenance::{parse_chunk_id, ChunkAudit, Provenance, CHUNKER_VERSION};
+pub use refresh::{search_with_refresh, RefreshedSearch};
+pub use review::{diff_hunks, Hunk, ReviewAnswer, ReviewHit, ReviewSession};
+pub use search::{locate_in_file, ContextRagSearcher, IndexedFile, IndexedFileState, LineSpan, Prefer, SearchHit, SearchOptions};
+pub use stats::{index_stats, IndexStats};
+pub use status::{index_status, IndexStatus};
+pub use terms::{term_report, FieldTerms, TermFrequency, TermReport};
+pub use tune::{tune_fusion, FusionTuning};
+pub use vectors::{backfill_vectors, reembed_vectors, AnnMode, BackfillResult, FailedChunks, VectorScores, VectorStore};
+
+const EARLY_COMMIT_FILES: usize = 200;
+const WRITER_BUDGET: usize = 50_000_000;
+// Tantivy's floor for a single writer thread
+const MIN_SHARD_WRITER_BUDGET: usize = 15_000_000;
+// Preprocessing switches, kept as marker files next to the index so
+// single-file refreshes treat files the way the last run did
+const STRIP_LICENSE_FLAG: &str = "strip-license-headers";
+const EXTRACT_COMMENTS_FLAG: &str = "extract-comments";
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct IndexConfig {
     pub include: Vec<String>,
     pub exclude: Vec<String>,
+    // A directory, or ":memory:" for an index that lives in RAM until the
+    // process exits
+    #[serde(alias = "storage")]
     pub storage_path: String,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub code_model: Option<String>,
+    // Content tokenizer: "default", "cjk" or "jieba"; fixed when the index
+    // is first created
+    #[serde(default)]
+    pub tokenizer: Option<String>,
+    // Sorted walk, zeroed timestamps and no segment merges, so repeated runs
+    // over the same tree export identical bytes
+    #[serde(default)]
+    pub deterministic: bool,
+    // Strip lines found in at least this fraction of indexed files
+    #[serde(default)]
+    pub boilerplate_threshold: Option<f32>,
+    // Drop leading license comments before chunking
+    #[serde(default)]
+    pub strip_license_headers: bool,
+    // Fill the `comments` field from code comments and docstrings
+    #[serde(default)]
+    pub extract_comments: bool,
+    // Split the index into this many tantivy indexes by path hash; fixed
+    // when the index is first created
+    #[serde(default)]
+    pub shards: Option<usize>,
+    // Bytes a hybrid search may hold vectors in; larger stores are scored
+    // straight off disk
+    #[serde(default)]
+    pub max_ann_memory: Option<u64>,
+    // File and chunk hash; unset keeps what the index was built with
+    #[serde(default)]
+    pub content_hash: Option<ContentHash>,
+    // Paths and terms searches of this index must never return
+    #[serde(default)]
+    pub blocklist: Blocklist,
+    // Files bigger than this many bytes are left out of the index
+    #[serde(default)]
+    pub max_file_size: Option<u64>,
+    // Directories indexed under another path, e.g. vendored packages under
+    // the workspace directory they come from
+    #[serde(default)]
+    pub aliases: PathAliases,
+    // Directory names the walk never enters, wherever they are
+    #[serde(default)]
+    pub prune_dirs: Vec<String>,
+    // Files more than this many levels below the project root are left out
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    // Where the walk starts and stored paths are relative to; unset is the
+    // working directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<PathBuf>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -28,83 +174,326 @@ pub struct IndexResult {
     pub indexed_files: usize,
     pub total_chunks: usize,
     pub processing_time_ms: u128,
+    // Distinct boilerplate lines stripped from every file that had them
+    #[serde(default)]
+    pub boilerplate_lines: usize,
+    // Files left out whole by a `context-rag: ignore` marker; not counted as indexed
+    #[serde(default)]
+    pub ignored_files: usize,
+    // Files the run couldn't read or index (locked, link loops, permissions,
+    // ...); the rest of the tree is indexed without them, so a run with
+    // entries here is a partial success
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub skipped: Vec<SkippedFile>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SkippedFile {
+    pub path: String,
+    pub reason: String,
+}
+
+impl SkippedFile {
+    fn new(path: &Path, reason: impl std::fmt::Display) -> Self {
+        SkippedFile { path: stored_path(path).unwrap_or_else(|| path.to_string_lossy().into_owned()), reason: reason.to_string() }
+    }
+
+    // A walk error past the excludes; one inside .git/, target/ and the
+    // like would only be noise
+    fn from_walk(error: walkdir::Error, config: &IndexConfig) -> Option<Self> {
+        let path = walked_path(error.path()?, config);
+        let path = path.as_ref();
+        if coverage::excluded_directory(path, config) || skip_reason(path, config) == Some(Uncovered::Excluded) {
+            return None;
+        }
+        let reason = match (error.loop_ancestor(), error.io_error()) {
+            (Some(ancestor), _) => format!("link loop back to {}", ancestor.display()),
+            (None, Some(io)) => io.to_string(),
+            (None, None) => error.to_string(),
+        };
+        Some(SkippedFile::new(path, reason))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexProgress {
+    pub current_file: String,
+    pub indexed_files: usize,
+    pub total_chunks: usize,
 }
 
 pub struct ContextRagIndexer {
     schema: Schema,
-    index: Index,
-    writer: IndexWriter,
+    // One per shard, in routing order
+    indexes: Vec<Index>,
+    writers: Vec<IndexWriter>,
+    provenance: Provenance,
+    boilerplate: Boilerplate,
+    strip_license_headers: bool,
+    extract_comments: bool,
+    content_hash: ContentHash,
+    blocklist: Blocklist,
+    aliases: PathAliases,
+    // The directory stored paths are relative to, for re-reading files
+    root: Option<PathBuf>,
 }
 
 impl ContextRagIndexer {
     pub fn new(storage_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let mut schema_builder = Schema::builder();
-        
-        schema_builder.add_text_field("file_path", TEXT | STORED);
-        schema_builder.add_text_field("content", TEXT);
-        schema_builder.add_u64_field("chunk_index", INDEXED | STORED);
-        schema_builder.add_text_field("file_hash", STRING | STORED);
-        schema_builder.add_i64_field("modified_time", INDEXED | STORED);
-        
-        let schema = schema_builder.build();
-        
+        Self::open(storage_path, None)
+    }
+
+    pub fn for_config(config: &IndexConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::open_sharded(&config.storage_path, Some(config.tokenizer.as_deref().unwrap_or("default")), config.shards)
+    }
+
+    pub fn open(storage_path: &str, tokenizer: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::open_sharded(storage_path, tokenizer, None)
+    }
+
+    // Opens an existing index as it was built, or creates one whose content
+    // uses `tokenizer`, split into `shards` indexes. Asking for a different
+    // tokenizer or shard count than an existing index was built with is an
+    // error, since its terms or file routing wouldn't match.
+    pub fn open_sharded(storage_path: &str, tokenizer: Option<&str>, shards: Option<usize>) -> Result<Self, Box<dyn std::error::Error>> {
+        let content_analyzer = analyzers::content_analyzer(tokenizer)?;
+        if memory::is_memory_storage(storage_path) {
+            if shards.is_some_and(|count| count > 1) {
+                return Err(format!("In-memory index {} can't be sharded", storage_path).into());
+            }
+            let index = memory::open_or_create(storage_path, || build_schema(content_analyzer));
+            check_tokenizer(storage_path, &index, tokenizer, content_analyzer)?;
+            let mut indexer = Self::from_memory_index(index)?;
+            indexer.aliases = PathAliases::load(storage_path)?;
+            return Ok(indexer);
+        }
+
+        // Read before the index below is created, which would make a new
+        // index look like one from before BLAKE3
+        let content_hash = ContentHash::load(storage_path);
         let index_path = Path::new(storage_path);
         fs::create_dir_all(index_path)?;
-        
-        let index = Index::create_in_dir(index_path, schema.clone())?;
-        let writer = index.writer(50_000_000)?; // 50MB buffer
-        
+        let existing = match ShardManifest::load(storage_path)? {
+            Some(manifest) => Some(manifest.count),
+            None => Index::exists(&MmapDirectory::open(index_path)?)?.then_some(1),
+        };
+        let count = match (existing, shards) {
+            (Some(existing), Some(requested)) if existing != requested.max(1) => {
+                return Err(format!(
+                    "Index at {} has {} shard(s) but the config asks for {}; remove it to rebuild",
+                    storage_path, existing, requested
+                )
+                .into());
+            }
+            (Some(existing), _) => existing,
+            (None, requested) => requested.unwrap_or(1).max(1),
+        };
+        if existing.is_none() {
+            content_hash.save(storage_path)?;
+            if count > 1 {
+                ShardManifest { count }.save(storage_path)?;
+            }
+        }
+
+        let mut indexes = Vec::with_capacity(count);
+        for dir in shards::shard_dirs(storage_path, count) {
+            fs::create_dir_all(&dir)?;
+            let directory = MmapDirectory::open(&dir)?;
+            let index = if Index::exists(&directory)? {
+                let index = Index::open(directory)?;
+                check_tokenizer(storage_path, &index, tokenizer, content_analyzer)?;
+                index
+            } else {
+                Index::create(directory, build_schema(content_analyzer), IndexSettings::default())?
+            };
+            analyzers::register_tokenizers(&index)?;
+            indexes.push(index);
+        }
+
+        // Shards write in parallel with one thread each, so the budget is
+        // split between them instead of between threads
+        let writers = if count == 1 {
+            vec![indexes[0].writer(WRITER_BUDGET)?]
+        } else {
+            let budget = (WRITER_BUDGET / count).max(MIN_SHARD_WRITER_BUDGET);
+            indexes
+                .iter()
+                .map(|index| index.writer_with_num_threads(1, budget))
+                .collect::<tantivy::Result<_>>()?
+        };
+
+        Ok(ContextRagIndexer {
+            schema: indexes[0].schema(),
+            indexes,
+            writers,
+            provenance: Provenance::for_run(None),
+            boilerplate: Boilerplate::load(storage_path)?,
+            strip_license_headers: load_flag(storage_path, STRIP_LICENSE_FLAG),
+            extract_comments: load_flag(storage_path, EXTRACT_COMMENTS_FLAG),
+            content_hash,
+            blocklist: Blocklist::load(storage_path)?,
+            aliases: PathAliases::load(storage_path)?,
+            root: None,
+        })
+    }
+
+    // An index that lives only in RAM; nothing is read from or written to
+    // disk, and it is gone once dropped. Use a `:memory:` storage path
+    // instead to reach it by path while the process lives.
+    pub fn in_memory(tokenizer: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_memory_index(Index::create_in_ram(build_schema(analyzers::content_analyzer(tokenizer)?)))
+    }
+
+    fn from_memory_index(index: Index) -> Result<Self, Box<dyn std::error::Error>> {
+        analyzers::register_tokenizers(&index)?;
+        let writer = index.writer_with_num_threads(1, MIN_SHARD_WRITER_BUDGET)?;
+
         Ok(ContextRagIndexer {
-            schema,
-            index,
-            writer,
+            schema: index.schema(),
+            indexes: vec![index],
+            writers: vec![writer],
+            provenance: Provenance::for_run(None),
+            boilerplate: Boilerplate::default(),
+            strip_license_headers: false,
+            extract_comments: false,
+            content_hash: ContentHash::default(),
+            blocklist: Blocklist::default(),
+            aliases: PathAliases::default(),
+            root: None,
         })
     }
 
     pub fn index_directory(&mut self, config: &IndexConfig) -> Result<IndexResult, Box<dyn std::error::Error>> {
+        self.index_directory_with_progress(config, |_| {})
+    }
+
+    // Same as `index_directory`, reporting after every indexed file so callers
+    // (e.g. the gRPC server) can stream progress for long runs.
+    pub fn index_directory_with_progress<F>(&mut self, config: &IndexConfig, mut on_progress: F) -> Result<IndexResult, Box<dyn std::error::Error>>
+    where
+        F: FnMut(&IndexProgress),
+    {
         let start_time = std::time::Instant::now();
         let mut indexed_files = 0;
+        let mut ignored_files = 0;
         let mut total_chunks = 0;
 
-        let file_path_field = self.schema.get_field("file_path").unwrap();
-        let content_field = self.schema.get_field("content").unwrap();
-        let chunk_index_field = self.schema.get_field("chunk_index").unwrap();
-        let file_hash_field = self.schema.get_field("file_hash").unwrap();
-        let modified_time_field = self.schema.get_field("modified_time").unwrap();
+        self.provenance = Provenance::for_run(Some(config));
+        let mut walker = WalkDir::new(config.root.as_deref().unwrap_or(Path::new(".")));
+        if config.deterministic {
+            for writer in &self.writers {
+                writer.set_merge_policy(Box::new(NoMergePolicy));
+            }
+            walker = walker.sort_by_file_name();
+        }
 
-        for entry in WalkDir::new(".").into_iter().filter_map(|e| e.ok()) {
-            let path = entry.path();
-            
-            if !self.should_include_file(path, config) {
-                continue;
+        let walk = crate::profile::span("walk");
+        let mut skipped = Vec::new();
+        let mut entries: Vec<_> = pruned_walk(walker, config)
+            .filter_map(|entry| entry.map_err(|e| skipped.extend(SkippedFile::from_walk(e, config))).ok())
+            .filter(|entry| !entry.file_type().is_dir() && indexed_path(&walked_path(entry.path(), config), config).is_some())
+            .collect();
+        if !config.deterministic {
+            priority::prioritize(&mut entries);
+        }
+        drop(walk);
+
+        // Document frequencies need every file up front, so pruning reads
+        // the tree twice
+        self.boilerplate = match config.boilerplate_threshold {
+            Some(threshold) => {
+                let _span = crate::profile::span("boilerplate");
+                let contents: Vec<_> = entries.iter().filter_map(|e| read_file(e.path()).ok()).collect();
+                Boilerplate::detect(contents.iter().filter_map(|content| content.text().ok()), threshold)
+            }
+            None => Boilerplate::default(),
+        };
+        let on_disk = !memory::is_memory_storage(&config.storage_path);
+        if on_disk {
+            self.boilerplate.save(&config.storage_path)?;
+        }
+        self.apply_flags(config)?;
+        // Only a full rebuild can switch hashes without mixing two in one index
+        if let Some(content_hash) = config.content_hash {
+            self.content_hash = content_hash;
+            if on_disk {
+                content_hash.save(&config.storage_path)?;
             }
+        }
+
+        // Readers of an index that already held files keep seeing them until
+        // the rebuild commits in one go; only a first build has nothing to
+        // lose by committing part of the way through
+        let mut started_empty = true;
+        for index in &self.indexes {
+            started_empty &= index.searchable_segment_metas()?.iter().all(|meta| meta.num_docs() == 0);
+        }
+
+        // A directory run always rebuilds the index from scratch
+        for writer in &self.writers {
+            writer.delete_all_documents()?;
+        }
 
-            if let Ok(content) = fs::read_to_string(path) {
-                let file_hash = self.calculate_file_hash(&content);
-                let modified_time = entry.metadata()?.modified()?
-                    .duration_since(std::time::UNIX_EPOCH)?
-                    .as_secs() as i64;
-
-                let chunks = self.chunk_content(&content);
-                
-                for (chunk_index, chunk) in chunks.iter().enumerate() {
-                    let doc = doc!(
-                        file_path_field => path.to_string_lossy().to_string(),
-                        content_field => chunk.clone(),
-                        chunk_index_field => chunk_index as u64,
-                        file_hash_field => file_hash.clone(),
-                        modified_time_field => modified_time
-                    );
-                    
-                    self.writer.add_document(doc)?;
-                    total_chunks += 1;
+        let contents = read_ahead::read_ahead(entries.iter().map(|entry| entry.path().to_path_buf()).collect());
+        for (entry, content) in entries.iter().zip(contents) {
+            let path = walked_path(entry.path(), config);
+            let path = path.as_ref();
+            let Some(file_path) = indexed_path(path, config) else {
+                continue;
+            };
+
+            let content = match content {
+                Ok(content) => content,
+                Err(e) => {
+                    skipped.extend(unreadable(path, e));
+                    continue;
                 }
-                
-                indexed_files += 1;
+            };
+            let Ok(content) = content.text() else {
+                skipped.push(not_text(path));
+                continue;
+            };
+            let _span = crate::profile::span("file");
+            let modified_time = if config.deterministic {
+                0
+            } else {
+                match modified_time(entry.path()) {
+                    Ok(modified_time) => modified_time,
+                    Err(e) => {
+                        skipped.push(SkippedFile::new(path, e));
+                        continue;
+                    }
+                }
+            };
+
+            let chunks = match self.add_file(&file_path, content, modified_time) {
+                Ok(chunks) => chunks,
+                Err(e) => {
+                    skipped.push(SkippedFile::new(path, e));
+                    continue;
+                }
+            };
+            if chunks == 0 {
+                ignored_files += 1;
+                continue;
+            }
+
+            total_chunks += chunks;
+            indexed_files += 1;
+            on_progress(&IndexProgress {
+                current_file: file_path,
+                indexed_files,
+                total_chunks,
+            });
+
+            // Make the high-priority files searchable while the long
+            // tail is still being indexed
+            if started_empty && indexed_files == EARLY_COMMIT_FILES && entries.len() > EARLY_COMMIT_FILES {
+                self.commit()?;
             }
         }
 
-        self.writer.commit()?;
+        self.commit()?;
         
         let processing_time = start_time.elapsed().as_millis();
         
@@ -112,76 +501,504 @@ impl ContextRagIndexer {
             indexed_files,
             total_chunks,
             processing_time_ms: processing_time,
+            boilerplate_lines: self.boilerplate.len(),
+            ignored_files,
+            skipped,
         })
     }
 
-    fn should_include_file(&self, path: &Path, config: &IndexConfig) -> bool {
-        let path_str = path.to_string_lossy();
-        
-        // Check exclusions first
-        for exclude_pattern in &config.exclude {
-            if path_str.contains(exclude_pattern) {
-                return false;
-            }
+    // Replaces every chunk of one file, or just drops them if the file is
+    // gone or can't be read. Changes become visible after `commit`.
+    pub fn reindex_file(&mut self, file_path: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        Ok(self.try_reindex_file(file_path)?.unwrap_or(0))
+    }
+
+    // The same, with why a file that's there couldn't be read
+    fn try_reindex_file(&mut self, file_path: &str) -> Result<Result<usize, SkippedFile>, Box<dyn std::error::Error>> {
+        let file_path = self.aliases.canonical(file_path).unwrap_or_else(|| file_path.to_string());
+        self.delete_file(&file_path)?;
+
+        let path = &self.aliases.source(self.root.as_deref(), &file_path);
+        let content = match read_file(path) {
+            Ok(content) => content,
+            Err(e) => return Ok(unreadable(path, e).map_or(Ok(0), Err)),
+        };
+        let Ok(content) = content.text() else {
+            return Ok(Err(not_text(path)));
+        };
+        match modified_time(path) {
+            Ok(modified_time) => Ok(self.add_file(&file_path, content, modified_time).map_err(|e| SkippedFile::new(path, e))),
+            Err(e) => Ok(Err(SkippedFile::new(path, e))),
         }
-        
-        // Check inclusions
-        for include_pattern in &config.include {
-            if include_pattern.ends_with('/') {
-                // Directory pattern
-                if path_str.starts_with(include_pattern) {
-                    return true;
+    }
+
+    fn apply_flags(&mut self, config: &IndexConfig) -> Result<(), Box<dyn std::error::Error>> {
+        self.strip_license_headers = config.strip_license_headers;
+        self.extract_comments = config.extract_comments;
+        self.blocklist = config.blocklist.clone();
+        self.blocklist.save(&config.storage_path)?;
+        self.aliases = config.aliases.clone();
+        self.aliases.save(&config.storage_path)?;
+        self.root = config.root.clone();
+        if memory::is_memory_storage(&config.storage_path) {
+            return Ok(());
+        }
+        save_flag(&config.storage_path, STRIP_LICENSE_FLAG, config.strip_license_headers)?;
+        save_flag(&config.storage_path, EXTRACT_COMMENTS_FLAG, config.extract_comments)?;
+        vectors::save_ann_budget(&config.storage_path, config.max_ann_memory)
+    }
+
+    pub fn delete_file(&mut self, file_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let path_key_field = self.schema.get_field("path_key")?;
+        let shard = shards::shard_for(file_path, self.writers.len());
+        self.writers[shard].delete_term(Term::from_field_text(path_key_field, file_path));
+        Ok(())
+    }
+
+    // Re-indexes only the files under `paths` that the config includes, and
+    // drops indexed files there that were deleted or are now excluded. The
+    // rest of the index is left untouched.
+    pub fn index_paths<F>(&mut self, config: &IndexConfig, paths: &[String], mut on_progress: F) -> Result<IndexResult, Box<dyn std::error::Error>>
+    where
+        F: FnMut(&IndexProgress),
+    {
+        let start_time = std::time::Instant::now();
+        let mut indexed_files = 0;
+        let mut ignored_files = 0;
+        let mut total_chunks = 0;
+        let mut skipped = Vec::new();
+
+        self.provenance = Provenance::for_run(Some(config));
+        self.apply_flags(config)?;
+        let indexed = self.searcher()?.file_states()?;
+
+        for requested in paths {
+            let root = relative_to_root(requested);
+
+            for file_path in indexed.keys().filter(|p| Path::new(p).starts_with(&root)) {
+                if !self.aliases.source(config.root.as_deref(), file_path).is_file() || !should_include_file(Path::new(file_path), config) {
+                    self.delete_file(file_path)?;
+                }
+            }
+
+            // A canonical directory may only exist under its alias
+            for entry in pruned_walk(WalkDir::new(self.aliases.source(config.root.as_deref(), &root)), config) {
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        skipped.extend(SkippedFile::from_walk(e, config));
+                        continue;
+                    }
+                };
+                if !entry.file_type().is_file() {
+                    continue;
                 }
-            } else if include_pattern.starts_with("*.") {
-                // Extension pattern
-                let ext = &include_pattern[2..];
-                if path.extension().map_or(false, |e| e == ext) {
-                    return true;
+                let Some(file_path) = indexed_path(&walked_path(entry.path(), config), config) else {
+                    continue;
+                };
+                let chunks = match self.try_reindex_file(&file_path)? {
+                    Ok(chunks) => chunks,
+                    Err(skip) => {
+                        skipped.push(skip);
+                        continue;
+                    }
+                };
+                if chunks == 0 {
+                    ignored_files += 1;
+                    continue;
                 }
-            } else if path_str.contains(include_pattern) {
-                // Filename pattern
-                return true;
+
+                total_chunks += chunks;
+                indexed_files += 1;
+                on_progress(&IndexProgress {
+                    current_file: file_path,
+                    indexed_files,
+                    total_chunks,
+                });
             }
         }
-        
-        false
+
+        self.commit()?;
+
+        Ok(IndexResult {
+            indexed_files,
+            total_chunks,
+            processing_time_ms: start_time.elapsed().as_millis(),
+            boilerplate_lines: self.boilerplate.len(),
+            ignored_files,
+            skipped,
+        })
     }
 
-    fn chunk_content(&self, content: &str) -> Vec<String> {
-        // Simple chunking strategy - split by paragraphs and limit size
-        const MAX_CHUNK_SIZE: usize = 1000;
-        let mut chunks = Vec::new();
-        let mut current_chunk = String::new();
-        
-        for line in content.lines() {
-            if current_chunk.len() + line.len() > MAX_CHUNK_SIZE && !current_chunk.is_empty() {
-                chunks.push(current_chunk.trim().to_string());
-                current_chunk = String::new();
-            }
-            
-            current_chunk.push_str(line);
-            current_chunk.push('\n');
+    // Shards commit in parallel, so a commit takes as long as the slowest
+    pub fn commit(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let _span = crate::profile::span("commit");
+        if let [writer] = self.writers.as_mut_slice() {
+            writer.commit()?;
+            return Ok(());
         }
-        
-        if !current_chunk.trim().is_empty() {
-            chunks.push(current_chunk.trim().to_string());
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = self.writers.iter_mut().map(|writer| scope.spawn(move || writer.commit())).collect();
+            handles
+                .into_iter()
+                .try_for_each(|handle| handle.join().expect("commit thread panicked").map(|_| ()))
+        })?;
+        Ok(())
+    }
+
+    // Indexes `content` as the file at `path` without reading anything from
+    // disk; searchable after the next commit
+    pub fn add_text(&mut self, path: &str, content: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        self.add_file(path, content, 0)
+    }
+
+    // `file_path` is the stored path, or an alias of one
+    fn add_file(&mut self, file_path: &str, content: &str, modified_time: i64) -> Result<usize, Box<dyn std::error::Error>> {
+        let file_path_field = self.schema.get_field("file_path")?;
+        let path_key_field = self.schema.get_field("path_key")?;
+        let content_field = self.schema.get_field("content")?;
+        let content_exact_field = self.schema.get_field("content_exact")?;
+        let content_cased_field = self.schema.get_field("content_cased")?;
+        let trigrams_field = self.schema.get_field("trigrams")?;
+        let sparse_field = self.schema.get_field("sparse")?;
+        let comments_field = self.schema.get_field("comments")?;
+        let chunk_index_field = self.schema.get_field("chunk_index")?;
+        let language_field = self.schema.get_field("language")?;
+        let file_hash_field = self.schema.get_field("file_hash")?;
+        let chunk_hash_field = self.schema.get_field("chunk_hash")?;
+        let modified_time_field = self.schema.get_field("modified_time")?;
+        let run_id_field = self.schema.get_field("run_id")?;
+        let config_hash_field = self.schema.get_field("config_hash")?;
+        let chunker_version_field = self.schema.get_field("chunker_version")?;
+        let model_field = self.schema.get_field("model")?;
+        let indexed_at_field = self.schema.get_field("indexed_at")?;
+        let license_stripped_field = self.schema.get_field("license_stripped")?;
+        let is_test_field = self.schema.get_field("is_test")?;
+
+        let file_path = self.aliases.canonical(file_path).unwrap_or_else(|| file_path.to_string());
+        let file_hash = self.content_hash.hash(content.as_bytes());
+        let language = language::detect_language(Path::new(&file_path));
+        let Some((chunks, license_stripped)) = prepare_chunks(content, self.strip_license_headers, &self.boilerplate) else {
+            return Ok(0);
+        };
+        let shard = shards::shard_for(&file_path, self.writers.len());
+
+        let _span = crate::profile::span("add_documents");
+        for (chunk_index, chunk) in chunks.iter().enumerate() {
+            let doc = doc!(
+                file_path_field => file_path.clone(),
+                path_key_field => file_path.clone(),
+                content_field => chunk.clone(),
+                content_exact_field => chunk.clone(),
+                content_cased_field => chunk.clone(),
+                trigrams_field => file_path.clone(),
+                trigrams_field => chunk.clone(),
+                sparse_field => sparse::field_text(&sparse::encode(chunk)),
+                comments_field => if self.extract_comments { comments::extract_comments(chunk, language) } else { String::new() },
+                chunk_index_field => chunk_index as u64,
+                language_field => language,
+                file_hash_field => file_hash.clone(),
+                chunk_hash_field => self.content_hash.hash(chunk.as_bytes()),
+                modified_time_field => modified_time,
+                run_id_field => self.provenance.run_id.clone(),
+                config_hash_field => self.provenance.config_hash.clone(),
+                chunker_version_field => self.provenance.chunker_version.clone(),
+                model_field => self.provenance.model.clone(),
+                indexed_at_field => self.provenance.indexed_at,
+                license_stripped_field => license_stripped,
+                is_test_field => test_code::is_test_chunk(Path::new(&file_path), chunk, language)
+            );
+
+            self.writers[shard].add_document(doc)?;
         }
-        
-        if chunks.is_empty() {
-            chunks.push(content.to_string());
+
+        Ok(chunks.len())
+    }
+
+    pub fn searcher(&self) -> Result<ContextRagSearcher, Box<dyn std::error::Error>> {
+        ContextRagSearcher::from_indexes(self.indexes.clone())?.with_blocklist(&self.blocklist)
+    }
+}
+
+// A file's chunks as indexing makes them, and whether a license header was
+// dropped first; None when a marker leaves the whole file out
+pub(super) fn prepare_chunks(content: &str, strip_license_headers: bool, boilerplate: &Boilerplate) -> Option<(Vec<String>, bool)> {
+    if ignore::ignores_file(content) {
+        return None;
+    }
+    let unignored = ignore::strip_ignored_regions(content);
+    let content = unignored.as_ref();
+    let license_stripped = if strip_license_headers { license::strip_license_header(content) } else { None };
+    let content = license_stripped.as_deref().unwrap_or(content);
+    let _span = crate::profile::span("chunk");
+    Some((chunk_content(&boilerplate.strip(content)), license_stripped.is_some()))
+}
+
+pub fn should_include_file(path: &Path, config: &IndexConfig) -> bool {
+    skip_reason(path, config).is_none()
+}
+
+// The stored path a walked file is indexed under, None if it isn't. Files
+// under an alias go by their canonical path, which the patterns are matched
+// against, and are left to the walk when a canonical copy exists.
+pub fn indexed_path(path: &Path, config: &IndexConfig) -> Option<String> {
+    let stored = stored_path(path)?;
+    match config.aliases.canonical(&stored) {
+        Some(canonical) if on_disk(Path::new(&canonical), config).exists() => None,
+        Some(canonical) => should_include_file(Path::new(&canonical), config).then_some(canonical),
+        None => should_include_file(path, config).then_some(stored),
+    }
+}
+
+// The walk without the directories the config prunes or finds too deep,
+// which are skipped rather than descended into
+pub fn pruned_walk(walker: WalkDir, config: &IndexConfig) -> walkdir::FilterEntry<walkdir::IntoIter, impl FnMut(&walkdir::DirEntry) -> bool + '_> {
+    walker.into_iter().filter_entry(|entry| !entry.file_type().is_dir() || !prunes_directory(&walked_path(entry.path(), config), config))
+}
+
+// A path from a walk of the config's root as it would be walked from the
+// working directory: "./" and the path below the root
+pub(super) fn walked_path<'a>(path: &'a Path, config: &IndexConfig) -> std::borrow::Cow<'a, Path> {
+    match config.root.as_deref().and_then(|root| path.strip_prefix(root).ok()) {
+        Some(relative) => std::borrow::Cow::Owned(Path::new(".").join(relative)),
+        None => std::borrow::Cow::Borrowed(path),
+    }
+}
+
+// The inverse of `walked_path`: where a "./" path lies when the config
+// indexes a root other than the working directory
+fn on_disk<'a>(path: &'a Path, config: &IndexConfig) -> std::borrow::Cow<'a, Path> {
+    match config.root.as_deref() {
+        Some(root) => std::borrow::Cow::Owned(root.join(path)),
+        None => std::borrow::Cow::Borrowed(path),
+    }
+}
+
+// Whether nothing under the directory at `path` can be indexed for its
+// place in the tree. Directories leading to an alias are always entered.
+pub fn prunes_directory(path: &Path, config: &IndexConfig) -> bool {
+    let Some(stored) = stored_path(path) else {
+        return false;
+    };
+    if config.aliases.leads_to_alias(&stored) {
+        return false;
+    }
+    let stored = config.aliases.canonical(&stored).unwrap_or(stored);
+    directory_skip_reason(&stored, config).is_some()
+}
+
+fn directory_skip_reason(directory: &str, config: &IndexConfig) -> Option<Uncovered> {
+    let components: Vec<_> = directory.split('/').filter(|component| !component.is_empty() && *component != ".").collect();
+    if components.iter().any(|component| config.prune_dirs.iter().any(|name| name.trim_matches('/') == *component)) {
+        Some(Uncovered::Pruned)
+    } else if config.max_depth.is_some_and(|max_depth| components.len() >= max_depth) {
+        Some(Uncovered::TooDeep)
+    } else {
+        None
+    }
+}
+
+// A read failure worth reporting: anything but a file deleted since the walk
+fn unreadable(path: &Path, error: std::io::Error) -> Option<SkippedFile> {
+    match error.kind() {
+        std::io::ErrorKind::NotFound => None,
+        _ => Some(SkippedFile::new(path, error)),
+    }
+}
+
+// Matched by the config but not text the index can hold
+fn not_text(path: &Path) -> SkippedFile {
+    SkippedFile::new(path, "not UTF-8 text")
+}
+
+// Why the config leaves `path` out of the index, if it does
+pub fn skip_reason(path: &Path, config: &IndexConfig) -> Option<Uncovered> {
+    let Some(stored) = stored_path(path) else {
+        return Some(Uncovered::NonUtf8Path);
+    };
+    // Patterns are relative to the project root, without the walker's "./"
+    let path_str = stored.strip_prefix("./").unwrap_or(&stored);
+
+    if let Some(reason) = directory_skip_reason(path_str.rsplit_once('/').map_or("", |(directory, _)| directory), config) {
+        return Some(reason);
+    }
+    
+    // Check exclusions first
+    for exclude_pattern in &config.exclude {
+        let exclude_pattern = forward_slashes(exclude_pattern);
+        let excluded = match exclude_pattern.strip_prefix("*.") {
+            // Extension pattern, as for includes
+            Some(ext) => path.extension().is_some_and(|e| e == ext),
+            None => path_str.contains(exclude_pattern.as_ref()),
+        };
+        if excluded {
+            return Some(Uncovered::Excluded);
         }
-        
-        chunks
     }
+    
+    // Check inclusions
+    let included = config.include.iter().any(|include_pattern| {
+        let include_pattern = forward_slashes(include_pattern);
+        let include_pattern = include_pattern.as_ref();
+        if include_pattern.ends_with('/') {
+            // Directory pattern
+            path_str.starts_with(include_pattern)
+        } else if let Some(ext) = include_pattern.strip_prefix("*.") {
+            // Extension pattern
+            path.extension().is_some_and(|e| e == ext)
+        } else {
+            // Filename pattern
+            path_str.contains(include_pattern)
+        }
+    });
+    if !included {
+        return Some(Uncovered::NotIncluded);
+    }
+
+    // Only stat files that would otherwise be indexed
+    match config.max_file_size {
+        Some(max) if fs::metadata(on_disk(path, config)).is_ok_and(|metadata| metadata.len() > max) => Some(Uncovered::TooLarge),
+        _ => None,
+    }
+}
+
+fn build_schema(content_analyzer: &str) -> Schema {
+    let mut schema_builder = Schema::builder();
+    
+    schema_builder.add_text_field("file_path", TEXT | STORED);
+    // Untokenized copy of the path for exact lookups and per-file deletes
+    schema_builder.add_text_field("path_key", STRING);
+    schema_builder.add_text_field("content", analyzed(content_analyzer, IndexRecordOption::WithFreqsAndPositions) | STORED);
+    // Parallel copies of the content for exact and case-sensitive queries
+    schema_builder.add_text_field("content_exact", analyzed(analyzers::EXACT, IndexRecordOption::WithFreqsAndPositions));
+    schema_builder.add_text_field("content_cased", analyzed(analyzers::CASED, IndexRecordOption::WithFreqsAndPositions));
+    // Lowercased trigrams of path and content for literal identifier lookups
+    schema_builder.add_text_field("trigrams", analyzed(analyzers::TRIGRAM, IndexRecordOption::Basic));
+    // Sparse term expansions, weighted by repetition
+    schema_builder.add_text_field("sparse", analyzed(analyzers::SPARSE, IndexRecordOption::WithFreqs));
+    // Comments and docstrings of code chunks, for natural-language queries
+    schema_builder.add_text_field("comments", analyzed(content_analyzer, IndexRecordOption::WithFreqsAndPositions));
+    schema_builder.add_u64_field("chunk_index", INDEXED | STORED);
+    // Detected from the extension; routes code chunks to a code embedding model
+    schema_builder.add_text_field("language", STRING | STORED);
+    schema_builder.add_text_field("file_hash", STRING | STORED);
+    // Keys the chunk's vector, so unchanged content keeps its embedding
+    schema_builder.add_text_field("chunk_hash", STRING | STORED);
+    schema_builder.add_i64_field("modified_time", INDEXED | STORED);
+    schema_builder.add_text_field("run_id", STRING | STORED);
+    schema_builder.add_text_field("config_hash", STRING | STORED);
+    schema_builder.add_text_field("chunker_version", STRING | STORED);
+    schema_builder.add_text_field("model", STRING | STORED);
+    schema_builder.add_i64_field("indexed_at", STORED);
+    schema_builder.add_bool_field("license_stripped", STORED);
+    schema_builder.add_bool_field("is_test", INDEXED | STORED);
+    
+    schema_builder.build()
+}
+
+fn load_flag(storage_path: &str, name: &str) -> bool {
+    Path::new(storage_path).join(name).exists()
+}
 
-    fn calculate_file_hash(&self, content: &str) -> String {
-        use sha2::{Digest, Sha256};
-        let mut hasher = Sha256::new();
-        hasher.update(content.as_bytes());
-        hex::encode(hasher.finalize())
+fn save_flag(storage_path: &str, name: &str, enabled: bool) -> std::io::Result<()> {
+    let path = Path::new(storage_path).join(name);
+    match (enabled, path.exists()) {
+        (true, false) => fs::write(path, b""),
+        (false, true) => fs::remove_file(path),
+        _ => Ok(()),
     }
 }
 
+fn check_tokenizer(storage_path: &str, index: &Index, tokenizer: Option<&str>, content_analyzer: &str) -> Result<(), String> {
+    let built_with = content_tokenizer(&index.schema());
+    if tokenizer.is_some() && built_with.as_deref() != Some(content_analyzer) {
+        return Err(format!(
+            "Index at {} was built with the {} analyzer but the config asks for {}; remove it to rebuild",
+            storage_path,
+            built_with.unwrap_or_default(),
+            content_analyzer
+        ));
+    }
+    Ok(())
+}
+
+fn content_tokenizer(schema: &Schema) -> Option<String> {
+    let field = schema.get_field("content").ok()?;
+    match schema.get_field_entry(field).field_type() {
+        FieldType::Str(options) => options.get_indexing_options().map(|o| o.tokenizer().to_string()),
+        _ => None,
+    }
+}
+
+fn analyzed(tokenizer: &str, record: IndexRecordOption) -> TextOptions {
+    TextOptions::default().set_indexing_options(
+        TextFieldIndexing::default()
+            .set_tokenizer(tokenizer)
+            .set_index_option(record),
+    )
+}
+
+// Stored paths are the walker's: relative to the project root, starting
+// with "./" and separated by forward slashes on every platform, so an index
+// and the patterns matched against it mean the same on Windows. None for a
+// path that isn't UTF-8, which a stored string can't give back.
+pub fn stored_path(path: &Path) -> Option<String> {
+    path.to_str().map(|path| forward_slashes(path).into_owned())
+}
+
+// A path given by a user or caller, with or without the "./", in stored form
+pub(super) fn relative_to_root(path: &str) -> String {
+    let path = forward_slashes(path);
+    if path == "." || path.starts_with("./") {
+        path.into_owned()
+    } else {
+        format!("./{}", path)
+    }
+}
+
+// Windows separators as forward slashes; elsewhere a backslash is an
+// ordinary file name character and is left alone
+pub(super) fn forward_slashes(path: &str) -> std::borrow::Cow<'_, str> {
+    match std::path::MAIN_SEPARATOR {
+        '/' => std::borrow::Cow::Borrowed(path),
+        separator => std::borrow::Cow::Owned(path.replace(separator, "/")),
+    }
+}
+
+pub fn chunk_content(content: &str) -> Vec<String> {
+    // Simple chunking strategy - split by paragraphs and limit size
+    const MAX_CHUNK_SIZE: usize = 1000;
+    let mut chunks = Vec::new();
+    let mut current_chunk = String::new();
+    
+    for line in content.lines() {
+        if current_chunk.len() + line.len() > MAX_CHUNK_SIZE && !current_chunk.is_empty() {
+            chunks.push(current_chunk.trim().to_string());
+            current_chunk = String::new();
+        }
+        
+        current_chunk.push_str(line);
+        current_chunk.push('\n');
+    }
+    
+    if !current_chunk.trim().is_empty() {
+        chunks.push(current_chunk.trim().to_string());
+    }
+    
+    if chunks.is_empty() {
+        chunks.push(content.to_string());
+    }
+    
+    chunks
+}
+
+pub fn calculate_file_hash(content: &str) -> String {
+    use sha2::{Digest, Sha256};
+    let mut hasher = Sha256::new();
+    hasher.update(content.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
 // Neon bindings for Node.js
 fn create_index(mut cx: FunctionContext) -> JsResult<JsString> {
     let storage_path = cx.argument::<JsString>(0)?.value(&mut cx);
@@ -196,12 +1013,14 @@ fn index_directory(mut cx: FunctionContext) -> JsResult<JsString> {
     let storage_path = cx.argument::<JsString>(0)?.value(&mut cx);
     let config_json = cx.argument::<JsString>(1)?.value(&mut cx);
     
-    let config: IndexConfig = match serde_json::from_str(&config_json) {
+    let mut config: IndexConfig = match serde_json::from_str(&config_json) {
         Ok(config) => config,
         Err(e) => return cx.throw_error(format!("Invalid config JSON: {}", e)),
     };
+    // The argument wins, so the flags and shards land in the index written
+    config.storage_path = storage_path;
     
-    match ContextRagIndexer::new(&storage_path) {
+    match ContextRagIndexer::for_config(&config) {
         Ok(mut indexer) => {
             match indexer.index_directory(&config) {
                 Ok(result) => {
@@ -215,9 +1034,213 @@ fn index_directory(mut cx: FunctionContext) -> JsResult<JsString> {
     }
 }
 
+// searchBatch(storagePath, queries, limit): a promise of each query's hits,
+// in query order, as JSON; the searches run on the Node worker pool
+fn search_batch(mut cx: FunctionContext) -> JsResult<JsPromise> {
+    let storage_path = cx.argument::<JsString>(0)?.value(&mut cx);
+    let queries = cx.argument::<JsArray>(1)?.to_vec(&mut cx)?;
+    let limit = cx.argument::<JsNumber>(2)?.value(&mut cx);
+    if !(limit >= 1.0 && limit.fract() == 0.0) {
+        return cx.throw_range_error(format!("limit must be a whole number of at least 1, got {}", limit));
+    }
+    let limit = limit as usize;
+
+    let mut query_strings = Vec::with_capacity(queries.len());
+    for query in queries {
+        let query = query.downcast_or_throw::<JsString, _>(&mut cx)?;
+        query_strings.push(query.value(&mut cx));
+    }
+
+    let promise = cx
+        .task(move || {
+            let searcher = ContextRagSearcher::open(&storage_path).map_err(|e| format!("Failed to open index: {}", e))?;
+            let results = searcher.search_batch(&query_strings, limit).map_err(|e| format!("Search failed: {}", e))?;
+            Ok::<_, String>(serde_json::to_string(&results).unwrap())
+        })
+        .promise(|mut cx, results| match results {
+            Ok(results_json) => Ok(cx.string(results_json)),
+            Err(e) => cx.throw_error(e),
+        });
+    Ok(promise)
+}
+
+// previewChunks(path, configJson): how the file would be chunked under the
+// config, as JSON; nothing is written to the index
+fn preview_file_chunks(mut cx: FunctionContext) -> JsResult<JsString> {
+    let path = cx.argument::<JsString>(0)?.value(&mut cx);
+    let config_json = cx.argument::<JsString>(1)?.value(&mut cx);
+
+    let config: IndexConfig = match serde_json::from_str(&config_json) {
+        Ok(config) => config,
+        Err(e) => return cx.throw_error(format!("Invalid config JSON: {}", e)),
+    };
+
+    match preview_chunks(&path, &config) {
+        Ok(preview) => Ok(cx.string(serde_json::to_string(&preview).unwrap())),
+        Err(e) => cx.throw_error(format!("Preview failed: {}", e)),
+    }
+}
+
+// getChunk(storagePath, "path#index"): the chunk's inspection as JSON, or
+// null when the index has no such chunk
+fn get_chunk(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let storage_path = cx.argument::<JsString>(0)?.value(&mut cx);
+    let id = cx.argument::<JsString>(1)?.value(&mut cx);
+
+    let Some((file_path, chunk_index)) = parse_chunk_id(&id) else {
+        return cx.throw_error(format!("Chunk id must look like <path>#<index>, got '{}'", id));
+    };
+    match inspect_chunk(&storage_path, file_path, chunk_index) {
+        Ok(Some(inspection)) => {
+            let inspection_json = serde_json::to_string(&inspection).unwrap();
+            Ok(cx.string(inspection_json).upcast())
+        }
+        Ok(None) => Ok(cx.null().upcast()),
+        Err(e) => cx.throw_error(format!("Inspect failed: {}", e)),
+    }
+}
+
+// getEmbedding(storagePath, "path#index"): the chunk's stored vector as a
+// Float32Array, or null when it has none yet
+fn get_embedding(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let storage_path = cx.argument::<JsString>(0)?.value(&mut cx);
+    let id = cx.argument::<JsString>(1)?.value(&mut cx);
+
+    match chunk_vectors(&storage_path, &[id]) {
+        Ok(mut vectors) => match vectors.pop().flatten() {
+            Some(vector) => Ok(float32_array(&mut cx, &vector)?.upcast()),
+            None => Ok(cx.null().upcast()),
+        },
+        Err(e) => cx.throw_error(format!("Failed to read embedding: {}", e)),
+    }
+}
+
+// getEmbeddings(storagePath, ids): one Float32Array or null per id, in order
+fn get_embeddings(mut cx: FunctionContext) -> JsResult<JsArray> {
+    let storage_path = cx.argument::<JsString>(0)?.value(&mut cx);
+    let ids = cx.argument::<JsArray>(1)?.to_vec(&mut cx)?;
+
+    let mut id_strings = Vec::with_capacity(ids.len());
+    for id in ids {
+        let id = id.downcast_or_throw::<JsString, _>(&mut cx)?;
+        id_strings.push(id.value(&mut cx));
+    }
+
+    let vectors = match chunk_vectors(&storage_path, &id_strings) {
+        Ok(vectors) => vectors,
+        Err(e) => return cx.throw_error(format!("Failed to read embeddings: {}", e)),
+    };
+    let array = JsArray::new(&mut cx, vectors.len() as u32);
+    for (position, vector) in vectors.iter().enumerate() {
+        let value: Handle<JsValue> = match vector {
+            Some(vector) => float32_array(&mut cx, vector)?.upcast(),
+            None => cx.null().upcast(),
+        };
+        array.set(&mut cx, position as u32, value)?;
+    }
+    Ok(array)
+}
+
+// dequantize(vectorJson): a QuantizedVector from `--quantize` output back as
+// a unit-length Float32Array, ready to score by dot product
+fn dequantize(mut cx: FunctionContext) -> JsResult<JsObject> {
+    let vector_json = cx.argument::<JsString>(0)?.value(&mut cx);
+    match serde_json::from_str::<QuantizedVector>(&vector_json) {
+        Ok(vector) => float32_array(&mut cx, &vector.dequantize()),
+        Err(e) => cx.throw_error(format!("Invalid quantized vector: {}", e)),
+    }
+}
+
+// Neon has no typed array constructor of its own: fill an ArrayBuffer and
+// view it through the global Float32Array, which reads native byte order
+fn float32_array<'a>(cx: &mut FunctionContext<'a>, vector: &[f32]) -> JsResult<'a, JsObject> {
+    let mut buffer = JsArrayBuffer::new(cx, vector.len() * 4)?;
+    for (bytes, value) in buffer.as_mut_slice(cx).chunks_exact_mut(4).zip(vector) {
+        bytes.copy_from_slice(&value.to_ne_bytes());
+    }
+    let constructor = cx.global().get::<JsFunction, _, _>(cx, "Float32Array")?;
+    constructor.construct(cx, [buffer.upcast::<JsValue>()])
+}
+
+// setIndexMeta(storagePath, key, value): stores a string with the index;
+// null removes the key
+fn put_index_meta(mut cx: FunctionContext) -> JsResult<JsUndefined> {
+    let storage_path = cx.argument::<JsString>(0)?.value(&mut cx);
+    let key = cx.argument::<JsString>(1)?.value(&mut cx);
+    let value = cx.argument::<JsValue>(2)?;
+    let value = match value.downcast::<JsString, _>(&mut cx) {
+        Ok(value) => Some(value.value(&mut cx)),
+        Err(_) if value.is_a::<JsNull, _>(&mut cx) || value.is_a::<JsUndefined, _>(&mut cx) => None,
+        Err(_) => return cx.throw_type_error("Index metadata values must be strings, or null to remove the key"),
+    };
+
+    match set_index_meta(&storage_path, &key, value.as_deref()) {
+        Ok(()) => Ok(cx.undefined()),
+        Err(e) => cx.throw_error(format!("Failed to set index metadata: {}", e)),
+    }
+}
+
+// getIndexMeta(storagePath, key): the stored string, or null
+fn get_index_meta(mut cx: FunctionContext) -> JsResult<JsValue> {
+    let storage_path = cx.argument::<JsString>(0)?.value(&mut cx);
+    let key = cx.argument::<JsString>(1)?.value(&mut cx);
+
+    match index_meta(&storage_path, &key) {
+        Ok(Some(value)) => Ok(cx.string(value).upcast()),
+        Ok(None) => Ok(cx.null().upcast()),
+        Err(e) => cx.throw_error(format!("Failed to read index metadata: {}", e)),
+    }
+}
+
+// Frees a `:memory:` index; searches against it fail from then on
+fn drop_index(mut cx: FunctionContext) -> JsResult<JsBoolean> {
+    let storage_path = cx.argument::<JsString>(0)?.value(&mut cx);
+    Ok(cx.boolean(memory::drop_index(&storage_path)))
+}
+
 #[neon::main]
 fn main(mut cx: ModuleContext) -> NeonResult<()> {
     cx.export_function("createIndex", create_index)?;
     cx.export_function("indexDirectory", index_directory)?;
+    cx.export_function("searchBatch", search_batch)?;
+    cx.export_function("previewChunks", preview_file_chunks)?;
+    cx.export_function("getChunk", get_chunk)?;
+    cx.export_function("getEmbedding", get_embedding)?;
+    cx.export_function("getEmbeddings", get_embeddings)?;
+    cx.export_function("dequantize", dequantize)?;
+    cx.export_function("setIndexMeta", put_index_meta)?;
+    cx.export_function("getIndexMeta", get_index_meta)?;
+    cx.export_function("dropIndex", drop_index)?;
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProjectConfig;
+    use crate::test_utils::TempDir;
+
+    // Pruned and too-deep directories are never entered, whichever root the
+    // walk starts from
+    #[test]
+    fn walks_skip_pruned_and_deep_directories() {
+        let dir = TempDir::new("pruned-walk").unwrap();
+        for file in ["src/lib.rs", "docs/api/guide.md", "docs/api/v1/old.md", "target/debug/build.rs", "crates/target/notes.md"] {
+            let path = dir.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, "walked").unwrap();
+        }
+        let project = ProjectConfig::parse("[index]\ninclude = [\"*.rs\", \"*.md\"]\nprune_dirs = [\"target\"]\nmax_depth = 3\n").unwrap();
+        let mut config = project.index_config();
+        config.root = Some(dir.path().to_path_buf());
+
+        let mut walked: Vec<_> = pruned_walk(WalkDir::new(dir.path()), &config)
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| walked_path(entry.path(), &config).to_string_lossy().into_owned())
+            .collect();
+        walked.sort();
+        assert_eq!(walked, ["./docs/api/guide.md", "./src/lib.rs"]);
+        assert_eq!(skip_reason(Path::new("./docs/api/v1/old.md"), &config), Some(Uncovered::TooDeep));
+        assert_eq!(skip_reason(Path::new("./crates/target/notes.md"), &config), Some(Uncovered::Pruned));
+    }
+}
@@ -0,0 +1,75 @@
+// Curated starting points for `[index] preset`: what a project of that kind
+// wants indexed, the dependency and build directories to prune, and files
+// never worth chunking. A config's own include, exclude and prune_dirs lists
+// extend the preset's rather than replace them.
+#[derive(Debug, Clone, Copy)]
+pub struct IndexPreset {
+    pub name: &'static str,
+    pub include: &'static [&'static str],
+    pub exclude: &'static [&'static str],
+    pub prune_dirs: &'static [&'static str],
+    // Used unless the config sets its own
+    pub max_file_size: u64,
+    pub extract_comments: bool,
+}
+
+const MB: u64 = 1024 * 1024;
+
+// Left out under every preset: media, archives, fonts and compiled objects
+// read as nothing but noise
+pub const BINARY_EXCLUDES: &[&str] = &[
+    "*.png", "*.jpg", "*.jpeg", "*.gif", "*.ico", "*.webp", "*.pdf", "*.zip", "*.gz", "*.tar", "*.tgz", "*.woff", "*.woff2",
+    "*.ttf", "*.eot", "*.so", "*.dylib", "*.dll", "*.exe", "*.wasm", "*.o", "*.a", "*.class", "*.jar", "*.pyc",
+];
+
+pub const INDEX_PRESETS: &[IndexPreset] = &[
+    IndexPreset {
+        name: "node",
+        include: &["*.js", "*.jsx", "*.mjs", "*.cjs", "*.ts", "*.tsx", "*.md", "docs/"],
+        exclude: &[".min.js", ".map", "package-lock.json", "yarn.lock", "pnpm-lock.yaml"],
+        prune_dirs: &["node_modules", ".git", "dist", "build", "coverage", ".next", ".nuxt", ".turbo", ".cache"],
+        max_file_size: MB,
+        extract_comments: true,
+    },
+    IndexPreset {
+        name: "rust",
+        include: &["*.rs", "*.md", "*.toml", "docs/"],
+        exclude: &["Cargo.lock"],
+        prune_dirs: &["target", ".git"],
+        max_file_size: MB,
+        extract_comments: true,
+    },
+    IndexPreset {
+        name: "python",
+        include: &["*.py", "*.pyi", "*.md", "*.rst", "docs/"],
+        exclude: &["poetry.lock", "uv.lock", "Pipfile.lock"],
+        prune_dirs: &[
+            ".git", ".venv", "venv", "__pycache__", ".tox", ".nox", ".mypy_cache", ".pytest_cache", ".ruff_cache", ".eggs", "build",
+            "dist", "site-packages",
+        ],
+        max_file_size: MB,
+        extract_comments: true,
+    },
+    // Several languages side by side; a tighter size limit since vendored
+    // and generated files pile up in big trees
+    IndexPreset {
+        name: "monorepo",
+        include: &[
+            "*.js", "*.jsx", "*.mjs", "*.cjs", "*.ts", "*.tsx", "*.rs", "*.py", "*.go", "*.java", "*.kt", "*.md", "*.rst", "docs/",
+        ],
+        exclude: &[".min.js", ".map", "package-lock.json", "yarn.lock", "pnpm-lock.yaml", "Cargo.lock", "poetry.lock", "uv.lock", "go.sum"],
+        prune_dirs: &[
+            ".git", "node_modules", "target", "dist", "build", "coverage", "vendor", ".venv", "venv", "__pycache__", ".next", ".turbo",
+            ".cache", ".gradle", "bazel-out",
+        ],
+        max_file_size: MB / 2,
+        extract_comments: true,
+    },
+];
+
+pub fn index_preset(name: &str) -> Result<&'static IndexPreset, String> {
+    INDEX_PRESETS.iter().find(|preset| preset.name == name).ok_or_else(|| {
+        let names: Vec<_> = INDEX_PRESETS.iter().map(|preset| preset.name).collect();
+        format!("Unknown index preset '{}' (expected one of: {})", name, names.join(", "))
+    })
+}
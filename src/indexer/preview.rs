@@ -0,0 +1,108 @@
+use super::boilerplate::Boilerplate;
+use super::context::estimate_tokens;
+use super::{comments, ignore, language, prepare_chunks, relative_to_root, skip_reason, test_code, ContentHash, IndexConfig, Uncovered};
+use crate::models::TokenCounter;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+// How an index run would chunk one file under a config, worked out without
+// opening the index for writing, so chunking settings can be tried a file
+// at a time
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FilePreview {
+    // As it would be stored, aliases applied
+    pub file_path: String,
+    pub language: String,
+    // The model its chunks would be embedded with
+    pub model: String,
+    // Why a directory run would leave the file out, if it would; it is
+    // chunked all the same
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub skipped: Option<Uncovered>,
+    // A `context-rag: ignore` marker keeps the whole file out
+    pub ignored: bool,
+    pub license_stripped: bool,
+    pub chunks: Vec<ChunkPreview>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChunkPreview {
+    pub chunk_index: u64,
+    // 1-based, inclusive; None where preprocessing left text that isn't in
+    // the file as it is
+    pub start_line: Option<usize>,
+    pub end_line: Option<usize>,
+    pub chars: usize,
+    // The estimate context budgets are counted in
+    pub estimated_tokens: usize,
+    // By the model's tokenizer.json when it is checked out, with whether the
+    // model would cut the chunk short
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_tokens: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub truncated: Option<bool>,
+    pub chunk_hash: String,
+    pub is_test: bool,
+    // What `extract_comments` would store for the chunk
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub comments: String,
+    pub content: String,
+}
+
+// Boilerplate and the content hash come from the index at the config's
+// storage path when there is one, the other settings from the config itself
+pub fn preview_chunks(path: &str, config: &IndexConfig) -> Result<FilePreview, Box<dyn std::error::Error>> {
+    let stored = relative_to_root(path);
+    let source = config.aliases.source(config.root.as_deref(), &stored);
+    let text = fs::read_to_string(&source).map_err(|e| format!("Failed to read {}: {}", source.display(), e))?;
+    let file_path = config.aliases.canonical(&stored).unwrap_or(stored);
+
+    let language = language::detect_language(Path::new(&file_path));
+    let model = match &config.code_model {
+        Some(code_model) if language::is_code(language) => code_model.clone(),
+        _ => config.model.clone(),
+    };
+    let content_hash = config.content_hash.unwrap_or_else(|| ContentHash::load(&config.storage_path));
+    let boilerplate = Boilerplate::load(&config.storage_path)?;
+    let (chunks, license_stripped) = prepare_chunks(&text, config.strip_license_headers, &boilerplate).unwrap_or_default();
+    let counter = TokenCounter::for_model(&model);
+
+    // Chunks come in file order, so each is looked for after the last
+    let mut searched_from = 0;
+    let chunks = chunks
+        .into_iter()
+        .enumerate()
+        .map(|(chunk_index, chunk)| {
+            let offset = text[searched_from..].find(&chunk).map(|found| searched_from + found);
+            let start_line = offset.map(|offset| text[..offset].matches('\n').count() + 1);
+            if let Some(offset) = offset {
+                searched_from = offset + chunk.len();
+            }
+            let fit = counter.as_ref().and_then(|counter| counter.fit(&chunk).ok());
+            ChunkPreview {
+                chunk_index: chunk_index as u64,
+                start_line,
+                end_line: start_line.map(|start_line| start_line + chunk.matches('\n').count()),
+                chars: chunk.chars().count(),
+                estimated_tokens: estimate_tokens(&chunk),
+                model_tokens: fit.as_ref().map(|fit| fit.tokens),
+                truncated: fit.as_ref().map(|fit| fit.cut.is_some()),
+                chunk_hash: content_hash.hash(chunk.as_bytes()),
+                is_test: test_code::is_test_chunk(Path::new(&file_path), &chunk, language),
+                comments: if config.extract_comments { comments::extract_comments(&chunk, language) } else { String::new() },
+                content: chunk,
+            }
+        })
+        .collect();
+
+    Ok(FilePreview {
+        skipped: skip_reason(Path::new(&file_path), config),
+        ignored: ignore::ignores_file(&text),
+        file_path,
+        language: language.to_string(),
+        model,
+        license_stripped,
+        chunks,
+    })
+}
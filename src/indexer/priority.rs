@@ -0,0 +1,36 @@
+use super::clock::{modified_time, now};
+use walkdir::DirEntry;
+
+// Files touched within this window are treated as actively worked on
+const RECENT_WINDOW: i64 = 7 * 24 * 60 * 60;
+const SMALL_FILE_BYTES: u64 = 16 * 1024;
+
+// Orders files so the ones most likely to be searched for land in the index
+// first: READMEs, then recently modified files, then small files, then the
+// long tail. Ties go to the newest file, then the smallest.
+pub fn prioritize(entries: &mut [DirEntry]) {
+    let now = now();
+    entries.sort_by_cached_key(|entry| {
+        let size = entry.metadata().map_or(u64::MAX, |m| m.len());
+        let age = modified_time(entry.path()).map_or(i64::MAX, |modified| now - modified);
+
+        let tier = if is_readme(entry) {
+            0
+        } else if age <= RECENT_WINDOW {
+            1
+        } else if size <= SMALL_FILE_BYTES {
+            2
+        } else {
+            3
+        };
+
+        (tier, age, size)
+    });
+}
+
+fn is_readme(entry: &DirEntry) -> bool {
+    entry
+        .file_name()
+        .to_str()
+        .is_some_and(|name| name.to_ascii_lowercase().starts_with("readme"))
+}
@@ -0,0 +1,378 @@
+use super::hybrid::dot;
+use super::language::is_code;
+use super::{ContextRagSearcher, VectorStore};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+
+// Power iterations per principal component; plenty for the top two
+const PCA_ITERATIONS: usize = 100;
+// Below this share of the variance a component is taken to be flat
+const FLAT_COMPONENT: f32 = 1e-4;
+// UMAP's defaults: min_dist 0.1 and spread 1 fit to this curve, 200 epochs
+// and 5 negative samples per positive one
+const UMAP_A: f32 = 1.577;
+const UMAP_B: f32 = 0.895;
+const UMAP_EPOCHS: usize = 200;
+const NEGATIVE_SAMPLES: usize = 5;
+// Layouts start from PCA scaled to this extent
+const INITIAL_EXTENT: f32 = 10.0;
+// Chunks UMAP lays out at most; its neighbor graph is brute force, so
+// larger stores are sampled down to this many, evenly across paths
+pub const UMAP_MAX_CHUNKS: usize = 5000;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectionMethod {
+    // Linear and fast; keeps global distances, blurs local structure
+    Pca,
+    // Nonlinear; pulls neighborhoods into visible clusters
+    Umap,
+}
+
+impl std::str::FromStr for ProjectionMethod {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "pca" => Ok(ProjectionMethod::Pca),
+            "umap" => Ok(ProjectionMethod::Umap),
+            other => Err(format!("Unknown projection method '{}' (expected pca or umap)", other)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProjectedChunk {
+    pub file_path: String,
+    pub chunk_index: u64,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub language: String,
+    // The chunk's top-level directory, for coloring points
+    pub label: String,
+    pub x: f32,
+    pub y: f32,
+}
+
+// Every embedded chunk placed on a plane, neighbors in vector space landing
+// near each other
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Projection {
+    pub model: String,
+    pub method: ProjectionMethod,
+    // Sorted by path and chunk index
+    pub chunks: Vec<ProjectedChunk>,
+    // Chunks without a vector from `model`: not embedded yet, or embedded by
+    // the code model, whose vectors don't share the space
+    pub skipped: usize,
+    // Chunks with a vector left out to keep UMAP under UMAP_MAX_CHUNKS
+    #[serde(default)]
+    pub sampled_out: usize,
+}
+
+impl Projection {
+    pub fn write_csv<W: Write>(&self, mut out: W) -> std::io::Result<()> {
+        writeln!(out, "file_path,chunk_index,language,label,x,y")?;
+        for chunk in &self.chunks {
+            writeln!(
+                out,
+                "{},{},{},{},{},{}",
+                csv_field(&chunk.file_path),
+                chunk.chunk_index,
+                csv_field(&chunk.language),
+                csv_field(&chunk.label),
+                chunk.x,
+                chunk.y
+            )?;
+        }
+        out.flush()
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+// 2D coordinates for the active store's vectors. UMAP builds its neighbor
+// graph by brute force, quadratic in the chunk count, so it projects an
+// evenly spaced sample of at most UMAP_MAX_CHUNKS; both methods are
+// deterministic, so the same index projects the same way every time.
+pub fn project_chunks(storage_path: &str, method: ProjectionMethod, neighbors: usize) -> Result<Projection, Box<dyn std::error::Error>> {
+    let store = VectorStore::load_active(storage_path)?.ok_or("Index has no vectors; run `backfill` first")?;
+    let mut chunks = ContextRagSearcher::open(storage_path)?.all_chunks()?;
+    chunks.sort_by(|a, b| a.file_path.cmp(&b.file_path).then(a.chunk_index.cmp(&b.chunk_index)));
+
+    let total = chunks.len();
+    let mut points = Vec::new();
+    let mut vectors = Vec::new();
+    for chunk in chunks {
+        if store.code_model.is_some() && is_code(&chunk.language) {
+            continue;
+        }
+        if let Some(vector) = store.get(&chunk.chunk_hash) {
+            vectors.push(vector.to_vec());
+            points.push(chunk);
+        }
+    }
+
+    let mut sampled_out = 0;
+    if method == ProjectionMethod::Umap && vectors.len() > UMAP_MAX_CHUNKS {
+        let keep: Vec<usize> = (0..UMAP_MAX_CHUNKS).map(|i| i * vectors.len() / UMAP_MAX_CHUNKS).collect();
+        sampled_out = vectors.len() - keep.len();
+        vectors = keep.iter().map(|&i| std::mem::take(&mut vectors[i])).collect();
+        points = keep.iter().map(|&i| points[i].clone()).collect();
+    }
+
+    let coordinates = match method {
+        ProjectionMethod::Pca => pca(&vectors),
+        ProjectionMethod::Umap => umap(&vectors, neighbors),
+    };
+    let chunks = points
+        .into_iter()
+        .zip(coordinates)
+        .map(|(chunk, [x, y])| ProjectedChunk {
+            label: top_directory(&chunk.file_path),
+            file_path: chunk.file_path,
+            chunk_index: chunk.chunk_index,
+            language: chunk.language,
+            x,
+            y,
+        })
+        .collect::<Vec<_>>();
+    Ok(Projection { model: store.model, method, skipped: total - chunks.len() - sampled_out, sampled_out, chunks })
+}
+
+fn top_directory(file_path: &str) -> String {
+    let path = file_path.strip_prefix("./").unwrap_or(file_path);
+    match path.split_once('/') {
+        Some((directory, _)) => directory.to_string(),
+        None => ".".to_string(),
+    }
+}
+
+// The two directions of greatest variance, found by power iteration on the
+// centered vectors without forming their covariance matrix
+fn pca(vectors: &[Vec<f32>]) -> Vec<[f32; 2]> {
+    let dimensions = vectors.first().map_or(0, Vec::len);
+    let count = vectors.len().max(1) as f32;
+    let mut mean = vec![0.0; dimensions];
+    for vector in vectors {
+        for (total, value) in mean.iter_mut().zip(vector) {
+            *total += value / count;
+        }
+    }
+    let centered: Vec<Vec<f32>> = vectors.iter().map(|vector| vector.iter().zip(&mean).map(|(value, mean)| value - mean).collect()).collect();
+
+    let mut components: Vec<Vec<f32>> = Vec::with_capacity(2);
+    for component in 0..2 {
+        // Any start works unless orthogonal to the answer; vary it per index
+        let mut direction: Vec<f32> = (0..dimensions).map(|i| 1.0 + ((i + component) % 7) as f32).collect();
+        for _ in 0..PCA_ITERATIONS {
+            let mut next = vec![0.0; dimensions];
+            for row in &centered {
+                let projected = dot(row, &direction);
+                for (total, value) in next.iter_mut().zip(row) {
+                    *total += projected * value;
+                }
+            }
+            let before = dot(&next, &next).sqrt();
+            for found in &components {
+                let overlap = dot(&next, found);
+                for (value, found) in next.iter_mut().zip(found) {
+                    *value -= overlap * found;
+                }
+            }
+            let norm = dot(&next, &next).sqrt();
+            // Whatever survives removing the components already found is
+            // rounding error when the vectors vary along fewer axes; left in,
+            // it normalizes into a copy of the first one
+            if norm <= before * FLAT_COMPONENT {
+                direction = vec![0.0; dimensions];
+                break;
+            }
+            direction = next.into_iter().map(|value| value / norm).collect();
+        }
+        components.push(direction);
+    }
+
+    let mut coordinates: Vec<[f32; 2]> = centered.iter().map(|row| [dot(row, &components[0]), dot(row, &components[1])]).collect();
+    // A component's sign is arbitrary; put the most extreme point on the
+    // positive side so layouts don't flip between runs
+    for axis in 0..2 {
+        let extreme = coordinates.iter().map(|point| point[axis]).fold(0.0f32, |extreme, value| if value.abs() > extreme.abs() { value } else { extreme });
+        if extreme < 0.0 {
+            coordinates.iter_mut().for_each(|point| point[axis] = -point[axis]);
+        }
+    }
+    coordinates
+}
+
+// McInnes et al.'s UMAP, in its plain form: a fuzzy k-nearest-neighbor graph
+// over cosine distance, laid out from PCA by stochastic gradient descent
+// with negative sampling
+fn umap(vectors: &[Vec<f32>], neighbors: usize) -> Vec<[f32; 2]> {
+    let count = vectors.len();
+    let mut layout = pca(vectors);
+    let neighbors = neighbors.min(count.saturating_sub(1));
+    if neighbors == 0 {
+        return layout;
+    }
+    let extent = layout.iter().flat_map(|point| point.iter().map(|value| value.abs())).fold(0.0f32, f32::max);
+    if extent > 0.0 {
+        layout.iter_mut().flatten().for_each(|value| *value *= INITIAL_EXTENT / extent);
+    }
+
+    // Each point's neighbors weighted so that their total is log2(k), past
+    // the nearest, which always counts fully
+    let mut graph: HashMap<(usize, usize), f32> = HashMap::new();
+    for (i, vector) in vectors.iter().enumerate() {
+        let mut distances: Vec<(usize, f32)> =
+            vectors.iter().enumerate().filter(|&(j, _)| j != i).map(|(j, other)| (j, (1.0 - dot(vector, other)).max(0.0))).collect();
+        distances.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        distances.truncate(neighbors);
+        let nearest = distances.iter().map(|&(_, distance)| distance).find(|&distance| distance > 0.0).unwrap_or_default();
+        let sigma = smooth_sigma(&distances, nearest, (neighbors as f32).log2());
+        for &(j, distance) in &distances {
+            let weight = (-(distance - nearest).max(0.0) / sigma).exp();
+            let key = (i.min(j), i.max(j));
+            // Fuzzy union of i's view of j and j's view of i
+            let existing = graph.entry(key).or_insert(0.0);
+            *existing = *existing + weight - *existing * weight;
+        }
+    }
+    let mut edges: Vec<((usize, usize), f32)> = graph.into_iter().collect();
+    edges.sort_by_key(|&(key, _)| key);
+
+    // Strong edges are sampled every epoch, weak ones proportionally less
+    let strongest = edges.iter().map(|&(_, weight)| weight).fold(0.0f32, f32::max);
+    let every: Vec<f32> = edges.iter().map(|&(_, weight)| if weight > 0.0 { strongest / weight } else { f32::INFINITY }).collect();
+    let mut next_sample = every.clone();
+    let mut random = XorShift(0x9e3779b97f4a7c15);
+    for epoch in 0..UMAP_EPOCHS {
+        let rate = 1.0 - epoch as f32 / UMAP_EPOCHS as f32;
+        for (edge, &((head, tail), _)) in edges.iter().enumerate() {
+            if next_sample[edge] > (epoch + 1) as f32 {
+                continue;
+            }
+            next_sample[edge] += every[edge];
+
+            let (delta, squared) = offset(&layout, head, tail);
+            let attraction = match squared > 0.0 {
+                true => -2.0 * UMAP_A * UMAP_B * squared.powf(UMAP_B - 1.0) / (UMAP_A * squared.powf(UMAP_B) + 1.0),
+                false => 0.0,
+            };
+            for axis in 0..2 {
+                let step = clip(attraction * delta[axis]) * rate;
+                layout[head][axis] += step;
+                layout[tail][axis] -= step;
+            }
+
+            for _ in 0..NEGATIVE_SAMPLES {
+                let other = (random.next() % count as u64) as usize;
+                if other == head {
+                    continue;
+                }
+                let (delta, squared) = offset(&layout, head, other);
+                let repulsion = 2.0 * UMAP_B / ((0.001 + squared) * (UMAP_A * squared.powf(UMAP_B) + 1.0));
+                for axis in 0..2 {
+                    layout[head][axis] += clip(repulsion * delta[axis]) * rate;
+                }
+            }
+        }
+    }
+    layout
+}
+
+// Binary search for the bandwidth that makes a point's neighbor weights sum
+// to `target`
+fn smooth_sigma(distances: &[(usize, f32)], nearest: f32, target: f32) -> f32 {
+    let (mut low, mut high, mut sigma) = (0.0f32, f32::INFINITY, 1.0f32);
+    for _ in 0..64 {
+        let total: f32 = distances.iter().map(|&(_, distance)| (-(distance - nearest).max(0.0) / sigma).exp()).sum();
+        if (total - target).abs() < 1e-5 {
+            break;
+        }
+        if total > target {
+            high = sigma;
+            sigma = (low + high) / 2.0;
+        } else {
+            low = sigma;
+            sigma = if high.is_infinite() { sigma * 2.0 } else { (low + high) / 2.0 };
+        }
+    }
+    sigma.max(1e-3)
+}
+
+// From `to` to `from`, and the squared distance between them
+fn offset(layout: &[[f32; 2]], from: usize, to: usize) -> ([f32; 2], f32) {
+    let delta = [layout[from][0] - layout[to][0], layout[from][1] - layout[to][1]];
+    (delta, delta[0] * delta[0] + delta[1] * delta[1])
+}
+
+fn clip(gradient: f32) -> f32 {
+    gradient.clamp(-4.0, 4.0)
+}
+
+// Negative samples only need to be spread out and repeatable
+struct XorShift(u64);
+
+impl XorShift {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(vector: [f32; 3]) -> Vec<f32> {
+        let norm = dot(&vector, &vector).sqrt();
+        vector.iter().map(|value| value / norm).collect()
+    }
+
+    #[test]
+    fn pca_lays_a_line_along_its_first_axis() {
+        let vectors: Vec<Vec<f32>> = [-1.0, 0.0, 1.0, 3.0].iter().map(|&t| vec![t, 2.0 * t, 0.0]).collect();
+        let coordinates = pca(&vectors);
+        assert!(coordinates.windows(2).all(|pair| pair[0][0] < pair[1][0]));
+        assert!((coordinates[3][0] - 2.25 * 5f32.sqrt()).abs() < 1e-3);
+        assert!(coordinates.iter().all(|point| point[1].abs() < 1e-3));
+
+        let grid = pca(&[vec![0.0, 0.0], vec![4.0, 0.0], vec![0.0, 1.0], vec![4.0, 1.0]]);
+        assert!(grid.iter().all(|point| (point[0].abs() - 2.0).abs() < 1e-3 && (point[1].abs() - 0.5).abs() < 1e-3));
+    }
+
+    #[test]
+    fn umap_keeps_clusters_apart_and_repeats_itself() {
+        let vectors: Vec<Vec<f32>> = (0..4)
+            .map(|i| unit([1.0, 0.1 * i as f32, 0.05]))
+            .chain((0..4).map(|i| unit([0.05, 0.1 * i as f32, 1.0])))
+            .collect();
+        let layout = umap(&vectors, 3);
+        assert_eq!(layout, umap(&vectors, 3));
+        let distance = |a: usize, b: usize| offset(&layout, a, b).1.sqrt();
+        let within = (0..8).flat_map(|a| (0..8).filter(move |&b| a / 4 == b / 4).map(move |b| (a, b))).map(|(a, b)| distance(a, b)).fold(0.0, f32::max);
+        let across = (0..4).flat_map(|a| (4..8).map(move |b| (a, b))).map(|(a, b)| distance(a, b)).fold(f32::INFINITY, f32::min);
+        assert!(within < across, "within {} across {}", within, across);
+    }
+
+    #[test]
+    fn csv_rows_quote_what_needs_it() {
+        let chunk = |file_path: &str| ProjectedChunk { file_path: file_path.to_string(), chunk_index: 0, language: String::new(), label: top_directory(file_path), x: 1.5, y: -2.0 };
+        let projection = Projection { model: "mock".to_string(), method: ProjectionMethod::Pca, chunks: vec![chunk("./README.md"), chunk("./docs/a, \"b\".md")], skipped: 0, sampled_out: 0 };
+        let mut out = Vec::new();
+        projection.write_csv(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "file_path,chunk_index,language,label,x,y\n./README.md,0,,.,1.5,-2\n\"./docs/a, \"\"b\"\".md\",0,,docs,1.5,-2\n"
+        );
+        assert!("tsne".parse::<ProjectionMethod>().is_err());
+    }
+}
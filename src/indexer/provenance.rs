@@ -0,0 +1,97 @@
+use super::{calculate_file_hash, IndexConfig, SearchHit};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+// Bump whenever `chunk_content` changes how files are split
+pub const CHUNKER_VERSION: &str = "lines-1000-v1";
+
+// Which pipeline produced a chunk; stored alongside every chunk
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Provenance {
+    pub run_id: String,
+    pub config_hash: String,
+    pub chunker_version: String,
+    pub model: String,
+    pub indexed_at: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChunkAudit {
+    pub chunk_id: String,
+    pub chunk: SearchHit,
+    pub file_hash: String,
+    pub provenance: Provenance,
+    // The file's leading license comment was dropped before chunking
+    #[serde(default)]
+    pub license_stripped: bool,
+}
+
+impl Provenance {
+    // Files added without a config record an empty hash
+    pub fn for_run(config: Option<&IndexConfig>) -> Self {
+        let now = super::clock::now();
+
+        let config_hash = config.map(config_hash).unwrap_or_default();
+
+        // Deterministic runs are identified by their config alone
+        let (run_id, indexed_at) = match config {
+            Some(config) if config.deterministic => (format!("deterministic-{}", &config_hash[..12]), 0),
+            _ => (format!("{:x}-{:x}", now, std::process::id()), now),
+        };
+
+        Provenance {
+            run_id,
+            config_hash,
+            chunker_version: CHUNKER_VERSION.to_string(),
+            model: config.map(|c| c.model.clone()).unwrap_or_default(),
+            indexed_at,
+        }
+    }
+
+    // A file refreshed on its own keeps the config and model of the run
+    // that indexed it, under a run of its own
+    pub fn refresh_of(previous: &Provenance) -> Self {
+        Provenance {
+            config_hash: previous.config_hash.clone(),
+            model: previous.model.clone(),
+            ..Self::for_run(None)
+        }
+    }
+}
+
+// Covers everything that decides which chunks exist, not where they're stored
+pub fn config_hash(config: &IndexConfig) -> String {
+    let mut canonical = json!({
+        "include": config.include,
+        "exclude": config.exclude,
+        "model": config.model,
+        "chunker_version": CHUNKER_VERSION,
+    });
+    // Only when set, so configs without it keep their earlier hash
+    if let Some(threshold) = config.boilerplate_threshold {
+        canonical["boilerplate_threshold"] = json!(threshold);
+    }
+    if config.strip_license_headers {
+        canonical["strip_license_headers"] = json!(true);
+    }
+    if let Some(max_file_size) = config.max_file_size {
+        canonical["max_file_size"] = json!(max_file_size);
+    }
+    if !config.prune_dirs.is_empty() {
+        canonical["prune_dirs"] = json!(config.prune_dirs);
+    }
+    if let Some(max_depth) = config.max_depth {
+        canonical["max_depth"] = json!(max_depth);
+    }
+    calculate_file_hash(&canonical.to_string())
+}
+
+pub fn chunk_id(file_path: &str, chunk_index: u64) -> String {
+    format!("{}#{}", file_path, chunk_index)
+}
+
+// Splits `path#index` as printed by search results
+pub fn parse_chunk_id(chunk_id: &str) -> Option<(&str, u64)> {
+    let (file_path, chunk_index) = chunk_id.rsplit_once('#')?;
+    Some((file_path, chunk_index.parse().ok()?))
+}
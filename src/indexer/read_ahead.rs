@@ -0,0 +1,46 @@
+use super::file_content::{read_file, FileContent};
+use std::collections::VecDeque;
+use std::io;
+use std::path::PathBuf;
+use std::sync::mpsc;
+
+// Files read ahead of the one being indexed; bounds the contents held at once
+const READ_AHEAD: usize = 32;
+
+// Reads `paths` on a background tokio runtime, up to READ_AHEAD at a time,
+// so the IO for the next files overlaps chunking and hashing the current
+// one. Results arrive in `paths` order, one per path.
+pub fn read_ahead(paths: Vec<PathBuf>) -> mpsc::IntoIter<io::Result<FileContent>> {
+    let (tx, rx) = mpsc::sync_channel(READ_AHEAD);
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .max_blocking_threads(READ_AHEAD)
+            .build();
+        let Ok(runtime) = runtime else {
+            for path in paths {
+                if tx.send(read_file(&path)).is_err() {
+                    return;
+                }
+            }
+            return;
+        };
+
+        runtime.block_on(async move {
+            let mut paths = paths.into_iter();
+            let mut pending = VecDeque::with_capacity(READ_AHEAD);
+            loop {
+                while pending.len() < READ_AHEAD {
+                    let Some(path) = paths.next() else { break };
+                    pending.push_back(tokio::task::spawn_blocking(move || read_file(&path)));
+                }
+                let Some(read) = pending.pop_front() else { break };
+                let content = read.await.unwrap_or_else(|e| Err(io::Error::other(e)));
+                // The indexer stopped early, e.g. on an error
+                if tx.send(content).is_err() {
+                    break;
+                }
+            }
+        });
+    });
+    rx.into_iter()
+}
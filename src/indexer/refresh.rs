@@ -0,0 +1,114 @@
+use super::clock::modified_time;
+use super::{backfill_vectors, ContentHash, ContextRagIndexer, ContextRagSearcher, PathAliases, Provenance, SearchHit, VectorStore};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RefreshedSearch {
+    pub hits: Vec<SearchHit>,
+    pub refreshed: Vec<String>,
+}
+
+// Searches, then re-indexes any hit whose file changed on disk since it was
+// indexed and runs the query again so results reflect the current content.
+// Stored paths are read under `root`, or the working directory when unset.
+// The index is only opened for writing when a hit is stale.
+pub fn search_with_refresh(
+    storage_path: &str,
+    root: Option<&Path>,
+    query: &str,
+    limit: usize,
+) -> Result<RefreshedSearch, Box<dyn std::error::Error>> {
+    let searcher = ContextRagSearcher::open(storage_path)?;
+    let hits = searcher.search(query, limit)?;
+    let aliases = PathAliases::load(storage_path)?;
+    let content_hash = ContentHash::load(storage_path);
+
+    // Stale files, with the provenance their chunks were indexed under
+    let mut stale = BTreeMap::new();
+    for hit in &hits {
+        if stale.contains_key(&hit.file_path) {
+            continue;
+        }
+        let Some(audit) = searcher.audit_chunk(&hit.file_path, hit.chunk_index)? else {
+            continue;
+        };
+        // Read through the clock indexing used; a moved mtime alone (or a
+        // deterministic index's zero) is only stale if the content changed
+        let path = aliases.source(root, &hit.file_path);
+        if modified_time(&path).ok() == Some(audit.chunk.modified_time) {
+            continue;
+        }
+        let unchanged = fs::read(&path).is_ok_and(|content| content_hash.hash(&content) == audit.file_hash);
+        if !unchanged {
+            stale.insert(hit.file_path.clone(), audit.provenance);
+        }
+    }
+
+    if stale.is_empty() {
+        return Ok(RefreshedSearch { hits, refreshed: Vec::new() });
+    }
+
+    let mut indexer = ContextRagIndexer::new(storage_path)?;
+    indexer.root = root.map(Path::to_path_buf);
+    for (file_path, provenance) in &stale {
+        indexer.provenance = Provenance::refresh_of(provenance);
+        indexer.reindex_file(file_path)?;
+    }
+    indexer.commit()?;
+    drop(indexer);
+
+    // Refreshed chunks get vectors for the model searches use, as after an
+    // index run
+    if let Some(active) = VectorStore::load_active(storage_path)? {
+        backfill_vectors(storage_path, &active.model, active.code_model.as_deref(), |_, _| {})?;
+    }
+
+    Ok(RefreshedSearch {
+        hits: ContextRagSearcher::open(storage_path)?.search(query, limit)?,
+        refreshed: stale.into_keys().collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProjectConfig;
+    use crate::test_utils::TempDir;
+    use std::time::{Duration, SystemTime};
+
+    #[test]
+    fn only_edited_hits_are_reindexed() {
+        let dir = TempDir::new("refresh").unwrap();
+        let notes = dir.join("source/notes.md");
+        fs::create_dir_all(notes.parent().unwrap()).unwrap();
+        fs::write(&notes, "rotation schedule for signing keys").unwrap();
+        let mut config = ProjectConfig::parse("[index]\ninclude = [\"*.md\"]\n").unwrap().index_config();
+        config.storage_path = dir.join("index").to_string_lossy().into_owned();
+        config.root = Some(dir.join("source"));
+        ContextRagIndexer::for_config(&config).unwrap().index_directory(&config).unwrap();
+        let (storage, root) = (config.storage_path.as_str(), config.root.as_deref());
+
+        let fresh = search_with_refresh(storage, root, "rotation", 10).unwrap();
+        assert!(fresh.refreshed.is_empty() && fresh.hits.len() == 1);
+
+        // A new mtime over the same bytes isn't an edit
+        let touched = SystemTime::now() + Duration::from_secs(3600);
+        fs::File::options().write(true).open(&notes).unwrap().set_modified(touched).unwrap();
+        assert!(search_with_refresh(storage, root, "rotation", 10).unwrap().refreshed.is_empty());
+
+        let before = ContextRagSearcher::open(storage).unwrap().audit_chunk("./notes.md", 0).unwrap().unwrap();
+        // Stored times are whole seconds, so the edit is dated apart from the index run
+        fs::write(&notes, "rotation schedule, now quarterly").unwrap();
+        let edited_at = SystemTime::now() - Duration::from_secs(60);
+        fs::File::options().write(true).open(&notes).unwrap().set_modified(edited_at).unwrap();
+        let edited = search_with_refresh(storage, root, "rotation", 10).unwrap();
+        assert_eq!(edited.refreshed, ["./notes.md"]);
+        assert!(edited.hits[0].content.contains("quarterly"));
+        let after = ContextRagSearcher::open(storage).unwrap().audit_chunk("./notes.md", 0).unwrap().unwrap();
+        assert_eq!(after.provenance.config_hash, before.provenance.config_hash);
+        assert!(search_with_refresh(storage, root, "rotation", 10).unwrap().refreshed.is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+use super::{ContextRagIndexer, ContextRagSearcher, SearchHit};
+use serde::{Deserialize, Serialize};
+use std::process::Command;
+
+// Unchanged lines kept on each side of a change, so a hunk carries enough
+// of its surroundings to match queries about what it touches
+pub const DEFAULT_CONTEXT_LINES: usize = 10;
+
+// The new side of one diff hunk: context and added lines, without removals
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Hunk {
+    pub file_path: String,
+    pub start_line: usize,
+    pub end_line: usize,
+    pub content: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ReviewHit {
+    #[serde(flatten)]
+    pub hit: SearchHit,
+    // Lines of the chunk in the reviewed revision
+    pub start_line: usize,
+    pub end_line: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ReviewAnswer {
+    pub query: String,
+    pub changes: Vec<ReviewHit>,
+    // From the main index, when the session searches it too
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub index: Vec<SearchHit>,
+}
+
+// Hunks changed between the two ends of `range` (e.g. `main..HEAD`), read
+// from `git diff` in the current directory
+pub fn diff_hunks(range: &str, context: usize) -> Result<Vec<Hunk>, Box<dyn std::error::Error>> {
+    let output = Command::new("git")
+        .args(["-c", "core.quotePath=false", "diff", "--no-color", "--no-ext-diff"])
+        .arg(format!("--unified={}", context))
+        .args([range, "--"])
+        .output()
+        .map_err(|e| format!("Failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(format!("git diff {} failed: {}", range, String::from_utf8_lossy(&output.stderr).trim()).into());
+    }
+    Ok(parse_diff(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// Unified diff to new-side hunks. Deleted files and hunks that only remove
+// lines have nothing left to search, so they're dropped.
+pub fn parse_diff(diff: &str) -> Vec<Hunk> {
+    let mut hunks = Vec::new();
+    let mut file_path: Option<String> = None;
+    let mut current: Option<Hunk> = None;
+
+    let mut finish = |hunk: Option<Hunk>| {
+        if let Some(hunk) = hunk.filter(|hunk| !hunk.content.is_empty()) {
+            hunks.push(hunk);
+        }
+    };
+
+    for line in diff.lines() {
+        if line.starts_with("diff --git ") {
+            finish(current.take());
+            file_path = None;
+        } else if let Some(path) = line.strip_prefix("+++ ").filter(|_| current.is_none()) {
+            let path = path.trim_matches('"');
+            file_path = path.strip_prefix("b/").map(str::to_string);
+        } else if let Some(header) = line.strip_prefix("@@ ") {
+            finish(current.take());
+            let (Some(path), Some(start_line)) = (&file_path, new_side_start(header)) else {
+                continue;
+            };
+            current = Some(Hunk { file_path: path.clone(), start_line, end_line: start_line, content: String::new() });
+        } else if let Some(hunk) = current.as_mut() {
+            let Some(text) = line.strip_prefix(' ').or_else(|| line.strip_prefix('+')) else {
+                // Removed lines and "\ No newline at end of file"
+                continue;
+            };
+            if !hunk.content.is_empty() {
+                hunk.end_line += 1;
+            }
+            hunk.content.push_str(text);
+            hunk.content.push('\n');
+        }
+    }
+    finish(current);
+    hunks
+}
+
+// `-12,5 +14,7 @@ fn name` -> 14
+fn new_side_start(header: &str) -> Option<usize> {
+    let new_side = header.split_whitespace().find_map(|part| part.strip_prefix('+'))?;
+    new_side.split(',').next()?.parse().ok()
+}
+
+// A changeset indexed in RAM for the length of one review, optionally
+// alongside the project's main index
+pub struct ReviewSession {
+    hunks: Vec<Hunk>,
+    changes: ContextRagSearcher,
+    index: Option<ContextRagSearcher>,
+}
+
+impl ReviewSession {
+    pub fn new(hunks: Vec<Hunk>, storage_path: Option<&str>) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut indexer = ContextRagIndexer::in_memory(None)?;
+        for hunk in &hunks {
+            indexer.add_text(&hunk.file_path, &hunk.content)?;
+        }
+        indexer.commit()?;
+
+        Ok(ReviewSession {
+            changes: indexer.searcher()?,
+            index: storage_path.map(ContextRagSearcher::open).transpose()?,
+            hunks,
+        })
+    }
+
+    pub fn hunks(&self) -> &[Hunk] {
+        &self.hunks
+    }
+
+    pub fn search(&self, query: &str, limit: usize) -> Result<ReviewAnswer, Box<dyn std::error::Error>> {
+        let changes = self
+            .changes
+            .search(query, limit)?
+            .into_iter()
+            .map(|hit| {
+                let (start_line, end_line) = self.locate(&hit);
+                ReviewHit { hit, start_line, end_line }
+            })
+            .collect();
+        let index = match &self.index {
+            Some(index) => index.search(query, limit)?,
+            None => Vec::new(),
+        };
+        Ok(ReviewAnswer { query: query.to_string(), changes, index })
+    }
+
+    // Hunks of one file share its path in the index, so a hit is placed by
+    // finding its text among them
+    fn locate(&self, hit: &SearchHit) -> (usize, usize) {
+        self.hunks
+            .iter()
+            .filter(|hunk| hunk.file_path == hit.file_path)
+            .find_map(|hunk| {
+                let offset = hunk.content.find(&hit.content)?;
+                let start_line = hunk.start_line + hunk.content[..offset].matches('\n').count();
+                Some((start_line, start_line + hit.content.matches('\n').count()))
+            })
+            .unwrap_or((0, 0))
+    }
+}
@@ -0,0 +1,660 @@
+use super::analyzers::register_tokenizers;
+use super::blocklist::{BlockMatcher, Blocklist};
+use super::identifiers::{identifier_score, identifier_terms, regex_prefix_literals, trigrams};
+use super::inspect::SegmentCopy;
+use super::provenance::{chunk_id, ChunkAudit, Provenance};
+use super::{shards, sparse};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tantivy::collector::{DocSetCollector, TopDocs};
+use tantivy::query::{BooleanQuery, BoostQuery, Occur, Query, QueryParser, TermQuery};
+use tantivy::schema::*;
+use tantivy::{DocSet, Index, IndexReader, ReloadPolicy, Searcher, TantivyDocument, TERMINATED};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SearchHit {
+    pub file_path: String,
+    pub chunk_index: u64,
+    pub content: String,
+    pub score: f32,
+    #[serde(default)]
+    pub modified_time: i64,
+    #[serde(default)]
+    pub chunk_hash: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub language: String,
+    // Classified as test code at index time
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_test: bool,
+    // 0..1 against this index's calibrated score distribution; keyword
+    // searches only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence: Option<f32>,
+}
+
+// Where a hit's text sits in its file, 1-based, for editors and code-scanning
+// tools that want lines rather than chunk numbers
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineSpan {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+}
+
+impl SearchHit {
+    // Found by looking for the chunk's text in the file under `root`; None
+    // when the file is gone or has changed since it was indexed
+    pub fn locate(&self, root: &std::path::Path) -> Option<LineSpan> {
+        locate_in_file(&root.join(self.file_path.trim_start_matches("./")), &self.content)
+    }
+}
+
+pub fn locate_in_file(path: &std::path::Path, content: &str) -> Option<LineSpan> {
+    let text = std::fs::read_to_string(path).ok()?;
+    let offset = text.find(content)?;
+    let line_start = text[..offset].rfind('\n').map_or(0, |newline| newline + 1);
+    let start_line = text[..offset].matches('\n').count() + 1;
+    Some(LineSpan {
+        start_line,
+        start_column: text[line_start..offset].chars().count() + 1,
+        end_line: start_line + content.trim_end_matches('\n').matches('\n').count(),
+    })
+}
+
+// Bias between test and production chunks; the other kind is down-ranked,
+// not dropped
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Prefer {
+    #[default]
+    Any,
+    Production,
+    Tests,
+}
+
+impl std::str::FromStr for Prefer {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "" | "any" => Ok(Prefer::Any),
+            "production" => Ok(Prefer::Production),
+            "tests" => Ok(Prefer::Tests),
+            other => Err(format!("Unknown preference '{}' (expected production, tests or any)", other)),
+        }
+    }
+}
+
+// Per-query matching toggles; the default matches stemmed, lowercased words
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct SearchOptions {
+    #[serde(default)]
+    pub case_sensitive: bool,
+    // Whole words only, without stemming (`indexing` won't match `index`)
+    #[serde(default)]
+    pub exact: bool,
+    // Also match comments and docstrings, weighted by this much; 0 leaves
+    // them out. Needs an index built with `extract_comments`.
+    #[serde(default)]
+    pub comment_boost: f32,
+    #[serde(default)]
+    pub prefer: Prefer,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexedFile {
+    pub file_path: String,
+    pub chunks: usize,
+}
+
+// Hash and mtime recorded for a file when it was last indexed
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct IndexedFileState {
+    pub file_hash: String,
+    pub modified_time: i64,
+}
+
+pub struct ContextRagSearcher {
+    // One per shard, in routing order; queries are parsed against the first
+    indexes: Vec<Index>,
+    readers: Vec<IndexReader>,
+    file_path_field: Field,
+    path_key_field: Field,
+    content_field: Field,
+    content_exact_field: Field,
+    content_cased_field: Field,
+    trigrams_field: Field,
+    sparse_field: Field,
+    comments_field: Field,
+    is_test_field: Field,
+    chunk_index_field: Field,
+    language_field: Field,
+    file_hash_field: Field,
+    chunk_hash_field: Field,
+    modified_time_field: Field,
+    run_id_field: Field,
+    config_hash_field: Field,
+    chunker_version_field: Field,
+    model_field: Field,
+    indexed_at_field: Field,
+    license_stripped_field: Field,
+    // The index's blocklist, kept out of every result: as MustNot clauses on
+    // queries, and through `blocked` for chunks read from the doc store
+    blocked: BlockMatcher,
+    exclusions: Vec<Box<dyn Query>>,
+}
+
+impl ContextRagSearcher {
+    pub fn open(storage_path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let _span = crate::profile::span("open");
+        Self::from_indexes(shards::open_indexes(storage_path)?)?.with_blocklist(&Blocklist::load(storage_path)?)
+    }
+
+    pub fn from_index(index: Index) -> Result<Self, Box<dyn std::error::Error>> {
+        Self::from_indexes(vec![index])
+    }
+
+    // Shards share one schema, so field handles from the first fit them all
+    pub fn from_indexes(indexes: Vec<Index>) -> Result<Self, Box<dyn std::error::Error>> {
+        let schema = indexes.first().ok_or("Index has no shards")?.schema();
+        let mut readers = Vec::with_capacity(indexes.len());
+        for index in &indexes {
+            register_tokenizers(index)?;
+            readers.push(index.reader_builder().reload_policy(ReloadPolicy::Manual).try_into()?);
+        }
+
+        Ok(ContextRagSearcher {
+            file_path_field: schema.get_field("file_path")?,
+            path_key_field: schema.get_field("path_key")?,
+            content_field: schema.get_field("content")?,
+            content_exact_field: schema.get_field("content_exact")?,
+            content_cased_field: schema.get_field("content_cased")?,
+            trigrams_field: schema.get_field("trigrams")?,
+            sparse_field: schema.get_field("sparse")?,
+            comments_field: schema.get_field("comments")?,
+            is_test_field: schema.get_field("is_test")?,
+            chunk_index_field: schema.get_field("chunk_index")?,
+            language_field: schema.get_field("language")?,
+            file_hash_field: schema.get_field("file_hash")?,
+            chunk_hash_field: schema.get_field("chunk_hash")?,
+            modified_time_field: schema.get_field("modified_time")?,
+            run_id_field: schema.get_field("run_id")?,
+            config_hash_field: schema.get_field("config_hash")?,
+            chunker_version_field: schema.get_field("chunker_version")?,
+            model_field: schema.get_field("model")?,
+            indexed_at_field: schema.get_field("indexed_at")?,
+            license_stripped_field: schema.get_field("license_stripped")?,
+            blocked: BlockMatcher::default(),
+            exclusions: Vec::new(),
+            indexes,
+            readers,
+        })
+    }
+
+    pub fn with_blocklist(mut self, blocklist: &Blocklist) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut analyzer = self.indexes[0].tokenizer_for_field(self.content_exact_field)?;
+        self.exclusions = blocklist.exclusions(self.path_key_field, self.content_exact_field, &mut analyzer)?;
+        self.blocked = blocklist.matcher()?;
+        Ok(self)
+    }
+
+    pub fn search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, Box<dyn std::error::Error>> {
+        self.search_with_options(query, limit, SearchOptions::default())
+    }
+
+    pub fn search_with_options(
+        &self,
+        query: &str,
+        limit: usize,
+        options: SearchOptions,
+    ) -> Result<Vec<SearchHit>, Box<dyn std::error::Error>> {
+        // Paths are only indexed lowercased, so case-sensitive queries skip them
+        let mut fields = if options.case_sensitive {
+            vec![self.content_cased_field]
+        } else if options.exact {
+            vec![self.content_exact_field, self.file_path_field]
+        } else {
+            vec![self.content_field, self.file_path_field]
+        };
+        // Comments are stemmed and lowercased like content
+        let boost_comments = options.comment_boost > 0.0 && !options.case_sensitive;
+        if boost_comments {
+            fields.push(self.comments_field);
+        }
+        let mut query_parser = QueryParser::for_index(&self.indexes[0], fields);
+        if boost_comments {
+            query_parser.set_field_boost(self.comments_field, options.comment_boost);
+        }
+        if options.prefer == Prefer::Any {
+            return Ok(self.run_query(&query_parser, query, limit)?);
+        }
+
+        // Over-fetch so preferred chunks just below the cut can move up
+        const CANDIDATE_FACTOR: usize = 3;
+        const DOWN_RANK: f32 = 0.5;
+        let mut hits = self.run_query(&query_parser, query, limit * CANDIDATE_FACTOR)?;
+        for hit in &mut hits {
+            if hit.is_test != (options.prefer == Prefer::Tests) {
+                hit.score *= DOWN_RANK;
+            }
+        }
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    // Every query term matches within one edit, so typos and near-miss
+    // spellings still find something; fuzzy matches all score alike
+    pub fn fuzzy_search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, Box<dyn std::error::Error>> {
+        let mut query_parser = self.query_parser();
+        query_parser.set_field_fuzzy(self.content_field, false, 1, true);
+        query_parser.set_field_fuzzy(self.file_path_field, false, 1, true);
+        Ok(self.run_query(&query_parser, query, limit)?)
+    }
+
+    // Runs the queries on rayon's pool, a core's worth at a time. Readers
+    // never reload, so results stay aligned with `queries` and consistent
+    // with each other.
+    pub fn search_batch(&self, queries: &[String], limit: usize) -> Result<Vec<Vec<SearchHit>>, Box<dyn std::error::Error>> {
+        let query_parser = self.query_parser();
+        let results = queries.par_iter().map(|query| self.run_query(&query_parser, query, limit)).collect::<tantivy::Result<Vec<_>>>()?;
+        Ok(results)
+    }
+
+    // Literal, case-insensitive substring matches of identifier-like query
+    // words against paths and content. Trigrams narrow the candidates, then
+    // each one is checked against the stored text, so tokenization can't
+    // split `parse_config` or `HttpClient::new` into unrelated words.
+    pub fn identifier_search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, Box<dyn std::error::Error>> {
+        const MAX_CANDIDATES: usize = 1000;
+
+        let terms = identifier_terms(query);
+        if terms.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut hits: BTreeMap<(String, u64), SearchHit> = BTreeMap::new();
+        for term in &terms {
+            for hit in self.trigram_candidates(term, MAX_CANDIDATES)? {
+                hits.entry((hit.file_path.clone(), hit.chunk_index)).or_insert(hit);
+            }
+        }
+
+        let mut hits: Vec<SearchHit> = hits
+            .into_values()
+            .filter_map(|mut hit| {
+                hit.score = identifier_score(&hit, &terms);
+                (hit.score > 0.0).then_some(hit)
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    // Learned-sparse style retrieval: the query's weighted expansion terms
+    // against each chunk's, scored by BM25 over the repeated terms
+    pub fn sparse_search(&self, query: &str, limit: usize) -> Result<Vec<SearchHit>, Box<dyn std::error::Error>> {
+        let clauses: Vec<(Occur, Box<dyn Query>)> = sparse::encode(query)
+            .into_iter()
+            .map(|(term, weight)| {
+                let query = TermQuery::new(Term::from_field_text(self.sparse_field, &term), IndexRecordOption::WithFreqs);
+                (Occur::Should, Box::new(BoostQuery::new(Box::new(query), weight)) as Box<dyn Query>)
+            })
+            .collect();
+        if clauses.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        Ok(self.search_shards(&BooleanQuery::new(clauses), limit)?)
+    }
+
+    // Chunks whose content matches `pattern`, scored by match count. When the
+    // pattern has literal prefixes the trigram index picks the candidates;
+    // otherwise every chunk is scanned.
+    pub fn regex_search(&self, pattern: &str, limit: usize) -> Result<Vec<SearchHit>, Box<dyn std::error::Error>> {
+        // Per literal; patterns whose prefixes are this common should be
+        // made more specific anyway
+        const MAX_CANDIDATES: usize = 10_000;
+
+        let regex = regex::Regex::new(pattern)?;
+        let score = |mut hit: SearchHit| {
+            hit.score = regex.find_iter(&hit.content).count() as f32;
+            (hit.score > 0.0).then_some(hit)
+        };
+
+        let mut hits = Vec::new();
+        match regex_prefix_literals(pattern) {
+            Some(literals) => {
+                let mut seen = std::collections::HashSet::new();
+                for literal in &literals {
+                    for hit in self.trigram_candidates(literal, MAX_CANDIDATES)? {
+                        if seen.insert((hit.file_path.clone(), hit.chunk_index)) {
+                            hits.extend(score(hit));
+                        }
+                    }
+                }
+            }
+            None => self.for_each_chunk(|hit| hits.extend(score(hit)))?,
+        }
+
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| (&a.file_path, a.chunk_index).cmp(&(&b.file_path, b.chunk_index)))
+        });
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    // Moves with every commit to any shard
+    pub(super) fn opstamp(&self) -> Result<u64, Box<dyn std::error::Error>> {
+        let mut opstamp = 0;
+        for index in &self.indexes {
+            opstamp += index.load_metas()?.opstamp;
+        }
+        Ok(opstamp)
+    }
+
+    // One reader per shard, for walking the term dictionaries
+    pub(super) fn readers(&self) -> &[IndexReader] {
+        &self.readers
+    }
+
+    pub fn num_chunks(&self) -> u64 {
+        self.readers.iter().map(|reader| reader.searcher().num_docs()).sum()
+    }
+
+    pub fn num_segments(&self) -> usize {
+        self.readers.iter().map(|reader| reader.searcher().segment_readers().len()).sum()
+    }
+
+    pub fn mapped_bytes(&self) -> Result<u64, Box<dyn std::error::Error>> {
+        let mut bytes = 0;
+        for reader in &self.readers {
+            bytes += reader.searcher().space_usage()?.total().get_bytes();
+        }
+        Ok(bytes)
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.readers.len()
+    }
+
+    pub fn list_files(&self) -> Result<Vec<IndexedFile>, Box<dyn std::error::Error>> {
+        let mut counts = BTreeMap::new();
+        self.for_each_chunk(|hit| *counts.entry(hit.file_path).or_insert(0) += 1)?;
+
+        Ok(counts
+            .into_iter()
+            .map(|(file_path, chunks)| IndexedFile { file_path, chunks })
+            .collect())
+    }
+
+    // Every stored chunk, in doc-store order
+    pub fn all_chunks(&self) -> Result<Vec<SearchHit>, Box<dyn std::error::Error>> {
+        let mut chunks = Vec::new();
+        self.for_each_chunk(|hit| chunks.push(hit))?;
+        Ok(chunks)
+    }
+
+    pub fn file_chunks(&self, file_path: &str) -> Result<Vec<SearchHit>, Box<dyn std::error::Error>> {
+        let mut chunks = Vec::new();
+        self.for_each_chunk(|hit| {
+            if hit.file_path == file_path {
+                chunks.push(hit);
+            }
+        })?;
+
+        chunks.sort_by_key(|hit| hit.chunk_index);
+        Ok(chunks)
+    }
+
+    pub fn file_states(&self) -> Result<BTreeMap<String, IndexedFileState>, Box<dyn std::error::Error>> {
+        let mut states = BTreeMap::new();
+        self.for_each_doc(|doc| {
+            states.entry(first_text(doc, self.file_path_field)).or_insert_with(|| IndexedFileState {
+                file_hash: first_text(doc, self.file_hash_field),
+                modified_time: doc.get_first(self.modified_time_field).and_then(|v| v.as_i64()).unwrap_or(0),
+            });
+        })?;
+        Ok(states)
+    }
+
+    pub fn audit_chunk(&self, file_path: &str, chunk_index: u64) -> Result<Option<ChunkAudit>, Box<dyn std::error::Error>> {
+        let searcher = self.readers[shards::shard_for(file_path, self.readers.len())].searcher();
+        let query = TermQuery::new(
+            Term::from_field_text(self.path_key_field, file_path),
+            IndexRecordOption::Basic,
+        );
+
+        for address in searcher.search(&query, &DocSetCollector)? {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let audit = self.to_audit(&doc);
+            if audit.chunk.chunk_index == chunk_index {
+                return Ok((!self.blocked.blocks(&audit.chunk)).then_some(audit));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Every stored copy of a chunk in its shard, deleted ones included: a
+    // re-indexed file leaves its old chunks in their segments until a merge
+    pub fn chunk_segments(&self, file_path: &str, chunk_index: u64) -> Result<Vec<SegmentCopy>, Box<dyn std::error::Error>> {
+        let shard = shards::shard_for(file_path, self.readers.len());
+        let term = Term::from_field_text(self.path_key_field, file_path);
+        let mut copies = Vec::new();
+        for segment_reader in self.readers[shard].searcher().segment_readers() {
+            let Some(mut postings) = segment_reader.inverted_index(self.path_key_field)?.read_postings(&term, IndexRecordOption::Basic)? else {
+                continue;
+            };
+            let store = segment_reader.get_store_reader(1)?;
+            while postings.doc() != TERMINATED {
+                let doc = postings.doc();
+                let stored: TantivyDocument = store.get(doc)?;
+                if stored.get_first(self.chunk_index_field).and_then(|v| v.as_u64()) == Some(chunk_index) {
+                    // A blocked chunk reads as missing, same as in audit_chunk
+                    if self.blocked.blocks(&self.to_hit(&stored, 0.0)) {
+                        return Ok(Vec::new());
+                    }
+                    copies.push(SegmentCopy {
+                        shard,
+                        segment: segment_reader.segment_id().uuid_string(),
+                        doc,
+                        deleted: segment_reader.is_deleted(doc),
+                    });
+                }
+                postings.advance();
+            }
+        }
+        Ok(copies)
+    }
+
+    // Every chunk with its provenance, ordered by path and chunk index so the
+    // result doesn't depend on segment layout
+    pub fn audit_all(&self) -> Result<Vec<ChunkAudit>, Box<dyn std::error::Error>> {
+        let mut audits = Vec::new();
+        self.for_each_doc(|doc| {
+            let audit = self.to_audit(doc);
+            if !self.blocked.blocks(&audit.chunk) {
+                audits.push(audit);
+            }
+        })?;
+
+        audits.sort_by(|a, b| (&a.chunk.file_path, a.chunk.chunk_index).cmp(&(&b.chunk.file_path, b.chunk.chunk_index)));
+        Ok(audits)
+    }
+
+    // Chunks containing every trigram of `text`; callers verify the match
+    fn trigram_candidates(&self, text: &str, limit: usize) -> tantivy::Result<Vec<SearchHit>> {
+        let clauses: Vec<(Occur, Box<dyn Query>)> = trigrams(text)
+            .into_iter()
+            .map(|gram| {
+                let query = TermQuery::new(Term::from_field_text(self.trigrams_field, &gram), IndexRecordOption::Basic);
+                (Occur::Must, Box::new(query) as Box<dyn Query>)
+            })
+            .collect();
+
+        let mut hits = self.search_shards(&BooleanQuery::new(clauses), limit)?;
+        for hit in &mut hits {
+            hit.score = 0.0;
+        }
+        Ok(hits)
+    }
+
+    fn for_each_chunk<F>(&self, mut visit: F) -> tantivy::Result<()>
+    where
+        F: FnMut(SearchHit),
+    {
+        self.for_each_doc(|doc| {
+            let hit = self.to_hit(doc, 0.0);
+            if !self.blocked.blocks(&hit) {
+                visit(hit);
+            }
+        })
+    }
+
+    // Visits every live document in the index straight from the doc store;
+    // meant for inspection tooling rather than the query path.
+    fn for_each_doc<F>(&self, mut visit: F) -> tantivy::Result<()>
+    where
+        F: FnMut(&TantivyDocument),
+    {
+        for reader in &self.readers {
+            for segment_reader in reader.searcher().segment_readers() {
+                let store = segment_reader.get_store_reader(1)?;
+                for doc in store.iter::<TantivyDocument>(segment_reader.alive_bitset()) {
+                    visit(&doc?);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn to_hit(&self, doc: &TantivyDocument, score: f32) -> SearchHit {
+        SearchHit {
+            file_path: first_text(doc, self.file_path_field),
+            chunk_index: doc.get_first(self.chunk_index_field).and_then(|v| v.as_u64()).unwrap_or(0),
+            content: first_text(doc, self.content_field),
+            score,
+            modified_time: doc.get_first(self.modified_time_field).and_then(|v| v.as_i64()).unwrap_or(0),
+            chunk_hash: first_text(doc, self.chunk_hash_field),
+            language: first_text(doc, self.language_field),
+            is_test: doc.get_first(self.is_test_field).and_then(|v| v.as_bool()).unwrap_or(false),
+            confidence: None,
+        }
+    }
+
+    fn to_audit(&self, doc: &TantivyDocument) -> ChunkAudit {
+        let chunk = self.to_hit(doc, 0.0);
+        ChunkAudit {
+            chunk_id: chunk_id(&chunk.file_path, chunk.chunk_index),
+            file_hash: first_text(doc, self.file_hash_field),
+            provenance: self.provenance(doc),
+            license_stripped: doc.get_first(self.license_stripped_field).and_then(|v| v.as_bool()).unwrap_or(false),
+            chunk,
+        }
+    }
+
+    fn provenance(&self, doc: &TantivyDocument) -> Provenance {
+        Provenance {
+            run_id: first_text(doc, self.run_id_field),
+            config_hash: first_text(doc, self.config_hash_field),
+            chunker_version: first_text(doc, self.chunker_version_field),
+            model: first_text(doc, self.model_field),
+            indexed_at: doc.get_first(self.indexed_at_field).and_then(|v| v.as_i64()).unwrap_or(0),
+        }
+    }
+
+    fn query_parser(&self) -> QueryParser {
+        QueryParser::for_index(&self.indexes[0], vec![self.content_field, self.file_path_field])
+    }
+
+    fn run_query(&self, query_parser: &QueryParser, query: &str, limit: usize) -> tantivy::Result<Vec<SearchHit>> {
+        let query = query_parser.parse_query(query)?;
+        self.search_shards(query.as_ref(), limit)
+    }
+
+    // Top `limit` hits of each shard, searched in parallel, merged by score.
+    // Shards score against their own term statistics, which for path-hashed
+    // shards of one repository stay close to the global ones.
+    fn search_shards(&self, query: &dyn Query, limit: usize) -> tantivy::Result<Vec<SearchHit>> {
+        let _span = crate::profile::span("query");
+        if let [reader] = self.readers.as_slice() {
+            return self.search_shard(&reader.searcher(), query, limit);
+        }
+
+        let mut hits = std::thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .readers
+                .iter()
+                .map(|reader| scope.spawn(move || self.search_shard(&reader.searcher(), query, limit)))
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().expect("shard search thread panicked"))
+                .collect::<tantivy::Result<Vec<_>>>()
+        })?
+        .concat();
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(limit);
+        Ok(hits)
+    }
+
+    fn search_shard(&self, searcher: &Searcher, query: &dyn Query, limit: usize) -> tantivy::Result<Vec<SearchHit>> {
+        // TopDocs panics on a limit of zero
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+        let top_docs = if self.exclusions.is_empty() {
+            searcher.search(query, &TopDocs::with_limit(limit))?
+        } else {
+            let mut clauses = vec![(Occur::Must, query.box_clone())];
+            clauses.extend(self.exclusions.iter().map(|exclusion| (Occur::MustNot, exclusion.box_clone())));
+            searcher.search(&BooleanQuery::new(clauses), &TopDocs::with_limit(limit))?
+        };
+
+        let mut hits = Vec::with_capacity(top_docs.len());
+        for (score, address) in top_docs {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            hits.push(self.to_hit(&doc, score));
+        }
+
+        Ok(hits)
+    }
+}
+
+fn first_text(doc: &TantivyDocument, field: Field) -> String {
+    doc.get_first(field)
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_utils::{memory_index, CorpusFile};
+
+    // Queries run in parallel, but each result list stays at its query's
+    // position
+    #[test]
+    fn batch_results_follow_query_order() {
+        let topics = ["shard", "ranking", "schema", "fusion", "tokenizer", "quantize", "boilerplate", "provenance"];
+        let files: Vec<CorpusFile> = topics
+            .iter()
+            .map(|topic| CorpusFile { path: format!("./docs/{}.md", topic), content: format!("notes on {}", topic) })
+            .collect();
+        let searcher = memory_index(&files).unwrap();
+
+        let queries: Vec<String> = topics.iter().rev().chain(&["missingterm"]).map(|topic| topic.to_string()).collect();
+        let results = searcher.search_batch(&queries, 5).unwrap();
+        assert_eq!(results.len(), queries.len());
+        for (query, hits) in queries.iter().zip(&results).take(topics.len()) {
+            assert_eq!(hits.iter().map(|hit| hit.file_path.as_str()).collect::<Vec<_>>(), [format!("./docs/{}.md", query)]);
+        }
+        assert!(results.last().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tantivy::directory::MmapDirectory;
+use tantivy::Index;
+
+// Present only in sharded indexes; an index without it is a single tantivy
+// index at the storage path itself
+pub(super) const SHARDS_FILE: &str = "shards.json";
+
+// A sharded index keeps one tantivy index per shard under the storage path.
+// Files are routed by a hash of their path, so all chunks of a file share a
+// shard and per-file deletes touch only that one.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ShardManifest {
+    pub count: usize,
+}
+
+impl ShardManifest {
+    pub fn load(storage_path: &str) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let path = Path::new(storage_path).join(SHARDS_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let manifest: ShardManifest = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("Invalid {}: {}", path.display(), e))?;
+        Ok(Some(manifest))
+    }
+
+    pub fn save(&self, storage_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        super::vectors::write_atomically(&Path::new(storage_path).join(SHARDS_FILE), &serde_json::to_vec(self)?)
+    }
+}
+
+// Shard directories in routing order; the storage path itself when unsharded
+pub fn shard_dirs(storage_path: &str, count: usize) -> Vec<PathBuf> {
+    if count <= 1 {
+        return vec![PathBuf::from(storage_path)];
+    }
+    (0..count).map(|shard| Path::new(storage_path).join(format!("shard-{:03}", shard))).collect()
+}
+
+// FNV-1a over the stored path; stable across runs and platforms, which the
+// std hasher doesn't promise
+pub fn shard_for(file_path: &str, count: usize) -> usize {
+    if count <= 1 {
+        return 0;
+    }
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in file_path.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    (hash % count as u64) as usize
+}
+
+// Every shard of an existing index, sharded or not, on disk or in memory
+pub fn open_indexes(storage_path: &str) -> Result<Vec<Index>, Box<dyn std::error::Error>> {
+    if super::memory::is_memory_storage(storage_path) {
+        return Ok(vec![super::memory::open(storage_path)?]);
+    }
+    let count = ShardManifest::load(storage_path)?.map_or(1, |manifest| manifest.count);
+    shard_dirs(storage_path, count)
+        .iter()
+        .map(|dir| Ok(Index::open(MmapDirectory::open(dir)?)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::indexer::{ContextRagIndexer, ContextRagSearcher};
+    use crate::test_utils::TempDir;
+
+    // Each file lands whole in the shard its path routes to, and searches
+    // read every shard back
+    #[test]
+    fn files_are_routed_to_their_shard() {
+        let dir = TempDir::new("shards").unwrap();
+        let storage = dir.join("index").to_string_lossy().into_owned();
+        let paths: Vec<String> = (0..12).map(|file| format!("./docs/note-{}.md", file)).collect();
+        let mut indexer = ContextRagIndexer::open_sharded(&storage, None, Some(3)).unwrap();
+        for path in &paths {
+            indexer.add_text(path, "sharded routing notes").unwrap();
+        }
+        indexer.commit().unwrap();
+        drop(indexer);
+
+        assert_eq!(ShardManifest::load(&storage).unwrap(), Some(ShardManifest { count: 3 }));
+        let indexes = open_indexes(&storage).unwrap();
+        assert_eq!(indexes.len(), 3);
+        for (shard, index) in indexes.iter().enumerate() {
+            let routed = paths.iter().filter(|path| shard_for(path, 3) == shard).count();
+            assert_eq!(index.reader().unwrap().searcher().num_docs() as usize, routed);
+        }
+        assert!(paths.iter().any(|path| shard_for(path, 3) != shard_for(&paths[0], 3)));
+        assert_eq!(ContextRagSearcher::open(&storage).unwrap().search("routing", 20).unwrap().len(), paths.len());
+
+        // The shard count is fixed once the index exists
+        assert!(ContextRagIndexer::open_sharded(&storage, None, Some(2)).is_err());
+        assert!(ContextRagIndexer::open_sharded(&storage, None, None).is_ok());
+    }
+}
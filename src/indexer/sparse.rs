@@ -0,0 +1,103 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// Weights are stored as repeat counts in the sparse field, so term frequency
+// carries them through the ordinary inverted index
+pub const MAX_WEIGHT: usize = 8;
+// Expansion terms count for less than the words that produced them
+const EXPANSION_WEIGHT: f32 = 0.5;
+
+const STOPWORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "by", "for", "from", "in", "is", "it", "of", "on", "or", "that", "the",
+    "this", "to", "was", "with",
+];
+
+// Weighted terms for a chunk or query: its words, log-scaled by frequency,
+// expanded with the parts of compound identifiers so `parseChunkId` also
+// answers for "chunk id". Sorted by term.
+pub fn encode(text: &str) -> Vec<(String, f32)> {
+    let mut counts: BTreeMap<String, f32> = BTreeMap::new();
+    for word in text.split(|c: char| !c.is_alphanumeric() && c != '_').filter(|w| !w.is_empty()) {
+        *counts.entry(word.to_lowercase()).or_default() += 1.0;
+        let parts = split_identifier(word);
+        if parts.len() > 1 {
+            for part in parts {
+                *counts.entry(part).or_default() += EXPANSION_WEIGHT;
+            }
+        }
+    }
+
+    counts
+        .into_iter()
+        .filter(|(term, _)| term.chars().count() >= 2 && !STOPWORDS.contains(&term.as_str()))
+        .map(|(term, count)| (term, count.ln_1p()))
+        .collect()
+}
+
+// `encode` in the form sparse-vector stores take: each term hashed to a
+// u32 dimension (32-bit FNV-1a, stable across runs and platforms), indices
+// ascending and weights of colliding terms summed
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SparseVector {
+    pub indices: Vec<u32>,
+    pub values: Vec<f32>,
+}
+
+impl SparseVector {
+    pub fn encode(text: &str) -> Self {
+        let mut weights: BTreeMap<u32, f32> = BTreeMap::new();
+        for (term, weight) in encode(text) {
+            *weights.entry(term_index(&term)).or_default() += weight;
+        }
+        let (indices, values) = weights.into_iter().unzip();
+        SparseVector { indices, values }
+    }
+}
+
+pub fn term_index(term: &str) -> u32 {
+    let mut hash: u32 = 0x811c9dc5;
+    for byte in term.bytes() {
+        hash ^= byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
+// Text for the sparse field: each term repeated by its quantized weight
+pub fn field_text(terms: &[(String, f32)]) -> String {
+    let mut text = String::new();
+    for (term, weight) in terms {
+        let repeats = (weight * 3.0).round().clamp(1.0, MAX_WEIGHT as f32) as usize;
+        for _ in 0..repeats {
+            text.push_str(term);
+            text.push(' ');
+        }
+    }
+    text
+}
+
+// snake_case, camelCase and letter/digit boundaries, lowercased
+fn split_identifier(word: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut current = String::new();
+    let mut prev: Option<char> = None;
+
+    for c in word.chars() {
+        let boundary = match prev {
+            _ if c == '_' => true,
+            Some(p) => (p.is_lowercase() && c.is_uppercase()) || (p.is_alphabetic() != c.is_alphabetic() && p != '_'),
+            None => false,
+        };
+        if boundary && !current.is_empty() {
+            parts.push(std::mem::take(&mut current).to_lowercase());
+        }
+        if c != '_' {
+            current.push(c);
+        }
+        prev = Some(c);
+    }
+    if !current.is_empty() {
+        parts.push(current.to_lowercase());
+    }
+    parts
+}
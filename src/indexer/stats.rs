@@ -0,0 +1,59 @@
+use super::vectors::{ann_mode, load_ann_budget, store_path};
+use super::{AnnMode, ContextRagSearcher, VectorStore};
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexStats {
+    pub storage_path: String,
+    pub files: usize,
+    pub chunks: u64,
+    pub segments: usize,
+    pub shards: usize,
+    pub size_bytes: u64,
+    // Segment data the keyword index maps into memory
+    pub mapped_bytes: u64,
+    // Model of the active vector namespace, and how many chunks it covers
+    pub vector_model: Option<String>,
+    pub embedded_chunks: usize,
+    // Estimated heap for the active vector store, and whether hybrid
+    // searches hold it in memory or score it off disk
+    pub vector_memory_bytes: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ann_mode: Option<AnnMode>,
+}
+
+pub fn index_stats(storage_path: &str) -> Result<IndexStats, Box<dyn std::error::Error>> {
+    let searcher = ContextRagSearcher::open(storage_path)?;
+
+    let size_bytes = WalkDir::new(storage_path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.metadata().ok())
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .sum();
+
+    let ann_mode = VectorStore::active_namespace(storage_path)
+        .and_then(|namespace| std::fs::metadata(store_path(storage_path, &namespace)).ok())
+        .map(|metadata| ann_mode(metadata.len(), load_ann_budget(storage_path)));
+    let vectors = VectorStore::load_active(storage_path)?;
+    let embedded_chunks = match &vectors {
+        Some(store) => searcher.all_chunks()?.iter().filter(|c| store.vector_for(c).is_some()).count(),
+        None => 0,
+    };
+
+    Ok(IndexStats {
+        storage_path: storage_path.to_string(),
+        files: searcher.list_files()?.len(),
+        chunks: searcher.num_chunks(),
+        segments: searcher.num_segments(),
+        shards: searcher.num_shards(),
+        size_bytes,
+        mapped_bytes: searcher.mapped_bytes()?,
+        vector_memory_bytes: vectors.as_ref().map_or(0, VectorStore::memory_bytes),
+        vector_model: vectors.map(|store| store.model),
+        embedded_chunks,
+        ann_mode,
+    })
+}
@@ -0,0 +1,74 @@
+use super::clock::modified_time;
+use super::file_content::read_file;
+use super::ignore;
+use super::{indexed_path, pruned_walk, ContentHash, ContextRagSearcher, IndexConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use walkdir::WalkDir;
+
+// How the working tree compares to what was indexed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct IndexStatus {
+    pub indexed_files: usize,
+    pub stale: bool,
+    // Indexed, but the content on disk differs
+    pub changed: Vec<String>,
+    // Indexed, but no longer on disk (or no longer matched by the config)
+    pub missing: Vec<String>,
+    // Matched by the config, but not in the index
+    pub new: Vec<String>,
+}
+
+pub fn index_status(config: &IndexConfig) -> Result<IndexStatus, Box<dyn std::error::Error>> {
+    let indexed = ContextRagSearcher::open(&config.storage_path)?.file_states()?;
+    let content_hash = ContentHash::load(&config.storage_path);
+
+    let mut seen = BTreeSet::new();
+    let mut changed = Vec::new();
+    let mut new = Vec::new();
+
+    for entry in pruned_walk(WalkDir::new("."), config).filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let Some(path_str) = indexed_path(path, config) else {
+            continue;
+        };
+        let Some(state) = indexed.get(&path_str) else {
+            // Indexing leaves marked files out, so they are never new
+            if !marked_ignored(path) {
+                new.push(path_str);
+            }
+            continue;
+        };
+        seen.insert(path_str.clone());
+
+        let modified_time = modified_time(path).ok();
+
+        // Only hash files whose mtime moved; touching a file without editing it is not a change
+        if modified_time != Some(state.modified_time) {
+            let unchanged = fs::read(path)
+                .map(|content| content_hash.hash(&content) == state.file_hash)
+                .unwrap_or(false);
+            if !unchanged {
+                changed.push(path_str);
+            }
+        }
+    }
+
+    let missing: Vec<String> = indexed.keys().filter(|path| !seen.contains(*path)).cloned().collect();
+
+    Ok(IndexStatus {
+        indexed_files: indexed.len(),
+        stale: !(changed.is_empty() && missing.is_empty() && new.is_empty()),
+        changed,
+        missing,
+        new,
+    })
+}
+
+fn marked_ignored(path: &std::path::Path) -> bool {
+    read_file(path).is_ok_and(|content| content.text().is_ok_and(ignore::ignores_file))
+}
@@ -0,0 +1,102 @@
+use super::ContextRagSearcher;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tantivy::postings::Postings;
+use tantivy::schema::{FieldType, IndexRecordOption};
+use tantivy::{DocSet, TERMINATED};
+
+// Term statistics read straight from the keyword index's term dictionaries,
+// for picking stopwords and boosts by evidence rather than by guess
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TermReport {
+    pub storage_path: String,
+    pub chunks: u64,
+    // Every indexed text field, in schema order
+    pub fields: Vec<FieldTerms>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FieldTerms {
+    pub field: String,
+    // Distinct terms in chunks that are still live
+    pub distinct_terms: usize,
+    // Most frequent terms by document frequency; only for the requested fields
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub top_terms: Vec<TermFrequency>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TermFrequency {
+    pub term: String,
+    // Chunks containing the term
+    pub doc_freq: u64,
+    // Occurrences across those chunks; the doc frequency again for fields
+    // indexed without frequencies
+    pub term_freq: u64,
+}
+
+// Cardinalities of every indexed text field and the `limit` most frequent
+// terms of each of `fields`. Counts come from walking postings, so chunks
+// deleted but not yet merged away don't inflate them.
+pub fn term_report(storage_path: &str, fields: &[String], limit: usize) -> Result<TermReport, Box<dyn std::error::Error>> {
+    let searcher = ContextRagSearcher::open(storage_path)?;
+    let readers = searcher.readers();
+    let schema = readers.first().ok_or("Index has no shards")?.searcher().schema().clone();
+
+    let text_fields: Vec<_> = schema
+        .fields()
+        .filter_map(|(field, entry)| match entry.field_type() {
+            FieldType::Str(options) => options.get_indexing_options().map(|indexing| (field, entry.name(), indexing.index_option())),
+            _ => None,
+        })
+        .collect();
+    for name in fields {
+        if !text_fields.iter().any(|(_, field_name, _)| field_name == name) {
+            let known: Vec<&str> = text_fields.iter().map(|(_, field_name, _)| *field_name).collect();
+            return Err(format!("No indexed text field '{}'; try one of {}", name, known.join(", ")).into());
+        }
+    }
+
+    let mut report = Vec::with_capacity(text_fields.len());
+    for (field, name, record_option) in text_fields {
+        let with_freqs = record_option.has_freq();
+        let record_option = if with_freqs { IndexRecordOption::WithFreqs } else { IndexRecordOption::Basic };
+        let mut counts: HashMap<Vec<u8>, (u64, u64)> = HashMap::new();
+        for reader in readers {
+            for segment in reader.searcher().segment_readers() {
+                let inverted_index = segment.inverted_index(field)?;
+                let alive = segment.alive_bitset();
+                let mut terms = inverted_index.terms().stream()?;
+                while terms.advance() {
+                    let mut postings = inverted_index.read_postings_from_terminfo(terms.value(), record_option)?;
+                    let (mut doc_freq, mut term_freq) = (0, 0);
+                    while postings.doc() != TERMINATED {
+                        if alive.is_none_or(|alive| alive.is_alive(postings.doc())) {
+                            doc_freq += 1;
+                            term_freq += if with_freqs { postings.term_freq() as u64 } else { 1 };
+                        }
+                        postings.advance();
+                    }
+                    if doc_freq > 0 {
+                        let entry = counts.entry(terms.key().to_vec()).or_default();
+                        entry.0 += doc_freq;
+                        entry.1 += term_freq;
+                    }
+                }
+            }
+        }
+
+        let mut top_terms = Vec::new();
+        if fields.iter().any(|requested| requested == name) {
+            top_terms = counts
+                .iter()
+                .map(|(term, &(doc_freq, term_freq))| TermFrequency { term: String::from_utf8_lossy(term).into_owned(), doc_freq, term_freq })
+                .collect();
+            top_terms.sort_by(|a, b| b.doc_freq.cmp(&a.doc_freq).then(b.term_freq.cmp(&a.term_freq)).then_with(|| a.term.cmp(&b.term)));
+            top_terms.truncate(limit);
+        }
+        report.push(FieldTerms { field: name.to_string(), distinct_terms: counts.len(), top_terms });
+    }
+
+    Ok(TermReport { storage_path: storage_path.to_string(), chunks: searcher.num_chunks(), fields: report })
+}
@@ -0,0 +1,36 @@
+use std::path::Path;
+
+// Directory names that hold tests in most ecosystems
+const TEST_DIRS: &[&str] = &["test", "tests", "__tests__", "spec", "specs", "testdata", "e2e"];
+// File name shapes: foo_test.go, test_foo.py, foo.test.ts, foo.spec.js, FooTest.java, FooTests.cs
+const TEST_SUFFIXES: &[&str] = &["_test", ".test", ".spec", "_spec", "Test", "Tests"];
+// Markers that make a chunk test code wherever it lives, e.g. Rust's inline test modules
+const TEST_MARKERS: &[&str] = &[
+    "#[test]",
+    "#[cfg(test)]",
+    "#[tokio::test]",
+    "describe(",
+    "it(\"",
+    "it('",
+    "test(\"",
+    "test('",
+    "def test_",
+    "@Test",
+    "func Test",
+];
+
+// Test or production code, from the path first and then the chunk itself.
+// Prose is never test code.
+pub fn is_test_chunk(path: &Path, chunk: &str, language: &str) -> bool {
+    if !super::language::is_code(language) {
+        return false;
+    }
+
+    let in_test_dir = path
+        .components()
+        .any(|component| component.as_os_str().to_str().is_some_and(|name| TEST_DIRS.contains(&name)));
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+    let test_name = stem.starts_with("test_") || TEST_SUFFIXES.iter().any(|suffix| stem.ends_with(suffix));
+
+    in_test_dir || test_name || TEST_MARKERS.iter().any(|marker| chunk.contains(marker))
+}
@@ -0,0 +1,67 @@
+use super::eval::EvalCase;
+use super::hybrid::{hybrid_channels, Channels, FusionWeights, CANDIDATE_FACTOR};
+use serde::{Deserialize, Serialize};
+
+// Candidate weights per channel; with RRF only the ratios matter, so the
+// vector weight stays at 1 whenever vectors exist
+const GRID: &[f32] = &[0.0, 0.25, 0.5, 0.75, 1.0, 1.5, 2.0, 3.0];
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FusionTuning {
+    pub best: FusionWeights,
+    // Mean reciprocal rank over the eval set at the tuned limit
+    pub mrr: f32,
+    pub baseline_mrr: f32,
+    pub cases: usize,
+    pub combinations: usize,
+    // Whether the vector channel took part; without vectors only keyword and
+    // identifier weights are tuned
+    pub with_vectors: bool,
+}
+
+// Grid search over fusion weights. Each query's channel rankings are
+// computed once and re-fused for every combination, so the cost is one
+// search per case however fine the grid.
+pub fn tune_fusion(storage_path: &str, cases: &[EvalCase], limit: usize) -> Result<FusionTuning, Box<dyn std::error::Error>> {
+    let runs: Vec<(&EvalCase, Channels)> = cases
+        .iter()
+        .map(|case| Ok((case, hybrid_channels(storage_path, &case.query, limit * CANDIDATE_FACTOR)?)))
+        .collect::<Result<_, Box<dyn std::error::Error>>>()?;
+    let with_vectors = runs.iter().any(|(_, channels)| channels.model.is_some());
+
+    let mrr = |weights: &FusionWeights| -> f32 {
+        let total: f32 = runs
+            .iter()
+            .map(|(case, channels)| case.reciprocal_rank(&channels.fuse(weights, limit)))
+            .sum();
+        total / runs.len() as f32
+    };
+
+    let baseline = FusionWeights::default();
+    let baseline_mrr = mrr(&baseline);
+    let (mut best, mut best_mrr) = (baseline, baseline_mrr);
+    let mut combinations = 0;
+
+    let keyword_grid: &[f32] = if with_vectors { GRID } else { &[1.0] };
+    for &keyword in keyword_grid {
+        for &identifier in GRID {
+            let weights = FusionWeights { keyword, identifier, vector: 1.0 };
+            combinations += 1;
+            // Strictly better only, so ties keep the defaults
+            let score = mrr(&weights);
+            if score > best_mrr {
+                best = weights;
+                best_mrr = score;
+            }
+        }
+    }
+
+    Ok(FusionTuning {
+        best,
+        mrr: best_mrr,
+        baseline_mrr,
+        cases: runs.len(),
+        combinations,
+        with_vectors,
+    })
+}
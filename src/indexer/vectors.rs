@@ -0,0 +1,480 @@
+use super::hybrid::dot;
+use super::language::is_code;
+use super::{ContextRagSearcher, SearchHit};
+use crate::embedding::{dimensions, embed_documents, embed_query, shorten};
+use serde::de::{DeserializeSeed, IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const VECTORS_DIR: &str = "vectors";
+// Names the namespace searches read from
+const ACTIVE_FILE: &str = "ACTIVE";
+// Bytes a search may spend holding a vector store; written at index time
+const ANN_BUDGET_FILE: &str = "ann-memory-budget";
+// Key and vector headers plus a hash table slot, per stored vector
+const ENTRY_OVERHEAD: usize = 64;
+// Backfills save this often so hybrid search picks up vectors as they land
+const SAVE_EVERY: usize = 256;
+const MAX_FAILED_BATCHES_IN_A_ROW: usize = 3;
+
+// Chunk embeddings for one model, stored next to the keyword index and keyed
+// by chunk content hash. Chunks without an entry have not been embedded yet.
+// With a code model, source chunks are embedded by it instead and kept
+// apart, since the two models' vectors can't be compared.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct VectorStore {
+    pub model: String,
+    pub dimensions: usize,
+    pub vectors: HashMap<String, Vec<f32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_model: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub code_vectors: HashMap<String, Vec<f32>>,
+}
+
+// Where a search keeps vectors while scoring them. A store over the
+// index's memory budget is scored as it streams off disk instead.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnnMode {
+    Memory,
+    Disk,
+}
+
+// Query similarity of every stored vector, by chunk hash
+#[derive(Debug)]
+pub struct VectorScores {
+    pub model: String,
+    pub code_model: Option<String>,
+    pub scores: HashMap<String, f32>,
+    pub code_scores: HashMap<String, f32>,
+    pub mode: AnnMode,
+}
+
+impl VectorScores {
+    // Scores one namespace for `query`, loading it whole when it fits the
+    // index's memory budget
+    pub fn load(storage_path: &str, namespace: &str, query: &str) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let path = store_path(storage_path, namespace);
+        let Ok(metadata) = fs::metadata(&path) else {
+            return Ok(None);
+        };
+        // JSON spends more than four bytes on every float, so the file
+        // outweighs the vectors it holds
+        if ann_mode(metadata.len(), load_ann_budget(storage_path)) == AnnMode::Memory {
+            return Ok(VectorStore::load(storage_path, namespace)?.map(|store| store.scores(query)).transpose()?);
+        }
+
+        let reader = std::io::BufReader::new(fs::File::open(&path)?);
+        let scores = StoreScorer { query }
+            .deserialize(&mut serde_json::Deserializer::from_reader(reader))
+            .map_err(|e| format!("Invalid vector store {}: {}", path.display(), e))?;
+        Ok(Some(scores))
+    }
+
+    pub fn score_for(&self, chunk: &SearchHit) -> Option<f32> {
+        if self.code_model.is_some() && is_code(&chunk.language) {
+            self.code_scores.get(&chunk.chunk_hash).copied()
+        } else {
+            self.scores.get(&chunk.chunk_hash).copied()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty() && self.code_scores.is_empty()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BackfillResult {
+    pub model: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_model: Option<String>,
+    pub embedded: usize,
+    pub reused: usize,
+    pub total_chunks: usize,
+    // Chunks whose batch the engine still failed on after its retries; they
+    // stay without vectors until the next backfill
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed: Option<FailedChunks>,
+    pub processing_time_ms: u128,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FailedChunks {
+    pub chunks: usize,
+    // The first failed batch's error
+    pub error: String,
+}
+
+impl VectorStore {
+    pub fn new(model: &str) -> Self {
+        VectorStore {
+            model: model.to_string(),
+            ..Default::default()
+        }
+    }
+
+    // Switching code models drops the old model's code vectors
+    pub fn with_code_model(mut self, code_model: Option<&str>) -> Self {
+        if self.code_model.as_deref() != code_model {
+            self.code_model = code_model.map(str::to_string);
+            self.code_vectors.clear();
+        }
+        self
+    }
+
+    // The model that embeds this chunk
+    pub fn model_for(&self, chunk: &SearchHit) -> &str {
+        match &self.code_model {
+            Some(code_model) if is_code(&chunk.language) => code_model,
+            _ => &self.model,
+        }
+    }
+
+    pub fn vector_for(&self, chunk: &SearchHit) -> Option<&[f32]> {
+        if self.code_model.is_some() && is_code(&chunk.language) {
+            self.code_vectors.get(&chunk.chunk_hash).map(|v| v.as_slice())
+        } else {
+            self.get(&chunk.chunk_hash)
+        }
+    }
+
+    pub fn insert_for(&mut self, chunk: &SearchHit, vector: Vec<f32>) {
+        if self.code_model.is_some() && is_code(&chunk.language) {
+            self.code_vectors.insert(chunk.chunk_hash.clone(), vector);
+        } else {
+            self.insert(chunk.chunk_hash.clone(), vector);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty() && self.code_vectors.is_empty()
+    }
+
+    // Estimated heap held by the loaded store
+    pub fn memory_bytes(&self) -> u64 {
+        self.vectors
+            .iter()
+            .chain(&self.code_vectors)
+            .map(|(hash, vector)| (hash.len() + vector.len() * 4 + ENTRY_OVERHEAD) as u64)
+            .sum()
+    }
+
+    pub fn scores(&self, query: &str) -> Result<VectorScores, String> {
+        let score_all = |model: &str, vectors: &HashMap<String, Vec<f32>>| -> Result<HashMap<String, f32>, String> {
+            let query_vector = embed_query(model, query)?;
+            Ok(vectors.iter().map(|(hash, vector)| (hash.clone(), dot(&query_vector, vector))).collect())
+        };
+        Ok(VectorScores {
+            scores: score_all(&self.model, &self.vectors)?,
+            code_scores: self.code_model.as_deref().map(|m| score_all(m, &self.code_vectors)).transpose()?.unwrap_or_default(),
+            model: self.model.clone(),
+            code_model: self.code_model.clone(),
+            mode: AnnMode::Memory,
+        })
+    }
+
+    // One namespace per model, named after it
+    pub fn namespace(model: &str) -> String {
+        model
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '.' { c } else { '_' })
+            .collect()
+    }
+
+    pub fn load(storage_path: &str, namespace: &str) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        let path = store_path(storage_path, namespace);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let store = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| format!("Invalid vector store {}: {}", path.display(), e))?;
+        Ok(Some(store))
+    }
+
+    pub fn load_active(storage_path: &str) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match Self::active_namespace(storage_path) {
+            Some(namespace) => Self::load(storage_path, &namespace),
+            None => Ok(None),
+        }
+    }
+
+    pub fn active_namespace(storage_path: &str) -> Option<String> {
+        let namespace = fs::read_to_string(vectors_dir(storage_path).join(ACTIVE_FILE)).ok()?;
+        Some(namespace.trim().to_string()).filter(|n| !n.is_empty())
+    }
+
+    pub fn set_active(storage_path: &str, namespace: &str) -> Result<(), Box<dyn std::error::Error>> {
+        write_atomically(&vectors_dir(storage_path).join(ACTIVE_FILE), namespace.as_bytes())
+    }
+
+    pub fn save(&self, storage_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let path = store_path(storage_path, &Self::namespace(&self.model));
+        write_atomically(&path, &serde_json::to_vec(self)?)
+    }
+
+    pub fn get(&self, chunk_hash: &str) -> Option<&[f32]> {
+        self.vectors.get(chunk_hash).map(|v| v.as_slice())
+    }
+
+    pub fn insert(&mut self, chunk_hash: String, vector: Vec<f32>) {
+        self.dimensions = vector.len();
+        self.vectors.insert(chunk_hash, vector);
+    }
+}
+
+// Embeds every indexed chunk that has no vector yet for `model`, and drops
+// vectors whose chunks are gone. The first namespace backfilled becomes the
+// active one.
+pub fn backfill_vectors<F>(
+    storage_path: &str,
+    model: &str,
+    code_model: Option<&str>,
+    on_progress: F,
+) -> Result<BackfillResult, Box<dyn std::error::Error>>
+where
+    F: FnMut(usize, usize),
+{
+    let store = VectorStore::load(storage_path, &VectorStore::namespace(model))?
+        .unwrap_or_else(|| VectorStore::new(model))
+        .with_code_model(code_model);
+    let result = fill_store(storage_path, store, true, on_progress)?;
+
+    if VectorStore::active_namespace(storage_path).is_none() {
+        VectorStore::set_active(storage_path, &VectorStore::namespace(model))?;
+    }
+    Ok(result)
+}
+
+// Embeds every stored chunk with `model` into a fresh namespace, using the
+// content kept in the index rather than walking the tree again, then makes
+// it the active namespace. Searches keep using the previous model until the
+// switch; the old namespace is left in place.
+pub fn reembed_vectors<F>(
+    storage_path: &str,
+    model: &str,
+    code_model: Option<&str>,
+    on_progress: F,
+) -> Result<BackfillResult, Box<dyn std::error::Error>>
+where
+    F: FnMut(usize, usize),
+{
+    let store = VectorStore::new(model).with_code_model(code_model);
+    let result = fill_store(storage_path, store, false, on_progress)?;
+    VectorStore::set_active(storage_path, &VectorStore::namespace(model))?;
+    Ok(result)
+}
+
+// Partial saves let hybrid search use vectors while a backfill runs; a
+// re-embed only writes once it's complete, so re-embedding the active model
+// never exposes a half-filled namespace.
+fn fill_store<F>(storage_path: &str, mut store: VectorStore, save_partial: bool, mut on_progress: F) -> Result<BackfillResult, Box<dyn std::error::Error>>
+where
+    F: FnMut(usize, usize),
+{
+    let start_time = std::time::Instant::now();
+
+    let chunks = ContextRagSearcher::open(storage_path)?.all_chunks()?;
+    let live: HashSet<&str> = chunks.iter().map(|c| c.chunk_hash.as_str()).collect();
+    store.vectors.retain(|hash, _| live.contains(hash.as_str()));
+    store.code_vectors.retain(|hash, _| live.contains(hash.as_str()));
+    // Vectors from a run with more dimensions than --dimensions asks for are
+    // shortened like fresh ones; those with fewer need embedding again
+    if let Some(dimensions) = dimensions() {
+        for vectors in [&mut store.vectors, &mut store.code_vectors] {
+            vectors.retain(|_, vector| vector.len() >= dimensions);
+            vectors.values_mut().for_each(shorten);
+        }
+        store.dimensions = store.dimensions.min(dimensions);
+    }
+
+    let missing: Vec<_> = chunks.iter().filter(|c| store.vector_for(c).is_none()).collect();
+    // Identical content appearing in several chunks is embedded once
+    let mut queued = HashSet::new();
+    let pending: Vec<_> = missing.iter().filter(|c| queued.insert((store.model_for(c).to_string(), c.chunk_hash.as_str()))).collect();
+    let mut embedded = 0;
+    let mut attempted = 0;
+    // A batch that still fails after the engine's retries is skipped, so one
+    // bad batch doesn't cost a long run what it has done; several in a row
+    // mean the provider is down and the run stops
+    let mut first_error = None;
+    let mut failures_in_a_row = 0;
+    'batches: for batch in pending.chunks(SAVE_EVERY) {
+        // Texts go to the engine a batch at a time, split by the model
+        // that embeds them
+        let mut by_model: HashMap<String, Vec<&SearchHit>> = HashMap::new();
+        for chunk in batch {
+            by_model.entry(store.model_for(chunk).to_string()).or_default().push(chunk);
+        }
+        for (model, group) in by_model {
+            let texts: Vec<String> = group.iter().map(|chunk| chunk.content.clone()).collect();
+            attempted += group.len();
+            match embed_documents(&model, &texts) {
+                Ok(vectors) => {
+                    embedded += group.len();
+                    failures_in_a_row = 0;
+                    for (chunk, vector) in group.into_iter().zip(vectors) {
+                        store.insert_for(chunk, vector);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Embedding {} chunks with {} failed: {}", group.len(), model, e);
+                    first_error.get_or_insert(e);
+                    failures_in_a_row += 1;
+                    if failures_in_a_row == MAX_FAILED_BATCHES_IN_A_ROW {
+                        break 'batches;
+                    }
+                }
+            }
+        }
+
+        if attempted < pending.len() {
+            if save_partial {
+                store.save(storage_path)?;
+            }
+            on_progress(attempted, pending.len());
+        }
+    }
+    let failed = match first_error {
+        // Nothing to keep, and most likely the setup rather than an outage
+        Some(error) if embedded == 0 => return Err(error.into()),
+        Some(error) => Some(FailedChunks { chunks: pending.len() - embedded, error }),
+        None => None,
+    };
+
+    store.save(storage_path)?;
+    on_progress(attempted, pending.len());
+
+    Ok(BackfillResult {
+        model: store.model,
+        code_model: store.code_model,
+        embedded,
+        reused: chunks.len() - missing.len(),
+        total_chunks: chunks.len(),
+        failed,
+        processing_time_ms: start_time.elapsed().as_millis(),
+    })
+}
+
+pub fn ann_mode(store_bytes: u64, budget: Option<u64>) -> AnnMode {
+    match budget {
+        Some(budget) if store_bytes > budget => AnnMode::Disk,
+        _ => AnnMode::Memory,
+    }
+}
+
+pub fn load_ann_budget(storage_path: &str) -> Option<u64> {
+    fs::read_to_string(vectors_dir(storage_path).join(ANN_BUDGET_FILE)).ok()?.trim().parse().ok()
+}
+
+pub(super) fn save_ann_budget(storage_path: &str, budget: Option<u64>) -> Result<(), Box<dyn std::error::Error>> {
+    let path = vectors_dir(storage_path).join(ANN_BUDGET_FILE);
+    match budget {
+        Some(budget) => write_atomically(&path, budget.to_string().as_bytes()),
+        None if path.exists() => Ok(fs::remove_file(path)?),
+        None => Ok(()),
+    }
+}
+
+// Reads a store one vector at a time, keeping only each vector's score.
+// Relies on `model` and `code_model` being written before the maps they
+// embed, which serde does in field order.
+struct StoreScorer<'a> {
+    query: &'a str,
+}
+
+impl<'de> DeserializeSeed<'de> for StoreScorer<'_> {
+    type Value = VectorScores;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for StoreScorer<'_> {
+    type Value = VectorScores;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a vector store")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut scores = VectorScores {
+            model: String::new(),
+            code_model: None,
+            scores: HashMap::new(),
+            code_scores: HashMap::new(),
+            mode: AnnMode::Disk,
+        };
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "model" => scores.model = map.next_value()?,
+                "code_model" => scores.code_model = map.next_value()?,
+                "vectors" if scores.model.is_empty() => return Err(serde::de::Error::custom("vectors come before the model")),
+                "vectors" => {
+                    let query_vector = embed_query(&scores.model, self.query).map_err(serde::de::Error::custom)?;
+                    scores.scores = map.next_value_seed(ScoreMap { query_vector: &query_vector })?;
+                }
+                "code_vectors" if scores.code_model.is_some() => {
+                    let query_vector =
+                        embed_query(scores.code_model.as_deref().unwrap_or_default(), self.query).map_err(serde::de::Error::custom)?;
+                    scores.code_scores = map.next_value_seed(ScoreMap { query_vector: &query_vector })?;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(scores)
+    }
+}
+
+struct ScoreMap<'a> {
+    query_vector: &'a [f32],
+}
+
+impl<'de> DeserializeSeed<'de> for ScoreMap<'_> {
+    type Value = HashMap<String, f32>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for ScoreMap<'_> {
+    type Value = HashMap<String, f32>;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("a map of chunk hashes to vectors")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut scores = HashMap::new();
+        while let Some((hash, vector)) = map.next_entry::<String, Vec<f32>>()? {
+            scores.insert(hash, dot(self.query_vector, &vector));
+        }
+        Ok(scores)
+    }
+}
+
+fn vectors_dir(storage_path: &str) -> PathBuf {
+    Path::new(storage_path).join(VECTORS_DIR)
+}
+
+pub(super) fn store_path(storage_path: &str, namespace: &str) -> PathBuf {
+    vectors_dir(storage_path).join(format!("{}.json", namespace))
+}
+
+// Readers never observe a half-written file
+pub(super) fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, contents)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,220 @@
+use anyhow::Result;
+use context_rag_indexer::config::{CONFIG_FILE, DEFAULT_MODEL, DEFAULT_STORAGE_PATH};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+// Directories that are never worth indexing; excluded when present
+const COMMON_EXCLUDES: &[&str] = &[
+    ".git", "node_modules", "target", "dist", "build", "vendor", ".venv", "venv", "__pycache__", ".next", "coverage",
+];
+
+const LANGUAGES: &[(&str, &[&str])] = &[
+    ("Rust", &["rs"]),
+    ("JavaScript", &["js", "jsx", "mjs", "cjs"]),
+    ("TypeScript", &["ts", "tsx"]),
+    ("Python", &["py"]),
+    ("Go", &["go"]),
+    ("Java", &["java"]),
+    ("Kotlin", &["kt"]),
+    ("Ruby", &["rb"]),
+    ("PHP", &["php"]),
+    ("C#", &["cs"]),
+    ("C/C++", &["c", "h", "cc", "cpp", "hpp"]),
+    ("Swift", &["swift"]),
+];
+
+struct Detection {
+    // language -> (file count, extensions seen)
+    languages: BTreeMap<&'static str, (usize, Vec<&'static str>)>,
+    excludes: Vec<String>,
+    has_docs_dir: bool,
+    markdown_files: usize,
+}
+
+pub fn run(root: &Path, force: bool, preset: Option<&str>) -> Result<()> {
+    let config_path = root.join(CONFIG_FILE);
+    if config_path.exists() && !force {
+        return Err(anyhow::anyhow!("{} already exists (use --force to overwrite)", config_path.display()));
+    }
+
+    let detection = detect(root);
+    fs::write(&config_path, render(&detection, preset))?;
+
+    println!("Wrote {}", config_path.display());
+    if detection.languages.is_empty() {
+        println!("No source languages detected; indexing markdown and docs only");
+    } else {
+        let names: Vec<_> = detection.languages.keys().copied().collect();
+        println!("Detected languages: {}", names.join(", "));
+    }
+    println!("Next: review the file, then run `context-rag-embedder index`");
+    Ok(())
+}
+
+fn detect(root: &Path) -> Detection {
+    let mut detection = Detection {
+        languages: BTreeMap::new(),
+        excludes: vec![".git/".to_string(), ".context-rag/".to_string()],
+        has_docs_dir: root.join("docs").is_dir(),
+        markdown_files: 0,
+    };
+
+    for name in COMMON_EXCLUDES {
+        let pattern = format!("{}/", name);
+        if root.join(name).is_dir() && !detection.excludes.contains(&pattern) {
+            detection.excludes.push(pattern);
+        }
+    }
+
+    let walker = WalkDir::new(root).into_iter().filter_entry(|entry| {
+        entry.depth() == 0 || !COMMON_EXCLUDES.iter().any(|name| entry.file_name() == *name)
+    });
+
+    for entry in walker.filter_map(|e| e.ok()).filter(|e| e.file_type().is_file()) {
+        let Some(ext) = entry.path().extension().and_then(|e| e.to_str()) else {
+            continue;
+        };
+
+        if ext == "md" {
+            detection.markdown_files += 1;
+            continue;
+        }
+
+        for (language, extensions) in LANGUAGES {
+            if let Some(known) = extensions.iter().find(|known| **known == ext) {
+                let (count, seen) = detection.languages.entry(language).or_insert((0, Vec::new()));
+                *count += 1;
+                if !seen.contains(known) {
+                    seen.push(known);
+                }
+            }
+        }
+    }
+
+    detection
+}
+
+fn render(detection: &Detection, preset: Option<&str>) -> String {
+    let mut include = vec!["\"README.md\"".to_string(), "\"*.md\"".to_string()];
+    if detection.has_docs_dir {
+        include.push("\"docs/\"".to_string());
+    }
+    for (_, extensions) in detection.languages.values() {
+        for ext in extensions {
+            include.push(format!("\"*.{}\"", ext));
+        }
+    }
+    let exclude: Vec<String> = detection.excludes.iter().map(|e| format!("\"{}\"", e)).collect();
+
+    let mut summary = String::new();
+    for (language, (count, _)) in &detection.languages {
+        summary.push_str(&format!("#   {:<12} {} files\n", language, count));
+    }
+    summary.push_str(&format!("#   {:<12} {} files\n", "Markdown", detection.markdown_files));
+
+    let embedder = match preset {
+        Some(preset) => format!(
+            r#"# Model preset: picks a vetted model together with its dimensions,
+# query/document prefixes and pooling.
+preset = "{}""#,
+            preset
+        ),
+        None => format!(
+            r#"# Sentence-transformer used for embeddings. all-MiniLM-L6-v2 (384 dims) is a
+# fast general-purpose default for mixed code and prose.
+model = "{}"
+
+# Or pick a preset: "fast", "quality", "multilingual" or "code".
+# preset = "multilingual"
+
+# Embed source files with a code model instead, chosen per chunk by its
+# detected language; docs and markdown stay on the model above.
+# code_model = "jinaai/jina-embeddings-v2-base-code""#,
+            DEFAULT_MODEL
+        ),
+    };
+
+    format!(
+        r#"# context-rag project configuration
+#
+# Generated by `context-rag-embedder init` from what was found in this repo:
+{summary}
+[index]
+# Or start from a curated preset ("node", "rust", "python" or "monorepo");
+# the lists below then add to its includes, excludes and pruned directories.
+# preset = "rust"
+
+# Patterns are matched against paths relative to the project root:
+#   "dir/"   everything under a directory
+#   "*.ext"  every file with that extension
+#   "name"   any path containing the text
+include = [{include}]
+
+# Excludes win over includes.
+exclude = [{exclude}]
+
+# Where the search index is written.
+storage_path = "{storage}"
+
+# Keyword tokenizer, fixed when the index is first built. Use "cjk" for
+# Chinese, Japanese or Korean docs ("jieba" needs the cjk-jieba feature).
+# tokenizer = "cjk"
+
+# Strip lines that appear in at least this fraction of files, such as license
+# headers and generated banners, so they don't dominate keyword scores.
+# boilerplate_threshold = 0.5
+
+# Drop leading license comments (SPDX lines, copyright blocks) before
+# chunking, so the first chunk of every file isn't the same header.
+# strip_license_headers = true
+
+# Index code comments and docstrings separately, so natural-language
+# searches can weight them up with `search --comment-boost 2`.
+# extract_comments = true
+
+# Split very large indexes into this many shards by path hash, so segments
+# and commits stay small; fixed when the index is first built.
+# shards = 8
+
+# Leave out files bigger than this, such as generated dumps or vendored
+# bundles; `analyze coverage` lists what it skipped.
+# max_file_size = "1MB"
+
+# Directories skipped wherever they appear, without walking their contents,
+# and how many levels below the root to look; large trees index faster.
+# prune_dirs = ["node_modules", "target", ".git"]
+# max_depth = 6
+
+# Memory hybrid search may use to hold vectors; bigger stores are scored
+# straight from disk instead, which is slower but doesn't swap.
+# max_ann_memory = "512MB"
+
+# Hash used to detect changed files and chunks. BLAKE3 is the fast default;
+# "sha256" suits snapshots that get signed. Takes effect on a full re-index.
+# content_hash = "sha256"
+
+# Paths and words no search may ever return, whatever the caller asks for.
+# [index.blocklist]
+# paths = ["**/secrets/**", "legacy/*.md"]
+# terms = ["deprecated_api"]
+
+[embedder]
+{embedder}
+
+[context]
+# Files, or single `path#chunk` chunks, that `context` always puts ahead of
+# search results, such as architecture notes or a style guide.
+# pinned = ["ARCHITECTURE.md"]
+
+# Estimated tokens an assembled context may hold, pins included.
+# max_tokens = 2000
+"#,
+        summary = summary,
+        include = include.join(", "),
+        exclude = exclude.join(", "),
+        storage = DEFAULT_STORAGE_PATH,
+        embedder = embedder,
+    )
+}
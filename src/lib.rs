@@ -1 +1,25 @@
-pub mod indexer;
\ No newline at end of file
+pub mod bench;
+pub mod cache;
+#[cfg(feature = "candle")]
+pub mod candle;
+pub mod config;
+pub mod daemon;
+pub mod embedding;
+#[cfg(feature = "gguf")]
+pub mod gguf;
+pub mod indexer;
+pub mod models;
+pub mod ollama;
+#[cfg(feature = "onnx")]
+pub mod onnx;
+pub mod openai;
+pub mod profile;
+pub mod quantize;
+pub mod recording;
+pub mod remote;
+pub mod rerank;
+pub mod selftest;
+pub mod server;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+pub mod usage;
@@ -1,138 +1,1961 @@
-use std::env;
-use std::io::{self, Read};
-use serde_json::{json, Value};
+use std::io::{self, BufRead, Read, Write};
+use std::sync::Arc;
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand};
+use serde_json::json;
 use anyhow::Result;
+use context_rag_indexer::bench;
+use context_rag_indexer::cache;
+use context_rag_indexer::config::{ProjectConfig, CONFIG_FILE, DEFAULT_MODEL, DEFAULT_STORAGE_PATH};
+use context_rag_indexer::daemon::{self, DaemonOptions};
+use context_rag_indexer::embedding::{self, EmbedMode, PRESETS};
+use context_rag_indexer::indexer::{
+    assemble_context, assess_answerability, backfill_vectors, coverage_report, diff_hunks, diff_snapshots, export_chunks,
+    export_training_pairs, hybrid_search_model, hybrid_search_weighted, index_stats, index_status, inspect_chunk, is_memory_storage,
+    load_eval_set, parse_chunk_id, preview_chunks, project_chunks, reembed_vectors, search_with_fallback, search_with_refresh, term_report, BackfillResult, ContextRagIndexer,
+    ContextRagSearcher, FallbackLadder, FallbackSearch, FallbackSearcher, HybridSearch, ReviewSession, SearchHit, SearchOptions, VectorStore,
+    MEMORY_STORAGE,
+};
+use context_rag_indexer::indexer::review::DEFAULT_CONTEXT_LINES;
+use context_rag_indexer::models;
+use context_rag_indexer::profile::{self, CountingAllocator};
+use context_rag_indexer::quantize::Quantization;
+use context_rag_indexer::recording::{self, Recording};
+use context_rag_indexer::remote;
+use context_rag_indexer::rerank::{self, DEFAULT_RERANK_MODEL};
+use context_rag_indexer::selftest;
+use context_rag_indexer::server::{ServerState, DEFAULT_SEARCH_LIMIT};
+use context_rag_indexer::usage;
+
+mod init;
+mod output;
+mod repl;
+
+// Lets --profile count allocations; otherwise just forwards to the system allocator
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Embedding and index engine for context-rag.
+///
+/// The top-level `--text`/`--model` flags keep the stdin interface used by the
+/// Node.js side; everything else lives in subcommands.
+#[derive(Parser)]
+#[command(name = "context-rag-embedder", version, args_conflicts_with_subcommands = true, arg_required_else_help = true)]
+#[command(group(ArgGroup::new("model_source").args(["model", "preset"])))]
+#[command(group(ArgGroup::new("model_given").args(["model", "preset", "model_path"]).multiple(true)))]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Embed a single text and print its vector
+    #[arg(long, requires = "model_given")]
+    text: Option<String>,
+
+    /// Model name; without --text, embeds the `chunks` array read from stdin
+    #[arg(long)]
+    model: Option<String>,
+
+    /// Vetted model preset to use instead of --model
+    #[arg(long, value_parser = preset_names())]
+    preset: Option<String>,
+
+    /// Print int8 or binary vectors with their scale instead of floats
+    #[arg(long, requires = "model_given", value_parser = ["int8", "binary"])]
+    quantize: Option<String>,
+
+    /// Stream chunks as JSON lines instead of one `chunks` document: each
+    /// stdin line is a chunk, each stdout line that chunk embedded, so
+    /// inputs of any size run in constant memory
+    #[arg(long, requires = "model_given", conflicts_with = "text")]
+    ndjson: bool,
+
+    /// Embed as search queries or as documents, with the instruction prefix
+    /// the model expects for that side ("query: " and "passage: " for e5,
+    /// bge's retrieval instruction before queries). Defaults to query for
+    /// --text and document for chunks
+    #[arg(long, requires = "model_given", value_parser = ["query", "document"])]
+    mode: Option<String>,
+
+    /// Also print each text's term weights as a sparse vector,
+    /// `{"indices": [...], "values": [...]}` with terms hashed to u32
+    /// dimensions, for stores that fuse sparse and dense retrieval
+    #[arg(long, requires = "model_given")]
+    sparse: bool,
+
+    /// Load the model once and keep answering JSON lines on stdin, one
+    /// answer per line, until stdin closes or a line is {"exit": true}:
+    /// {"chunks": [...]} as without --daemon, or {"text": "..."} as --text
+    /// prints it. An "id" is echoed back and a "mode" overrides --mode
+    #[arg(long, requires = "model_given", conflicts_with_all = ["text", "ndjson"])]
+    daemon: bool,
+
+    /// Serve the --daemon protocol on this Unix socket instead of stdin,
+    /// one connection per client, until one asks to exit
+    #[arg(long, requires = "daemon")]
+    socket: Option<String>,
+
+    /// `mock` for stand-in vectors, `onnx` to run the model with ONNX Runtime,
+    /// `candle` to run it in pure Rust or `gguf` to run a quantized GGUF file
+    /// (each needs the feature of the same name; models are read from
+    /// $CONTEXT_RAG_MODEL_DIR/<model>, default ~/.cache/context-rag/models).
+    /// `openai` calls $OPENAI_BASE_URL/embeddings (default OpenAI's API)
+    /// with $OPENAI_API_KEY; `ollama` calls the Ollama server at $OLLAMA_HOST
+    /// (default 127.0.0.1:11434), --model naming an Ollama model. Without
+    /// --engine the mock engine stands in, with a warning on stderr and in
+    /// every response
+    #[arg(long, global = true, value_parser = ["mock", "onnx", "candle", "gguf", "openai", "ollama"])]
+    engine: Option<String>,
+
+    /// Model file for `--engine gguf`, e.g. a bge-small .gguf; names the
+    /// model after the file when --model is not given. Only the `bert`
+    /// architecture (bge, MiniLM, e5, ...) is supported: nomic-bert and
+    /// other variants are refused when the file is loaded
+    #[arg(long, global = true)]
+    model_path: Option<std::path::PathBuf>,
+
+    /// Texts per forward pass for the onnx, candle and gguf engines; texts
+    /// longer than the model's max tokens are split across windows
+    #[arg(long, global = true, default_value_t = embedding::DEFAULT_BATCH_SIZE, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    batch_size: usize,
+
+    /// Keep only the first N dimensions of every vector, made unit length
+    /// again; for matryoshka-trained models such as text-embedding-3 or
+    /// nomic-embed-text. Use the same N for indexing and searching; indexed
+    /// vectors are shortened in place, and only `reembed` brings them back
+    #[arg(long, global = true, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    dimensions: Option<usize>,
+
+    /// Where the onnx, candle and gguf engines run the model; falls back to
+    /// the CPU when the GPU can't be used. candle and gguf need a build with
+    /// the `cuda` or `metal` feature, onnx an ONNX Runtime built with CUDA
+    /// or CoreML
+    #[arg(long, global = true, default_value = "cpu", value_parser = ["cpu", "cuda", "metal"])]
+    device: String,
+
+    /// Threads embedding at once on the CPU: the candle, gguf and mock
+    /// engines split texts across them, onnx runs each batch's operators on
+    /// them. 1 keeps candle and gguf on one batch at a time and leaves onnx
+    /// to size its own pool
+    #[arg(long, global = true, default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    threads: usize,
+
+    /// How the onnx, candle and gguf engines turn token states into one
+    /// vector, overriding the model's preset or GGUF metadata; match what
+    /// the sentence-transformer was trained with. ONNX exports that already
+    /// output a pooled `sentence_embedding` keep their own
+    #[arg(long, global = true, value_parser = ["mean", "cls", "max"])]
+    pooling: Option<String>,
+
+    /// Hand back vectors as the model produced them instead of unit length,
+    /// for dot-product scoring or quantizing downstream. Index and search
+    /// with the same setting; searches rank by dot product either way
+    #[arg(long, global = true)]
+    no_normalize: bool,
+
+    /// Threads ONNX Runtime runs each operator on, overriding --threads for
+    /// the onnx engine (or $CONTEXT_RAG_ONNX_INTRA_THREADS); size it to a
+    /// container's CPU quota, which ONNX Runtime doesn't see
+    #[arg(long, global = true, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    onnx_intra_threads: Option<usize>,
+
+    /// Threads ONNX Runtime runs independent operators on at once (or
+    /// $CONTEXT_RAG_ONNX_INTER_THREADS); above 1 runs the graph in parallel
+    #[arg(long, global = true, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    onnx_inter_threads: Option<usize>,
+
+    /// ONNX Runtime's graph optimization level (or
+    /// $CONTEXT_RAG_ONNX_OPTIMIZATION; default all). Lower levels load
+    /// large models faster, for short-lived runs
+    #[arg(long, global = true, value_parser = ["disable", "basic", "extended", "all"])]
+    onnx_optimization: Option<String>,
+
+    /// Where embeddings are cached by content and model, so texts embedded
+    /// before are read back instead of run through the model again
+    /// (default ~/.cache/context-rag/embeddings; the mock engine skips it)
+    #[arg(long, global = true)]
+    cache_dir: Option<std::path::PathBuf>,
+
+    /// Also write every vector the engine returns to this directory, keyed
+    /// by engine, model, vector settings and text, for --replay later
+    #[arg(long, global = true, conflicts_with = "replay")]
+    record: Option<std::path::PathBuf>,
+
+    /// Answer from vectors written by --record instead of running the
+    /// engine, so CI needs no model download or API key; pass the engine,
+    /// model and vector flags the recording was made with. Texts with no
+    /// recorded vector fail
+    #[arg(long, global = true)]
+    replay: Option<std::path::PathBuf>,
+
+    /// Times the openai and ollama engines retry a request after a rate
+    /// limit (429), server error or dropped connection, waiting twice as
+    /// long each time. Batches that still fail are reported and left for
+    /// the next `backfill` instead of ending the run
+    #[arg(long, global = true, default_value_t = remote::DEFAULT_MAX_RETRIES)]
+    max_retries: u32,
+
+    /// Wait before the first retry, in milliseconds
+    #[arg(long, global = true, default_value_t = remote::DEFAULT_RETRY_DELAY_MS)]
+    retry_delay_ms: u64,
+
+    /// Requests a minute the openai and ollama engines may send, spaced
+    /// evenly, to stay under a provider's quota
+    #[arg(long, global = true, value_parser = clap::builder::RangedU64ValueParser::<u32>::new().range(1..))]
+    requests_per_minute: Option<u32>,
+
+    /// Make every mock engine call wait this long first, in milliseconds
+    /// (or $CONTEXT_RAG_MOCK_LATENCY_MS), to test callers' timeouts
+    #[arg(long, global = true)]
+    mock_latency_ms: Option<u64>,
+
+    /// Share of mock engine calls that fail, 0 to 1 (or
+    /// $CONTEXT_RAG_MOCK_FAILURE_RATE), to test callers' retries
+    #[arg(long, global = true)]
+    mock_failure_rate: Option<f64>,
+
+    /// Seed for mock vectors and for which calls fail (or
+    /// $CONTEXT_RAG_MOCK_SEED); the same seed fails the same calls every run
+    #[arg(long, global = true)]
+    mock_seed: Option<u64>,
+
+    /// Count index, search and context runs and their latencies in
+    /// ~/.cache/context-rag/usage.json (or set $CONTEXT_RAG_USAGE_STATS=1),
+    /// for `stats report`. Kept on this machine and never sent anywhere;
+    /// what was indexed or searched for is not recorded
+    #[arg(long, global = true)]
+    usage_stats: bool,
+}
+
+fn preset_names() -> clap::builder::PossibleValuesParser {
+    clap::builder::PossibleValuesParser::new(PRESETS.iter().map(|p| p.name))
+}
+
+fn model_path_name(path: &Option<std::path::PathBuf>) -> Option<String> {
+    Some(path.as_ref()?.file_stem()?.to_string_lossy().into_owned())
+}
+
+fn preset_model(name: &str) -> Result<String> {
+    Ok(embedding::preset(name).map_err(|e| anyhow::anyhow!(e))?.model.to_string())
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Embed the `texts` array read from stdin as JSON
+    Embed {
+        /// Print int8 or binary vectors with their scale instead of floats
+        #[arg(long, value_parser = ["int8", "binary"])]
+        quantize: Option<String>,
+    },
+    /// Score the `chunks` read from stdin against their `query` with a
+    /// cross-encoder and print them best first; `search --json` output works
+    /// as input too
+    Rerank(RerankArgs),
+    /// Inspect the repository and write a commented .context-rag.toml
+    Init {
+        /// Overwrite an existing config file
+        #[arg(long)]
+        force: bool,
+        /// Configure a model preset instead of the default model
+        #[arg(long, value_parser = preset_names())]
+        preset: Option<String>,
+    },
+    /// Build the index described by .context-rag.toml
+    Index(IndexArgs),
+    /// Search an index and print the best matching chunks
+    Search(SearchArgs),
+    /// Assemble context for a query: pinned chunks, then search hits, within a token budget
+    Context(ContextArgs),
+    /// Show file, chunk and size statistics for an index
+    Stats(StatsArgs),
+    /// Explore what the keyword index holds
+    Analyze {
+        #[command(subcommand)]
+        action: AnalyzeAction,
+    },
+    /// Report whether the index is stale relative to the working tree
+    Status(StatusArgs),
+    /// Show how a file would be chunked under the config, without indexing it
+    Preview(PreviewArgs),
+    /// Index a changeset in memory and search it, for code review
+    Review(ReviewArgs),
+    /// Show how an indexed chunk was produced
+    Audit(AuditArgs),
+    /// Show everything stored for one chunk: content, metadata, vector norm
+    /// and the segments holding it
+    Inspect(InspectArgs),
+    /// Dump every indexed chunk as JSON lines, sorted by path
+    Export(ExportArgs),
+    /// Embed indexed chunks that don't have vectors yet
+    Backfill(BackfillArgs),
+    /// Re-embed every indexed chunk with a new model and switch searches to it
+    Reembed(ReembedArgs),
+    /// Tune search settings against an eval set
+    Tune {
+        #[command(subcommand)]
+        action: TuneAction,
+    },
+    /// Run a long-lived server over gRPC, HTTP or a Unix socket
+    Serve(ServeArgs),
+    /// Interactive search session over an index
+    Repl(ReplArgs),
+    /// Manage the embedding cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+    /// Time embedding a synthetic corpus for each engine, batch size and
+    /// thread count, to tune --batch-size and --threads
+    Bench(BenchArgs),
+    /// Check that the selected engine loads, embeds sound vectors and
+    /// round-trips an index in a temporary directory
+    Selftest(SelftestArgs),
+    /// Report on known and checked-out models
+    Models {
+        #[command(subcommand)]
+        action: ModelsAction,
+    },
+    /// Print a shell completion script
+    Completions {
+        shell: clap_complete::Shell,
+    },
+}
+
+#[derive(Subcommand)]
+enum CacheAction {
+    /// Delete cached embeddings
+    Clear(CacheClearArgs),
+}
+
+#[derive(clap::Args)]
+struct BenchArgs {
+    /// Model to embed with; defaults to the config's
+    #[arg(long)]
+    model: Option<String>,
+    #[arg(long, default_value = CONFIG_FILE)]
+    config: String,
+    /// Engines to compare; defaults to the one --engine selects
+    #[arg(long, value_delimiter = ',', value_parser = ["mock", "onnx", "candle", "gguf", "openai", "ollama"])]
+    engines: Vec<String>,
+    #[arg(long, value_delimiter = ',', default_values_t = [8, 32, 128])]
+    batch_sizes: Vec<usize>,
+    /// Defaults to 1 and every core
+    #[arg(long, value_delimiter = ',')]
+    thread_counts: Vec<usize>,
+    /// Synthetic chunks per run
+    #[arg(long, default_value_t = 256)]
+    chunks: usize,
+    /// Print machine-readable JSON instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct SelftestArgs {
+    /// Model to test; defaults to the config's
+    #[arg(long)]
+    model: Option<String>,
+    #[arg(long, default_value = CONFIG_FILE)]
+    config: String,
+    /// Print machine-readable JSON instead of a report
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Subcommand)]
+enum ModelsAction {
+    /// Dimensions, token limit, pooling and disk size of each preset model
+    /// and every checkout in the model directory
+    Info(ModelsInfoArgs),
+}
+
+#[derive(clap::Args)]
+struct ModelsInfoArgs {
+    /// Only this model, checked out or not
+    model: Option<String>,
+    /// Print machine-readable JSON instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct CacheClearArgs {
+    /// Only this model's embeddings
+    #[arg(long)]
+    model: Option<String>,
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+#[command(args_conflicts_with_subcommands = true)]
+struct IndexArgs {
+    #[command(subcommand)]
+    action: Option<IndexAction>,
+    #[arg(long, default_value = CONFIG_FILE)]
+    config: String,
+    /// Build a reproducible index (sorted walk, fixed timestamps, no merges)
+    #[arg(long, conflicts_with = "paths")]
+    deterministic: bool,
+    /// Only index or refresh these files and directories
+    #[arg(long, num_args = 1..)]
+    paths: Vec<String>,
+    /// Skip embedding; vectors can be added later with `backfill`
+    #[arg(long)]
+    keyword_only: bool,
+    #[command(flatten)]
+    profile: ProfileArgs,
+    /// Print machine-readable JSON instead of a summary
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args, Clone)]
+struct ProfileArgs {
+    /// Print per-phase wall/CPU time and allocations to stderr
+    #[arg(long)]
+    profile: bool,
+    /// Also write the phases as folded stacks for flamegraph tools
+    #[arg(long, value_name = "FILE")]
+    profile_out: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum IndexAction {
+    /// Compare two snapshots (index directories or `export` files)
+    Diff(DiffArgs),
+}
+
+#[derive(Subcommand)]
+enum TuneAction {
+    /// Grid-search hybrid fusion weights and write the best to the config
+    Fusion(TuneFusionArgs),
+}
+
+#[derive(clap::Args)]
+struct TuneFusionArgs {
+    /// JSONL eval set, one {"query": ..., "relevant": ["path" or "path#chunk"]} per line
+    #[arg(long)]
+    eval: String,
+    #[arg(long, default_value = CONFIG_FILE)]
+    config: String,
+    /// Results per query that count towards MRR
+    #[arg(long, default_value_t = DEFAULT_SEARCH_LIMIT, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    limit: usize,
+    /// Report the best weights without writing them
+    #[arg(long)]
+    dry_run: bool,
+    /// Print machine-readable JSON instead of a summary
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct DiffArgs {
+    before: String,
+    after: String,
+    /// Print machine-readable JSON instead of a summary
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct SearchArgs {
+    #[arg(required_unless_present = "stdin", default_value = "")]
+    query: String,
+    #[arg(long, default_value = DEFAULT_STORAGE_PATH)]
+    storage: String,
+    #[arg(long, default_value_t = DEFAULT_SEARCH_LIMIT, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    limit: usize,
+    /// Config holding tuned [search.fusion] weights for hybrid search
+    #[arg(long, default_value = CONFIG_FILE)]
+    config: String,
+    /// Re-index files among the hits that changed since they were indexed
+    #[arg(long, conflicts_with = "hybrid")]
+    refresh_stale: bool,
+    /// Match words exactly as cased, so `Index` doesn't match `index`
+    #[arg(long, conflicts_with_all = ["hybrid", "regex", "model_variant", "refresh_stale"])]
+    case_sensitive: bool,
+    /// Match whole words without stemming
+    #[arg(long, conflicts_with_all = ["hybrid", "regex", "model_variant", "refresh_stale"])]
+    exact: bool,
+    /// Also match code comments and docstrings, weighted this much (needs
+    /// `extract_comments` in the config)
+    #[arg(long, conflicts_with_all = ["hybrid", "regex", "sparse", "model_variant", "refresh_stale", "case_sensitive"])]
+    comment_boost: Option<f32>,
+    /// Down-rank test code (production) or production code (tests)
+    #[arg(long, default_value = "any", value_parser = ["production", "tests", "any"])]
+    #[arg(conflicts_with_all = ["hybrid", "regex", "sparse", "model_variant", "refresh_stale"])]
+    prefer: String,
+    /// Drop hits whose calibrated confidence (0-1) is lower; defaults to
+    /// [search] min_confidence in the config
+    #[arg(long, conflicts_with_all = ["hybrid", "regex", "sparse", "model_variant", "refresh_stale"])]
+    min_confidence: Option<f32>,
+    /// Looser searches to try, in order, when too few hits come back:
+    /// relax, fuzzy, vector, keywords; defaults to [search.fallback] rungs
+    #[arg(long, value_delimiter = ',', conflicts_with_all = ["hybrid", "regex", "sparse", "model_variant", "refresh_stale"])]
+    fallback: Option<Vec<String>>,
+    /// Hits a search needs before the fallback ladder is skipped; defaults
+    /// to [search.fallback] min_results
+    #[arg(long, conflicts_with_all = ["hybrid", "regex", "sparse", "model_variant", "refresh_stale"])]
+    min_results: Option<usize>,
+    /// Treat the query as a regular expression over chunk content
+    #[arg(long, conflicts_with_all = ["hybrid", "refresh_stale", "model_variant"])]
+    regex: bool,
+    /// Rank by weighted term expansions; semantic-ish recall without vectors
+    #[arg(long, conflicts_with_all = ["hybrid", "regex", "refresh_stale", "model_variant", "case_sensitive", "exact"])]
+    sparse: bool,
+    /// Fuse keyword, literal identifier and vector rankings; chunks
+    /// without vectors yet rank by the first two only
+    #[arg(long)]
+    hybrid: bool,
+    /// Hybrid search with the active model (a), --compare-model (b), or both
+    /// side by side
+    #[arg(long, value_enum, conflicts_with = "refresh_stale")]
+    model_variant: Option<ModelVariant>,
+    /// Model for variant b; its vectors must exist (see `backfill --model`)
+    #[arg(long)]
+    compare_model: Option<String>,
+    /// Rescore hybrid candidates against per-token vectors (experimental)
+    #[cfg(feature = "late-interaction")]
+    #[arg(long, requires = "hybrid", conflicts_with = "model_variant")]
+    late_interaction: bool,
+    #[command(flatten)]
+    profile: ProfileArgs,
+    /// Print machine-readable JSON instead of a table
+    #[arg(long)]
+    json: bool,
+    /// Print hits as `path:line:col: snippet` lines for editor quickfix
+    /// lists, or as a SARIF log for code-scanning tools
+    #[arg(long, value_enum, conflicts_with = "json")]
+    format: Option<HitFormat>,
+    /// Read one query per line from stdin and print one JSON line per
+    /// query, opening the index once for all of them
+    #[arg(long, conflicts_with_all = ["query", "hybrid", "model_variant", "refresh_stale", "format"])]
+    stdin: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq)]
+enum HitFormat {
+    Vimgrep,
+    Sarif,
+}
+
+#[derive(clap::Args)]
+struct ContextArgs {
+    query: String,
+    #[arg(long, default_value = DEFAULT_STORAGE_PATH)]
+    storage: String,
+    /// Search hits to consider after the pinned chunks
+    #[arg(long, default_value_t = DEFAULT_SEARCH_LIMIT, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    limit: usize,
+    /// Token budget; defaults to [context] max_tokens in the config
+    #[arg(long)]
+    max_tokens: Option<usize>,
+    /// Config holding the [context] pinned list
+    #[arg(long, default_value = CONFIG_FILE)]
+    config: String,
+    /// Print machine-readable JSON instead of the assembled text
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq)]
+enum ModelVariant {
+    A,
+    B,
+    Both,
+}
+
+#[derive(clap::Args)]
+#[command(args_conflicts_with_subcommands = true)]
+struct StatsArgs {
+    #[command(subcommand)]
+    action: Option<StatsAction>,
+    #[arg(long, default_value = DEFAULT_STORAGE_PATH)]
+    storage: String,
+    /// Print machine-readable JSON instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Subcommand)]
+enum StatsAction {
+    /// Summarize the index, search and context runs --usage-stats recorded
+    Report(StatsReportArgs),
+}
+
+#[derive(clap::Args)]
+struct StatsReportArgs {
+    /// Usage file to read, e.g. one collected from a teammate (default
+    /// ~/.cache/context-rag/usage.json)
+    #[arg(long)]
+    file: Option<std::path::PathBuf>,
+    /// Print machine-readable JSON instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(Subcommand)]
+enum AnalyzeAction {
+    /// Report top terms, document frequencies and per-field cardinalities
+    Terms(AnalyzeTermsArgs),
+    /// Compare the file tree with the index: which directories and
+    /// extensions are left out, and why
+    Coverage(AnalyzeCoverageArgs),
+    /// Project chunk vectors to 2D coordinates for plotting the corpus
+    Project(AnalyzeProjectArgs),
+}
+
+#[derive(clap::Args)]
+struct AnalyzeProjectArgs {
+    #[arg(long, default_value = DEFAULT_STORAGE_PATH)]
+    storage: String,
+    /// `pca` is fast and keeps global distances; `umap` brings out clusters,
+    /// but projects an even sample of at most 5000 chunks
+    #[arg(long, default_value = "pca", value_parser = ["pca", "umap"])]
+    method: String,
+    /// Neighbors each chunk is tied to in the UMAP graph; larger values
+    /// favor global structure over local clusters
+    #[arg(long, default_value_t = 15)]
+    neighbors: usize,
+    #[arg(long, value_enum, default_value_t = ProjectionFormat::Json)]
+    format: ProjectionFormat,
+    /// Write to a file instead of stdout
+    #[arg(long)]
+    output: Option<String>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq)]
+enum ProjectionFormat {
+    Json,
+    Csv,
+}
+
+#[derive(clap::Args)]
+struct AnalyzeCoverageArgs {
+    #[arg(long, default_value = CONFIG_FILE)]
+    config: String,
+    /// Directory levels to show in the tree
+    #[arg(long, default_value_t = 3)]
+    depth: usize,
+    /// Print machine-readable JSON instead of a tree
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct AnalyzeTermsArgs {
+    #[arg(long, default_value = DEFAULT_STORAGE_PATH)]
+    storage: String,
+    /// Fields to list top terms for (content, content_exact, comments,
+    /// sparse, file_path, ...); every field's cardinality is reported
+    #[arg(long = "field", default_value = "content", value_delimiter = ',')]
+    fields: Vec<String>,
+    /// Top terms to list per field
+    #[arg(long, default_value_t = 25)]
+    limit: usize,
+    /// Print machine-readable JSON instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct ReviewArgs {
+    /// Revision range to review, e.g. main..HEAD
+    #[arg(long)]
+    diff: String,
+    /// Queries to answer; without any, reads one per line from stdin
+    queries: Vec<String>,
+    /// Unchanged lines kept around each change
+    #[arg(long, default_value_t = DEFAULT_CONTEXT_LINES)]
+    context: usize,
+    /// Also search the main index at --storage
+    #[arg(long)]
+    with_index: bool,
+    #[arg(long, default_value = DEFAULT_STORAGE_PATH)]
+    storage: String,
+    #[arg(long, default_value_t = DEFAULT_SEARCH_LIMIT, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    limit: usize,
+    /// Print one JSON answer per query instead of tables
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct StatusArgs {
+    #[arg(long, default_value = CONFIG_FILE)]
+    config: String,
+    /// Print machine-readable JSON instead of a summary
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct PreviewArgs {
+    /// File to chunk, e.g. src/lib.rs
+    path: String,
+    #[arg(long, default_value = CONFIG_FILE)]
+    config: String,
+    /// Print machine-readable JSON instead of a table
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct AuditArgs {
+    /// Chunk id as printed by search, e.g. ./docs/guide.md#3
+    chunk_id: String,
+    #[arg(long, default_value = DEFAULT_STORAGE_PATH)]
+    storage: String,
+    /// Print machine-readable JSON instead of a summary
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct InspectArgs {
+    /// Indexed file, e.g. docs/guide.md
+    path: String,
+    chunk_index: u64,
+    #[arg(long, default_value = DEFAULT_STORAGE_PATH)]
+    storage: String,
+    /// Print machine-readable JSON instead of a summary
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct BackfillArgs {
+    #[arg(long, default_value = CONFIG_FILE)]
+    config: String,
+    /// Embedding model; defaults to the one searches use, then the config
+    #[arg(long)]
+    model: Option<String>,
+    /// Model preset to use instead of --model
+    #[arg(long, value_parser = preset_names(), conflicts_with = "model")]
+    preset: Option<String>,
+    /// Model for source chunks; defaults to [embedder] code_model
+    #[arg(long)]
+    code_model: Option<String>,
+    /// Also store per-token vectors for `search --late-interaction`
+    #[cfg(feature = "late-interaction")]
+    #[arg(long)]
+    late_interaction: bool,
+    /// Print machine-readable JSON instead of a summary
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+struct ReembedArgs {
+    #[arg(long, required_unless_present = "preset")]
+    model: Option<String>,
+    /// Model preset to use instead of --model
+    #[arg(long, value_parser = preset_names(), conflicts_with = "model")]
+    preset: Option<String>,
+    /// Model for source chunks; defaults to [embedder] code_model
+    #[arg(long)]
+    code_model: Option<String>,
+    #[arg(long, default_value = CONFIG_FILE)]
+    config: String,
+    /// Print machine-readable JSON instead of a summary
+    #[arg(long)]
+    json: bool,
+}
+
+#[derive(clap::Args)]
+#[command(args_conflicts_with_subcommands = true)]
+struct ExportArgs {
+    #[command(subcommand)]
+    action: Option<ExportAction>,
+    #[arg(long, default_value = DEFAULT_STORAGE_PATH)]
+    storage: String,
+    /// Write to a file instead of stdout
+    #[arg(long)]
+    output: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum ExportAction {
+    /// Mine (query, positive, hard negative) triplets from an eval set for
+    /// fine-tuning a sentence-transformers embedder
+    TrainingPairs(TrainingPairsArgs),
+}
+
+#[derive(clap::Args)]
+struct TrainingPairsArgs {
+    /// JSONL eval set, one {"query": ..., "relevant": ["path" or "path#chunk"]} per line
+    #[arg(long)]
+    eval: String,
+    #[arg(long, default_value = DEFAULT_STORAGE_PATH)]
+    storage: String,
+    /// Hard negatives, and so triplets, per query
+    #[arg(long, default_value_t = 3)]
+    negatives: usize,
+    /// Write to a file instead of stdout
+    #[arg(long)]
+    output: Option<String>,
+}
+
+#[derive(clap::Args)]
+#[command(group(ArgGroup::new("transport").required(true).args(["grpc", "http", "unix", "lsp"])))]
+struct ServeArgs {
+    /// Serve gRPC on this address (requires the `grpc` feature)
+    #[arg(long)]
+    grpc: Option<String>,
+    /// Serve HTTP on this address (requires the `http` feature)
+    #[arg(long)]
+    http: Option<String>,
+    /// JSON file with API keys, rate limits and CORS settings for HTTP mode
+    #[arg(long)]
+    http_config: Option<String>,
+    /// Static API key accepted by HTTP mode; may be repeated
+    #[arg(long = "api-key")]
+    api_keys: Vec<String>,
+    /// Requests per minute each --api-key key may make; unlimited if unset
+    #[arg(long, value_name = "N", requires = "api_keys")]
+    rate_limit: Option<u32>,
+    /// Serve the JSON line protocol on this Unix domain socket
+    #[arg(long)]
+    unix: Option<String>,
+    /// Octal permissions applied to the Unix socket file
+    #[arg(long)]
+    socket_mode: Option<String>,
+    /// Speak the Language Server Protocol on stdin/stdout, for editors:
+    /// `workspace/symbol` and `contextRag/search` requests
+    #[arg(long)]
+    lsp: bool,
+    /// Root directory holding one subdirectory per collection
+    #[arg(long, default_value = DEFAULT_STORAGE_PATH)]
+    storage: String,
+    /// Model used by collections that don't configure their own
+    #[arg(long, default_value = DEFAULT_MODEL)]
+    model: String,
+    /// Append a JSON line per request naming the chunks it returned and who
+    /// asked; requests fail if it can't be written
+    #[arg(long, value_name = "FILE")]
+    audit_log: Option<String>,
+    /// Directory collections may set as their source_root (repeatable);
+    /// without one, only the working directory and what's below it
+    #[arg(long = "allow-root", value_name = "DIR")]
+    allow_roots: Vec<std::path::PathBuf>,
+}
+
+#[derive(clap::Args)]
+struct RerankArgs {
+    /// Cross-encoder checkout, run by --engine candle or onnx
+    #[arg(long, default_value = DEFAULT_RERANK_MODEL)]
+    model: String,
+    /// Score against this query instead of the input's
+    #[arg(long)]
+    query: Option<String>,
+    /// Print only the best N chunks
+    #[arg(long)]
+    limit: Option<usize>,
+}
+
+#[derive(clap::Args)]
+struct ReplArgs {
+    #[arg(long, default_value = DEFAULT_STORAGE_PATH)]
+    storage: String,
+    #[arg(long, default_value_t = DEFAULT_SEARCH_LIMIT, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    limit: usize,
+}
 
 fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-    
-    if args.len() > 1 && args[1] == "--version" {
-        println!("context-rag-embedder 0.1.0");
-        return Ok(());
+    let cli = Cli::parse();
+    if let Some(engine) = &cli.engine {
+        embedding::set_engine(engine).map_err(|e| anyhow::anyhow!(e))?;
     }
-    
-    // Check if called with --text argument (single text embedding interface)
-    if args.len() > 4 && args[1] == "--text" && args[3] == "--model" {
-        let text = &args[2];
-        let model = &args[4];
-        
-        let embedding = generate_mock_embedding(text);
-        
-        let response = json!({
-            "embedding": embedding,
-            "model": model,
-            "engine": "rust"
-        });
-        
+    embedding::set_model_path(cli.model_path.clone());
+    embedding::set_batch_size(cli.batch_size);
+    embedding::set_dimensions(cli.dimensions);
+    embedding::set_device(cli.device.parse().map_err(|e: String| anyhow::anyhow!(e))?);
+    embedding::set_threads(cli.threads);
+    embedding::set_normalize(!cli.no_normalize);
+    embedding::set_pooling(cli.pooling.as_deref().map(str::parse).transpose().map_err(|e: String| anyhow::anyhow!(e))?);
+    cache::set_cache_dir(Some(cli.cache_dir.clone().unwrap_or_else(cache::default_cache_dir)));
+    recording::set_recording(match (cli.record.clone(), cli.replay.clone()) {
+        (Some(dir), _) => Some(Recording::Record(dir)),
+        (None, Some(dir)) => Some(Recording::Replay(dir)),
+        (None, None) => None,
+    });
+    remote::set_retries(cli.max_retries, std::time::Duration::from_millis(cli.retry_delay_ms));
+    remote::set_requests_per_minute(cli.requests_per_minute);
+    let mut mock = embedding::MockSettings::from_env().map_err(|e| anyhow::anyhow!(e))?;
+    if let Some(ms) = cli.mock_latency_ms {
+        mock.latency = std::time::Duration::from_millis(ms);
+    }
+    mock.failure_rate = cli.mock_failure_rate.unwrap_or(mock.failure_rate);
+    mock.seed = cli.mock_seed.unwrap_or(mock.seed);
+    embedding::set_mock_settings(mock).map_err(|e| anyhow::anyhow!(e))?;
+    let onnx = embedding::OnnxTuning::from_env().map_err(|e| anyhow::anyhow!(e))?;
+    embedding::set_onnx_tuning(onnx_flags(&cli, onnx)?);
+    usage::set_usage_file((cli.usage_stats || usage::enabled_by_env()).then(usage::default_usage_file));
+
+    match cli.command {
+        Some(Command::Embed { quantize }) => embed_texts(parse_quantization(quantize)?),
+        Some(Command::Rerank(args)) => rerank(args),
+        Some(Command::Init { force, preset }) => init::run(std::path::Path::new("."), force, preset.as_deref()),
+        Some(Command::Index(IndexArgs { action: Some(IndexAction::Diff(args)), .. })) => diff(args),
+        Some(Command::Index(args)) => tracked("index", || profiled(args.profile.clone(), "index", || index(args))),
+        Some(Command::Search(args)) => tracked("search", || profiled(args.profile.clone(), "search", || search(args))),
+        Some(Command::Context(args)) => tracked("context", || context(args)),
+        Some(Command::Stats(StatsArgs { action: Some(StatsAction::Report(args)), .. })) => stats_report(args),
+        Some(Command::Stats(args)) => stats(args),
+        Some(Command::Analyze { action: AnalyzeAction::Terms(args) }) => analyze_terms(args),
+        Some(Command::Analyze { action: AnalyzeAction::Coverage(args) }) => analyze_coverage(args),
+        Some(Command::Analyze { action: AnalyzeAction::Project(args) }) => analyze_project(args),
+        Some(Command::Status(args)) => status(args),
+        Some(Command::Preview(args)) => preview(args),
+        Some(Command::Review(args)) => review(args),
+        Some(Command::Audit(args)) => audit(args),
+        Some(Command::Inspect(args)) => inspect(args),
+        Some(Command::Export(ExportArgs { action: Some(ExportAction::TrainingPairs(args)), .. })) => training_pairs(args),
+        Some(Command::Export(args)) => export(args),
+        Some(Command::Backfill(args)) => backfill(args),
+        Some(Command::Reembed(args)) => reembed(args),
+        Some(Command::Tune { action: TuneAction::Fusion(args) }) => tune_fusion(args),
+        Some(Command::Serve(args)) => serve(args),
+        Some(Command::Repl(args)) => repl::run(&args.storage, args.limit),
+        Some(Command::Cache { action: CacheAction::Clear(args) }) => cache_clear(args),
+        Some(Command::Bench(args)) => bench(args),
+        Some(Command::Selftest(args)) => selftest(args),
+        Some(Command::Models { action: ModelsAction::Info(args) }) => models_info(args),
+        Some(Command::Completions { shell }) => {
+            clap_complete::generate(shell, &mut Cli::command(), "context-rag-embedder", &mut io::stdout());
+            Ok(())
+        }
+        None => match (cli.text, cli.model.or(cli.preset.as_deref().map(preset_model).transpose()?).or(model_path_name(&cli.model_path))) {
+            (Some(text), Some(model)) => {
+                embed_text(&text, &model, parse_mode(cli.mode, EmbedMode::Query)?, parse_quantization(cli.quantize)?, cli.sparse)
+            }
+            (None, Some(model)) if cli.daemon => serve_daemon(
+                DaemonOptions {
+                    model,
+                    mode: cli.mode.map(|mode| mode.parse()).transpose().map_err(|e: String| anyhow::anyhow!(e))?,
+                    quantization: parse_quantization(cli.quantize)?,
+                    sparse: cli.sparse,
+                },
+                cli.socket.as_deref(),
+            ),
+            (None, Some(model)) if cli.ndjson => {
+                embed_chunk_lines(&model, parse_mode(cli.mode, EmbedMode::Document)?, parse_quantization(cli.quantize)?, cli.sparse)
+            }
+            (None, Some(model)) => embed_chunks(&model, parse_mode(cli.mode, EmbedMode::Document)?, parse_quantization(cli.quantize)?, cli.sparse),
+            _ => {
+                Cli::command().print_help()?;
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+// Counts the run towards usage stats when they are being recorded
+// The --onnx-* flags over the tuning read from the environment
+fn onnx_flags(cli: &Cli, mut onnx: embedding::OnnxTuning) -> Result<embedding::OnnxTuning> {
+    onnx.intra_threads = cli.onnx_intra_threads.or(onnx.intra_threads);
+    onnx.inter_threads = cli.onnx_inter_threads.or(onnx.inter_threads);
+    if let Some(optimization) = &cli.onnx_optimization {
+        onnx.optimization = Some(optimization.parse().map_err(|e: String| anyhow::anyhow!(e))?);
+    }
+    Ok(onnx)
+}
+
+fn tracked(command: &str, run: impl FnOnce() -> Result<()>) -> Result<()> {
+    let started = std::time::Instant::now();
+    let result = run();
+    usage::record(command, started.elapsed(), result.is_ok());
+    result
+}
+
+// Runs `run` as one top-level phase, reporting the phases recorded inside it
+// when profiling was asked for
+fn profiled(args: ProfileArgs, name: &'static str, run: impl FnOnce() -> Result<()>) -> Result<()> {
+    if !args.profile && args.profile_out.is_none() {
+        return run();
+    }
+    profile::enable();
+    let span = profile::span(name);
+    let result = run();
+    drop(span);
+
+    if args.profile {
+        output::print_profile(&profile::report());
+    }
+    if let Some(path) = &args.profile_out {
+        profile::write_folded(std::path::Path::new(path))
+            .map_err(|e| anyhow::anyhow!("Failed to write profile to {}: {}", path, e))?;
+    }
+    result
+}
+
+fn parse_quantization(quantize: Option<String>) -> Result<Option<Quantization>> {
+    quantize.map(|quantize| quantize.parse().map_err(|e: String| anyhow::anyhow!(e))).transpose()
+}
+
+fn parse_mode(mode: Option<String>, default: EmbedMode) -> Result<EmbedMode> {
+    mode.map_or(Ok(default), |mode| mode.parse().map_err(|e: String| anyhow::anyhow!(e)))
+}
+
+// Single text embedding interface
+fn embed_text(text: &str, model: &str, mode: EmbedMode, quantization: Option<Quantization>, sparse: bool) -> Result<()> {
+    let response = embedding::embed_text_request(model, text, mode, quantization, sparse).map_err(|e| anyhow::anyhow!(e))?;
+    println!("{}", serde_json::to_string(&response)?);
+    Ok(())
+}
+
+// context-rag embedder service interface: chunks in, chunks with embeddings out
+fn embed_chunks(model: &str, mode: EmbedMode, quantization: Option<Quantization>, sparse: bool) -> Result<()> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+
+    let response = embedding::embed_chunks_request(model, &input, mode, quantization, sparse).map_err(|e| anyhow::anyhow!(e))?;
+    println!("{}", serde_json::to_string(&response)?);
+    Ok(())
+}
+
+fn embed_chunk_lines(model: &str, mode: EmbedMode, quantization: Option<Quantization>, sparse: bool) -> Result<()> {
+    embedding::embed_ndjson(model, io::stdin().lock(), io::BufWriter::new(io::stdout().lock()), mode, quantization, sparse).map_err(|e| anyhow::anyhow!(e))?;
+    Ok(())
+}
+
+fn serve_daemon(options: DaemonOptions, socket: Option<&str>) -> Result<()> {
+    daemon::warm_up(&options.model).map_err(|e| anyhow::anyhow!(e))?;
+    match socket {
+        Some(socket_path) => serve_daemon_socket(socket_path, options),
+        None => {
+            eprintln!("context-rag embedder ready ({} on {})", options.model, embedding::engine_name());
+            daemon::serve_lines(io::stdin().lock(), io::stdout().lock(), &options)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(unix)]
+fn serve_daemon_socket(socket_path: &str, options: DaemonOptions) -> Result<()> {
+    use context_rag_indexer::server::unix;
+
+    eprintln!("context-rag embedder ready ({} on {}), listening on {}", options.model, embedding::engine_name(), socket_path);
+    daemon::serve_socket(socket_path, unix::DEFAULT_SOCKET_MODE, options)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_daemon_socket(_socket_path: &str, _options: DaemonOptions) -> Result<()> {
+    Err(anyhow::anyhow!("--socket needs a Unix platform"))
+}
+
+// Legacy embed command interface
+fn embed_texts(quantization: Option<Quantization>) -> Result<()> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+
+    let response = embedding::embed_texts_request(&input, quantization).map_err(|e| anyhow::anyhow!(e))?;
+    println!("{}", serde_json::to_string(&response)?);
+    Ok(())
+}
+
+fn rerank(args: RerankArgs) -> Result<()> {
+    let mut input = String::new();
+    io::stdin().read_to_string(&mut input)?;
+
+    let response = rerank::rerank_request(&args.model, &input, args.query.as_deref(), args.limit).map_err(|e| anyhow::anyhow!(e))?;
+    println!("{}", serde_json::to_string(&response)?);
+    Ok(())
+}
+
+fn index(args: IndexArgs) -> Result<()> {
+    let mut config = ProjectConfig::load_or_default(std::path::Path::new(&args.config))
+        .map_err(|e| anyhow::anyhow!("{}", e))?
+        .index_config();
+    config.deterministic |= args.deterministic;
+    if is_memory_storage(&config.storage_path) {
+        anyhow::bail!("{} indexes only live as long as one process; use them through the Node API", MEMORY_STORAGE);
+    }
+
+    let mut indexer = ContextRagIndexer::for_config(&config)
+        .map_err(|e| anyhow::anyhow!("Failed to create indexer: {}", e))?;
+    let result = if args.paths.is_empty() {
+        indexer.index_directory(&config)
+    } else {
+        indexer.index_paths(&config, &args.paths, |_| {})
+    }
+    .map_err(|e| anyhow::anyhow!("Indexing failed: {}", e))?;
+    drop(indexer);
+
+    if !args.json {
+        println!(
+            "Indexed {} files ({} chunks) into {} in {} ms",
+            result.indexed_files, result.total_chunks, config.storage_path, result.processing_time_ms
+        );
+        if result.boilerplate_lines > 0 {
+            println!("Stripped {} boilerplate lines shared across files", result.boilerplate_lines);
+        }
+        if result.ignored_files > 0 {
+            println!("Left out {} files marked `context-rag: ignore`", result.ignored_files);
+        }
+        for skipped in &result.skipped {
+            eprintln!("Skipped {}: {}", skipped.path, skipped.reason);
+        }
+    }
+
+    // Keyword search is usable from here on; vectors follow, for whichever
+    // model searches currently use
+    let vectors = if args.keyword_only {
+        None
+    } else {
+        let model = match VectorStore::load_active(&config.storage_path).map_err(|e| anyhow::anyhow!("{}", e))? {
+            Some(active) if active.model != config.model => {
+                eprintln!(
+                    "Index vectors use {} but the config names {}; run `context-rag-embedder reembed --model {}` to switch",
+                    active.model, config.model, config.model
+                );
+                active.model
+            }
+            _ => config.model.clone(),
+        };
+        let embed = profile::span("embed");
+        let vectors = backfill_vectors(&config.storage_path, &model, config.code_model.as_deref(), |_, _| {})
+            .map_err(|e| anyhow::anyhow!("Embedding failed: {}", e))?;
+        drop(embed);
+        if !args.json {
+            println!("Embedded {} chunks ({} unchanged) in {} ms", vectors.embedded, vectors.reused, vectors.processing_time_ms);
+            report_failed(&vectors);
+        }
+        Some(vectors)
+    };
+
+    if args.json {
+        let mut response = serde_json::to_value(&result)?;
+        response["vectors"] = json!(vectors);
         println!("{}", serde_json::to_string(&response)?);
-        return Ok(());
     }
-    
-    // Check if called with --model argument (context-rag embedder service interface)
-    if args.len() > 2 && args[1] == "--model" {
-        // Read JSON input from stdin
-        let mut input = String::new();
-        io::stdin().read_to_string(&mut input)?;
-        
-        let input_data: Value = serde_json::from_str(&input)?;
-        let chunks = input_data["chunks"].as_array()
-            .ok_or_else(|| anyhow::anyhow!("Missing 'chunks' array in input"))?;
-        
-        // Generate embeddings for each chunk
-        let mut chunk_embeddings = Vec::new();
-        
-        for chunk in chunks {
-            let content = chunk["content"].as_str().unwrap_or("");
-            let embedding = generate_mock_embedding(content);
-            
-            let chunk_with_embedding = json!({
-                "content": content,
-                "embedding": embedding,
-                "file_path": chunk.get("file_path").unwrap_or(&json!("")),
-                "chunk_index": chunk.get("chunk_index").unwrap_or(&json!(0))
-            });
-            
-            chunk_embeddings.push(chunk_with_embedding);
-        }
-        
-        let response = json!({
-            "chunks": chunk_embeddings,
-            "model": args[2],
-            "engine": "rust"
-        });
-        
+    Ok(())
+}
+
+fn report_failed(result: &BackfillResult) {
+    if let Some(failed) = &result.failed {
+        eprintln!(
+            "{} chunks are still without vectors ({}); run `context-rag-embedder backfill --model {}` to retry them",
+            failed.chunks, failed.error, result.model
+        );
+    }
+}
+
+fn reembed(args: ReembedArgs) -> Result<()> {
+    let project = ProjectConfig::load_or_default(std::path::Path::new(&args.config)).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let storage_path = project.index.storage_path;
+    let previous = VectorStore::load_active(&storage_path)
+        .map_err(|e| anyhow::anyhow!("{}", e))?
+        .map(|store| store.model);
+
+    let model = match args.preset {
+        Some(preset) => preset_model(&preset)?,
+        None => args.model.unwrap_or_default(),
+    };
+    let code_model = args.code_model.or(project.embedder.code_model.clone());
+    let result = reembed_vectors(&storage_path, &model, code_model.as_deref(), |done, total| {
+        if !args.json {
+            eprint!("\rEmbedded {}/{}", done, total);
+        }
+    })
+    .map_err(|e| anyhow::anyhow!("Re-embedding failed: {}", e))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&json!({ "result": result, "previous_model": previous }))?);
+    } else {
+        eprintln!();
+        println!("Re-embedded {} chunks with {} in {} ms", result.embedded, result.model, result.processing_time_ms);
+        report_failed(&result);
+        match previous {
+            Some(previous) if previous != result.model => println!("Searches now use {} (was {})", result.model, previous),
+            _ => println!("Searches now use {}", result.model),
+        }
+        if project.embedder.model != result.model {
+            println!("Update [embedder] model in {} so future index runs embed with it", args.config);
+        }
+    }
+    Ok(())
+}
+
+fn backfill(args: BackfillArgs) -> Result<()> {
+    let project = ProjectConfig::load_or_default(std::path::Path::new(&args.config)).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let storage_path = project.index.storage_path;
+    let model = match (args.model, args.preset) {
+        (Some(model), _) => model,
+        (None, Some(preset)) => preset_model(&preset)?,
+        (None, None) => VectorStore::load_active(&storage_path)
+            .map_err(|e| anyhow::anyhow!("{}", e))?
+            .map_or(project.embedder.model, |active| active.model),
+    };
+
+    let code_model = args.code_model.or(project.embedder.code_model);
+    let result = backfill_vectors(&storage_path, &model, code_model.as_deref(), |done, total| {
+        if !args.json && total > 0 {
+            eprint!("\rEmbedded {}/{}", done, total);
+        }
+    })
+    .map_err(|e| anyhow::anyhow!("Backfill failed: {}", e))?;
+
+    if !args.json {
+        if result.embedded > 0 {
+            eprintln!();
+        }
+        println!(
+            "Embedded {} chunks with {} ({} already had vectors) in {} ms",
+            result.embedded, result.model, result.reused, result.processing_time_ms
+        );
+        report_failed(&result);
+    }
+
+    let response = serde_json::to_value(&result)?;
+    #[cfg(feature = "late-interaction")]
+    let response = if args.late_interaction {
+        backfill_tokens(response, &storage_path, &result.model, args.json)?
+    } else {
+        response
+    };
+    if args.json {
         println!("{}", serde_json::to_string(&response)?);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "late-interaction")]
+fn backfill_tokens(mut response: serde_json::Value, storage_path: &str, model: &str, json: bool) -> Result<serde_json::Value> {
+    let tokens = context_rag_indexer::indexer::backfill_token_vectors(storage_path, model, |done, total| {
+        if !json && done % 64 == 0 {
+            eprint!("\rStored token vectors {}/{}", done, total);
+        }
+    })
+    .map_err(|e| anyhow::anyhow!("Token vector backfill failed: {}", e))?;
+
+    if !json {
+        if tokens.embedded >= 64 {
+            eprintln!();
+        }
+        println!(
+            "Stored token vectors for {} chunks ({} unchanged) in {} ms",
+            tokens.embedded, tokens.reused, tokens.processing_time_ms
+        );
+    }
+    response["token_vectors"] = json!(tokens);
+    Ok(response)
+}
+
+fn tune_fusion(args: TuneFusionArgs) -> Result<()> {
+    let config_path = std::path::Path::new(&args.config);
+    let project = ProjectConfig::load_or_default(config_path).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let cases = load_eval_set(&args.eval).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let tuning = context_rag_indexer::indexer::tune_fusion(&project.index.storage_path, &cases, args.limit)
+        .map_err(|e| anyhow::anyhow!("Tuning failed: {}", e))?;
+
+    let changed = tuning.best != project.search.fusion;
+    if changed && !args.dry_run {
+        ProjectConfig::write_fusion_weights(config_path, &tuning.best).map_err(|e| anyhow::anyhow!("{}", e))?;
+    }
+
+    if args.json {
+        println!("{}", serde_json::to_string(&json!({ "tuning": tuning, "written": changed && !args.dry_run }))?);
         return Ok(());
     }
-    
-    // Legacy embed command interface
-    if args.len() > 1 && args[1] == "embed" {
-        // Read JSON input from stdin
-        let mut input = String::new();
-        io::stdin().read_to_string(&mut input)?;
-        
-        let input_data: Value = serde_json::from_str(&input)?;
-        let texts = input_data["texts"].as_array()
-            .ok_or_else(|| anyhow::anyhow!("Missing 'texts' array in input"))?;
-        
-        // For now, return mock embeddings (384-dimensional vectors like all-MiniLM-L6-v2)
-        let mut embeddings = Vec::new();
-        
-        for text in texts {
-            let text_str = text.as_str().unwrap_or("");
-            // Generate a simple hash-based mock embedding
-            let embedding = generate_mock_embedding(text_str);
-            embeddings.push(embedding);
-        }
-        
-        let response = json!({
-            "embeddings": embeddings,
-            "model": "sentence-transformers/all-MiniLM-L6-v2",
-            "engine": "rust"
-        });
-        
-        println!("{}", serde_json::to_string(&response)?);
+
+    println!(
+        "MRR@{} over {} queries: {:.3} with defaults, {:.3} tuned ({} combinations{})",
+        args.limit,
+        tuning.cases,
+        tuning.baseline_mrr,
+        tuning.mrr,
+        tuning.combinations,
+        if tuning.with_vectors { "" } else { ", no vectors so keyword and identifier only" },
+    );
+    println!(
+        "Best weights: keyword {}, identifier {}, vector {}",
+        tuning.best.keyword, tuning.best.identifier, tuning.best.vector
+    );
+    if !changed {
+        println!("{} already has these weights", args.config);
+    } else if args.dry_run {
+        println!("Dry run; {} left unchanged", args.config);
+    } else {
+        println!("Wrote [search.fusion] to {}", args.config);
+    }
+    Ok(())
+}
+
+fn diff(args: DiffArgs) -> Result<()> {
+    let diff = diff_snapshots(&args.before, &args.after).map_err(|e| anyhow::anyhow!("Diff failed: {}", e))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&diff)?);
+    } else {
+        output::print_diff(&diff);
+    }
+    Ok(())
+}
+
+fn search(args: SearchArgs) -> Result<()> {
+    if args.stdin {
+        return search_stdin(&args);
+    }
+    if args.hybrid || args.model_variant.is_some() {
+        return search_hybrid(&args);
+    }
+
+    if args.regex || args.sparse {
+        let searcher = ContextRagSearcher::open(&args.storage)
+            .map_err(|e| anyhow::anyhow!("Failed to open index at {}: {}", args.storage, e))?;
+        let hits = if args.regex {
+            searcher.regex_search(&args.query, args.limit).map_err(|e| anyhow::anyhow!("Regex search failed: {}", e))?
+        } else {
+            searcher.sparse_search(&args.query, args.limit).map_err(|e| anyhow::anyhow!("Sparse search failed: {}", e))?
+        };
+        print_hits(&args, &hits, 0)?;
         return Ok(());
     }
-    
-    eprintln!("Usage: context-rag-embedder [--version | --text <text> --model <model> | --model <model_name> | embed]");
-    eprintln!("For --text command: returns single embedding for the provided text");
-    eprintln!("For --model command, provide JSON input via stdin with format:");
-    eprintln!(r#"{{"chunks": [{{"content": "text", "file_path": "path", "chunk_index": 0}}, ...]}}"#);
-    eprintln!("For embed command, provide JSON input via stdin with format:");
-    eprintln!(r#"{{"texts": ["text1", "text2", ...]}}"#);
-    std::process::exit(1);
-}
-
-fn generate_mock_embedding(text: &str) -> Vec<f32> {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
-    
-    // Create a deterministic but varied embedding based on text content
-    let mut hasher = DefaultHasher::new();
-    text.hash(&mut hasher);
-    let base_hash = hasher.finish();
-    
-    let mut embedding = Vec::with_capacity(384);
-    
-    // Generate 384-dimensional vector with values between -1 and 1
-    for i in 0..384 {
-        let mut hasher = DefaultHasher::new();
-        (base_hash.wrapping_add(i as u64)).hash(&mut hasher);
-        let hash_val = hasher.finish();
-        
-        // Convert to float between -1 and 1
-        let normalized = (hash_val as f64 / u64::MAX as f64) * 2.0 - 1.0;
-        embedding.push(normalized as f32);
+
+    if args.refresh_stale {
+        let result = search_with_refresh(&args.storage, None, &args.query, args.limit)
+            .map_err(|e| anyhow::anyhow!("Search failed: {}", e))?;
+
+        if let Some(format) = args.format {
+            for path in &result.refreshed {
+                eprintln!("refreshed {}", path);
+            }
+            return print_formatted(format, &args.query, &result.hits);
+        }
+        let answerability = assess_answerability(&args.query, &result.hits, None);
+        if args.json {
+            println!(
+                "{}",
+                serde_json::to_string(&json!({
+                    "query": args.query,
+                    "hits": result.hits,
+                    "refreshed": result.refreshed,
+                    "answerability": answerability,
+                }))?
+            );
+        } else {
+            for path in &result.refreshed {
+                eprintln!("refreshed {}", path);
+            }
+            output::print_hits_table(&result.hits);
+            output::warn_unanswerable(&result.hits, &answerability);
+        }
+        return Ok(());
     }
-    
-    // Normalize the vector to unit length (like real embeddings)
-    let magnitude: f32 = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
-    if magnitude > 0.0 {
-        for val in &mut embedding {
-            *val /= magnitude;
+
+    let (options, min_confidence, ladder) = fallback_settings(&args)?;
+    let result = search_with_fallback(&args.storage, &args.query, args.limit, options, min_confidence, &ladder)
+        .map_err(|e| anyhow::anyhow!("Search failed: {}", e))?;
+    print_fallback_hits(&args, &result)
+}
+
+// Search options, confidence cutoff and fallback ladder from the flags, with
+// the config's [search] settings filling in the rest
+fn fallback_settings(args: &SearchArgs) -> Result<(SearchOptions, f32, FallbackLadder)> {
+    let options = SearchOptions {
+        case_sensitive: args.case_sensitive,
+        exact: args.exact,
+        comment_boost: args.comment_boost.unwrap_or(0.0),
+        prefer: args.prefer.parse().map_err(|e: String| anyhow::anyhow!(e))?,
+    };
+    let search_config = ProjectConfig::load_or_default(std::path::Path::new(&args.config))
+        .map_err(|e| anyhow::anyhow!("{}", e))?
+        .search;
+    let mut ladder = search_config.fallback;
+    if let Some(rungs) = &args.fallback {
+        ladder.rungs = rungs
+            .iter()
+            .map(|rung| rung.parse())
+            .collect::<Result<_, String>>()
+            .map_err(|e| anyhow::anyhow!(e))?;
+    }
+    if let Some(min_results) = args.min_results {
+        ladder.min_results = min_results;
+    }
+    Ok((options, args.min_confidence.unwrap_or(search_config.min_confidence), ladder))
+}
+
+// One JSON line per query read from stdin, against an index, calibration and
+// embedding model loaded once. A query that fails gets an `error` line and the
+// rest still run.
+fn search_stdin(args: &SearchArgs) -> Result<()> {
+    let (options, min_confidence, ladder) = fallback_settings(args)?;
+    let searcher = FallbackSearcher::open(&args.storage)
+        .map_err(|e| anyhow::anyhow!("Failed to open index at {}: {}", args.storage, e))?;
+    let mut stdout = std::io::stdout().lock();
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        let query = line.trim();
+        if query.is_empty() {
+            continue;
+        }
+        let response = if args.regex || args.sparse {
+            let hits = match args.regex {
+                true => searcher.searcher().regex_search(query, args.limit),
+                false => searcher.searcher().sparse_search(query, args.limit),
+            };
+            hits.map(|hits| {
+                let answerability = assess_answerability(query, &hits, None);
+                json!({ "query": query, "hits": hits, "answerability": answerability, "below_confidence": 0 })
+            })
+        } else {
+            searcher.search(query, args.limit, options, min_confidence, &ladder).map(|result| {
+                let answerability = assess_answerability(query, &result.hits, None);
+                json!({
+                    "query": query,
+                    "hits": result.hits,
+                    "answerability": answerability,
+                    "below_confidence": result.below_confidence,
+                    "fallback": result.rung,
+                    "fallback_tried": result.tried,
+                })
+            })
+        };
+        let response = response.unwrap_or_else(|e| json!({ "query": query, "error": e.to_string() }));
+        writeln!(stdout, "{}", serde_json::to_string(&response)?)?;
+        // Flushed per query so a script can pipe queries in one at a time
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+fn print_fallback_hits(args: &SearchArgs, result: &FallbackSearch) -> Result<()> {
+    if args.json {
+        let answerability = assess_answerability(&args.query, &result.hits, None);
+        println!(
+            "{}",
+            serde_json::to_string(&json!({
+                "query": args.query,
+                "hits": result.hits,
+                "answerability": answerability,
+                "below_confidence": result.below_confidence,
+                "fallback": result.rung,
+                "fallback_tried": result.tried,
+            }))?
+        );
+        return Ok(());
+    }
+
+    if let Some(rung) = result.rung {
+        eprintln!("Too few hits for the query; these come from the {} fallback", rung);
+    } else if !result.tried.is_empty() {
+        eprintln!("No fallback found more hits than the query itself");
+    }
+    print_hits(args, &result.hits, result.below_confidence)
+}
+
+fn print_hits(args: &SearchArgs, hits: &[SearchHit], below_confidence: usize) -> Result<()> {
+    if let Some(format) = args.format {
+        return print_formatted(format, &args.query, hits);
+    }
+    let answerability = assess_answerability(&args.query, hits, None);
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string(&json!({
+                "query": args.query,
+                "hits": hits,
+                "answerability": answerability,
+                "below_confidence": below_confidence,
+            }))?
+        );
+        return Ok(());
+    }
+
+    output::print_hits_table(hits);
+    if below_confidence > 0 {
+        eprintln!("{} weaker matches fell below the confidence cutoff", below_confidence);
+    }
+    output::warn_unanswerable(hits, &answerability);
+    Ok(())
+}
+
+fn print_formatted(format: HitFormat, query: &str, hits: &[SearchHit]) -> Result<()> {
+    match format {
+        HitFormat::Vimgrep => output::print_vimgrep(hits),
+        HitFormat::Sarif => output::print_sarif(query, hits)?,
+    }
+    Ok(())
+}
+
+fn search_hybrid(args: &SearchArgs) -> Result<()> {
+    let weights = ProjectConfig::load_or_default(std::path::Path::new(&args.config))
+        .map_err(|e| anyhow::anyhow!("{}", e))?
+        .search
+        .fusion;
+    let run = |variant: ModelVariant| -> Result<HybridSearch> {
+        let result = match variant {
+            ModelVariant::B => {
+                let model = args
+                    .compare_model
+                    .as_deref()
+                    .ok_or_else(|| anyhow::anyhow!("--model-variant b needs --compare-model"))?;
+                hybrid_search_model(&args.storage, model, &args.query, args.limit, &weights)
+            }
+            #[cfg(feature = "late-interaction")]
+            _ if args.late_interaction => context_rag_indexer::indexer::late_interaction_search(&args.storage, &args.query, args.limit),
+            _ => hybrid_search_weighted(&args.storage, &args.query, args.limit, &weights),
+        };
+        result.map_err(|e| anyhow::anyhow!("Search failed: {}", e))
+    };
+
+    if args.model_variant != Some(ModelVariant::Both) {
+        let result = run(args.model_variant.unwrap_or(ModelVariant::A))?;
+        if let Some(format) = args.format {
+            return print_formatted(format, &args.query, &result.hits);
+        }
+        if args.json {
+            let mut response = serde_json::to_value(&result)?;
+            response["query"] = json!(args.query);
+            println!("{}", serde_json::to_string(&response)?);
+        } else {
+            print_hybrid(&result);
+        }
+        return Ok(());
+    }
+
+    if args.format.is_some() {
+        return Err(anyhow::anyhow!("--format prints one list of hits; compare variants without it"));
+    }
+    let a = run(ModelVariant::A)?;
+    let b = run(ModelVariant::B)?;
+    let locations = |result: &HybridSearch| -> std::collections::HashSet<(String, u64)> {
+        result.hits.iter().map(|hit| (hit.file_path.clone(), hit.chunk_index)).collect()
+    };
+    let overlap = locations(&a).intersection(&locations(&b)).count();
+
+    if args.json {
+        println!("{}", serde_json::to_string(&json!({ "query": args.query, "a": a, "b": b, "overlap": overlap }))?);
+    } else {
+        for (label, result) in [("A", &a), ("B", &b)] {
+            println!("{}: {}", label, result.model.as_deref().unwrap_or("keyword only"));
+            print_hybrid(result);
+            println!();
+        }
+        println!("{} of {} results appear in both", overlap, a.hits.len().max(b.hits.len()));
+    }
+    Ok(())
+}
+
+fn print_hybrid(result: &HybridSearch) {
+    if result.mode != "hybrid" {
+        eprintln!("No vectors yet; showing keyword results");
+    } else if result.vector_coverage < 1.0 {
+        eprintln!("Vectors cover {:.0}% of chunks; the rest rank by keyword only", result.vector_coverage * 100.0);
+    }
+    output::print_hits_table(&result.hits);
+    output::warn_unanswerable(&result.hits, &result.answerability);
+}
+
+fn context(args: ContextArgs) -> Result<()> {
+    let config = ProjectConfig::load_or_default(std::path::Path::new(&args.config))
+        .map_err(|e| anyhow::anyhow!("{}", e))?
+        .context;
+    let context = assemble_context(
+        &args.storage,
+        &args.query,
+        args.limit,
+        args.max_tokens.unwrap_or(config.max_tokens),
+        &config.pinned,
+    )
+    .map_err(|e| anyhow::anyhow!("Failed to assemble context: {}", e))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&context)?);
+        return Ok(());
+    }
+    for pin in &context.missing_pins {
+        eprintln!("Pinned {} is not in the index", pin);
+    }
+    output::print_context(&context);
+    Ok(())
+}
+
+fn stats(args: StatsArgs) -> Result<()> {
+    let stats = index_stats(&args.storage)
+        .map_err(|e| anyhow::anyhow!("Failed to read index at {}: {}", args.storage, e))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&stats)?);
+    } else {
+        output::print_stats_table(&stats);
+    }
+    Ok(())
+}
+
+fn stats_report(args: StatsReportArgs) -> Result<()> {
+    let report = usage::usage_report(&args.file.unwrap_or_else(usage::default_usage_file)).map_err(|e| anyhow::anyhow!(e))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&report)?);
+    } else {
+        output::print_usage_report(&report);
+    }
+    Ok(())
+}
+
+fn analyze_terms(args: AnalyzeTermsArgs) -> Result<()> {
+    let report = term_report(&args.storage, &args.fields, args.limit)
+        .map_err(|e| anyhow::anyhow!("Failed to read index at {}: {}", args.storage, e))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&report)?);
+    } else {
+        output::print_term_report(&report);
+    }
+    Ok(())
+}
+
+fn analyze_coverage(args: AnalyzeCoverageArgs) -> Result<()> {
+    let config = ProjectConfig::load_or_default(std::path::Path::new(&args.config))
+        .map_err(|e| anyhow::anyhow!("{}", e))?
+        .index_config();
+    let report = coverage_report(&config)
+        .map_err(|e| anyhow::anyhow!("Failed to read index at {}: {}", config.storage_path, e))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&report)?);
+    } else {
+        output::print_coverage(&report, args.depth);
+    }
+    Ok(())
+}
+
+fn cache_clear(args: CacheClearArgs) -> Result<()> {
+    let dir = cache::cache_dir().unwrap_or_else(cache::default_cache_dir);
+    let cleared = cache::clear_cache(&dir, args.model.as_deref())
+        .map_err(|e| anyhow::anyhow!("Failed to clear {}: {}", dir.display(), e))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&cleared)?);
+    } else {
+        println!("Removed {} cached embeddings ({:.1} MB) from {}", cleared.entries, cleared.bytes as f64 / 1_048_576.0, dir.display());
+    }
+    Ok(())
+}
+
+fn bench(args: BenchArgs) -> Result<()> {
+    let model = match args.model {
+        Some(model) => model,
+        None => ProjectConfig::load_or_default(std::path::Path::new(&args.config)).map_err(|e| anyhow::anyhow!("{}", e))?.index_config().model,
+    };
+    let engines = if args.engines.is_empty() { vec![embedding::engine_name()] } else { args.engines };
+    let mut thread_counts = args.thread_counts;
+    if thread_counts.is_empty() {
+        thread_counts = vec![1, std::thread::available_parallelism().map_or(1, usize::from)];
+        thread_counts.dedup();
+    }
+    if args.chunks == 0 || args.batch_sizes.contains(&0) || thread_counts.contains(&0) {
+        anyhow::bail!("--chunks, --batch-sizes and --thread-counts must be at least 1");
+    }
+
+    let chunks = bench::synthetic_chunks(args.chunks, 42);
+    let runs = bench::run_bench(&model, &chunks, &engines, &args.batch_sizes, &thread_counts)
+        .map_err(|e| anyhow::anyhow!("Benchmark failed: {}", e))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&runs)?);
+    } else {
+        output::print_bench(&model, &runs);
+    }
+    Ok(())
+}
+
+fn selftest(args: SelftestArgs) -> Result<()> {
+    let model = match args.model {
+        Some(model) => model,
+        None => ProjectConfig::load_or_default(std::path::Path::new(&args.config)).map_err(|e| anyhow::anyhow!("{}", e))?.index_config().model,
+    };
+    let report = selftest::run_selftest(&model);
+
+    if args.json {
+        println!("{}", serde_json::to_string(&report)?);
+    } else {
+        output::print_selftest(&report);
+    }
+    let failed = report.checks.iter().filter(|check| !check.passed).count();
+    if failed > 0 {
+        anyhow::bail!("{} of {} self-test checks failed", failed, report.checks.len());
+    }
+    Ok(())
+}
+
+fn models_info(args: ModelsInfoArgs) -> Result<()> {
+    let models = match &args.model {
+        Some(model) => vec![models::model_info(model)],
+        None => models::models_info(),
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string(&models)?);
+    } else {
+        output::print_models(&models);
+    }
+    Ok(())
+}
+
+fn analyze_project(args: AnalyzeProjectArgs) -> Result<()> {
+    let method = args.method.parse().map_err(|e: String| anyhow::anyhow!(e))?;
+    let projection = project_chunks(&args.storage, method, args.neighbors)
+        .map_err(|e| anyhow::anyhow!("Failed to project {}: {}", args.storage, e))?;
+
+    let mut out: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(io::stdout().lock()),
+    };
+    match args.format {
+        ProjectionFormat::Json => {
+            serde_json::to_writer(&mut out, &projection)?;
+            writeln!(out)?;
         }
+        ProjectionFormat::Csv => projection.write_csv(&mut out)?,
+    }
+    out.flush()?;
+
+    if projection.skipped > 0 {
+        eprintln!("Skipped {} chunks without a {} vector", projection.skipped, projection.model);
+    }
+    if projection.sampled_out > 0 {
+        eprintln!(
+            "Warning: UMAP projected an even sample of {} of {} chunks; use --method pca to place them all",
+            projection.chunks.len(),
+            projection.chunks.len() + projection.sampled_out
+        );
+    }
+    if let Some(path) = &args.output {
+        eprintln!("Projected {} chunks with {} to {}", projection.chunks.len(), args.method, path);
+    }
+    Ok(())
+}
+
+fn status(args: StatusArgs) -> Result<()> {
+    let config = ProjectConfig::load_or_default(std::path::Path::new(&args.config))
+        .map_err(|e| anyhow::anyhow!("{}", e))?
+        .index_config();
+    let status = index_status(&config)
+        .map_err(|e| anyhow::anyhow!("Failed to read index at {}: {}", config.storage_path, e))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&status)?);
+    } else {
+        output::print_status(&status);
+    }
+    Ok(())
+}
+
+fn preview(args: PreviewArgs) -> Result<()> {
+    let config = ProjectConfig::load_or_default(std::path::Path::new(&args.config))
+        .map_err(|e| anyhow::anyhow!("{}", e))?
+        .index_config();
+    let preview = preview_chunks(&args.path, &config).map_err(|e| anyhow::anyhow!("Preview failed: {}", e))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&preview)?);
+    } else {
+        output::print_file_preview(&preview);
+    }
+    Ok(())
+}
+
+fn review(args: ReviewArgs) -> Result<()> {
+    let hunks = diff_hunks(&args.diff, args.context).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let session = ReviewSession::new(hunks, args.with_index.then_some(args.storage.as_str()))
+        .map_err(|e| anyhow::anyhow!("Failed to index the changeset: {}", e))?;
+    if !args.json {
+        eprintln!("Indexed {} changed hunks from {}", session.hunks().len(), args.diff);
+    }
+
+    let answer = |query: &str| -> Result<()> {
+        let answer = session.search(query, args.limit).map_err(|e| anyhow::anyhow!("Search failed: {}", e))?;
+        if args.json {
+            println!("{}", serde_json::to_string(&answer)?);
+        } else {
+            output::print_review(&answer, args.with_index);
+        }
+        Ok(())
+    };
+
+    if !args.queries.is_empty() {
+        return args.queries.iter().try_for_each(|query| answer(query));
+    }
+    for line in io::stdin().lines() {
+        let line = line?;
+        if !line.trim().is_empty() {
+            answer(line.trim())?;
+        }
+    }
+    Ok(())
+}
+
+fn audit(args: AuditArgs) -> Result<()> {
+    let (file_path, chunk_index) = parse_chunk_id(&args.chunk_id)
+        .ok_or_else(|| anyhow::anyhow!("Chunk id must look like <path>#<index>, got '{}'", args.chunk_id))?;
+    let searcher = ContextRagSearcher::open(&args.storage)
+        .map_err(|e| anyhow::anyhow!("Failed to open index at {}: {}", args.storage, e))?;
+    let audit = searcher
+        .audit_chunk(file_path, chunk_index)
+        .map_err(|e| anyhow::anyhow!("Audit failed: {}", e))?
+        .ok_or_else(|| anyhow::anyhow!("No chunk {} in the index", args.chunk_id))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&audit)?);
+    } else {
+        output::print_audit(&audit);
+    }
+    Ok(())
+}
+
+fn inspect(args: InspectArgs) -> Result<()> {
+    let inspection = inspect_chunk(&args.storage, &args.path, args.chunk_index)
+        .map_err(|e| anyhow::anyhow!("Inspect failed: {}", e))?
+        .ok_or_else(|| anyhow::anyhow!("No chunk {}#{} in the index", args.path, args.chunk_index))?;
+
+    if args.json {
+        println!("{}", serde_json::to_string(&inspection)?);
+    } else {
+        output::print_inspection(&inspection);
+    }
+    Ok(())
+}
+
+fn export(args: ExportArgs) -> Result<()> {
+    let exported = match &args.output {
+        Some(path) => export_chunks(&args.storage, io::BufWriter::new(std::fs::File::create(path)?)),
+        None => export_chunks(&args.storage, io::stdout().lock()),
+    }
+    .map_err(|e| anyhow::anyhow!("Export failed: {}", e))?;
+
+    if let Some(path) = &args.output {
+        eprintln!("Exported {} chunks to {}", exported, path);
+    }
+    Ok(())
+}
+
+fn training_pairs(args: TrainingPairsArgs) -> Result<()> {
+    let cases = load_eval_set(&args.eval).map_err(|e| anyhow::anyhow!("{}", e))?;
+    let export = match &args.output {
+        Some(path) => export_training_pairs(&args.storage, &cases, args.negatives, io::BufWriter::new(std::fs::File::create(path)?)),
+        None => export_training_pairs(&args.storage, &cases, args.negatives, io::stdout().lock()),
+    }
+    .map_err(|e| anyhow::anyhow!("Export failed: {}", e))?;
+
+    for query in &export.unmatched {
+        eprintln!("No indexed chunk matches the relevant entries for {:?}; skipped", query);
+    }
+    let destination = args.output.as_deref().unwrap_or("stdout");
+    eprintln!("Exported {} triplets from {} queries to {}", export.triplets, export.queries, destination);
+    Ok(())
+}
+
+fn serve(args: ServeArgs) -> Result<()> {
+    let mut state = ServerState::new(&args.storage, &args.model)
+        .with_source_roots(&args.allow_roots)
+        .map_err(|e| anyhow::anyhow!("Invalid --allow-root: {}", e))?;
+    if let Some(path) = &args.audit_log {
+        state = state
+            .with_audit_log(std::path::Path::new(path))
+            .map_err(|e| anyhow::anyhow!("Failed to open audit log {}: {}", path, e))?;
+    }
+    let state = Arc::new(state);
+
+    if let Some(addr) = &args.grpc {
+        return serve_grpc(addr, state);
+    }
+    
+    if let Some(addr) = &args.http {
+        return serve_http(addr, &args, state);
+    }
+
+    if args.lsp {
+        return context_rag_indexer::server::lsp::serve(state).map_err(Into::into);
     }
     
-    embedding
-}
\ No newline at end of file
+    match &args.unix {
+        Some(socket_path) => serve_unix(socket_path, args.socket_mode.as_deref(), state),
+        None => unreachable!("clap requires one transport"),
+    }
+}
+
+#[cfg(feature = "http")]
+fn serve_http(addr: &str, args: &ServeArgs, state: Arc<ServerState>) -> Result<()> {
+    use context_rag_indexer::server::auth::{ApiKey, HttpConfig};
+
+    let mut config: HttpConfig = match &args.http_config {
+        Some(path) => serde_json::from_str(&std::fs::read_to_string(path)?)?,
+        None => HttpConfig::default(),
+    };
+
+    for key in &args.api_keys {
+        config.api_keys.push(ApiKey {
+            key: key.clone(),
+            name: None,
+            rate_limit_per_minute: args.rate_limit,
+            allowed_paths: Vec::new(),
+        });
+    }
+
+    eprintln!("context-rag HTTP server listening on {}", addr);
+    context_rag_indexer::server::http::serve(addr, config, state)
+        .map_err(|e| anyhow::anyhow!("HTTP server failed: {}", e))
+}
+
+#[cfg(not(feature = "http"))]
+fn serve_http(_addr: &str, _args: &ServeArgs, _state: Arc<ServerState>) -> Result<()> {
+    Err(anyhow::anyhow!("HTTP support not compiled in; rebuild with --features http"))
+}
+
+#[cfg(unix)]
+fn serve_unix(socket_path: &str, mode: Option<&str>, state: Arc<ServerState>) -> Result<()> {
+    use context_rag_indexer::server::unix;
+
+    let mode = match mode {
+        Some(mode) => u32::from_str_radix(mode, 8)
+            .map_err(|_| anyhow::anyhow!("Invalid --socket-mode '{}', expected octal like 600", mode))?,
+        None => unix::DEFAULT_SOCKET_MODE,
+    };
+
+    eprintln!("context-rag server listening on {} (mode {:o})", socket_path, mode);
+    unix::serve(socket_path, mode, state)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn serve_unix(_socket_path: &str, _mode: Option<&str>, _state: Arc<ServerState>) -> Result<()> {
+    Err(anyhow::anyhow!("Unix domain sockets are not supported on this platform"))
+}
+
+#[cfg(feature = "grpc")]
+fn serve_grpc(addr: &str, state: Arc<ServerState>) -> Result<()> {
+    let addr = addr.parse()?;
+    eprintln!("context-rag gRPC server listening on {}", addr);
+
+    tokio::runtime::Runtime::new()?
+        .block_on(context_rag_indexer::server::grpc::serve(addr, state))
+        .map_err(|e| anyhow::anyhow!("gRPC server failed: {}", e))
+}
+
+#[cfg(not(feature = "grpc"))]
+fn serve_grpc(_addr: &str, _state: Arc<ServerState>) -> Result<()> {
+    Err(anyhow::anyhow!("gRPC support not compiled in; rebuild with --features grpc"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use context_rag_indexer::embedding::{GraphOptimization, OnnxTuning};
+
+    #[test]
+    fn onnx_flags_override_the_environment() {
+        let from_env = OnnxTuning { intra_threads: Some(4), inter_threads: Some(2), optimization: Some(GraphOptimization::All) };
+        let cli = Cli::try_parse_from(["context-rag-embedder", "stats", "--onnx-intra-threads", "8", "--onnx-optimization", "basic"]).unwrap();
+        let tuning = onnx_flags(&cli, from_env).unwrap();
+        assert_eq!(tuning, OnnxTuning { intra_threads: Some(8), inter_threads: Some(2), optimization: Some(GraphOptimization::Basic) });
+        assert_eq!(onnx_flags(&Cli::try_parse_from(["context-rag-embedder", "stats"]).unwrap(), from_env).unwrap(), from_env);
+
+        assert!(Cli::try_parse_from(["context-rag-embedder", "stats", "--onnx-inter-threads", "0"]).is_err());
+        assert!(Cli::try_parse_from(["context-rag-embedder", "stats", "--onnx-optimization", "fast"]).is_err());
+        assert!("fast".parse::<GraphOptimization>().is_err());
+    }
+}
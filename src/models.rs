@@ -0,0 +1,302 @@
+use crate::embedding::{self, preset_for_model, unit_length, Pooling, PRESETS};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokenizers::{Tokenizer, TruncationParams};
+
+// Rows of token ids padded to the longest, with masks telling real tokens
+// from padding; row-major, `rows` by `len`
+pub struct Batch {
+    pub rows: usize,
+    pub len: usize,
+    pub ids: Vec<u32>,
+    pub type_ids: Vec<u32>,
+    pub mask: Vec<u32>,
+}
+
+impl Batch {
+    pub fn row_mask(&self, row: usize) -> Vec<f32> {
+        self.mask[row * self.len..(row + 1) * self.len].iter().map(|&m| m as f32).collect()
+    }
+}
+
+// Where `<org>/<name>` model checkouts live unless CONTEXT_RAG_MODEL_DIR
+// says otherwise, e.g. `git clone https://huggingface.co/<org>/<name>`
+const DEFAULT_MODEL_DIR: &str = ".cache/context-rag/models";
+// BERT-style position embeddings stop here, and smaller models sooner
+const MAX_TOKENS: usize = 512;
+
+// A model given as a directory is used as is; names resolve under the
+// model directory
+pub fn model_dir(model: &str) -> PathBuf {
+    if Path::new(model).is_dir() {
+        return PathBuf::from(model);
+    }
+    model_root().join(model)
+}
+
+pub fn model_root() -> PathBuf {
+    match std::env::var_os("CONTEXT_RAG_MODEL_DIR") {
+        Some(root) => PathBuf::from(root),
+        None => std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default().join(DEFAULT_MODEL_DIR),
+    }
+}
+
+// What a local engine would embed with for a model, so callers can check a
+// config before indexing with it
+#[derive(Serialize, Debug, Clone)]
+pub struct ModelInfo {
+    pub model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub preset: Option<&'static str>,
+    // config.json's hidden_size when checked out, else the preset's; None
+    // when neither says
+    pub dimensions: Option<usize>,
+    // Tokens per window, special tokens included
+    pub max_tokens: usize,
+    pub pooling: Pooling,
+    pub installed: bool,
+    pub path: String,
+    pub size_bytes: u64,
+}
+
+pub fn model_info(model: &str) -> ModelInfo {
+    let dir = model_dir(model);
+    let preset = preset_for_model(model);
+    let hidden_size = std::fs::read_to_string(dir.join("config.json"))
+        .ok()
+        .and_then(|config| serde_json::from_str::<serde_json::Value>(&config).ok()?["hidden_size"].as_u64())
+        .map(|size| size as usize);
+    ModelInfo {
+        model: model.to_string(),
+        preset: preset.map(|preset| preset.name),
+        dimensions: hidden_size.or(preset.map(|preset| preset.dimensions)),
+        max_tokens: max_tokens(&dir),
+        // As the engines pick it; --pooling wins over the preset's
+        pooling: embedding::pooling().unwrap_or(preset.map_or(Pooling::Mean, |preset| preset.pooling)),
+        installed: dir.join("tokenizer.json").is_file(),
+        size_bytes: walkdir::WalkDir::new(&dir)
+            .into_iter()
+            .filter_map(|entry| entry.ok()?.metadata().ok())
+            .filter(|metadata| metadata.is_file())
+            .map(|metadata| metadata.len())
+            .sum(),
+        path: dir.display().to_string(),
+    }
+}
+
+// Every preset's model, then each other checkout under the model directory
+// (`<name>` or `<org>/<name>` holding a tokenizer.json), by name
+pub fn models_info() -> Vec<ModelInfo> {
+    let mut models: Vec<String> = PRESETS.iter().map(|preset| preset.model.to_string()).collect();
+    let root = model_root();
+    let mut installed: Vec<String> = walkdir::WalkDir::new(&root)
+        .min_depth(1)
+        .max_depth(2)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_dir() && entry.path().join("tokenizer.json").is_file())
+        .filter_map(|entry| Some(entry.path().strip_prefix(&root).ok()?.to_string_lossy().replace('\\', "/")))
+        .filter(|model| !models.contains(model))
+        .collect();
+    installed.sort();
+    models.extend(installed);
+    models.iter().map(|model| model_info(model)).collect()
+}
+
+// The checkout's tokenizer.json, cutting texts into windows as long as the
+// model's position embeddings reach (config.json's max_position_embeddings,
+// at most MAX_TOKENS) and never padding; `embed_batched` pads each batch itself
+pub fn load_tokenizer(dir: &Path) -> Result<Tokenizer, String> {
+    let mut tokenizer = Tokenizer::from_file(dir.join("tokenizer.json")).map_err(|e| format!("tokenizer.json: {}", e))?;
+    tokenizer
+        .with_truncation(Some(TruncationParams { max_length: max_tokens(dir), ..Default::default() }))
+        .map_err(|e| e.to_string())?;
+    tokenizer.with_padding(None);
+    Ok(tokenizer)
+}
+
+fn max_tokens(dir: &Path) -> usize {
+    std::fs::read_to_string(dir.join("config.json"))
+        .ok()
+        .and_then(|config| serde_json::from_str::<serde_json::Value>(&config).ok()?["max_position_embeddings"].as_u64())
+        .map_or(MAX_TOKENS, |positions| (positions as usize).min(MAX_TOKENS))
+}
+
+static COUNTERS: Mutex<BTreeMap<String, Option<Arc<TokenCounter>>>> = Mutex::new(BTreeMap::new());
+
+// A model's own tokenizer, untruncated, for counting tokens and cutting texts
+// at its limit before they go to an engine that doesn't tokenize them itself
+pub struct TokenCounter {
+    tokenizer: Tokenizer,
+    pub max_tokens: usize,
+}
+
+// The length of a text in tokens, and where to cut it to fit
+pub struct Fit {
+    // Special tokens included, as the model sees them
+    pub tokens: usize,
+    // Byte offset ending the longest prefix within the limit; None when the
+    // whole text fits
+    pub cut: Option<usize>,
+}
+
+impl TokenCounter {
+    // The counter for the checkout `model` resolves to, or None without a
+    // tokenizer.json there (remote model names, GGUF files); loaded once,
+    // misses included
+    pub fn for_model(model: &str) -> Option<Arc<TokenCounter>> {
+        let mut counters = COUNTERS.lock().unwrap_or_else(|e| e.into_inner());
+        counters
+            .entry(model.to_string())
+            .or_insert_with(|| {
+                let dir = model_dir(model);
+                let mut tokenizer = Tokenizer::from_file(dir.join("tokenizer.json")).ok()?;
+                tokenizer.with_truncation(None).ok()?;
+                tokenizer.with_padding(None);
+                Some(Arc::new(TokenCounter { tokenizer, max_tokens: max_tokens(&dir) }))
+            })
+            .clone()
+    }
+
+    pub fn fit(&self, text: &str) -> Result<Fit, String> {
+        let encoding = self.tokenizer.encode(text, true).map_err(|e| e.to_string())?;
+        let tokens = encoding.len();
+        if tokens <= self.max_tokens {
+            return Ok(Fit { tokens, cut: None });
+        }
+        // Room left once [CLS], [SEP] and the like are in
+        let special = encoding.get_special_tokens_mask().iter().filter(|&&special| special == 1).count();
+        let room = self.max_tokens.saturating_sub(special);
+        let mut content = encoding.get_special_tokens_mask().iter().zip(encoding.get_offsets()).filter(|(&special, _)| special == 0);
+        let cut = match room {
+            0 => 0,
+            room => content.nth(room - 1).map_or(0, |(_, &(_, end))| end),
+        };
+        let cut = (0..=cut.min(text.len())).rev().find(|&end| text.is_char_boundary(end)).unwrap_or_default();
+        Ok(Fit { tokens, cut: Some(cut) })
+    }
+}
+
+// One vector from the final hidden state of every token, then unit length
+// like the stored vectors it is compared with (unless --no-normalize); --pooling, when given, wins
+// over the model's own
+pub fn pool(states: Vec<Vec<f32>>, mask: &[f32], pooling: Pooling) -> Vec<f32> {
+    let mut embedding = match crate::embedding::pooling().unwrap_or(pooling) {
+        Pooling::Cls => states.into_iter().next().unwrap_or_default(),
+        // Padding never wins
+        Pooling::Max => {
+            let mut max = vec![f32::MIN; states.first().map_or(0, Vec::len)];
+            for (token, _) in states.iter().zip(mask).filter(|(_, weight)| **weight > 0.0) {
+                for (largest, value) in max.iter_mut().zip(token) {
+                    *largest = largest.max(*value);
+                }
+            }
+            max.into_iter().map(|value| if value == f32::MIN { 0.0 } else { value }).collect()
+        }
+        Pooling::Mean => {
+            let mut sum = vec![0.0; states.first().map_or(0, Vec::len)];
+            for (token, weight) in states.iter().zip(mask) {
+                for (total, value) in sum.iter_mut().zip(token) {
+                    *total += value * weight;
+                }
+            }
+            let count = mask.iter().sum::<f32>().max(1.0);
+            sum.into_iter().map(|total| total / count).collect()
+        }
+    };
+    unit_length(&mut embedding);
+    embedding
+}
+
+// One vector per text from `forward`, which gets `batch_size`
+// windows at a time and returns one pooled vector per row. A text longer than
+// the model's max tokens is split into several windows, which may land in
+// different batches, and comes back as their token-weighted average.
+pub fn embed_batched(
+    tokenizer: &Tokenizer,
+    texts: &[&str],
+    batch_size: usize,
+    mut forward: impl FnMut(&Batch) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>>,
+) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+    let mut windows = Vec::with_capacity(texts.len());
+    for (text, mut encoding) in tokenizer.encode_batch(texts.to_vec(), true).map_err(|e| e.to_string())?.into_iter().enumerate() {
+        let overflowing = encoding.take_overflowing();
+        windows.push((text, encoding));
+        windows.extend(overflowing.into_iter().map(|window| (text, window)));
+    }
+    // Similar lengths side by side keep the padding short
+    windows.sort_by_key(|(_, window)| window.len());
+
+    let mut embeddings: Vec<Vec<f32>> = vec![Vec::new(); texts.len()];
+    let mut weights = vec![0.0; texts.len()];
+    for group in windows.chunks(batch_size.max(1)) {
+        let len = group.iter().map(|(_, window)| window.len()).max().unwrap_or_default();
+        let mut batch = Batch { rows: group.len(), len, ids: Vec::new(), type_ids: Vec::new(), mask: Vec::new() };
+        for (_, window) in group {
+            let padding = len - window.len();
+            for (row, values) in [(&mut batch.ids, window.get_ids()), (&mut batch.type_ids, window.get_type_ids()), (&mut batch.mask, window.get_attention_mask())] {
+                row.extend_from_slice(values);
+                row.extend(std::iter::repeat_n(0, padding));
+            }
+        }
+        let pooled = forward(&batch)?;
+        if pooled.len() != group.len() {
+            return Err(format!("{} vectors for a batch of {}", pooled.len(), group.len()).into());
+        }
+        for ((text, window), vector) in group.iter().zip(pooled) {
+            let weight = window.len() as f32;
+            weights[*text] += weight;
+            let embedding = &mut embeddings[*text];
+            embedding.resize(vector.len(), 0.0);
+            for (total, value) in embedding.iter_mut().zip(vector) {
+                *total += value * weight;
+            }
+        }
+    }
+    for (embedding, weight) in embeddings.iter_mut().zip(weights) {
+        if weight > 0.0 {
+            embedding.iter_mut().for_each(|value| *value /= weight);
+        }
+        unit_length(embedding);
+    }
+    Ok(embeddings)
+}
+
+// One score per text from a cross-encoder reading `query` and the text as a
+// pair, `forward` getting `batch_size` pairs at a time and returning a score
+// per row. Pairs over the model's max tokens are cut to fit, trimming the
+// longer of the two; only the first window is scored.
+pub fn score_batched(
+    tokenizer: &Tokenizer,
+    query: &str,
+    texts: &[&str],
+    batch_size: usize,
+    mut forward: impl FnMut(&Batch) -> Result<Vec<f32>, Box<dyn std::error::Error>>,
+) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+    let pairs: Vec<(&str, &str)> = texts.iter().map(|text| (query, *text)).collect();
+    let mut encodings: Vec<(usize, tokenizers::Encoding)> = tokenizer.encode_batch(pairs, true).map_err(|e| e.to_string())?.into_iter().enumerate().collect();
+    encodings.sort_by_key(|(_, encoding)| encoding.len());
+
+    let mut scores = vec![0.0; texts.len()];
+    for group in encodings.chunks(batch_size.max(1)) {
+        let len = group.iter().map(|(_, encoding)| encoding.len()).max().unwrap_or_default();
+        let mut batch = Batch { rows: group.len(), len, ids: Vec::new(), type_ids: Vec::new(), mask: Vec::new() };
+        for (_, encoding) in group {
+            let padding = len - encoding.len();
+            for (row, values) in [(&mut batch.ids, encoding.get_ids()), (&mut batch.type_ids, encoding.get_type_ids()), (&mut batch.mask, encoding.get_attention_mask())] {
+                row.extend_from_slice(values);
+                row.extend(std::iter::repeat_n(0, padding));
+            }
+        }
+        let batch_scores = forward(&batch)?;
+        if batch_scores.len() != group.len() {
+            return Err(format!("{} scores for a batch of {}", batch_scores.len(), group.len()).into());
+        }
+        for ((text, _), score) in group.iter().zip(batch_scores) {
+            scores[*text] = score;
+        }
+    }
+    Ok(scores)
+}
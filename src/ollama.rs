@@ -0,0 +1,71 @@
+use crate::embedding::{unit_length, EmbeddingEngine};
+use crate::remote::{post_json, with_retries, Failure};
+
+// Where `ollama serve` listens unless OLLAMA_HOST, which Ollama's own CLI
+// also reads, says otherwise
+const DEFAULT_HOST: &str = "http://127.0.0.1:11434";
+const DEFAULT_PORT: u16 = 11434;
+// Texts per request; the server embeds a request's inputs together
+const BATCH_SIZE: usize = 32;
+
+// Registered as `ollama`
+pub struct OllamaEngine;
+
+impl EmbeddingEngine for OllamaEngine {
+    fn embed_batch(&self, model: &str, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+        embed_batch(model, texts)
+    }
+}
+
+// Embeddings for `texts` from a local Ollama server's `/api/embed`, in order
+// and unit length. `model` is an Ollama model tag such as nomic-embed-text.
+pub fn embed_batch(model: &str, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+    let host = host();
+    let url = format!("{}/api/embed", host);
+
+    let mut embeddings = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(BATCH_SIZE) {
+        let body = serde_json::json!({ "model": model, "input": batch }).to_string();
+        // A server that isn't running won't be by the next attempt
+        let response = with_retries(false, || post_json(&url, None, &body)).map_err(|e| diagnose(e, &host, model))?;
+        let vectors = response["embeddings"].as_array().ok_or("Ollama response has no 'embeddings' array")?;
+        if vectors.len() != batch.len() {
+            return Err(format!("Ollama returned {} embeddings for {} texts", vectors.len(), batch.len()));
+        }
+        for vector in vectors {
+            let mut embedding: Vec<f32> = vector.as_array().into_iter().flatten().map(|value| value.as_f64().unwrap_or_default() as f32).collect();
+            unit_length(&mut embedding);
+            embeddings.push(embedding);
+        }
+    }
+    Ok(embeddings)
+}
+
+// OLLAMA_HOST may leave out the scheme and port, as in `0.0.0.0` or
+// `localhost:8080`
+fn host() -> String {
+    let Some(host) = std::env::var("OLLAMA_HOST").ok().filter(|host| !host.trim().is_empty()) else {
+        return DEFAULT_HOST.to_string();
+    };
+    let host = host.trim().trim_end_matches('/');
+    let (scheme, address) = host.split_once("://").unwrap_or(("http", host));
+    match address.contains(':') {
+        true => format!("{}://{}", scheme, address),
+        false => format!("{}://{}:{}", scheme, address, DEFAULT_PORT),
+    }
+}
+
+// Turns the usual ways of holding Ollama wrong into instructions
+fn diagnose(failure: Failure, host: &str, model: &str) -> String {
+    match failure {
+        Failure::Unreachable(detail) => format!(
+            "Ollama server at {} is unreachable ({}); start it with `ollama serve` or point OLLAMA_HOST at a running one",
+            host, detail
+        ),
+        Failure::Status(404, message) if message.contains("not found") && message.contains("model") => {
+            format!("Ollama has no model '{}' ({}); fetch it with `ollama pull {}`", model, message, model)
+        }
+        Failure::Status(404, message) => format!("Ollama at {} has no /api/embed ({}); upgrade to Ollama 0.3.4 or later", host, message),
+        failure => format!("Ollama at {} {}", host, failure),
+    }
+}
@@ -0,0 +1,212 @@
+use crate::embedding::{
+    batch_size, device, fall_back_to_cpu, note_device_used, onnx_tuning, threads, ComputeDevice, EmbeddingEngine, GraphOptimization, preset_for_model,
+    unit_length, Pooling,
+};
+use crate::models::{embed_batched, load_tokenizer, model_dir, pool, score_batched};
+use ndarray::{Array2, Axis};
+use ort::execution_providers::{CUDAExecutionProvider, CoreMLExecutionProvider, ExecutionProvider, ExecutionProviderDispatch};
+use ort::session::builder::{GraphOptimizationLevel, SessionBuilder};
+use ort::session::Session;
+use ort::value::Tensor;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tokenizers::Tokenizer;
+
+// One loaded model; sessions need `&mut` to run, so each sits behind a lock
+struct OnnxModel {
+    session: Session,
+    tokenizer: Tokenizer,
+    pooling: Pooling,
+    token_type_ids: bool,
+    compute: ComputeDevice,
+}
+
+static MODELS: Mutex<BTreeMap<String, Arc<Mutex<OnnxModel>>>> = Mutex::new(BTreeMap::new());
+
+// Registered as `onnx`; texts go through the session --batch-size at a time,
+// loading the model on first use
+pub struct OnnxEngine;
+
+impl EmbeddingEngine for OnnxEngine {
+    fn embed_batch(&self, model: &str, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+        let loaded = load(model)?;
+        let mut loaded = loaded.lock().unwrap_or_else(|e| e.into_inner());
+        note_device_used(loaded.compute);
+        loaded.embed(texts).map_err(|e| format!("ONNX embedding with {} failed: {}", model, e))
+    }
+
+    fn score_pairs(&self, model: &str, query: &str, texts: &[&str]) -> Result<Vec<f32>, String> {
+        let loaded = load(model)?;
+        let mut loaded = loaded.lock().unwrap_or_else(|e| e.into_inner());
+        note_device_used(loaded.compute);
+        loaded.score(query, texts).map_err(|e| format!("ONNX reranking with {} failed: {}", model, e))
+    }
+
+    fn splits_long_inputs(&self) -> bool {
+        true
+    }
+}
+
+fn load(model: &str) -> Result<Arc<Mutex<OnnxModel>>, String> {
+    let mut models = MODELS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(loaded) = models.get(model) {
+        return Ok(loaded.clone());
+    }
+    let dir = model_dir(model);
+    let loaded = Arc::new(Mutex::new(OnnxModel::open(model, &dir).map_err(|e| format!("Failed to load {} from {}: {}", model, dir.display(), e))?));
+    models.insert(model.to_string(), loaded.clone());
+    Ok(loaded)
+}
+
+impl OnnxModel {
+    fn open(model: &str, dir: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        // Hugging Face repos keep the export under onnx/
+        let onnx_path = [dir.join("model.onnx"), dir.join("onnx").join("model.onnx")]
+            .into_iter()
+            .find(|path| path.is_file())
+            .ok_or("no model.onnx or onnx/model.onnx")?;
+        let tokenizer = load_tokenizer(dir)?;
+
+        // A session runs one batch at a time behind its lock, so --threads
+        // goes to ONNX Runtime's own operator threads instead; left unset,
+        // it picks as many as there are cores. --onnx-intra-threads and the
+        // other tuning flags override both
+        let tuning = onnx_tuning();
+        let builder = || -> ort::Result<SessionBuilder> {
+            let mut builder = match tuning.intra_threads.unwrap_or(threads()) {
+                1 if tuning.intra_threads.is_none() => Session::builder()?,
+                threads => Session::builder()?.with_intra_threads(threads)?,
+            };
+            if let Some(threads) = tuning.inter_threads {
+                builder = builder.with_parallel_execution(threads > 1)?.with_inter_threads(threads)?;
+            }
+            if let Some(optimization) = tuning.optimization {
+                builder = builder.with_optimization_level(match optimization {
+                    GraphOptimization::Disable => GraphOptimizationLevel::Disable,
+                    GraphOptimization::Basic => GraphOptimizationLevel::Level1,
+                    GraphOptimization::Extended => GraphOptimizationLevel::Level2,
+                    GraphOptimization::All => GraphOptimizationLevel::Level3,
+                })?;
+            }
+            Ok(builder)
+        };
+        let (session, compute) = match provider(device()) {
+            Ok(None) => (builder()?.commit_from_file(&onnx_path)?, ComputeDevice::Cpu),
+            Ok(Some(provider)) => match builder()?.with_execution_providers([provider.error_on_failure()]) {
+                Ok(builder) => (builder.commit_from_file(&onnx_path)?, device()),
+                Err(e) => (builder()?.commit_from_file(&onnx_path)?, fall_back_to_cpu(device(), e)),
+            },
+            Err(reason) => (builder()?.commit_from_file(&onnx_path)?, fall_back_to_cpu(device(), reason)),
+        };
+        let token_type_ids = session.inputs.iter().any(|input| input.name == "token_type_ids");
+        Ok(OnnxModel {
+            session,
+            tokenizer,
+            pooling: preset_for_model(model).map_or(Pooling::Mean, |preset| preset.pooling),
+            token_type_ids,
+            compute,
+        })
+    }
+
+    fn embed(&mut self, texts: &[&str]) -> Result<Vec<Vec<f32>>, Box<dyn std::error::Error>> {
+        let OnnxModel { session, tokenizer, pooling, token_type_ids, .. } = self;
+        embed_batched(tokenizer, texts, batch_size(), |batch| {
+            let rows = |values: &[u32]| Array2::from_shape_vec((batch.rows, batch.len), values.iter().map(|&v| v as i64).collect());
+            let mut inputs = ort::inputs! {
+                "input_ids" => Tensor::from_array(rows(&batch.ids)?)?,
+                "attention_mask" => Tensor::from_array(rows(&batch.mask)?)?,
+            };
+            if *token_type_ids {
+                inputs.push(("token_type_ids".into(), Tensor::from_array(rows(&batch.type_ids)?)?.into()));
+            }
+            let outputs = session.run(inputs)?;
+            // Exports either stop at the token states ([rows, tokens, hidden])
+            // or include a pooled `sentence_embedding` ([rows, hidden])
+            let output = outputs.get("sentence_embedding").unwrap_or(&outputs[0]).try_extract_array::<f32>()?;
+            match output.ndim() {
+                2 => Ok(output
+                    .axis_iter(Axis(0))
+                    .map(|row| {
+                        let mut embedding: Vec<f32> = row.iter().copied().collect();
+                        unit_length(&mut embedding);
+                        embedding
+                    })
+                    .collect()),
+                3 => Ok(output
+                    .axis_iter(Axis(0))
+                    .enumerate()
+                    .map(|(row, states)| {
+                        let states = states.axis_iter(Axis(0)).map(|token| token.iter().copied().collect()).collect();
+                        pool(states, &batch.row_mask(row), *pooling)
+                    })
+                    .collect()),
+                rank => Err(format!("unexpected output rank {}", rank).into()),
+            }
+        })
+    }
+
+    // Sequence-classification exports output `logits` ([rows, labels]); the
+    // score is the last label's, the "relevant" class of two-way classifiers
+    fn score(&mut self, query: &str, texts: &[&str]) -> Result<Vec<f32>, Box<dyn std::error::Error>> {
+        let OnnxModel { session, tokenizer, token_type_ids, .. } = self;
+        score_batched(tokenizer, query, texts, batch_size(), |batch| {
+            let rows = |values: &[u32]| Array2::from_shape_vec((batch.rows, batch.len), values.iter().map(|&v| v as i64).collect());
+            let mut inputs = ort::inputs! {
+                "input_ids" => Tensor::from_array(rows(&batch.ids)?)?,
+                "attention_mask" => Tensor::from_array(rows(&batch.mask)?)?,
+            };
+            if *token_type_ids {
+                inputs.push(("token_type_ids".into(), Tensor::from_array(rows(&batch.type_ids)?)?.into()));
+            }
+            let outputs = session.run(inputs)?;
+            let logits = outputs.get("logits").unwrap_or(&outputs[0]).try_extract_array::<f32>()?;
+            if logits.ndim() != 2 {
+                return Err(format!("expected [rows, labels] logits, got rank {}", logits.ndim()).into());
+            }
+            Ok(logits.axis_iter(Axis(0)).map(|row| row.iter().last().copied().unwrap_or_default()).collect())
+        })
+    }
+}
+
+// The execution provider for `device`, CoreML standing in for Metal; Err
+// when the loaded ONNX Runtime was built without it
+fn provider(device: ComputeDevice) -> Result<Option<ExecutionProviderDispatch>, String> {
+    let (name, available, provider) = match device {
+        ComputeDevice::Cpu => return Ok(None),
+        ComputeDevice::Cuda => {
+            let provider = CUDAExecutionProvider::default();
+            (provider.name(), provider.supported_by_platform() && provider.is_available().unwrap_or(false), provider.build())
+        }
+        ComputeDevice::Metal => {
+            let provider = CoreMLExecutionProvider::default();
+            (provider.name(), provider.supported_by_platform() && provider.is_available().unwrap_or(false), provider.build())
+        }
+    };
+    match available {
+        true => Ok(Some(provider)),
+        false => Err(format!("the ONNX Runtime library has no {}", name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    // Neither test reaches ONNX Runtime, so they pass without its library
+    #[test]
+    fn checkouts_without_an_export_are_refused() {
+        let dir = TempDir::new("onnx-missing").unwrap();
+        let model = dir.path().to_str().unwrap();
+        let error = OnnxEngine.embed_batch(model, &["text"]).err().unwrap();
+        assert!(error.starts_with(&format!("Failed to load {}", model)), "{}", error);
+        assert!(error.ends_with("no model.onnx or onnx/model.onnx"), "{}", error);
+        assert!(!MODELS.lock().unwrap().contains_key(model));
+    }
+
+    #[test]
+    fn the_cpu_needs_no_execution_provider() {
+        assert!(matches!(provider(ComputeDevice::Cpu), Ok(None)));
+    }
+}
@@ -0,0 +1,64 @@
+use crate::embedding::{dimensions, unit_length, EmbeddingEngine};
+use crate::remote::{post_json, with_retries, Failure};
+use serde_json::{json, Value};
+
+// OpenAI itself unless OPENAI_BASE_URL points at a compatible server
+// (vLLM, LM Studio, llama.cpp's server, a gateway, ...)
+const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+// Texts per request; well under the API's limit of 2048 inputs, so a batch
+// of long chunks also stays under its per-request token limit
+const BATCH_SIZE: usize = 96;
+
+// Registered as `openai`
+pub struct OpenAiEngine;
+
+impl EmbeddingEngine for OpenAiEngine {
+    fn embed_batch(&self, model: &str, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+        embed_batch(model, texts)
+    }
+}
+
+// Embeddings for `texts` from the `/embeddings` endpoint, in order and unit
+// length. Rate limits, server errors and dropped connections are retried
+// with exponential backoff.
+pub fn embed_batch(model: &str, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+    let base_url = std::env::var("OPENAI_BASE_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.to_string());
+    let url = format!("{}/embeddings", base_url.trim_end_matches('/'));
+    let api_key = std::env::var("OPENAI_API_KEY").ok();
+
+    let mut embeddings = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(BATCH_SIZE) {
+        let mut body = json!({ "model": model, "input": batch });
+        // text-embedding-3 shortens vectors itself, saving the transfer
+        if let Some(dimensions) = dimensions() {
+            body["dimensions"] = json!(dimensions);
+        }
+        let body = body.to_string();
+        let response = with_retries(true, || post_json(&url, api_key.as_deref(), &body)).map_err(|e| match e {
+            Failure::Status(..) => format!("{} {}", url, e),
+            _ => e.to_string(),
+        })?;
+        embeddings.extend(parse_embeddings(&response, batch.len())?);
+    }
+    Ok(embeddings)
+}
+
+// `{"data": [{"index", "embedding"}]}`; entries are put back in input order
+// rather than trusting the response's
+fn parse_embeddings(response: &Value, expected: usize) -> Result<Vec<Vec<f32>>, String> {
+    let data = response["data"].as_array().ok_or("Embedding response has no 'data' array")?;
+    let mut embeddings = vec![None; expected];
+    for (position, entry) in data.iter().enumerate() {
+        let index = entry["index"].as_u64().map_or(position, |index| index as usize);
+        let values = entry["embedding"].as_array().ok_or("Embedding response entry has no 'embedding' array")?;
+        let mut embedding: Vec<f32> = values.iter().map(|value| value.as_f64().unwrap_or_default() as f32).collect();
+        unit_length(&mut embedding);
+        if let Some(slot) = embeddings.get_mut(index) {
+            *slot = Some(embedding);
+        }
+    }
+    embeddings
+        .into_iter()
+        .collect::<Option<Vec<_>>>()
+        .ok_or_else(|| format!("Embedding response has {} entries for {} texts", data.len(), expected))
+}
@@ -0,0 +1,579 @@
+use context_rag_indexer::bench::BenchRun;
+use context_rag_indexer::indexer::{
+    AnnMode, Answerability, AssembledContext, ChunkAudit, ChunkInspection, Coverage, CoverageReport, FilePreview, Uncovered, IndexStats, IndexStatus, ReviewAnswer, SearchHit, SnapshotDiff, TermReport,
+};
+use context_rag_indexer::models::ModelInfo;
+use context_rag_indexer::profile::PhaseStats;
+use context_rag_indexer::selftest::SelftestReport;
+use context_rag_indexer::usage::UsageReport;
+use serde_json::{json, Value};
+use std::io::IsTerminal;
+use std::path::Path;
+
+const PREVIEW_CHARS: usize = 100;
+const MAX_LISTED_PATHS: usize = 20;
+const SARIF_RULE: &str = "context-rag/search-hit";
+
+// ANSI styling, only when stdout is a terminal and NO_COLOR is unset
+pub struct Painter {
+    enabled: bool,
+}
+
+impl Painter {
+    pub fn stdout() -> Self {
+        Painter {
+            enabled: std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        }
+    }
+
+    fn paint(&self, text: &str, code: &str) -> String {
+        if self.enabled {
+            format!("\x1b[{}m{}\x1b[0m", code, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    pub fn bold(&self, text: &str) -> String {
+        self.paint(text, "1")
+    }
+
+    pub fn dim(&self, text: &str) -> String {
+        self.paint(text, "2")
+    }
+
+    pub fn green(&self, text: &str) -> String {
+        self.paint(text, "32")
+    }
+
+    pub fn cyan(&self, text: &str) -> String {
+        self.paint(text, "36")
+    }
+
+    pub fn yellow(&self, text: &str) -> String {
+        self.paint(text, "33")
+    }
+}
+
+pub fn print_hits_table(hits: &[SearchHit]) {
+    let locations: Vec<String> = hits
+        .iter()
+        .map(|hit| format!("{}#{}", hit.file_path, hit.chunk_index))
+        .collect();
+    print_located_hits(hits.iter(), &locations);
+}
+
+// Changed hunks are located by line range, since their chunk numbers only
+// count within one hunk
+pub fn print_review(answer: &ReviewAnswer, with_index: bool) {
+    let painter = Painter::stdout();
+    println!("{} {}", painter.bold("Changes matching"), answer.query);
+    let locations: Vec<String> = answer
+        .changes
+        .iter()
+        .map(|change| format!("{}:{}-{}", change.hit.file_path, change.start_line, change.end_line))
+        .collect();
+    print_located_hits(answer.changes.iter().map(|change| &change.hit), &locations);
+
+    if with_index {
+        println!();
+        println!("{}", painter.bold("Elsewhere in the index"));
+        print_hits_table(&answer.index);
+    }
+}
+
+fn print_located_hits<'a>(hits: impl Iterator<Item = &'a SearchHit>, locations: &[String]) {
+    let painter = Painter::stdout();
+
+    if locations.is_empty() {
+        println!("{}", painter.dim("No results"));
+        return;
+    }
+
+    let width = locations.iter().map(|l| l.chars().count()).max().unwrap_or(0).max("LOCATION".len());
+
+    // Pad before painting so escape codes don't skew column widths
+    println!(
+        "{}  {}  {}  {}",
+        painter.bold(&format!("{:>3}", "#")),
+        painter.bold(&format!("{:>6}", "SCORE")),
+        painter.bold(&format!("{:<width$}", "LOCATION", width = width)),
+        painter.bold("PREVIEW"),
+    );
+
+    for (i, (hit, location)) in hits.zip(locations).enumerate() {
+        println!(
+            "{:>3}  {}  {}  {}",
+            i + 1,
+            painter.green(&format!("{:>6.3}", hit.score)),
+            painter.cyan(&format!("{:<width$}", location, width = width)),
+            preview(&hit.content),
+        );
+    }
+}
+
+// `path:line:col: snippet` per hit, what Vim's and most editors' quickfix
+// lists parse by default; hits whose text can't be found in their file point
+// at its first line
+pub fn print_vimgrep(hits: &[SearchHit]) {
+    for hit in hits {
+        let span = hit.locate(Path::new(""));
+        println!(
+            "{}:{}:{}: {}",
+            hit.file_path.trim_start_matches("./"),
+            span.map_or(1, |span| span.start_line),
+            span.map_or(1, |span| span.start_column),
+            preview(&hit.content),
+        );
+    }
+}
+
+// A SARIF 2.1.0 log with one `note` result per hit, for code-scanning UIs
+pub fn print_sarif(query: &str, hits: &[SearchHit]) -> serde_json::Result<()> {
+    let results: Vec<Value> = hits
+        .iter()
+        .map(|hit| {
+            let mut location = json!({ "artifactLocation": { "uri": hit.file_path.trim_start_matches("./") } });
+            if let Some(span) = hit.locate(Path::new("")) {
+                location["region"] = json!({ "startLine": span.start_line, "startColumn": span.start_column, "endLine": span.end_line });
+            }
+            let mut result = json!({
+                "ruleId": SARIF_RULE,
+                "level": "note",
+                "message": { "text": format!("Matches \"{}\": {}", query, preview(&hit.content)) },
+                "locations": [{ "physicalLocation": location }],
+                "properties": { "score": hit.score, "chunkIndex": hit.chunk_index, "chunkHash": hit.chunk_hash },
+            });
+            if let Some(confidence) = hit.confidence {
+                result["rank"] = json!((confidence * 100.0).clamp(0.0, 100.0));
+            }
+            result
+        })
+        .collect();
+
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": { "driver": {
+                "name": "context-rag",
+                "version": env!("CARGO_PKG_VERSION"),
+                "rules": [{ "id": SARIF_RULE, "shortDescription": { "text": "Indexed content matching a search query" } }],
+            } },
+            "results": results,
+        }],
+    });
+    println!("{}", serde_json::to_string_pretty(&log)?);
+    Ok(())
+}
+
+// Goes to stderr so piped result tables stay clean
+pub fn warn_unanswerable(hits: &[SearchHit], answerability: &Answerability) {
+    if answerability.answerable || hits.is_empty() {
+        return;
+    }
+    let painter = Painter::stdout();
+    eprintln!(
+        "{}",
+        painter.yellow(&format!(
+            "Results may not answer this query ({:.0}% of its terms matched); consider a broader search",
+            answerability.term_coverage * 100.0
+        ))
+    );
+}
+
+pub fn print_stats_table(stats: &IndexStats) {
+    let painter = Painter::stdout();
+    let rows = [
+        ("Index", stats.storage_path.clone()),
+        ("Files", stats.files.to_string()),
+        ("Chunks", stats.chunks.to_string()),
+        ("Segments", stats.segments.to_string()),
+        ("Shards", stats.shards.to_string()),
+        ("Size on disk", human_bytes(stats.size_bytes)),
+        ("Mapped", human_bytes(stats.mapped_bytes)),
+        ("Vectors", match &stats.vector_model {
+            Some(model) => format!("{} of {} chunks ({})", stats.embedded_chunks, stats.chunks, model),
+            None => painter.dim("none (run `context-rag-embedder backfill`)"),
+        }),
+        ("Vector RAM", match stats.ann_mode {
+            Some(AnnMode::Disk) => format!("{} (over budget; scored off disk)", human_bytes(stats.vector_memory_bytes)),
+            Some(AnnMode::Memory) => human_bytes(stats.vector_memory_bytes),
+            None => painter.dim("none"),
+        }),
+    ];
+
+    for (label, value) in rows {
+        println!("{}  {}", painter.bold(&format!("{:<12}", label)), value);
+    }
+}
+
+// Each requested field's top terms with the share of chunks holding them,
+// then every field's cardinality
+pub fn print_term_report(report: &TermReport) {
+    let painter = Painter::stdout();
+    let chunks = report.chunks.max(1) as f64;
+
+    for field in report.fields.iter().filter(|field| !field.top_terms.is_empty()) {
+        println!("{} ({} distinct terms)", painter.bold(&field.field), field.distinct_terms);
+        println!("  {}", painter.dim(&format!("{:<32} {:>8} {:>7} {:>11}", "term", "chunks", "share", "occurrences")));
+        for term in &field.top_terms {
+            println!(
+                "  {:<32} {:>8} {:>6.1}% {:>11}",
+                term.term,
+                term.doc_freq,
+                term.doc_freq as f64 / chunks * 100.0,
+                term.term_freq
+            );
+        }
+        println!();
+    }
+
+    println!("{} ({} chunks)", painter.bold("Distinct terms per field"), report.chunks);
+    for field in &report.fields {
+        println!("  {:<20} {:>10}", field.field, field.distinct_terms);
+    }
+}
+
+// The directory tree down to `depth` levels with the share of each subtree
+// that is indexed, then the extensions with files left out
+pub fn print_coverage(report: &CoverageReport, depth: usize) {
+    let painter = Painter::stdout();
+    let line = |label: &str, coverage: &Coverage| {
+        let share = coverage.indexed as f64 / coverage.files.max(1) as f64;
+        let share = format!("{:>3.0}%", share * 100.0);
+        let share = match coverage.indexed {
+            indexed if indexed == coverage.files => painter.green(&share),
+            0 => painter.dim(&share),
+            _ => painter.yellow(&share),
+        };
+        let reasons: Vec<String> = coverage.uncovered.iter().map(|(reason, files)| format!("{} {}", files, reason)).collect();
+        let line = format!("{:<40} {} {:>6}/{:<6} {}", label, share, coverage.indexed, coverage.files, painter.dim(&reasons.join(", ")));
+        println!("{}", line.trim_end());
+    };
+
+    for directory in &report.directories {
+        let level = match directory.path.as_str() {
+            "." => 0,
+            path => path.matches('/').count() + 1,
+        };
+        if level > depth {
+            continue;
+        }
+        let name = directory.path.rsplit('/').next().unwrap_or_default();
+        let label = match level {
+            0 => "./".to_string(),
+            _ => format!("{}{}/", "  ".repeat(level), name),
+        };
+        line(&label, &directory.coverage);
+    }
+
+    let uncovered: Vec<_> = report.extensions.iter().filter(|extension| extension.coverage.indexed < extension.coverage.files).collect();
+    if !uncovered.is_empty() {
+        println!();
+        println!("{}", painter.bold("Extensions with files left out"));
+        for extension in uncovered.iter().take(MAX_LISTED_PATHS) {
+            let label = match extension.extension.as_str() {
+                "" => "(none)".to_string(),
+                extension => format!(".{}", extension),
+            };
+            line(&format!("  {}", label), &extension.coverage);
+        }
+        if uncovered.len() > MAX_LISTED_PATHS {
+            println!("  … and {} more", uncovered.len() - MAX_LISTED_PATHS);
+        }
+    }
+
+    if !report.excluded_directories.is_empty() {
+        println!();
+        println!("{} {}", painter.bold("Excluded directories (not walked):"), report.excluded_directories.join(", "));
+    }
+    if report.total.uncovered.contains_key(&Uncovered::NotIndexed) {
+        println!("Run `context-rag-embedder index` to pick up files not indexed yet");
+    }
+}
+
+pub fn print_status(status: &IndexStatus) {
+    let painter = Painter::stdout();
+
+    if !status.stale {
+        println!("{} ({} files indexed)", painter.green("Index is up to date"), status.indexed_files);
+        return;
+    }
+
+    println!(
+        "{} ({} files indexed): {} changed, {} missing, {} new",
+        painter.yellow("Index is stale"),
+        status.indexed_files,
+        status.changed.len(),
+        status.missing.len(),
+        status.new.len(),
+    );
+
+    for (label, paths) in [("changed", &status.changed), ("missing", &status.missing), ("new", &status.new)] {
+        for path in paths.iter().take(MAX_LISTED_PATHS) {
+            println!("  {} {}", painter.dim(&format!("{:<8}", label)), path);
+        }
+        if paths.len() > MAX_LISTED_PATHS {
+            println!("  {} … and {} more", painter.dim(&format!("{:<8}", label)), paths.len() - MAX_LISTED_PATHS);
+        }
+    }
+    println!("Run `context-rag-embedder index` to refresh");
+}
+
+pub fn print_diff(diff: &SnapshotDiff) {
+    let painter = Painter::stdout();
+    let chunk_delta = diff.chunks_after as i64 - diff.chunks_before as i64;
+
+    println!(
+        "{} added, {} removed, {} changed, {} unchanged; chunks {} -> {} ({:+})",
+        diff.added.len(),
+        diff.removed.len(),
+        diff.changed.len(),
+        diff.unchanged,
+        diff.chunks_before,
+        diff.chunks_after,
+        chunk_delta,
+    );
+
+    for (label, files) in [("added", &diff.added), ("removed", &diff.removed), ("changed", &diff.changed)] {
+        for file in files.iter().take(MAX_LISTED_PATHS) {
+            let delta = file.chunks_after as i64 - file.chunks_before as i64;
+            println!(
+                "  {} {} {}",
+                painter.dim(&format!("{:<8}", label)),
+                file.file_path,
+                painter.dim(&format!("({} -> {} chunks, {:+})", file.chunks_before, file.chunks_after, delta)),
+            );
+        }
+        if files.len() > MAX_LISTED_PATHS {
+            println!("  {} … and {} more", painter.dim(&format!("{:<8}", label)), files.len() - MAX_LISTED_PATHS);
+        }
+    }
+}
+
+pub fn print_audit(audit: &ChunkAudit) {
+    let painter = Painter::stdout();
+    for (label, value) in audit_rows(audit, &painter) {
+        println!("{}  {}", painter.bold(&format!("{:<12}", label)), value);
+    }
+    println!();
+    println!("{}", audit.chunk.content);
+}
+
+// The audit, then the chunk's vector and every segment holding a copy of it
+pub fn print_inspection(inspection: &ChunkInspection) {
+    let painter = Painter::stdout();
+    let chunk = &inspection.audit.chunk;
+    let mut rows = audit_rows(&inspection.audit, &painter);
+    rows.push(("Chunk hash", chunk.chunk_hash.clone()));
+    rows.push(("Language", if chunk.language.is_empty() { painter.dim("(none)") } else { chunk.language.clone() }));
+    rows.push(("Test code", chunk.is_test.to_string()));
+    rows.push(("Vector", match &inspection.vector {
+        Some(vector) => format!("{} dimensions, norm {:.4} ({})", vector.dimensions, vector.norm, vector.model),
+        None => painter.dim("none (run `context-rag-embedder backfill`)"),
+    }));
+    for copy in &inspection.segments {
+        let state = if copy.deleted { painter.yellow("deleted, awaiting merge") } else { painter.green("live") };
+        rows.push(("Segment", format!("shard {} segment {} doc {} ({})", copy.shard, copy.segment, copy.doc, state)));
+    }
+
+    for (label, value) in rows {
+        println!("{}  {}", painter.bold(&format!("{:<12}", label)), value);
+    }
+    println!();
+    println!("{}", chunk.content);
+}
+
+fn audit_rows(audit: &ChunkAudit, painter: &Painter) -> Vec<(&'static str, String)> {
+    let provenance = &audit.provenance;
+    let or_unknown = |value: &str| if value.is_empty() { painter.dim("(unknown)") } else { value.to_string() };
+
+    vec![
+        ("Chunk", painter.cyan(&audit.chunk_id)),
+        ("File hash", audit.file_hash.clone()),
+        ("Modified", audit.chunk.modified_time.to_string()),
+        ("Run", or_unknown(&provenance.run_id)),
+        ("Indexed at", provenance.indexed_at.to_string()),
+        // Single-file refreshes don't carry a config
+        ("Config hash", or_unknown(&provenance.config_hash)),
+        ("Chunker", or_unknown(&provenance.chunker_version)),
+        ("Model", or_unknown(&provenance.model)),
+        ("License", if audit.license_stripped { "header stripped".to_string() } else { painter.dim("not stripped") }),
+    ]
+}
+
+// The file's settings, then a row per chunk; a chunk the model would cut
+// short is flagged
+pub fn print_file_preview(file: &FilePreview) {
+    let painter = Painter::stdout();
+    println!("{}  {}", painter.bold(&format!("{:<12}", "File")), painter.cyan(&file.file_path));
+    println!("{}  {}", painter.bold(&format!("{:<12}", "Language")), if file.language.is_empty() { painter.dim("(none)") } else { file.language.clone() });
+    println!("{}  {}", painter.bold(&format!("{:<12}", "Model")), file.model);
+    if file.license_stripped {
+        println!("{}  header stripped", painter.bold(&format!("{:<12}", "License")));
+    }
+    if file.ignored {
+        println!("{}", painter.yellow("An ignore marker keeps this file out of the index"));
+    } else if let Some(reason) = &file.skipped {
+        println!("{}", painter.yellow(&format!("An index run would leave this file out ({})", reason)));
+    }
+    println!();
+
+    println!("{}", painter.bold(&format!("{:>5}  {:<11}  {:>6}  {:>6}  Preview", "Chunk", "Lines", "Chars", "Tokens")));
+    for chunk in &file.chunks {
+        let lines = match (chunk.start_line, chunk.end_line) {
+            (Some(start), Some(end)) => format!("{}-{}", start, end),
+            _ => "?".to_string(),
+        };
+        let tokens = chunk.model_tokens.unwrap_or(chunk.estimated_tokens);
+        let mut flags = Vec::new();
+        if chunk.truncated == Some(true) {
+            flags.push(painter.yellow("truncated"));
+        }
+        if chunk.is_test {
+            flags.push(painter.dim("test"));
+        }
+        println!(
+            "{:>5}  {:<11}  {:>6}  {:>6}  {}{}",
+            chunk.chunk_index,
+            lines,
+            chunk.chars,
+            tokens,
+            if flags.is_empty() { String::new() } else { format!("[{}] ", flags.join(", ")) },
+            painter.dim(&preview(&chunk.content)),
+        );
+    }
+    println!("{} chunks", file.chunks.len());
+}
+
+// One row per run, the fastest marked
+pub fn print_bench(model: &str, runs: &[BenchRun]) {
+    let painter = Painter::stdout();
+    let fastest = runs.iter().map(|run| run.chunks_per_sec).fold(0.0, f64::max);
+    println!("{} chunks, ~{} tokens, embedded with {}", runs.first().map_or(0, |run| run.chunks), runs.first().map_or(0, |run| run.tokens), model);
+    println!("{}", painter.bold(&format!("{:<8}  {:>5}  {:>7}  {:>10}  {:>10}  {:>10}", "Engine", "Batch", "Threads", "Chunks/s", "Tokens/s", "Peak heap")));
+    for run in runs {
+        let line = format!(
+            "{:<8}  {:>5}  {:>7}  {:>10.1}  {:>10.0}  {:>10}",
+            run.engine,
+            run.batch_size,
+            run.threads,
+            run.chunks_per_sec,
+            run.tokens_per_sec,
+            human_bytes(run.peak_memory_bytes),
+        );
+        println!("{}", if run.chunks_per_sec == fastest { painter.green(&line) } else { line });
+    }
+}
+
+pub fn print_selftest(report: &SelftestReport) {
+    let painter = Painter::stdout();
+    println!("Self-test of the {} engine with {}", painter.bold(&report.engine), painter.bold(&report.model));
+    for check in &report.checks {
+        let status = if check.passed { painter.green("pass") } else { painter.yellow("FAIL") };
+        println!("  {}  {:<14} {}", status, check.name, check.detail);
+    }
+}
+
+pub fn print_usage_report(report: &UsageReport) {
+    let painter = Painter::stdout();
+    if report.commands.is_empty() {
+        println!("No usage recorded in {}", report.file);
+        if !report.recording {
+            println!("{}", painter.dim("Pass --usage-stats or set CONTEXT_RAG_USAGE_STATS=1 to start recording"));
+        }
+        return;
+    }
+    if let (Some(first), Some(last)) = (report.first_recorded, report.last_recorded) {
+        println!("Usage recorded in {} from {} to {}", report.file, first.format("%Y-%m-%d"), last.format("%Y-%m-%d"));
+    }
+    println!("{}", painter.bold(&format!("{:<8}  {:>8}  {:>8}  {:>10}  {:>10}", "Command", "Runs", "Failed", "Average", "Slowest")));
+    for command in &report.commands {
+        println!(
+            "{:<8}  {:>8}  {:>8}  {:>10}  {:>10}",
+            command.command,
+            command.runs,
+            command.failures,
+            format!("{:.0} ms", command.average_ms),
+            format!("{} ms", command.max_ms),
+        );
+    }
+}
+
+pub fn print_models(models: &[ModelInfo]) {
+    let painter = Painter::stdout();
+    println!("{}", painter.bold(&format!("{:<44}  {:>10}  {:>10}  {:<7}  {:>10}", "Model", "Dimensions", "Max tokens", "Pooling", "Size")));
+    for info in models {
+        println!(
+            "{:<44}  {:>10}  {:>10}  {:<7}  {:>10}",
+            info.model,
+            info.dimensions.map_or("?".to_string(), |dimensions| dimensions.to_string()),
+            info.max_tokens,
+            info.pooling.to_string(),
+            if info.installed { human_bytes(info.size_bytes) } else { painter.dim("not found") },
+        );
+    }
+}
+
+// Plain text meant to be pasted or piped into a prompt; the budget summary
+// goes to stderr
+pub fn print_context(context: &AssembledContext) {
+    for chunk in &context.chunks {
+        let pin = if chunk.pinned { " (pinned)" } else { "" };
+        println!("--- {}#{}{}", chunk.hit.file_path, chunk.hit.chunk_index, pin);
+        println!("{}", chunk.hit.content);
+        println!();
+    }
+    eprintln!(
+        "{} chunks, ~{} of {} tokens{}",
+        context.chunks.len(),
+        context.tokens,
+        context.max_tokens,
+        if context.dropped > 0 { format!("; {} didn't fit", context.dropped) } else { String::new() }
+    );
+}
+
+// Goes to stderr so profiling doesn't disturb JSON on stdout; nested phases
+// are indented under the phase they ran in
+pub fn print_profile(phases: &[(String, PhaseStats)]) {
+    eprintln!("{:<28} {:>7} {:>10} {:>10} {:>10} {:>10}", "Phase", "Calls", "Wall ms", "CPU ms", "Allocs", "Allocated");
+    for (stack, stats) in phases {
+        let depth = stack.matches(';').count();
+        let name = stack.rsplit(';').next().unwrap_or(stack);
+        eprintln!(
+            "{:<28} {:>7} {:>10.1} {:>10.1} {:>10} {:>10}",
+            format!("{}{}", "  ".repeat(depth), name),
+            stats.calls,
+            stats.wall_us as f64 / 1000.0,
+            stats.cpu_us as f64 / 1000.0,
+            stats.allocations,
+            human_bytes(stats.allocated_bytes),
+        );
+    }
+}
+
+pub fn preview(content: &str) -> String {
+    let flat = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    if flat.chars().count() > PREVIEW_CHARS {
+        format!("{}…", flat.chars().take(PREVIEW_CHARS).collect::<String>())
+    } else {
+        flat
+    }
+}
+
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Off unless a run asks for it, so spans cost one atomic load otherwise
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+// Bytes allocated and not yet freed, and the most there have been since the
+// last `reset_peak`
+static LIVE_BYTES: AtomicU64 = AtomicU64::new(0);
+static PEAK_BYTES: AtomicU64 = AtomicU64::new(0);
+// Keyed by the `;`-joined span stack, the folded format flamegraph tools read
+static PHASES: Mutex<BTreeMap<String, PhaseStats>> = Mutex::new(BTreeMap::new());
+
+thread_local! {
+    static STACK: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+// Counts every allocation made through it. Binaries that want allocation
+// stats install it as their global allocator; without it they read zero.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed);
+        let live = LIVE_BYTES.fetch_add(layout.size() as u64, Ordering::Relaxed) + layout.size() as u64;
+        PEAK_BYTES.fetch_max(live, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        LIVE_BYTES.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+// Totals for one span stack. Times include nested spans; allocations are
+// process-wide, so spans overlapping other threads' work count theirs too.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct PhaseStats {
+    pub calls: u64,
+    pub wall_us: u64,
+    // This thread's CPU time; zero where the platform doesn't report it
+    pub cpu_us: u64,
+    pub allocations: u64,
+    pub allocated_bytes: u64,
+}
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+// Records the time between now and the guard's drop under `name`, nested in
+// whatever spans this thread has open
+pub fn span(name: &'static str) -> Span {
+    if !is_enabled() {
+        return Span { start: None };
+    }
+    STACK.with(|stack| stack.borrow_mut().push(name));
+    Span {
+        start: Some(SpanStart {
+            wall: Instant::now(),
+            cpu: thread_cpu_time(),
+            allocations: ALLOCATIONS.load(Ordering::Relaxed),
+            allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+        }),
+    }
+}
+
+pub struct Span {
+    start: Option<SpanStart>,
+}
+
+struct SpanStart {
+    wall: Instant,
+    cpu: Duration,
+    allocations: u64,
+    allocated_bytes: u64,
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let Some(start) = self.start.take() else {
+            return;
+        };
+        let stack = STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            let folded = stack.join(";");
+            stack.pop();
+            folded
+        });
+
+        let mut phases = PHASES.lock().unwrap_or_else(|e| e.into_inner());
+        let phase = phases.entry(stack).or_default();
+        phase.calls += 1;
+        phase.wall_us += start.wall.elapsed().as_micros() as u64;
+        phase.cpu_us += thread_cpu_time().saturating_sub(start.cpu).as_micros() as u64;
+        phase.allocations += ALLOCATIONS.load(Ordering::Relaxed) - start.allocations;
+        phase.allocated_bytes += ALLOCATED_BYTES.load(Ordering::Relaxed) - start.allocated_bytes;
+    }
+}
+
+// Starts measuring peak heap use from what is live now, which it returns
+pub fn reset_peak() -> u64 {
+    let live = LIVE_BYTES.load(Ordering::Relaxed);
+    PEAK_BYTES.store(live, Ordering::Relaxed);
+    live
+}
+
+pub fn peak_bytes() -> u64 {
+    PEAK_BYTES.load(Ordering::Relaxed)
+}
+
+// Every span stack recorded so far, sorted so parents precede children
+pub fn report() -> Vec<(String, PhaseStats)> {
+    let phases = PHASES.lock().unwrap_or_else(|e| e.into_inner());
+    phases.iter().map(|(stack, stats)| (stack.clone(), stats.clone())).collect()
+}
+
+// Folded stacks weighted by wall-clock microseconds spent in each span
+// itself, ready for `inferno-flamegraph` or `flamegraph.pl`
+pub fn write_folded(path: &Path) -> std::io::Result<()> {
+    let phases = report();
+    let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+    for (stack, stats) in &phases {
+        let prefix = format!("{};", stack);
+        let children: u64 = phases
+            .iter()
+            .filter(|(child, _)| child.strip_prefix(&prefix).is_some_and(|rest| !rest.contains(';')))
+            .map(|(_, child)| child.wall_us)
+            .sum();
+        writeln!(file, "{} {}", stack, stats.wall_us.saturating_sub(children))?;
+    }
+    file.flush()
+}
+
+#[cfg(unix)]
+fn thread_cpu_time() -> Duration {
+    let mut time = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // SAFETY: `time` is a valid timespec for the call to fill in
+    if unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time) } != 0 {
+        return Duration::ZERO;
+    }
+    Duration::new(time.tv_sec as u64, time.tv_nsec as u32)
+}
+
+#[cfg(not(unix))]
+fn thread_cpu_time() -> Duration {
+    Duration::ZERO
+}
@@ -0,0 +1,84 @@
+use crate::embedding::normalize;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+// Compact encodings for vectors handed to callers that store many of them:
+// int8 keeps a byte per dimension, binary a bit, against four for f32
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Quantization {
+    Int8,
+    Binary,
+}
+
+impl std::str::FromStr for Quantization {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "int8" => Ok(Quantization::Int8),
+            "binary" => Ok(Quantization::Binary),
+            other => Err(format!("Unknown quantization '{}' (expected int8 or binary)", other)),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "quantization", rename_all = "lowercase")]
+pub enum QuantizedVector {
+    // Symmetric: each value is round(x / scale), scale being the largest
+    // magnitude over 127
+    Int8 { scale: f32, values: Vec<i8> },
+    // One sign bit per dimension, packed eight to a byte with the first
+    // dimension in the high bit; set means positive. Every dimension comes
+    // back as plus or minus `scale`, the mean magnitude.
+    Binary { dimensions: usize, scale: f32, bits: Vec<u8> },
+}
+
+impl QuantizedVector {
+    pub fn new(embedding: &[f32], quantization: Quantization) -> Self {
+        match quantization {
+            Quantization::Int8 => {
+                let largest = embedding.iter().fold(0.0f32, |largest, value| largest.max(value.abs()));
+                let scale = if largest > 0.0 { largest / 127.0 } else { 1.0 };
+                let values = embedding.iter().map(|value| (value / scale).round().clamp(-127.0, 127.0) as i8).collect();
+                QuantizedVector::Int8 { scale, values }
+            }
+            Quantization::Binary => {
+                let scale = embedding.iter().map(|value| value.abs()).sum::<f32>() / embedding.len().max(1) as f32;
+                let mut bits = vec![0u8; embedding.len().div_ceil(8)];
+                for (dimension, value) in embedding.iter().enumerate() {
+                    if *value > 0.0 {
+                        bits[dimension / 8] |= 0x80 >> (dimension % 8);
+                    }
+                }
+                QuantizedVector::Binary { dimensions: embedding.len(), scale, bits }
+            }
+        }
+    }
+
+    // Back to floats, unit length again so they score against query vectors
+    // (and stored ones) by dot product like any other
+    pub fn dequantize(&self) -> Vec<f32> {
+        let mut embedding: Vec<f32> = match self {
+            QuantizedVector::Int8 { scale, values } => values.iter().map(|&value| value as f32 * scale).collect(),
+            QuantizedVector::Binary { dimensions, scale, bits } => (0..*dimensions)
+                .map(|dimension| match bits.get(dimension / 8).is_some_and(|byte| byte & (0x80 >> (dimension % 8)) != 0) {
+                    true => *scale,
+                    false => -scale,
+                })
+                .collect(),
+        };
+        normalize(&mut embedding);
+        embedding
+    }
+}
+
+// An embedding as the stdin protocol prints it: a plain array of floats, or
+// the quantized object when one was asked for
+pub fn embedding_json(embedding: &[f32], quantization: Option<Quantization>) -> Value {
+    match quantization {
+        Some(quantization) => json!(QuantizedVector::new(embedding, quantization)),
+        None => json!(embedding),
+    }
+}
@@ -0,0 +1,113 @@
+use crate::cache::EmbeddingCache;
+use crate::embedding::{self, EmbeddingEngine};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+// Vectors the engine returns can be written to a directory and read back in
+// place of the engine on a later run, so downstream projects can test
+// against real model output without downloading the model or calling a
+// provider. Recordings use the embedding cache's layout and keys: one file
+// per text, so they replay whatever the batch size or thread count, and only
+// for the engine, model and vector settings they were recorded with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Recording {
+    // Every vector the engine returns is also written to the directory
+    Record(PathBuf),
+    // Vectors come from the directory alone; a text with none recorded fails
+    Replay(PathBuf),
+}
+
+// None (the library's default) calls the engine as usual
+static RECORDING: Mutex<Option<Recording>> = Mutex::new(None);
+
+pub fn set_recording(recording: Option<Recording>) {
+    *RECORDING.lock().unwrap_or_else(|e| e.into_inner()) = recording;
+}
+
+pub fn recording() -> Option<Recording> {
+    RECORDING.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+// Stands between callers and the selected engine while a recording is set
+pub struct RecordingEngine {
+    pub inner: Arc<dyn EmbeddingEngine>,
+    // The selected engine's name, which recordings are keyed by
+    pub engine: String,
+    pub recording: Recording,
+}
+
+impl RecordingEngine {
+    // Keyed without the model file, which replays run without
+    fn store(&self, dir: &std::path::Path, model: &str) -> EmbeddingCache {
+        EmbeddingCache::new(dir, &self.engine, model, None, embedding::dimensions(), embedding::pooling(), embedding::normalizes())
+    }
+}
+
+impl EmbeddingEngine for RecordingEngine {
+    fn embed_batch(&self, model: &str, texts: &[&str]) -> Result<Vec<Vec<f32>>, String> {
+        match &self.recording {
+            Recording::Record(dir) => {
+                let store = self.store(dir, model);
+                let embeddings = self.inner.embed_batch(model, texts)?;
+                for (text, embedding) in texts.iter().zip(&embeddings) {
+                    store.write(text, embedding).map_err(|e| format!("Failed to record an embedding in {}: {}", dir.display(), e))?;
+                }
+                Ok(embeddings)
+            }
+            Recording::Replay(dir) => {
+                let store = self.store(dir, model);
+                texts
+                    .iter()
+                    .map(|text| {
+                        store.get(text).ok_or_else(|| {
+                            let start: String = text.chars().take(60).collect();
+                            format!("No {} embedding from {} recorded in {} for {:?}; record it with --record", model, self.engine, dir.display(), start)
+                        })
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    // Texts reach the recording cut as the engine would see them, so they
+    // replay under the same keys
+    fn splits_long_inputs(&self) -> bool {
+        self.inner.splits_long_inputs()
+    }
+
+    fn parallel_on_cpu(&self) -> bool {
+        self.inner.parallel_on_cpu()
+    }
+
+    fn score_pairs(&self, model: &str, query: &str, texts: &[&str]) -> Result<Vec<f32>, String> {
+        match &self.recording {
+            Recording::Record(_) => self.inner.score_pairs(model, query, texts),
+            Recording::Replay(_) => Err(format!("Rerank scores aren't recorded, so {} can't rerank under --replay", model)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::MockEngine;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn recorded_vectors_replay_for_their_engine_only() {
+        let dir = TempDir::new("recording").unwrap();
+        let engine = |recording| RecordingEngine { inner: Arc::new(MockEngine), engine: "mock".to_string(), recording };
+        let recorded = engine(Recording::Record(dir.path().to_path_buf())).embed_batch("mock-model", &["alpha", "beta"]).unwrap();
+        assert_eq!(recorded, MockEngine.embed_batch("mock-model", &["alpha", "beta"]).unwrap());
+
+        let replay = engine(Recording::Replay(dir.path().to_path_buf()));
+        assert_eq!(replay.embed_batch("mock-model", &["beta", "alpha"]).unwrap(), [recorded[1].clone(), recorded[0].clone()]);
+        let missing = replay.embed_batch("mock-model", &["alpha", "gamma"]).unwrap_err();
+        assert!(missing.starts_with("No mock-model embedding from mock recorded in") && missing.contains("\"gamma\""), "{}", missing);
+        assert!(replay.embed_batch("other-model", &["alpha"]).is_err());
+        assert!(replay.score_pairs("mock-model", "alpha", &["beta"]).is_err());
+
+        let other = RecordingEngine { engine: "openai".to_string(), ..engine(Recording::Replay(dir.path().to_path_buf())) };
+        assert!(other.embed_batch("mock-model", &["alpha"]).is_err());
+    }
+}
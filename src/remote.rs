@@ -0,0 +1,145 @@
+use serde_json::Value;
+use std::fmt;
+use std::io::Write;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+pub const DEFAULT_RETRY_DELAY_MS: u64 = 500;
+// Backoff stops doubling here, so long retry budgets don't wait for hours
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+const REQUEST_TIMEOUT_SECS: u64 = 120;
+
+// Retries after a failed request, and the wait before the first of them
+static MAX_RETRIES: AtomicU32 = AtomicU32::new(DEFAULT_MAX_RETRIES);
+static RETRY_DELAY_MS: AtomicU64 = AtomicU64::new(DEFAULT_RETRY_DELAY_MS);
+// Requests a minute over every remote engine, 0 for no limit, and when the
+// next one may go out
+static REQUESTS_PER_MINUTE: AtomicU32 = AtomicU32::new(0);
+static NEXT_REQUEST: Mutex<Option<Instant>> = Mutex::new(None);
+
+pub fn set_retries(max_retries: u32, first_delay: Duration) {
+    MAX_RETRIES.store(max_retries, Ordering::Relaxed);
+    RETRY_DELAY_MS.store(first_delay.as_millis() as u64, Ordering::Relaxed);
+}
+
+// Spaces requests evenly rather than sending a burst and then waiting, which
+// is what providers' per-minute quotas reward
+pub fn set_requests_per_minute(limit: Option<u32>) {
+    REQUESTS_PER_MINUTE.store(limit.unwrap_or_default(), Ordering::Relaxed);
+    *NEXT_REQUEST.lock().unwrap_or_else(|e| e.into_inner()) = None;
+}
+
+// Waits until the rate limit lets another request go, and books its slot
+fn throttle() {
+    let limit = REQUESTS_PER_MINUTE.load(Ordering::Relaxed);
+    if limit == 0 {
+        return;
+    }
+    // Held while sleeping, so concurrent callers queue up for their slots
+    let mut next = NEXT_REQUEST.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Instant::now();
+    let start = next.filter(|next| *next > now).unwrap_or(now);
+    std::thread::sleep(start - now);
+    *next = Some(start + Duration::from_secs(60) / limit);
+}
+
+// Why a call to a remote embedding server failed
+pub enum Failure {
+    // Nothing answered: refused, timed out, unresolvable, reset
+    Unreachable(String),
+    // The server answered with an error status and message
+    Status(u16, String),
+    Fatal(String),
+}
+
+impl Failure {
+    fn is_transient(&self) -> bool {
+        matches!(self, Failure::Unreachable(_) | Failure::Status(408 | 409 | 429 | 500..=599, _))
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Failure::Unreachable(message) | Failure::Fatal(message) => write!(f, "{}", message),
+            Failure::Status(status, message) => write!(f, "answered {}: {}", status, message),
+        }
+    }
+}
+
+// Runs `request` again after rate limits, server errors and dropped
+// connections, with exponential backoff; unreachable servers are retried only
+// when `retry_unreachable` is set. Every attempt waits its turn under the
+// rate limit.
+pub fn with_retries(retry_unreachable: bool, mut request: impl FnMut() -> Result<Value, Failure>) -> Result<Value, Failure> {
+    let mut delay = Duration::from_millis(RETRY_DELAY_MS.load(Ordering::Relaxed));
+    let mut retries = 0;
+    loop {
+        throttle();
+        match request() {
+            Err(failure)
+                if retries < MAX_RETRIES.load(Ordering::Relaxed)
+                    && failure.is_transient()
+                    && (retry_unreachable || !matches!(failure, Failure::Unreachable(_))) =>
+            {
+                eprintln!("Embedding request failed ({}), retrying in {} ms", failure, delay.as_millis());
+                std::thread::sleep(delay);
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+                retries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// One JSON POST through curl, which brings TLS and proxy support without
+// linking either in. Everything, the API key included, goes in on stdin as a
+// curl config, so the key never shows up in the process list.
+pub fn post_json(url: &str, api_key: Option<&str>, body: &str) -> Result<Value, Failure> {
+    let mut config = format!(
+        "url = {}\nrequest = \"POST\"\nheader = \"Content-Type: application/json\"\nmax-time = {}\nwrite-out = \"\\n%{{http_code}}\"\n",
+        quote(url),
+        REQUEST_TIMEOUT_SECS
+    );
+    if let Some(key) = api_key {
+        config.push_str(&format!("header = {}\n", quote(&format!("Authorization: Bearer {}", key))));
+    }
+    config.push_str(&format!("data-binary = {}\n", quote(body)));
+
+    let mut curl = Command::new("curl")
+        .args(["--silent", "--show-error", "--config", "-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Failure::Fatal(format!("Remote embedding engines need curl on PATH: {}", e)))?;
+    if let Some(mut stdin) = curl.stdin.take() {
+        stdin.write_all(config.as_bytes()).map_err(|e| Failure::Fatal(format!("Failed to start request: {}", e)))?;
+    }
+    let output = curl.wait_with_output().map_err(|e| Failure::Fatal(format!("Failed to run curl: {}", e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Failure::Unreachable(format!("{} unreachable: {}", url, stderr.trim())));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (body, status) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+    let status: u16 = status.trim().parse().unwrap_or(0);
+    let response: Value = serde_json::from_str(body).unwrap_or(Value::Null);
+    match status {
+        200..=299 => Ok(response),
+        _ => {
+            // OpenAI nests the message, Ollama doesn't
+            let message = response["error"]["message"].as_str().or_else(|| response["error"].as_str());
+            Err(Failure::Status(status, message.map_or_else(|| body.trim().to_string(), str::to_string)))
+        }
+    }
+}
+
+// A double-quoted curl config value
+fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
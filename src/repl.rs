@@ -0,0 +1,259 @@
+use anyhow::Result;
+use context_rag_indexer::indexer::{hybrid_search, ContextRagSearcher, SearchHit};
+use context_rag_indexer::rerank::{self, DEFAULT_RERANK_MODEL};
+
+const PROMPT: &str = "context-rag> ";
+#[cfg(feature = "repl")]
+const HISTORY_FILE: &str = ".context-rag/repl_history";
+const PREVIEW_CHARS: usize = 160;
+// First-stage hits per result the cross-encoder picks from with :rerank on
+const RERANK_CANDIDATES: usize = 4;
+
+struct ReplState {
+    storage_path: String,
+    limit: usize,
+    preview: bool,
+    hybrid: bool,
+    rerank: bool,
+    last_hits: Vec<SearchHit>,
+}
+
+// Interactive search loop over an index opened once for the whole session
+pub fn run(storage_path: &str, limit: usize) -> Result<()> {
+    let searcher = ContextRagSearcher::open(storage_path)
+        .map_err(|e| anyhow::anyhow!("Failed to open index at {}: {}", storage_path, e))?;
+    let mut state = ReplState {
+        storage_path: storage_path.to_string(),
+        limit,
+        preview: true,
+        hybrid: false,
+        rerank: false,
+        last_hits: Vec::new(),
+    };
+
+    println!("context-rag repl on {} (type :help for commands)", storage_path);
+
+    let mut input = LineReader::new();
+    while let Some(line) = input.read_line(PROMPT) {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        match handle_line(line, &searcher, &mut state) {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(e) => eprintln!("error: {}", e),
+        }
+    }
+
+    input.save_history();
+    Ok(())
+}
+
+// Returns Ok(false) when the session should end
+fn handle_line(line: &str, searcher: &ContextRagSearcher, state: &mut ReplState) -> Result<bool> {
+    let Some(command) = line.strip_prefix(':') else {
+        let started = std::time::Instant::now();
+        state.last_hits = search(line, searcher, state)?;
+        print_hits(&state.last_hits, state.preview);
+        println!("({} results in {} ms)", state.last_hits.len(), started.elapsed().as_millis());
+        return Ok(true);
+    };
+
+    let mut parts = command.split_whitespace();
+    match (parts.next().unwrap_or(""), parts.next()) {
+        ("q" | "quit" | "exit", _) => return Ok(false),
+        ("help" | "h", _) => print_help(),
+        ("limit", Some(value)) => {
+            state.limit = value.parse().ok().filter(|&limit| limit > 0).ok_or_else(|| anyhow::anyhow!("limit must be a number of at least 1"))?;
+            println!("limit = {}", state.limit);
+        }
+        ("limit", None) => println!("limit = {}", state.limit),
+        ("preview", Some(value)) => {
+            state.preview = parse_toggle(value)?;
+            println!("preview = {}", if state.preview { "on" } else { "off" });
+        }
+        ("hybrid", Some(value)) => {
+            state.hybrid = parse_toggle(value)?;
+            println!("hybrid = {}", if state.hybrid { "on" } else { "off" });
+        }
+        ("rerank", Some(value)) => {
+            state.rerank = parse_toggle(value)?;
+            println!("rerank = {}", if state.rerank { "on" } else { "off" });
+        }
+        ("show", Some(value)) => {
+            let n: usize = value.parse().map_err(|_| anyhow::anyhow!("usage: :show <n>"))?;
+            let hit = n
+                .checked_sub(1)
+                .and_then(|i| state.last_hits.get(i))
+                .ok_or_else(|| anyhow::anyhow!("no result #{} in the last search", n))?;
+            println!("{}#{} (score {:.3})\n{}", hit.file_path, hit.chunk_index, hit.score, hit.content);
+        }
+        ("files", _) => {
+            let files = searcher.list_files().map_err(|e| anyhow::anyhow!("{}", e))?;
+            for file in &files {
+                println!("{:>5}  {}", file.chunks, file.file_path);
+            }
+            println!("({} files)", files.len());
+        }
+        ("chunks", Some(path)) => {
+            state.last_hits = searcher.file_chunks(path).map_err(|e| anyhow::anyhow!("{}", e))?;
+            print_hits(&state.last_hits, state.preview);
+        }
+        (other, _) => println!("Unknown command ':{}' (try :help)", other),
+    }
+
+    Ok(true)
+}
+
+// Keyword or hybrid hits, then with :rerank on a cross-encoder's pick of
+// several times as many, scored by it
+fn search(query: &str, searcher: &ContextRagSearcher, state: &ReplState) -> Result<Vec<SearchHit>> {
+    let candidates = if state.rerank { state.limit * RERANK_CANDIDATES } else { state.limit };
+    let mut hits = if state.hybrid {
+        hybrid_search(&state.storage_path, query, candidates).map_err(|e| anyhow::anyhow!("{}", e))?.hits
+    } else {
+        searcher.search(query, candidates).map_err(|e| anyhow::anyhow!("{}", e))?
+    };
+    if state.rerank {
+        let contents: Vec<&str> = hits.iter().map(|hit| hit.content.as_str()).collect();
+        let scores = rerank::rerank(DEFAULT_RERANK_MODEL, query, &contents).map_err(|e| anyhow::anyhow!(e))?;
+        for (hit, score) in hits.iter_mut().zip(scores) {
+            hit.score = score;
+        }
+        // Stable, so ties keep their first-stage order
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(state.limit);
+    }
+    Ok(hits)
+}
+
+fn parse_toggle(value: &str) -> Result<bool> {
+    match value {
+        "on" | "true" | "1" => Ok(true),
+        "off" | "false" | "0" => Ok(false),
+        _ => Err(anyhow::anyhow!("expected on or off")),
+    }
+}
+
+fn print_hits(hits: &[SearchHit], preview: bool) {
+    for (i, hit) in hits.iter().enumerate() {
+        println!("{:>3}. {:.3}  {}#{}", i + 1, hit.score, hit.file_path, hit.chunk_index);
+        if preview {
+            let snippet: String = hit.content.chars().take(PREVIEW_CHARS).collect();
+            println!("     {}", snippet.replace('\n', " "));
+        }
+    }
+}
+
+fn print_help() {
+    println!("<query>           search the index");
+    println!(":show <n>         print the full content of result n");
+    println!(":limit [n]        show or set the number of results");
+    println!(":preview on|off   toggle content previews in result lists");
+    println!(":hybrid on|off    fuse keyword and vector rankings");
+    println!(":rerank on|off    reorder results with a cross-encoder");
+    println!(":files            list indexed files with chunk counts");
+    println!(":chunks <path>    list the chunks stored for a file");
+    println!(":quit             leave the repl");
+}
+
+// Readline editing and persistent history when built with the `repl`
+// feature, plain stdin lines otherwise.
+#[cfg(feature = "repl")]
+struct LineReader {
+    editor: Option<rustyline::DefaultEditor>,
+}
+
+#[cfg(feature = "repl")]
+impl LineReader {
+    fn new() -> Self {
+        let editor = rustyline::DefaultEditor::new().ok().map(|mut editor| {
+            let _ = editor.load_history(HISTORY_FILE);
+            editor
+        });
+        LineReader { editor }
+    }
+
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        let editor = self.editor.as_mut()?;
+        match editor.readline(prompt) {
+            Ok(line) => {
+                let _ = editor.add_history_entry(line.as_str());
+                Some(line)
+            }
+            Err(rustyline::error::ReadlineError::Interrupted) => Some(String::new()),
+            Err(_) => None,
+        }
+    }
+
+    fn save_history(&mut self) {
+        if let Some(editor) = self.editor.as_mut() {
+            if let Some(parent) = std::path::Path::new(HISTORY_FILE).parent() {
+                let _ = std::fs::create_dir_all(parent);
+            }
+            let _ = editor.save_history(HISTORY_FILE);
+        }
+    }
+}
+
+#[cfg(not(feature = "repl"))]
+struct LineReader;
+
+#[cfg(not(feature = "repl"))]
+impl LineReader {
+    fn new() -> Self {
+        LineReader
+    }
+
+    fn read_line(&mut self, prompt: &str) -> Option<String> {
+        use std::io::Write;
+
+        print!("{}", prompt);
+        let _ = std::io::stdout().flush();
+
+        let mut line = String::new();
+        match std::io::stdin().read_line(&mut line) {
+            Ok(0) | Err(_) => None,
+            Ok(_) => Some(line),
+        }
+    }
+
+    fn save_history(&mut self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use context_rag_indexer::test_utils::{memory_index, CorpusFile};
+
+    fn file(path: &str, content: &str) -> CorpusFile {
+        CorpusFile { path: path.to_string(), content: content.to_string() }
+    }
+
+    #[test]
+    fn lines_search_and_commands_change_the_session() {
+        let searcher = memory_index(&[file("./guide.md", "rotating the signing keys"), file("./keys.md", "signing key inventory")]).unwrap();
+        let mut state = ReplState { storage_path: String::new(), limit: 10, preview: true, hybrid: false, rerank: false, last_hits: Vec::new() };
+
+        assert!(handle_line("signing", &searcher, &mut state).unwrap());
+        assert_eq!(state.last_hits.len(), 2);
+        assert!(handle_line(":limit 1", &searcher, &mut state).unwrap());
+        handle_line("signing", &searcher, &mut state).unwrap();
+        assert_eq!(state.last_hits.len(), 1);
+        assert!(handle_line(":limit 0", &searcher, &mut state).is_err());
+        assert!(handle_line(":show 2", &searcher, &mut state).is_err());
+        assert!(handle_line(":show 1", &searcher, &mut state).is_ok());
+
+        handle_line(":preview off", &searcher, &mut state).unwrap();
+        handle_line(":hybrid on", &searcher, &mut state).unwrap();
+        assert!(!state.preview && state.hybrid);
+        assert!(handle_line(":rerank maybe", &searcher, &mut state).is_err());
+
+        handle_line(":chunks ./keys.md", &searcher, &mut state).unwrap();
+        assert_eq!(state.last_hits.iter().map(|hit| hit.file_path.as_str()).collect::<Vec<_>>(), ["./keys.md"]);
+        assert!(handle_line(":unknown", &searcher, &mut state).unwrap());
+        assert!(!handle_line(":quit", &searcher, &mut state).unwrap());
+    }
+}
@@ -0,0 +1,78 @@
+use crate::embedding::{device_used, engine, with_engine};
+use crate::indexer::sparse;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+
+pub const DEFAULT_RERANK_MODEL: &str = "cross-encoder/ms-marco-MiniLM-L-6-v2";
+
+// Second-stage ranking: a cross-encoder reads the query and each candidate
+// together, which first-stage retrieval can't afford over a whole index.
+// One score per text, in order.
+pub fn rerank(model: &str, query: &str, texts: &[&str]) -> Result<Vec<f32>, String> {
+    if texts.is_empty() {
+        return Ok(Vec::new());
+    }
+    let scores = engine().score_pairs(model, query, texts)?;
+    if scores.len() != texts.len() {
+        return Err(format!("{} scores for {} texts from {}", scores.len(), texts.len(), model));
+    }
+    Ok(scores)
+}
+
+// The stdin protocol for `rerank`: `{"query": ..., "chunks": [...]}` in, or
+// a `search --json` document whose `hits` stand in for the chunks. Chunks
+// come back most relevant first with a `rerank_score`, every other field as
+// it was; plain strings are taken as chunk contents. `query` overrides the
+// input's, `limit` keeps only the best.
+pub fn rerank_request(model: &str, input: &str, query: Option<&str>, limit: Option<usize>) -> Result<Value, String> {
+    let input: Value = serde_json::from_str(input).map_err(|e| e.to_string())?;
+    let query = query.or(input["query"].as_str()).ok_or("Missing 'query' in input")?;
+    let chunks = input
+        .get("chunks")
+        .or(input.get("hits"))
+        .and_then(Value::as_array)
+        .ok_or("Missing 'chunks' array in input")?;
+
+    let contents: Vec<&str> = chunks.iter().map(|chunk| chunk.as_str().or(chunk["content"].as_str()).unwrap_or("")).collect();
+    let scores = rerank(model, query, &contents)?;
+    let mut ranked: Vec<(Value, f32)> = chunks
+        .iter()
+        .zip(&contents)
+        .map(|(chunk, content)| match chunk {
+            Value::Object(_) => chunk.clone(),
+            _ => json!({ "content": content }),
+        })
+        .zip(scores)
+        .collect();
+    // Stable, so ties keep their first-stage order
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked.truncate(limit.unwrap_or(usize::MAX));
+
+    Ok(with_engine(json!({
+        "query": query,
+        "chunks": ranked
+            .into_iter()
+            .map(|(mut chunk, score)| {
+                chunk["rerank_score"] = json!(score);
+                chunk
+            })
+            .collect::<Vec<_>>(),
+        "model": model,
+        "device": device_used()
+    })))
+}
+
+// The mock engine's stand-in for a cross-encoder: cosine similarity of the
+// query's and the text's term weights, so matching chunks still rise
+pub fn mock_score(query: &str, text: &str) -> f32 {
+    let query: BTreeMap<String, f32> = sparse::encode(query).into_iter().collect();
+    let text = sparse::encode(text);
+    let dot = text.iter().filter_map(|(term, weight)| query.get(term).map(|query| query * weight)).fold(0.0, |dot, product| dot + product);
+    let norm = |weights: &mut dyn Iterator<Item = f32>| weights.map(|weight| weight * weight).sum::<f32>().sqrt();
+    let norms = norm(&mut query.values().copied()) * norm(&mut text.iter().map(|(_, weight)| *weight));
+    if norms > 0.0 {
+        dot / norms
+    } else {
+        0.0
+    }
+}
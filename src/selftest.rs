@@ -0,0 +1,136 @@
+use crate::embedding::{self, embed_query};
+use crate::indexer::{backfill_vectors, hybrid_search_model, ContextRagIndexer, FusionWeights};
+use crate::models;
+use serde::Serialize;
+use std::time::Instant;
+
+// What the engine embeds and the round trip searches for, next to a chunk it
+// shouldn't be confused with
+const CANARY: &str = "Self-test canary for context-rag, rotating the signing keys of the billing service.";
+const DISTRACTOR: &str = "Release notes for the dashboard: new colour themes and keyboard shortcuts.";
+// How far a vector may be from unit length, and two embeddings of the same
+// text from each other, before the engine is suspect
+const TOLERANCE: f32 = 1e-3;
+
+// Whether the selected engine works end to end for a model: it is chosen, it
+// loads and embeds, its vectors look right, and an index built with them in
+// a temporary directory finds what was written
+#[derive(Serialize, Debug, Clone)]
+pub struct SelftestReport {
+    pub engine: String,
+    pub model: String,
+    pub passed: bool,
+    pub checks: Vec<SelftestCheck>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct SelftestCheck {
+    pub name: &'static str,
+    pub passed: bool,
+    // What was seen, or why it failed
+    pub detail: String,
+}
+
+fn check(name: &'static str, result: Result<String, String>) -> SelftestCheck {
+    match result {
+        Ok(detail) => SelftestCheck { name, passed: true, detail },
+        Err(detail) => SelftestCheck { name, passed: false, detail },
+    }
+}
+
+// Checks after a failed embed are left out, since each needs a vector
+pub fn run_selftest(model: &str) -> SelftestReport {
+    let engine = embedding::engine_name();
+    let mut checks = vec![check(
+        "engine",
+        match embedding::engine_warning() {
+            Some(warning) => Err(warning.to_string()),
+            None => Ok(format!("{} selected", engine)),
+        },
+    )];
+
+    let started = Instant::now();
+    let embedded = embed_query(model, CANARY);
+    let elapsed = started.elapsed().as_millis();
+    checks.push(check(
+        "embed",
+        match &embedded {
+            Ok(_) => Ok(match embedding::device_used() {
+                Some(device) => format!("{} ms on {}", elapsed, device),
+                None => format!("{} ms", elapsed),
+            }),
+            Err(e) => Err(e.clone()),
+        },
+    ));
+
+    if let Ok(vector) = embedded {
+        checks.push(check("dimensions", check_dimensions(&engine, model, &vector)));
+        checks.push(check("normalization", check_norm(&vector)));
+        checks.push(check("determinism", check_determinism(model, &vector)));
+        checks.push(check("index", round_trip(model).map_err(|e| e.to_string())));
+    }
+
+    SelftestReport { passed: checks.iter().all(|check| check.passed), engine, model: model.to_string(), checks }
+}
+
+// Against --dimensions, or else the model's own size when it is known; mock
+// vectors have a fixed size whatever the model
+fn check_dimensions(engine: &str, model: &str, vector: &[f32]) -> Result<String, String> {
+    let expected = embedding::dimensions().or_else(|| (engine != "mock").then(|| models::model_info(model).dimensions).flatten());
+    match expected {
+        _ if vector.is_empty() => Err("the engine returned an empty vector".to_string()),
+        Some(expected) if expected != vector.len() => Err(format!("{} dimensions, expected {}", vector.len(), expected)),
+        Some(_) => Ok(format!("{} dimensions", vector.len())),
+        None => Ok(format!("{} dimensions (nothing to compare with)", vector.len())),
+    }
+}
+
+fn check_norm(vector: &[f32]) -> Result<String, String> {
+    if vector.iter().any(|value| !value.is_finite()) {
+        return Err("the vector holds NaN or infinite values".to_string());
+    }
+    let norm = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+    match embedding::normalizes() {
+        false => Ok(format!("norm {:.4} (--no-normalize)", norm)),
+        true if (norm - 1.0).abs() <= TOLERANCE => Ok(format!("norm {:.4}", norm)),
+        true => Err(format!("norm {:.4}, expected unit length", norm)),
+    }
+}
+
+fn check_determinism(model: &str, vector: &[f32]) -> Result<String, String> {
+    let again = embed_query(model, CANARY)?;
+    let drift = vector.iter().zip(&again).map(|(a, b)| (a - b).abs()).fold(0.0, f32::max);
+    match again.len() == vector.len() && drift <= TOLERANCE {
+        true => Ok(format!("max drift {:.1e}", drift)),
+        false => Err(format!("embedding the same text twice differs (max drift {:.1e})", drift)),
+    }
+}
+
+// Writes the canary and a distractor to an index in a temporary directory,
+// embeds them and expects the canary back first from a hybrid search; the
+// directory is removed whatever happens
+fn round_trip(model: &str) -> Result<String, Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir().join(format!("context-rag-selftest-{}", std::process::id()));
+    let result = (|| -> Result<String, Box<dyn std::error::Error>> {
+        let storage = dir.to_str().ok_or("temporary directory is not valid UTF-8")?;
+        let mut indexer = ContextRagIndexer::open(storage, None)?;
+        indexer.add_text("./canary.md", CANARY)?;
+        indexer.add_text("./other.md", DISTRACTOR)?;
+        indexer.commit()?;
+        drop(indexer);
+
+        let backfill = backfill_vectors(storage, model, None, |_, _| {})?;
+        if let Some(failed) = backfill.failed {
+            return Err(failed.error.into());
+        }
+        let search = hybrid_search_model(storage, model, "signing keys billing", 2, &FusionWeights::default())?;
+        match search.hits.first() {
+            _ if search.mode != "hybrid" => Err(format!("search ran in {} mode without vectors", search.mode).into()),
+            Some(hit) if hit.file_path == "./canary.md" => Ok(format!("wrote, embedded and found {} chunks", backfill.total_chunks)),
+            Some(hit) => Err(format!("searching for the canary found {} first", hit.file_path).into()),
+            None => Err("searching for the canary found nothing".into()),
+        }
+    })();
+    let _ = std::fs::remove_dir_all(&dir);
+    result
+}
@@ -0,0 +1,85 @@
+use super::auth::ApiKey;
+use crate::indexer::provenance::chunk_id;
+use crate::indexer::SearchHit;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+// One JSON line per request that served indexed content: who asked, what
+// for, and exactly which chunks went back
+#[derive(Serialize, Debug)]
+pub struct AuditRecord {
+    pub timestamp: String,
+    pub requester: String,
+    pub collection: String,
+    pub method: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub queries: Vec<String>,
+    pub chunks: Vec<ServedChunk>,
+}
+
+// A chunk is the unit of content served; its hash pins the exact text
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct ServedChunk {
+    pub chunk_id: String,
+    pub chunk_hash: String,
+}
+
+impl From<&SearchHit> for ServedChunk {
+    fn from(hit: &SearchHit) -> Self {
+        ServedChunk { chunk_id: chunk_id(&hit.file_path, hit.chunk_index), chunk_hash: hit.chunk_hash.clone() }
+    }
+}
+
+// Opened for appending only, so records already written are never touched
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AuditLog { file: Mutex::new(file) })
+    }
+
+    // Each record goes out in a single write, so lines from concurrent
+    // requests never interleave
+    pub fn record(&self, record: &AuditRecord) -> std::io::Result<()> {
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(&line)?;
+        file.flush()
+    }
+}
+
+// A key's configured name, or a fingerprint that identifies it without
+// putting the secret in the log
+pub fn requester(key: Option<&ApiKey>) -> String {
+    match key {
+        Some(ApiKey { name: Some(name), .. }) => name.clone(),
+        Some(key) => format!("key:{}", &hex::encode(Sha256::digest(key.key.as_bytes()))[..12]),
+        None => "anonymous".to_string(),
+    }
+}
+
+// Chunks in a JSON response: search `hits`, batch `results` and
+// `file_chunks` `chunks`
+pub fn served_chunks(response: &Value) -> Vec<ServedChunk> {
+    let hits = response["hits"].as_array().into_iter().flatten();
+    let batches = response["results"].as_array().into_iter().flatten().filter_map(Value::as_array).flatten();
+    let chunks = response["chunks"].as_array().into_iter().flatten();
+    hits.chain(batches)
+        .chain(chunks)
+        .filter_map(|hit| {
+            Some(ServedChunk {
+                chunk_id: chunk_id(hit["file_path"].as_str()?, hit["chunk_index"].as_u64()?),
+                chunk_hash: hit["chunk_hash"].as_str().unwrap_or_default().to_string(),
+            })
+        })
+        .collect()
+}
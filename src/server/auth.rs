@@ -0,0 +1,200 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60);
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ApiKey {
+    pub key: String,
+    // Who the key belongs to, as shown in the audit log; a fingerprint of
+    // the key otherwise
+    #[serde(default)]
+    pub name: Option<String>,
+    // Requests allowed per minute; unlimited when absent
+    #[serde(default)]
+    pub rate_limit_per_minute: Option<u32>,
+    // Path prefixes, relative to the collection root, whose files this key
+    // may see in results; every file when empty
+    #[serde(default)]
+    pub allowed_paths: Vec<String>,
+}
+
+// Decides which indexed files a caller may see. The server applies it to
+// every hit, file list and chunk it returns; implement it to plug in rules
+// beyond per-key path prefixes. `key` is None when no API keys are set up
+// or the transport has none.
+pub trait AccessPolicy: Send + Sync {
+    fn allows(&self, key: Option<&ApiKey>, collection: &str, file_path: &str) -> bool;
+
+    // Whether the caller may index, embed or create collections, which act on
+    // the whole tree rather than the files it may see; by default only keys
+    // without `allowed_paths` may
+    fn allows_changes(&self, key: Option<&ApiKey>, _collection: &str) -> bool {
+        key.is_none_or(|key| key.allowed_paths.is_empty())
+    }
+}
+
+// The default policy: a key sees the files under its `allowed_paths`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PathPrefixPolicy;
+
+impl AccessPolicy for PathPrefixPolicy {
+    fn allows(&self, key: Option<&ApiKey>, _collection: &str, file_path: &str) -> bool {
+        let Some(key) = key.filter(|key| !key.allowed_paths.is_empty()) else {
+            return true;
+        };
+        let path = file_path.trim_start_matches("./");
+        key.allowed_paths.iter().any(|prefix| {
+            let prefix = prefix.trim_start_matches("./").trim_start_matches('/').trim_end_matches('/');
+            // `docs` covers docs/a.md but not docs-old/a.md
+            prefix.is_empty() || path.strip_prefix(prefix).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CorsConfig {
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default = "default_allowed_methods")]
+    pub allowed_methods: Vec<String>,
+    #[serde(default = "default_allowed_headers")]
+    pub allowed_headers: Vec<String>,
+    #[serde(default = "default_max_age")]
+    pub max_age_secs: u64,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        CorsConfig {
+            allowed_origins: Vec::new(),
+            allowed_methods: default_allowed_methods(),
+            allowed_headers: default_allowed_headers(),
+            max_age_secs: default_max_age(),
+        }
+    }
+}
+
+impl CorsConfig {
+    // Returns the value for Access-Control-Allow-Origin, if the origin may call us
+    pub fn allow_origin(&self, origin: &str) -> Option<String> {
+        if self.allowed_origins.iter().any(|allowed| allowed == "*") {
+            Some("*".to_string())
+        } else if self.allowed_origins.iter().any(|allowed| allowed == origin) {
+            Some(origin.to_string())
+        } else {
+            None
+        }
+    }
+}
+
+fn default_allowed_methods() -> Vec<String> {
+    vec!["GET".to_string(), "POST".to_string(), "OPTIONS".to_string()]
+}
+
+fn default_allowed_headers() -> Vec<String> {
+    vec!["content-type".to_string(), "authorization".to_string(), "x-api-key".to_string()]
+}
+
+fn default_max_age() -> u64 {
+    600
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct HttpConfig {
+    #[serde(default)]
+    pub api_keys: Vec<ApiKey>,
+    #[serde(default)]
+    pub cors: CorsConfig,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AuthError {
+    MissingKey,
+    InvalidKey,
+    RateLimited,
+}
+
+// Static API-key check plus a fixed one-minute window rate limit per key.
+// With no keys configured every request is allowed.
+pub struct Authenticator {
+    keys: HashMap<String, ApiKey>,
+    windows: Mutex<HashMap<String, (Instant, u32)>>,
+}
+
+impl Authenticator {
+    pub fn new(keys: Vec<ApiKey>) -> Self {
+        Authenticator {
+            keys: keys.into_iter().map(|key| (key.key.clone(), key)).collect(),
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    pub fn authorize(&self, presented: Option<&str>) -> Result<Option<&ApiKey>, AuthError> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+
+        let presented = presented.ok_or(AuthError::MissingKey)?;
+        let key = self.keys.get(presented).ok_or(AuthError::InvalidKey)?;
+
+        if let Some(limit) = key.rate_limit_per_minute {
+            let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let window = windows.entry(key.key.clone()).or_insert((now, 0));
+
+            if now.duration_since(window.0) >= RATE_LIMIT_WINDOW {
+                *window = (now, 0);
+            }
+            if window.1 >= limit {
+                return Err(AuthError::RateLimited);
+            }
+            window.1 += 1;
+        }
+
+        Ok(Some(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: &str, rate_limit_per_minute: Option<u32>) -> ApiKey {
+        ApiKey { key: key.to_string(), name: None, rate_limit_per_minute, allowed_paths: Vec::new() }
+    }
+
+    #[test]
+    fn keys_are_checked_and_rate_limited() {
+        assert!(Authenticator::new(Vec::new()).authorize(None).unwrap().is_none());
+
+        let auth = Authenticator::new(vec![key("limited", Some(2)), key("open", None)]);
+        assert_eq!(auth.authorize(None).unwrap_err(), AuthError::MissingKey);
+        assert_eq!(auth.authorize(Some("wrong")).unwrap_err(), AuthError::InvalidKey);
+        for _ in 0..2 {
+            assert_eq!(auth.authorize(Some("limited")).unwrap().unwrap().key, "limited");
+        }
+        assert_eq!(auth.authorize(Some("limited")).unwrap_err(), AuthError::RateLimited);
+        // Windows are per key
+        for _ in 0..5 {
+            assert!(auth.authorize(Some("open")).is_ok());
+        }
+    }
+
+    #[test]
+    fn only_listed_origins_are_allowed() {
+        let origins = |origins: &[&str]| CorsConfig { allowed_origins: origins.iter().map(|origin| origin.to_string()).collect(), ..CorsConfig::default() };
+        assert_eq!(CorsConfig::default().allow_origin("https://example.com"), None);
+
+        let listed = origins(&["https://docs.example.com"]);
+        assert_eq!(listed.allow_origin("https://docs.example.com").as_deref(), Some("https://docs.example.com"));
+        assert_eq!(listed.allow_origin("https://docs.example.com.evil.test"), None);
+        assert_eq!(origins(&["*"]).allow_origin("https://anything.test").as_deref(), Some("*"));
+    }
+}
@@ -0,0 +1,126 @@
+use crate::config::ByteSize;
+use crate::indexer::{Blocklist, PathAliases};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_COLLECTION: &str = "default";
+const COLLECTION_CONFIG_FILE: &str = "collection.json";
+
+// Per-collection settings stored next to the collection's index
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CollectionConfig {
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub code_model: Option<String>,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    #[serde(default)]
+    pub tokenizer: Option<String>,
+    // Fixed when the collection is first indexed
+    #[serde(default)]
+    pub shards: Option<usize>,
+    #[serde(default)]
+    pub blocklist: Blocklist,
+    #[serde(default)]
+    pub aliases: PathAliases,
+    // The index settings of a project config, applied on every index run
+    #[serde(default)]
+    pub deterministic: bool,
+    #[serde(default)]
+    pub boilerplate_threshold: Option<f32>,
+    #[serde(default)]
+    pub strip_license_headers: bool,
+    #[serde(default)]
+    pub extract_comments: bool,
+    // Bytes, or a size such as "1MB"
+    #[serde(default)]
+    pub max_file_size: Option<ByteSize>,
+    #[serde(default)]
+    pub prune_dirs: Vec<String>,
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    // The directory this collection indexes; unset is the server's working
+    // directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_root: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Collection {
+    pub name: String,
+    pub root: PathBuf,
+    pub config: CollectionConfig,
+}
+
+impl Collection {
+    // Every collection lives in its own subdirectory of the storage root:
+    // <storage_root>/<name>/collection.json and <storage_root>/<name>/index/
+    pub fn open(storage_root: &Path, name: &str) -> Result<Self, String> {
+        validate_name(name)?;
+
+        let root = storage_root.join(name);
+        let config_path = root.join(COLLECTION_CONFIG_FILE);
+        let config = if config_path.exists() {
+            let content = fs::read_to_string(&config_path)
+                .map_err(|e| format!("Failed to read {}: {}", config_path.display(), e))?;
+            serde_json::from_str(&content)
+                .map_err(|e| format!("Invalid collection config {}: {}", config_path.display(), e))?
+        } else {
+            CollectionConfig::default()
+        };
+
+        Ok(Collection {
+            name: name.to_string(),
+            root,
+            config,
+        })
+    }
+
+    // Whether a config was saved for this collection
+    pub fn exists(&self) -> bool {
+        self.root.join(COLLECTION_CONFIG_FILE).exists()
+    }
+
+    pub fn storage_path(&self) -> String {
+        self.root.join("index").to_string_lossy().to_string()
+    }
+
+    pub fn save_config(&self) -> Result<(), String> {
+        fs::create_dir_all(&self.root).map_err(|e| format!("Failed to create collection: {}", e))?;
+        let content = serde_json::to_string_pretty(&self.config).map_err(|e| e.to_string())?;
+        fs::write(self.root.join(COLLECTION_CONFIG_FILE), content)
+            .map_err(|e| format!("Failed to write collection config: {}", e))
+    }
+}
+
+pub fn list_collections(storage_root: &Path) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(storage_root)
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|name| validate_name(name).is_ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+// Collection names become directory names, so keep them to a safe alphabet
+fn validate_name(name: &str) -> Result<(), String> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(format!("Invalid collection name '{}': use letters, digits, '-' or '_'", name))
+    }
+}
@@ -0,0 +1,255 @@
+use super::audit::ServedChunk;
+use super::collections::Collection;
+use super::{ServerState, DEFAULT_SEARCH_LIMIT};
+use crate::embedding::{self, EmbedMode};
+use crate::indexer::{assess_answerability, FallbackLadder, SearchHit, SearchOptions};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status};
+
+pub mod proto {
+    tonic::include_proto!("context_rag");
+}
+
+use proto::context_rag_server::{ContextRag, ContextRagServer};
+
+pub struct GrpcService {
+    state: Arc<ServerState>,
+}
+
+impl GrpcService {
+    pub fn new(state: Arc<ServerState>) -> Self {
+        GrpcService { state }
+    }
+
+    fn collection(&self, name: &str) -> Result<Collection, Status> {
+        self.state.collection(Some(name)).map_err(Status::invalid_argument)
+    }
+}
+
+#[tonic::async_trait]
+impl ContextRag for GrpcService {
+    async fn embed(&self, request: Request<proto::EmbedRequest>) -> Result<Response<proto::EmbedResponse>, Status> {
+        let request = request.into_inner();
+        let collection = self.collection(&request.collection)?;
+        let mode = match request.mode.as_str() {
+            "" => EmbedMode::Document,
+            mode => mode.parse().map_err(Status::invalid_argument)?,
+        };
+        let embeddings = self
+            .state
+            .embed(&collection, &request.texts, mode)
+            .map_err(Status::internal)?
+            .into_iter()
+            .map(|values| proto::Embedding { values })
+            .collect();
+
+        Ok(Response::new(proto::EmbedResponse {
+            embeddings,
+            model: self.state.model(&collection),
+            engine: embedding::engine_name(),
+            warning: embedding::engine_warning().unwrap_or_default().to_string(),
+        }))
+    }
+
+    type IndexStream = ReceiverStream<Result<proto::IndexProgress, Status>>;
+
+    async fn index(&self, request: Request<proto::IndexRequest>) -> Result<Response<Self::IndexStream>, Status> {
+        let request = request.into_inner();
+        let collection = self.collection(&request.collection)?;
+        let state = self.state.clone();
+        let (tx, rx) = mpsc::channel(64);
+
+        tokio::task::spawn_blocking(move || {
+            let result = state.index(&collection, request.include, request.exclude, |progress| {
+                // A dropped client only stops the stream, not the index run
+                let _ = tx.blocking_send(Ok(proto::IndexProgress {
+                    current_file: progress.current_file.clone(),
+                    indexed_files: progress.indexed_files as u64,
+                    total_chunks: progress.total_chunks as u64,
+                    done: false,
+                    processing_time_ms: 0,
+                    skipped: Vec::new(),
+                    backfill: String::new(),
+                }));
+            });
+
+            let last = match result {
+                Ok(result) => Ok(proto::IndexProgress {
+                    current_file: String::new(),
+                    indexed_files: result.indexed_files as u64,
+                    total_chunks: result.total_chunks as u64,
+                    done: true,
+                    processing_time_ms: result.processing_time_ms as u64,
+                    skipped: result.skipped.into_iter().map(|skipped| proto::SkippedFile { path: skipped.path, reason: skipped.reason }).collect(),
+                    backfill: state.backfill_status(&collection).map(|status| status.to_string()).unwrap_or_default(),
+                }),
+                Err(e) => Err(Status::internal(e)),
+            };
+            let _ = tx.blocking_send(last);
+        });
+
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+
+    async fn search(&self, request: Request<proto::SearchRequest>) -> Result<Response<proto::SearchResponse>, Status> {
+        let request = request.into_inner();
+        let collection = self.collection(&request.collection)?;
+        let fallback = FallbackLadder {
+            rungs: request
+                .fallback
+                .iter()
+                .map(|rung| rung.parse())
+                .collect::<Result<_, String>>()
+                .map_err(Status::invalid_argument)?,
+            min_results: if request.min_results == 0 { 1 } else { request.min_results as usize },
+        };
+        let result = self
+            .state
+            .search(
+                &collection,
+                &request.query,
+                search_limit(request.limit),
+                SearchOptions {
+                    case_sensitive: request.case_sensitive,
+                    exact: request.exact,
+                    comment_boost: request.comment_boost,
+                    prefer: request.prefer.parse().map_err(Status::invalid_argument)?,
+                },
+                request.min_confidence,
+                &fallback,
+            )
+            .map_err(Status::internal)?;
+        let served = result.hits.iter().map(ServedChunk::from).collect();
+        self.state
+            .audit(None, &collection, "search", vec![request.query.clone()], served)
+            .map_err(Status::internal)?;
+
+        let mut response = to_search_response(&request.query, result.hits);
+        response.below_confidence = result.below_confidence as u32;
+        response.fallback = result.rung.map(|rung| rung.to_string()).unwrap_or_default();
+        Ok(Response::new(response))
+    }
+
+    async fn search_batch(
+        &self,
+        request: Request<proto::SearchBatchRequest>,
+    ) -> Result<Response<proto::SearchBatchResponse>, Status> {
+        let request = request.into_inner();
+        let collection = self.collection(&request.collection)?;
+        let results = self
+            .state
+            .search_batch(&collection, &request.queries, search_limit(request.limit))
+            .map_err(Status::internal)?;
+        let served = results.iter().flatten().map(ServedChunk::from).collect();
+        self.state
+            .audit(None, &collection, "search_batch", request.queries.clone(), served)
+            .map_err(Status::internal)?;
+
+        Ok(Response::new(proto::SearchBatchResponse {
+            results: request
+                .queries
+                .iter()
+                .zip(results)
+                .map(|(query, hits)| to_search_response(query, hits))
+                .collect(),
+        }))
+    }
+}
+
+pub async fn serve(addr: SocketAddr, state: Arc<ServerState>) -> Result<(), Box<dyn std::error::Error>> {
+    tonic::transport::Server::builder()
+        .add_service(ContextRagServer::new(GrpcService::new(state)))
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+// proto3 has no optional scalars, so an unset limit arrives as 0
+fn search_limit(limit: u32) -> usize {
+    if limit == 0 {
+        DEFAULT_SEARCH_LIMIT
+    } else {
+        limit as usize
+    }
+}
+
+fn to_search_response(query: &str, hits: Vec<SearchHit>) -> proto::SearchResponse {
+    let answerability = assess_answerability(query, &hits, None);
+    proto::SearchResponse {
+        answerability: Some(proto::Answerability {
+            score: answerability.score,
+            term_coverage: answerability.term_coverage,
+            answerable: answerability.answerable,
+        }),
+        hits: hits
+            .into_iter()
+            .map(|hit| proto::SearchHit {
+                file_path: hit.file_path,
+                chunk_index: hit.chunk_index,
+                content: hit.content,
+                score: hit.score,
+                confidence: hit.confidence.unwrap_or(0.0),
+            })
+            .collect(),
+        below_confidence: 0,
+        fallback: String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::BackfillStatus;
+    use crate::test_utils::TempDir;
+    use tokio_stream::StreamExt;
+
+    // Each RPC is called straight on the service, without a network in
+    // between, over a collection rooted in a temp directory
+    #[tokio::test]
+    async fn index_streams_progress_and_searches_answer_from_it() {
+        let dir = TempDir::new("grpc").unwrap();
+        std::fs::create_dir_all(dir.join("source")).unwrap();
+        std::fs::write(dir.join("source/guide.md"), "rotating the signing keys").unwrap();
+        std::fs::write(dir.join("source/notes.md"), "release notes").unwrap();
+        let state = ServerState::new(dir.join("storage").to_str().unwrap(), "mock-model")
+            .with_source_roots(&[dir.path().to_path_buf()])
+            .unwrap();
+        let create = serde_json::json!({ "method": "create_collection", "collection": "docs", "config": { "source_root": dir.join("source"), "include": ["*.md"] } });
+        assert_eq!(state.handle_json(&create.to_string())["status"], "success");
+        let service = GrpcService::new(Arc::new(state));
+
+        let index = proto::IndexRequest { collection: "docs".to_string(), ..Default::default() };
+        let progress: Vec<_> = service.index(Request::new(index)).await.unwrap().into_inner().collect().await;
+        let (last, files) = progress.split_last().unwrap();
+        let last = last.as_ref().unwrap();
+        assert!(last.done && last.indexed_files == 2 && !last.backfill.is_empty());
+        assert!(files.iter().all(|message| !message.as_ref().unwrap().done));
+
+        let search = proto::SearchRequest { query: "signing keys".to_string(), collection: "docs".to_string(), ..Default::default() };
+        let response = service.search(Request::new(search.clone())).await.unwrap().into_inner();
+        assert_eq!(response.hits[0].file_path, "./guide.md");
+        assert!(response.answerability.is_some());
+        let unknown = proto::SearchRequest { prefer: "docs".to_string(), ..search };
+        assert_eq!(service.search(Request::new(unknown)).await.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        let batch = proto::SearchBatchRequest { queries: vec!["release".to_string(), "signing".to_string()], limit: 1, collection: "docs".to_string() };
+        let results = service.search_batch(Request::new(batch)).await.unwrap().into_inner().results;
+        let first: Vec<_> = results.iter().map(|result| result.hits[0].file_path.as_str()).collect();
+        assert_eq!(first, ["./notes.md", "./guide.md"]);
+
+        // The backfill writes under the temp directory, so it's waited out
+        let collection = service.state.collection(Some("docs")).unwrap();
+        while service.state.backfill_status(&collection) == Some(BackfillStatus::Pending) {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+    }
+
+    #[test]
+    fn unset_limits_fall_back_to_the_default() {
+        assert_eq!(search_limit(0), DEFAULT_SEARCH_LIMIT);
+        assert_eq!(search_limit(3), 3);
+    }
+}
@@ -0,0 +1,152 @@
+use super::auth::{ApiKey, AuthError, Authenticator, HttpConfig};
+use super::ServerState;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+pub fn serve(addr: &str, config: HttpConfig, state: Arc<ServerState>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let server = Server::http(addr)?;
+    let auth = Arc::new(Authenticator::new(config.api_keys.clone()));
+    let config = Arc::new(config);
+
+    if !auth.is_enabled() {
+        eprintln!("Warning: no API keys configured, HTTP server accepts unauthenticated requests");
+    }
+
+    for request in server.incoming_requests() {
+        let state = state.clone();
+        let auth = auth.clone();
+        let config = config.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = handle_request(request, &state, &auth, &config) {
+                eprintln!("Failed to send response: {}", e);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn handle_request(mut request: Request, state: &ServerState, auth: &Authenticator, config: &HttpConfig) -> std::io::Result<()> {
+    let cors_headers = cors_headers(&request, config);
+
+    // Preflight requests never carry credentials, so answer them before auth
+    if *request.method() == Method::Options {
+        let mut response = Response::empty(204);
+        for header in cors_headers {
+            response.add_header(header);
+        }
+        return request.respond(response);
+    }
+
+    #[cfg(feature = "web-ui")]
+    if *request.method() == Method::Get && matches!(request.url(), "/" | "/ui" | "/index.html") {
+        return serve_web_ui(request, cors_headers);
+    }
+
+    let (status, body) = match auth.authorize(api_key(&request).as_deref()) {
+        Err(AuthError::MissingKey) => (401, error_body("Missing API key")),
+        Err(AuthError::InvalidKey) => (401, error_body("Invalid API key")),
+        Err(AuthError::RateLimited) => (429, error_body("Rate limit exceeded")),
+        Ok(key) => route(&mut request, state, key),
+    };
+
+    let mut response = Response::from_string(body.to_string()).with_status_code(status);
+    for header in header("Content-Type", "application/json").into_iter().chain(cors_headers) {
+        response.add_header(header);
+    }
+    request.respond(response)
+}
+
+// The web UI is static and holds no data, so it is served without auth;
+// every call it makes back to the API still presents the user's key.
+#[cfg(feature = "web-ui")]
+fn serve_web_ui(request: Request, cors_headers: Vec<Header>) -> std::io::Result<()> {
+    const INDEX_HTML: &str = include_str!("../../web/index.html");
+
+    let mut response = Response::from_string(INDEX_HTML);
+    for header in header("Content-Type", "text/html; charset=utf-8").into_iter().chain(cors_headers) {
+        response.add_header(header);
+    }
+    request.respond(response)
+}
+
+fn route(request: &mut Request, state: &ServerState, key: Option<&ApiKey>) -> (u16, Value) {
+    let path = request.url().split('?').next().unwrap_or("").trim_matches('/').to_string();
+
+    match (request.method(), path.as_str()) {
+        (Method::Get, "health") => (200, json!({ "status": "success" })),
+        (Method::Post, "embed" | "index" | "search" | "search_batch" | "list_files" | "file_chunks" | "list_collections" | "create_collection") => {
+            let mut body = String::new();
+            if let Err(e) = request.as_reader().read_to_string(&mut body) {
+                return (400, error_body(&format!("Failed to read request body: {}", e)));
+            }
+
+            // The endpoint name selects the method of the shared JSON protocol
+            let mut payload: Value = match serde_json::from_str(if body.trim().is_empty() { "{}" } else { &body }) {
+                Ok(payload) => payload,
+                Err(e) => return (400, error_body(&format!("Invalid JSON body: {}", e))),
+            };
+            if !payload.is_object() {
+                return (400, error_body("Request body must be a JSON object"));
+            }
+            payload["method"] = json!(path);
+
+            let response = state.handle_json_as(&payload.to_string(), key);
+            let status = if response["status"] == "success" { 200 } else { 400 };
+            (status, response)
+        }
+        _ => (404, error_body("Not found")),
+    }
+}
+
+fn api_key(request: &Request) -> Option<String> {
+    for header in request.headers() {
+        if header.field.equiv("X-Api-Key") {
+            return Some(header.value.as_str().to_string());
+        }
+        if header.field.equiv("Authorization") {
+            if let Some(token) = header.value.as_str().strip_prefix("Bearer ") {
+                return Some(token.trim().to_string());
+            }
+        }
+    }
+    None
+}
+
+fn cors_headers(request: &Request, config: &HttpConfig) -> Vec<Header> {
+    let origin = request
+        .headers()
+        .iter()
+        .find(|header| header.field.equiv("Origin"))
+        .map(|header| header.value.as_str().to_string());
+
+    let allowed = match origin.and_then(|origin| config.cors.allow_origin(&origin)) {
+        Some(allowed) => allowed,
+        None => return Vec::new(),
+    };
+
+    // An origin that can't be echoed back as a header gets no CORS grant
+    [
+        header("Access-Control-Allow-Origin", &allowed),
+        header("Access-Control-Allow-Methods", &config.cors.allowed_methods.join(", ")),
+        header("Access-Control-Allow-Headers", &config.cors.allowed_headers.join(", ")),
+        header("Access-Control-Max-Age", &config.cors.max_age_secs.to_string()),
+        header("Vary", "Origin"),
+    ]
+    .into_iter()
+    .collect::<Option<Vec<_>>>()
+    .unwrap_or_default()
+}
+
+// None for a value that isn't a valid header, such as one with newlines
+fn header(name: &str, value: &str) -> Option<Header> {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).ok()
+}
+
+fn error_body(message: &str) -> Value {
+    json!({
+        "status": "error",
+        "message": message,
+    })
+}
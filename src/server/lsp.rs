@@ -0,0 +1,285 @@
+use super::{ServerEnvelope, ServerState, DEFAULT_SEARCH_LIMIT};
+use crate::indexer::locate_in_file;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// JSON-RPC error codes the protocol defines
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const REQUEST_FAILED: i64 = -32803;
+// SymbolKind.File: a hit is a stretch of a file, not a declaration
+const SYMBOL_KIND_FILE: u32 = 1;
+// Long enough to tell chunks apart in an editor's symbol picker
+const SYMBOL_NAME_CHARS: usize = 80;
+
+// A language server on stdin/stdout, so editors that already speak LSP get
+// search without a plugin. `workspace/symbol` lists matching chunks as
+// symbols; `contextRag/search` takes the same params as the JSON `search`
+// request and answers with its response, each hit carrying a `location`.
+pub fn serve(state: Arc<ServerState>) -> io::Result<()> {
+    let mut reader = BufReader::new(io::stdin().lock());
+    let mut writer = io::stdout().lock();
+    let mut session = Session { state, root: std::env::current_dir()?, shut_down: false };
+
+    while let Some(message) = read_message(&mut reader)? {
+        let message = match serde_json::from_slice::<Value>(&message) {
+            Ok(message) => message,
+            Err(e) => {
+                write_message(&mut writer, &error(Value::Null, PARSE_ERROR, &format!("Invalid JSON: {}", e)))?;
+                continue;
+            }
+        };
+        let method = message["method"].as_str().unwrap_or_default();
+        if method == "exit" {
+            break;
+        }
+        // Notifications (initialized, didOpen, ...) carry no id and get no answer
+        let Some(id) = message.get("id").cloned() else {
+            continue;
+        };
+        let response = match session.handle(method, &message["params"]) {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => error(id, code, &message),
+        };
+        write_message(&mut writer, &response)?;
+    }
+
+    Ok(())
+}
+
+struct Session {
+    state: Arc<ServerState>,
+    // Indexed paths are relative to the workspace the client opened
+    root: PathBuf,
+    shut_down: bool,
+}
+
+impl Session {
+    fn handle(&mut self, method: &str, params: &Value) -> Result<Value, (i64, String)> {
+        if self.shut_down {
+            return Err((INVALID_REQUEST, "Server is shut down".to_string()));
+        }
+        match method {
+            "initialize" => {
+                let root = params["rootUri"].as_str().or_else(|| params["workspaceFolders"][0]["uri"].as_str()).and_then(uri_path);
+                if let Some(root) = root.or_else(|| params["rootPath"].as_str().map(PathBuf::from)) {
+                    self.root = root;
+                }
+                Ok(json!({
+                    "capabilities": { "workspaceSymbolProvider": true },
+                    "serverInfo": { "name": "context-rag", "version": env!("CARGO_PKG_VERSION") },
+                }))
+            }
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Value::Null)
+            }
+            "workspace/symbol" => self.symbols(params["query"].as_str().unwrap_or_default()),
+            "contextRag/search" => self.search(params),
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        }
+    }
+
+    fn symbols(&self, query: &str) -> Result<Value, (i64, String)> {
+        // Editors ask with an empty query as the picker opens
+        if query.trim().is_empty() {
+            return Ok(json!([]));
+        }
+        let response = self.dispatch(json!({ "method": "search", "query": query, "limit": DEFAULT_SEARCH_LIMIT }))?;
+        let symbols: Vec<Value> = response["hits"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|hit| {
+                json!({
+                    "name": symbol_name(hit["content"].as_str().unwrap_or_default()),
+                    "kind": SYMBOL_KIND_FILE,
+                    "location": self.location(hit),
+                    "containerName": hit["file_path"].as_str().unwrap_or_default().trim_start_matches("./"),
+                })
+            })
+            .collect();
+        Ok(json!(symbols))
+    }
+
+    fn search(&self, params: &Value) -> Result<Value, (i64, String)> {
+        let mut request = match params {
+            Value::Object(params) => params.clone(),
+            _ => return Err((INVALID_PARAMS, "contextRag/search takes an object like {\"query\": \"...\"}".to_string())),
+        };
+        request.insert("method".to_string(), json!("search"));
+        let mut response = self.dispatch(Value::Object(request))?;
+        if let Some(hits) = response["hits"].as_array_mut() {
+            for hit in hits {
+                hit["location"] = self.location(hit);
+            }
+        }
+        Ok(response)
+    }
+
+    fn dispatch(&self, request: Value) -> Result<Value, (i64, String)> {
+        let envelope: ServerEnvelope = serde_json::from_value(request).map_err(|e| (INVALID_PARAMS, format!("Invalid request: {}", e)))?;
+        self.state.dispatch(envelope).map_err(|message| (REQUEST_FAILED, message))
+    }
+
+    // The top of the file when the hit's text can't be found in it
+    fn location(&self, hit: &Value) -> Value {
+        let path = self.root.join(hit["file_path"].as_str().unwrap_or_default().trim_start_matches("./"));
+        // LSP lines are 0-based and the range ends where the next line starts
+        let (start, end) = locate_in_file(&path, hit["content"].as_str().unwrap_or_default())
+            .map_or((0, 0), |span| (span.start_line - 1, span.end_line));
+        json!({
+            "uri": file_uri(&path),
+            "range": { "start": { "line": start, "character": 0 }, "end": { "line": end, "character": 0 } },
+        })
+    }
+}
+
+// The chunk's first non-blank line, stripped of heading and comment markers
+fn symbol_name(content: &str) -> String {
+    let line = content.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+    let line = line.trim_start_matches(['#', '/', '*', '-', ' ']);
+    match line.char_indices().nth(SYMBOL_NAME_CHARS) {
+        Some((end, _)) => format!("{}…", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+// Messages are framed by a Content-Length header and a blank line; None at
+// the end of input
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let length = length.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Message without a Content-Length header"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+// file:// URIs percent-encode everything outside the unreserved set and
+// separate with forward slashes; a Windows path gets a slash before its drive
+fn file_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    let path = path.to_string_lossy().replace(std::path::MAIN_SEPARATOR, "/");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+fn uri_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut path = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.iter();
+    while let Some(&byte) = bytes.next() {
+        if byte == b'%' {
+            let hex = [*bytes.next()?, *bytes.next()?];
+            path.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+        } else {
+            path.push(byte);
+        }
+    }
+    let path = String::from_utf8(path).ok()?;
+    // file:///C:/... on Windows
+    match path.strip_prefix('/') {
+        Some(drive) if cfg!(windows) && drive.as_bytes().get(1) == Some(&b':') => Some(PathBuf::from(drive)),
+        _ => Some(PathBuf::from(path)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::BackfillStatus;
+    use std::fs;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn messages_round_trip_through_their_framing() {
+        let mut framed = Vec::new();
+        write_message(&mut framed, &json!({ "id": 1, "method": "shutdown" })).unwrap();
+        write_message(&mut framed, &json!({ "method": "exit" })).unwrap();
+        let mut reader = &framed[..];
+        assert_eq!(serde_json::from_slice::<Value>(&read_message(&mut reader).unwrap().unwrap()).unwrap()["id"], 1);
+        assert_eq!(serde_json::from_slice::<Value>(&read_message(&mut reader).unwrap().unwrap()).unwrap()["method"], "exit");
+        assert!(read_message(&mut reader).unwrap().is_none());
+        assert!(read_message(&mut &b"Content-Type: json\r\n\r\n{}"[..]).is_err());
+    }
+
+    #[test]
+    fn uris_escape_and_unescape_paths() {
+        let path = Path::new("/work/my notes/50%.md");
+        assert_eq!(file_uri(path), "file:///work/my%20notes/50%25.md");
+        assert_eq!(uri_path(&file_uri(path)).as_deref(), Some(path));
+        assert_eq!(uri_path("https://example.com/a"), None);
+        assert_eq!(symbol_name("\n  ## Rotating keys\nbody"), "Rotating keys");
+        assert_eq!(symbol_name(&"x".repeat(100)).chars().count(), SYMBOL_NAME_CHARS + 1);
+    }
+
+    #[test]
+    fn symbols_point_at_the_lines_a_hit_came_from() {
+        let dir = TempDir::new("lsp").unwrap();
+        let source = dir.join("source");
+        fs::create_dir_all(&source).unwrap();
+        fs::write(source.join("guide.md"), "# Guide\n\nrotating the signing keys\n").unwrap();
+        let state = ServerState::new(dir.join("storage").to_str().unwrap(), "mock-model").with_source_roots(std::slice::from_ref(&source)).unwrap();
+        let create = json!({ "method": "create_collection", "config": { "source_root": source, "include": ["*.md"] } });
+        assert_eq!(state.handle_json(&create.to_string())["status"], "success");
+        assert_eq!(state.handle_json(r#"{"method": "index"}"#)["status"], "success");
+        let state = Arc::new(state);
+        let mut session = Session { state: state.clone(), root: PathBuf::new(), shut_down: false };
+
+        let initialized = session.handle("initialize", &json!({ "rootUri": file_uri(&source) })).unwrap();
+        assert_eq!(initialized["capabilities"]["workspaceSymbolProvider"], true);
+        assert_eq!(session.handle("workspace/symbol", &json!({ "query": " " })).unwrap(), json!([]));
+        let symbols = session.handle("workspace/symbol", &json!({ "query": "signing" })).unwrap();
+        assert_eq!(symbols[0]["containerName"], "guide.md");
+        assert_eq!(symbols[0]["location"]["uri"], file_uri(&source.join("guide.md")));
+        let found = session.handle("contextRag/search", &json!({ "query": "signing", "limit": 1 })).unwrap();
+        assert_eq!(found["hits"][0]["location"], symbols[0]["location"]);
+
+        assert_eq!(session.handle("contextRag/search", &json!("signing")).unwrap_err().0, INVALID_PARAMS);
+        assert_eq!(session.handle("textDocument/hover", &Value::Null).unwrap_err().0, METHOD_NOT_FOUND);
+        session.handle("shutdown", &Value::Null).unwrap();
+        assert_eq!(session.handle("workspace/symbol", &json!({ "query": "signing" })).unwrap_err().0, INVALID_REQUEST);
+
+        let collection = state.collection(None).unwrap();
+        while state.backfill_status(&collection) == Some(BackfillStatus::Pending) {
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+    }
+}
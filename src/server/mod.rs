@@ -0,0 +1,562 @@
+use crate::embedding::{embed_as, with_engine, EmbedMode};
+use crate::indexer::{
+    assess_answerability, backfill_vectors, hybrid_search, search_with_refresh, ContextRagIndexer, ContextRagSearcher, IndexConfig, IndexProgress,
+    search_with_fallback, FallbackLadder, FallbackSearch, IndexResult, Prefer, RefreshedSearch, SearchHit, SearchOptions,
+};
+use audit::{AuditLog, AuditRecord, ServedChunk};
+use auth::{AccessPolicy, ApiKey, PathPrefixPolicy};
+use collections::{Collection, CollectionConfig, DEFAULT_COLLECTION};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+pub mod audit;
+pub mod auth;
+pub mod collections;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "http")]
+pub mod http;
+pub mod lsp;
+#[cfg(unix)]
+pub mod unix;
+
+pub const DEFAULT_SEARCH_LIMIT: usize = 10;
+
+// JSON request shape shared by the line-delimited transports. Every
+// request may name a collection; omitting it targets the default one.
+#[derive(Deserialize, Debug)]
+pub struct ServerEnvelope {
+    #[serde(default)]
+    pub collection: Option<String>,
+    #[serde(flatten)]
+    pub request: ServerRequest,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum ServerRequest {
+    Embed {
+        texts: Vec<String>,
+        // "query" embeds the texts as search queries, with the model's query
+        // prefix; they are documents otherwise
+        #[serde(default)]
+        mode: Option<EmbedMode>,
+    },
+    Index {
+        #[serde(default)]
+        include: Vec<String>,
+        #[serde(default)]
+        exclude: Vec<String>,
+    },
+    Search {
+        query: String,
+        #[serde(default = "default_search_limit")]
+        limit: usize,
+        // Re-index hits whose files changed on disk before answering
+        #[serde(default)]
+        refresh: bool,
+        // Fuse keyword and vector rankings
+        #[serde(default)]
+        hybrid: bool,
+        // Treat the query as a regular expression
+        #[serde(default)]
+        regex: bool,
+        // Weighted term expansions instead of plain BM25
+        #[serde(default)]
+        sparse: bool,
+        #[serde(default)]
+        case_sensitive: bool,
+        #[serde(default)]
+        exact: bool,
+        // Weight of comment/docstring matches; 0 leaves them out
+        #[serde(default)]
+        comment_boost: f32,
+        // Down-rank test or production chunks
+        #[serde(default)]
+        prefer: Prefer,
+        // Drop hits under this calibrated confidence (0-1)
+        #[serde(default)]
+        min_confidence: f32,
+        // Looser searches to try when too few hits come back
+        #[serde(default)]
+        fallback: FallbackLadder,
+    },
+    SearchBatch {
+        queries: Vec<String>,
+        #[serde(default = "default_search_limit")]
+        limit: usize,
+    },
+    ListFiles,
+    FileChunks {
+        file_path: String,
+    },
+    ListCollections,
+    CreateCollection {
+        // Boxed, as the config outweighs every other request
+        #[serde(default)]
+        config: Box<CollectionConfig>,
+        // Overwrite the config of a collection that already exists
+        #[serde(default)]
+        replace: bool,
+    },
+}
+
+// Where the vector backfill after an index run stands
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum BackfillStatus {
+    Pending,
+    Done,
+    Failed(String),
+}
+
+impl std::fmt::Display for BackfillStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            BackfillStatus::Pending => write!(f, "pending"),
+            BackfillStatus::Done => write!(f, "done"),
+            BackfillStatus::Failed(e) => write!(f, "failed: {}", e),
+        }
+    }
+}
+
+fn default_search_limit() -> usize {
+    DEFAULT_SEARCH_LIMIT
+}
+
+// Transport-independent state shared by every server transport. Each
+// transport only translates its wire format into calls on this type.
+pub struct ServerState {
+    pub storage_root: PathBuf,
+    pub default_model: String,
+    // Tantivy allows a single writer per index, so index runs are
+    // serialized per collection
+    index_locks: Mutex<HashMap<String, Arc<Mutex<()>>>>,
+    // Each collection's latest vector backfill; at most one is pending
+    backfills: Arc<Mutex<HashMap<String, BackfillStatus>>>,
+    // Filters what each caller's results may show
+    access: Arc<dyn AccessPolicy>,
+    audit: Option<AuditLog>,
+    // Canonical directories a collection's source_root must sit under;
+    // empty is the working directory
+    source_roots: Vec<PathBuf>,
+}
+
+impl ServerState {
+    pub fn new(storage_root: &str, default_model: &str) -> Self {
+        ServerState {
+            storage_root: PathBuf::from(storage_root),
+            default_model: default_model.to_string(),
+            index_locks: Mutex::new(HashMap::new()),
+            backfills: Arc::default(),
+            access: Arc::new(PathPrefixPolicy),
+            audit: None,
+            source_roots: Vec::new(),
+        }
+    }
+
+    // Lets collections index trees under `roots` instead of the working
+    // directory
+    pub fn with_source_roots(mut self, roots: &[PathBuf]) -> std::io::Result<Self> {
+        self.source_roots = roots.iter().map(fs::canonicalize).collect::<Result<_, _>>()?;
+        Ok(self)
+    }
+
+    // The canonical form of a requested source root, refused unless it lies
+    // under a directory this server may index
+    fn source_root(&self, requested: &Path) -> Result<PathBuf, String> {
+        let root = fs::canonicalize(requested).map_err(|e| format!("Invalid source_root {}: {}", requested.display(), e))?;
+        let allowed = if self.source_roots.is_empty() {
+            let cwd = std::env::current_dir().and_then(fs::canonicalize).map_err(|e| format!("Failed to read working directory: {}", e))?;
+            root.starts_with(cwd)
+        } else {
+            self.source_roots.iter().any(|allowed| root.starts_with(allowed))
+        };
+        if !allowed {
+            return Err(format!("source_root {} is outside the directories this server may index", requested.display()));
+        }
+        Ok(root)
+    }
+
+    // Records every request that reads indexed content to `path`
+    pub fn with_audit_log(mut self, path: &std::path::Path) -> std::io::Result<Self> {
+        self.audit = Some(AuditLog::open(path)?);
+        Ok(self)
+    }
+
+    // Results are only handed out once their record is written, so a log
+    // that can't be written fails the request rather than losing the trail
+    pub fn audit(
+        &self,
+        key: Option<&ApiKey>,
+        collection: &Collection,
+        method: &str,
+        queries: Vec<String>,
+        chunks: Vec<ServedChunk>,
+    ) -> Result<(), String> {
+        let Some(log) = &self.audit else {
+            return Ok(());
+        };
+        let record = AuditRecord {
+            timestamp: chrono::Utc::now().to_rfc3339(),
+            requester: audit::requester(key),
+            collection: collection.name.clone(),
+            method: method.to_string(),
+            queries,
+            chunks,
+        };
+        log.record(&record).map_err(|e| format!("Failed to write audit log, withholding results: {}", e))
+    }
+
+    pub fn with_access_policy(mut self, access: Arc<dyn AccessPolicy>) -> Self {
+        self.access = access;
+        self
+    }
+
+    pub fn collection(&self, name: Option<&str>) -> Result<Collection, String> {
+        let name = match name {
+            Some(name) if !name.is_empty() => name,
+            _ => DEFAULT_COLLECTION,
+        };
+        Collection::open(&self.storage_root, name)
+    }
+
+    pub fn model(&self, collection: &Collection) -> String {
+        collection.config.model.clone().unwrap_or_else(|| self.default_model.clone())
+    }
+
+    pub fn embed(&self, collection: &Collection, texts: &[String], mode: EmbedMode) -> Result<Vec<Vec<f32>>, String> {
+        let model = self.model(collection);
+        embed_as(&model, texts, mode)
+    }
+
+    // Empty include/exclude lists fall back to the collection's config
+    pub fn index<F>(&self, collection: &Collection, include: Vec<String>, exclude: Vec<String>, on_progress: F) -> Result<IndexResult, String>
+    where
+        F: FnMut(&IndexProgress),
+    {
+        let lock = self.index_lock(&collection.name);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        let config = IndexConfig {
+            include: if include.is_empty() { collection.config.include.clone() } else { include },
+            exclude: if exclude.is_empty() { collection.config.exclude.clone() } else { exclude },
+            storage_path: collection.storage_path(),
+            model: self.model(collection),
+            code_model: collection.config.code_model.clone(),
+            tokenizer: collection.config.tokenizer.clone(),
+            deterministic: collection.config.deterministic,
+            boilerplate_threshold: collection.config.boilerplate_threshold,
+            strip_license_headers: collection.config.strip_license_headers,
+            extract_comments: collection.config.extract_comments,
+            shards: collection.config.shards,
+            max_ann_memory: None,
+            content_hash: None,
+            max_file_size: collection.config.max_file_size.map(|size| size.0),
+            blocklist: collection.config.blocklist.clone(),
+            aliases: collection.config.aliases.clone(),
+            prune_dirs: collection.config.prune_dirs.clone(),
+            max_depth: collection.config.max_depth,
+            root: collection.config.source_root.clone(),
+        };
+
+        let mut indexer = ContextRagIndexer::for_config(&config)
+            .map_err(|e| format!("Failed to create indexer: {}", e))?;
+        let result = indexer
+            .index_directory_with_progress(&config, on_progress)
+            .map_err(|e| format!("Indexing failed: {}", e))?;
+
+        // Keyword results are served right away; vectors are backfilled in
+        // the background once this run releases the collection lock
+        self.start_backfill(collection, lock.clone());
+
+        Ok(result)
+    }
+
+    // A backfill still pending is waiting on the lock this run holds, so it
+    // will cover this run's chunks too and no second one is started
+    fn start_backfill(&self, collection: &Collection, lock: Arc<Mutex<()>>) -> bool {
+        let mut backfills = self.backfills.lock().unwrap_or_else(|e| e.into_inner());
+        if backfills.get(&collection.name) == Some(&BackfillStatus::Pending) {
+            return false;
+        }
+        backfills.insert(collection.name.clone(), BackfillStatus::Pending);
+
+        let backfills = self.backfills.clone();
+        let (name, storage_path, model, code_model) =
+            (collection.name.clone(), collection.storage_path(), self.model(collection), collection.config.code_model.clone());
+        std::thread::spawn(move || {
+            let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+            let status = match backfill_vectors(&storage_path, &model, code_model.as_deref(), |_, _| {}) {
+                Ok(_) => BackfillStatus::Done,
+                Err(e) => {
+                    eprintln!("Vector backfill for {} failed: {}", storage_path, e);
+                    BackfillStatus::Failed(e.to_string())
+                }
+            };
+            backfills.lock().unwrap_or_else(|e| e.into_inner()).insert(name, status);
+        });
+        true
+    }
+
+    // None until the collection is first indexed by this server
+    pub fn backfill_status(&self, collection: &Collection) -> Option<BackfillStatus> {
+        self.backfills.lock().unwrap_or_else(|e| e.into_inner()).get(&collection.name).cloned()
+    }
+
+    pub fn search(
+        &self,
+        collection: &Collection,
+        query: &str,
+        limit: usize,
+        options: SearchOptions,
+        min_confidence: f32,
+        fallback: &FallbackLadder,
+    ) -> Result<FallbackSearch, String> {
+        search_with_fallback(&collection.storage_path(), query, limit, options, min_confidence, fallback)
+            .map_err(|e| format!("Search failed: {}", e))
+    }
+
+    pub fn search_with_refresh(&self, collection: &Collection, query: &str, limit: usize) -> Result<RefreshedSearch, String> {
+        let lock = self.index_lock(&collection.name);
+        let _guard = lock.lock().unwrap_or_else(|e| e.into_inner());
+
+        search_with_refresh(&collection.storage_path(), collection.config.source_root.as_deref(), query, limit)
+            .map_err(|e| format!("Search failed: {}", e))
+    }
+
+    pub fn search_batch(&self, collection: &Collection, queries: &[String], limit: usize) -> Result<Vec<Vec<SearchHit>>, String> {
+        self.open_searcher(collection)?
+            .search_batch(queries, limit)
+            .map_err(|e| format!("Search failed: {}", e))
+    }
+
+    // Parses and executes one JSON request, always answering with a JSON
+    // object carrying either `"status": "success"` or an error message.
+    pub fn handle_json(&self, request: &str) -> Value {
+        self.handle_json_as(request, None)
+    }
+
+    // Same as `handle_json` for a caller authenticated with `key`, whose
+    // results the access policy narrows
+    pub fn handle_json_as(&self, request: &str, key: Option<&ApiKey>) -> Value {
+        let result = serde_json::from_str::<ServerEnvelope>(request)
+            .map_err(|e| format!("Invalid request: {}", e))
+            .and_then(|envelope| self.dispatch_as(envelope, key));
+
+        match result {
+            Ok(mut response) => {
+                response["status"] = json!("success");
+                response
+            }
+            Err(message) => json!({
+                "status": "error",
+                "message": message,
+            }),
+        }
+    }
+
+    pub fn dispatch(&self, envelope: ServerEnvelope) -> Result<Value, String> {
+        self.dispatch_as(envelope, None)
+    }
+
+    // Hits are dropped after ranking, so a restricted caller may get fewer
+    // than `limit` of them
+    pub fn dispatch_as(&self, envelope: ServerEnvelope, key: Option<&ApiKey>) -> Result<Value, String> {
+        if let ServerRequest::Search { limit: 0, .. } | ServerRequest::SearchBatch { limit: 0, .. } = envelope.request {
+            return Err("limit must be at least 1".to_string());
+        }
+        let collection = self.collection(envelope.collection.as_deref())?;
+        let change = match &envelope.request {
+            ServerRequest::Embed { .. } => Some("embed"),
+            ServerRequest::Index { .. } => Some("index"),
+            ServerRequest::CreateCollection { .. } => Some("create collections"),
+            _ => None,
+        };
+        if let Some(change) = change.filter(|_| !self.access.allows_changes(key, &collection.name)) {
+            return Err(format!("Permission denied: this key may not {} in collection '{}'", change, collection.name));
+        }
+        let visible = |hits: &mut Vec<SearchHit>| {
+            let before = hits.len();
+            hits.retain(|hit| self.access.allows(key, &collection.name, &hit.file_path));
+            hits.len() < before
+        };
+        let audited = match &envelope.request {
+            ServerRequest::Search { query, .. } => Some(("search", vec![query.clone()])),
+            ServerRequest::SearchBatch { queries, .. } => Some(("search_batch", queries.clone())),
+            ServerRequest::ListFiles => Some(("list_files", Vec::new())),
+            ServerRequest::FileChunks { file_path } => Some(("file_chunks", vec![file_path.clone()])),
+            _ => None,
+        };
+
+        let response: Result<Value, String> = match envelope.request {
+            ServerRequest::Embed { texts, mode } => Ok(with_engine(json!({
+                "embeddings": self.embed(&collection, &texts, mode.unwrap_or(EmbedMode::Document))?,
+                "model": self.model(&collection),
+                "collection": collection.name,
+            }))),
+            ServerRequest::Index { include, exclude } => {
+                let result = self.index(&collection, include, exclude, |_| {})?;
+                Ok(json!({ "result": result, "backfill": self.backfill_status(&collection), "collection": collection.name }))
+            }
+            ServerRequest::Search { query, limit, refresh: true, .. } => {
+                let mut result = self.search_with_refresh(&collection, &query, limit)?;
+                visible(&mut result.hits);
+                Ok(json!({
+                    "hits": result.hits,
+                    "refreshed": result.refreshed,
+                    "answerability": assess_answerability(&query, &result.hits, None),
+                    "collection": collection.name,
+                }))
+            }
+            ServerRequest::Search { query, limit, regex: true, .. } => {
+                let mut hits = self
+                    .open_searcher(&collection)?
+                    .regex_search(&query, limit)
+                    .map_err(|e| format!("Regex search failed: {}", e))?;
+                visible(&mut hits);
+                Ok(hits_response(&query, hits, &collection))
+            }
+            ServerRequest::Search { query, limit, sparse: true, .. } => {
+                let mut hits = self
+                    .open_searcher(&collection)?
+                    .sparse_search(&query, limit)
+                    .map_err(|e| format!("Sparse search failed: {}", e))?;
+                visible(&mut hits);
+                Ok(hits_response(&query, hits, &collection))
+            }
+            ServerRequest::Search { query, limit, hybrid: true, .. } => {
+                let mut result = hybrid_search(&collection.storage_path(), &query, limit).map_err(|e| format!("Search failed: {}", e))?;
+                // Judged again without the hidden hits, which shouldn't vouch for the rest
+                if visible(&mut result.hits) {
+                    result.answerability = assess_answerability(&query, &result.hits, None);
+                }
+                Ok(json!({
+                    "hits": result.hits,
+                    "mode": result.mode,
+                    "vector_coverage": result.vector_coverage,
+                    "backfill": self.backfill_status(&collection),
+                    "answerability": result.answerability,
+                    "collection": collection.name,
+                }))
+            }
+            ServerRequest::Search { query, limit, case_sensitive, exact, comment_boost, prefer, min_confidence, fallback, .. } => {
+                let options = SearchOptions { case_sensitive, exact, comment_boost, prefer };
+                let mut result = self.search(&collection, &query, limit, options, min_confidence, &fallback)?;
+                visible(&mut result.hits);
+                let mut response = hits_response(&query, result.hits, &collection);
+                response["below_confidence"] = json!(result.below_confidence);
+                response["fallback"] = json!(result.rung);
+                response["fallback_tried"] = json!(result.tried);
+                Ok(response)
+            }
+            ServerRequest::SearchBatch { queries, limit } => {
+                let mut results = self.search_batch(&collection, &queries, limit)?;
+                for hits in &mut results {
+                    visible(hits);
+                }
+                Ok(json!({ "results": results, "collection": collection.name }))
+            }
+            ServerRequest::ListFiles => {
+                let mut files = self
+                    .open_searcher(&collection)?
+                    .list_files()
+                    .map_err(|e| format!("Failed to list files: {}", e))?;
+                files.retain(|file| self.access.allows(key, &collection.name, &file.file_path));
+                Ok(json!({ "files": files, "collection": collection.name }))
+            }
+            ServerRequest::FileChunks { file_path } => {
+                // Hidden files look the same as files that aren't indexed
+                let mut chunks = self
+                    .open_searcher(&collection)?
+                    .file_chunks(&file_path)
+                    .map_err(|e| format!("Failed to read chunks: {}", e))?;
+                visible(&mut chunks);
+                Ok(json!({ "file_path": file_path, "chunks": chunks, "collection": collection.name }))
+            }
+            ServerRequest::ListCollections => Ok(json!({
+                "collections": collections::list_collections(&self.storage_root),
+            })),
+            ServerRequest::CreateCollection { config, replace } => {
+                if collection.exists() && !replace {
+                    return Err(format!("Collection '{}' already exists; pass \"replace\": true to overwrite its config", collection.name));
+                }
+                let mut config = *config;
+                config.source_root = config.source_root.map(|root| self.source_root(&root)).transpose()?;
+                let collection = Collection { config, ..collection.clone() };
+                collection.save_config()?;
+                Ok(json!({ "collection": collection.name }))
+            }
+        };
+
+        let response = response?;
+        if let Some((method, queries)) = audited {
+            self.audit(key, &collection, method, queries, audit::served_chunks(&response))?;
+        }
+        Ok(response)
+    }
+
+    fn index_lock(&self, collection: &str) -> Arc<Mutex<()>> {
+        let mut locks = self.index_locks.lock().unwrap_or_else(|e| e.into_inner());
+        locks.entry(collection.to_string()).or_default().clone()
+    }
+
+    fn open_searcher(&self, collection: &Collection) -> Result<ContextRagSearcher, String> {
+        ContextRagSearcher::open(&collection.storage_path())
+            .map_err(|e| format!("Failed to open index for collection '{}': {}", collection.name, e))
+    }
+}
+
+fn hits_response(query: &str, hits: Vec<SearchHit>, collection: &Collection) -> Value {
+    json!({
+        "answerability": assess_answerability(query, &hits, None),
+        "hits": hits,
+        "collection": collection.name,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+    use std::time::{Duration, Instant};
+
+    fn settled(state: &ServerState, collection: &Collection) -> Option<BackfillStatus> {
+        let deadline = Instant::now() + Duration::from_secs(30);
+        while state.backfill_status(collection) == Some(BackfillStatus::Pending) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        state.backfill_status(collection)
+    }
+
+    #[test]
+    fn one_backfill_is_pending_per_collection() {
+        let dir = TempDir::new("backfills").unwrap();
+        fs::write(dir.join("notes.md"), "backfilled notes").unwrap();
+        let state = ServerState::new(dir.join("storage").to_str().unwrap(), "mock-model").with_source_roots(&[dir.path().to_path_buf()]).unwrap();
+        let create = json!({ "method": "create_collection", "collection": "docs", "config": { "source_root": dir.path(), "include": ["*.md"] } });
+        assert_eq!(state.handle_json(&create.to_string())["status"], "success");
+        let collection = state.collection(Some("docs")).unwrap();
+        assert_eq!(state.backfill_status(&collection), None);
+
+        let index = state.handle_json(r#"{"method": "index", "collection": "docs"}"#);
+        assert_eq!(index["backfill"], "pending", "{}", index);
+        assert_eq!(settled(&state, &collection), Some(BackfillStatus::Done));
+
+        // While an index run holds the lock, later runs leave the pending
+        // backfill to cover them
+        let lock = state.index_lock(&collection.name);
+        let guard = lock.lock().unwrap();
+        assert!(state.start_backfill(&collection, lock.clone()));
+        assert!(!state.start_backfill(&collection, lock.clone()));
+        assert_eq!(state.backfill_status(&collection), Some(BackfillStatus::Pending));
+        drop(guard);
+        assert_eq!(settled(&state, &collection), Some(BackfillStatus::Done));
+        assert!(state.start_backfill(&collection, lock));
+        assert_eq!(settled(&state, &collection), Some(BackfillStatus::Done));
+    }
+}
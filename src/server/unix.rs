@@ -0,0 +1,116 @@
+use super::ServerState;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Arc;
+
+// Owner-only by default: the socket file's permissions are the access control
+pub const DEFAULT_SOCKET_MODE: u32 = 0o600;
+
+pub fn serve(socket_path: &str, mode: u32, state: Arc<ServerState>) -> std::io::Result<()> {
+    let listener = bind(socket_path, mode)?;
+
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let state = state.clone();
+                std::thread::spawn(move || {
+                    if let Err(e) = handle_connection(stream, &state) {
+                        eprintln!("Connection error: {}", e);
+                    }
+                });
+            }
+            Err(e) => eprintln!("Failed to accept connection: {}", e),
+        }
+    }
+
+    Ok(())
+}
+
+// Listens on `socket_path` with `mode` permissions
+pub fn bind(socket_path: &str, mode: u32) -> std::io::Result<UnixListener> {
+    let path = Path::new(socket_path);
+
+    // Clear a socket left behind by a previous run, but never clobber a regular file
+    if let Ok(metadata) = fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            fs::remove_file(path)?;
+        } else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", socket_path),
+            ));
+        }
+    }
+
+    // Bound inside an owner-only directory and moved into place once its mode
+    // is set, so the socket is never reachable at the umask's mode
+    let staging = path.with_file_name(format!(".{}.{}.tmp", path.file_name().map_or_else(String::new, |name| name.to_string_lossy().into_owned()), std::process::id()));
+    fs::DirBuilder::new().mode(0o700).create(&staging)?;
+    let staged = staging.join("socket");
+    let bound = UnixListener::bind(&staged).and_then(|listener| {
+        fs::set_permissions(&staged, fs::Permissions::from_mode(mode))?;
+        fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_file(&staged);
+    let _ = fs::remove_dir(&staging);
+    bound
+}
+
+// One JSON request per line in, one JSON response per line out
+fn handle_connection(stream: UnixStream, state: &ServerState) -> std::io::Result<()> {
+    let mut writer = stream.try_clone()?;
+    let reader = BufReader::new(stream);
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = state.handle_json(&line);
+        writeln!(writer, "{}", response)?;
+        writer.flush()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::TempDir;
+
+    #[test]
+    fn sockets_get_their_mode_and_answer_json_lines() {
+        let dir = TempDir::new("unix-socket").unwrap();
+        let socket = dir.join("server.sock");
+        let socket_path = socket.to_str().unwrap();
+        let listener = bind(socket_path, DEFAULT_SOCKET_MODE).unwrap();
+        assert_eq!(fs::metadata(&socket).unwrap().permissions().mode() & 0o777, DEFAULT_SOCKET_MODE);
+        // Only the socket is left, not the staging directory it was bound in
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let state = ServerState::new(dir.join("storage").to_str().unwrap(), "mock-model");
+        let server = std::thread::spawn(move || handle_connection(listener.accept().unwrap().0, &state));
+        let mut client = UnixStream::connect(&socket).unwrap();
+        writeln!(client, "\n{{\"method\": \"list_collections\"}}\nnot json").unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        let responses: Vec<serde_json::Value> = BufReader::new(client).lines().map(|line| serde_json::from_str(&line.unwrap()).unwrap()).collect();
+        server.join().unwrap().unwrap();
+        assert_eq!(responses.len(), 2);
+        assert_eq!(responses[0]["status"], "success");
+        assert_eq!(responses[1]["status"], "error");
+
+        // A stale socket is replaced, anything else is left alone
+        drop(bind(socket_path, 0o660).unwrap());
+        assert_eq!(fs::metadata(&socket).unwrap().permissions().mode() & 0o777, 0o660);
+        let file = dir.join("regular");
+        fs::write(&file, "keep").unwrap();
+        assert_eq!(bind(file.to_str().unwrap(), DEFAULT_SOCKET_MODE).unwrap_err().kind(), std::io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "keep");
+    }
+}
@@ -115,6 +115,9 @@ class EmbeddingService {
         if (code === 0) {
           try {
             const result = JSON.parse(output);
+            if (result.warning) {
+              console.log(chalk.yellow(`⚠️  Rust embedder: ${result.warning}`));
+            }
             resolve(result.chunks);
           } catch (error) {
             reject(new Error(`Failed to parse Rust embedder output: ${error.message}`));
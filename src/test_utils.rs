@@ -0,0 +1,165 @@
+// Helpers for testing retrieval without a project on disk: deterministic
+// generated corpora, and indexes built from them in RAM. Behind the
+// `test-utils` feature so they never ship in a release build by accident.
+
+use crate::indexer::{ContextRagIndexer, ContextRagSearcher};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+pub const WORDS: &[&str] = &[
+    "index", "search", "chunk", "vector", "query", "shard", "segment", "token", "config", "embedding", "storage",
+    "writer", "reader", "commit", "fusion", "ranking", "boilerplate", "license", "comment", "schema", "path",
+    "language", "confidence", "fallback", "document", "score", "hash", "cache", "model", "server",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorpusFile {
+    pub path: String,
+    pub content: String,
+}
+
+// Picks from WORDS with a xorshift generator, so a seed always yields the
+// same text
+pub struct Words(u64);
+
+impl Words {
+    pub fn new(seed: u64) -> Self {
+        // Xorshift never leaves zero
+        Words(seed ^ 0x9e3779b97f4a7c15)
+    }
+
+    pub fn next_word(&mut self) -> &'static str {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        WORDS[(self.0 % WORDS.len() as u64) as usize]
+    }
+
+    pub fn sentence(&mut self, words: usize) -> String {
+        (0..words).map(|_| self.next_word()).collect::<Vec<_>>().join(" ")
+    }
+}
+
+// Markdown with headings and paragraphs, roughly `bytes` long
+pub fn markdown(words: &mut Words, bytes: usize) -> String {
+    let mut text = String::with_capacity(bytes + 256);
+    while text.len() < bytes {
+        text.push_str(&format!("## {}\n\n", words.sentence(3)));
+        for _ in 0..3 {
+            text.push_str(&words.sentence(40));
+            text.push_str(".\n\n");
+        }
+    }
+    text
+}
+
+// Rust functions with doc comments and snake_case identifiers built from WORDS
+pub fn rust_source(words: &mut Words, functions: usize) -> String {
+    let mut text = String::new();
+    for function in 0..functions {
+        text.push_str(&format!(
+            "/// {}\npub fn {}_handler_{}(config: &Config) -> Result<()> {{\n    let {} = config.{}();\n    {}(&{})\n}}\n\n",
+            words.sentence(12),
+            words.next_word(),
+            function,
+            words.next_word(),
+            words.next_word(),
+            words.next_word(),
+            words.next_word(),
+        ));
+    }
+    text
+}
+
+// `files` files alternating 4 KB markdown notes and Rust sources, spread
+// over 16 module directories
+pub fn generate_corpus(seed: u64, files: usize) -> Vec<CorpusFile> {
+    let mut words = Words::new(seed);
+    (0..files)
+        .map(|file| {
+            let dir = format!("module_{}", file % 16);
+            if file % 2 == 0 {
+                CorpusFile { path: format!("{}/notes_{}.md", dir, file), content: markdown(&mut words, 4 * 1024) }
+            } else {
+                CorpusFile { path: format!("{}/handler_{}.rs", dir, file), content: rust_source(&mut words, 24) }
+            }
+        })
+        .collect()
+}
+
+// For code paths that only work on a real tree, such as `index_directory`
+pub fn write_corpus(root: &Path, files: &[CorpusFile]) -> io::Result<()> {
+    for file in files {
+        let path = root.join(&file.path);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, &file.content)?;
+    }
+    Ok(())
+}
+
+// A committed in-RAM index of `files`, ready to search
+pub fn memory_index(files: &[CorpusFile]) -> Result<ContextRagSearcher, Box<dyn std::error::Error>> {
+    let mut indexer = ContextRagIndexer::in_memory(None)?;
+    for file in files {
+        indexer.add_text(&file.path, &file.content)?;
+    }
+    indexer.commit()?;
+    indexer.searcher()
+}
+
+// A tokenizer.json splitting on whitespace into WORDS, unknown words and
+// BERT's special tokens, for engine tests that need a model checkout but
+// not a trained model. WORDS start at id 4, after [PAD], [UNK], [CLS], [SEP].
+pub fn write_tokenizer(dir: &Path) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    use tokenizers::models::wordlevel::WordLevel;
+    use tokenizers::pre_tokenizers::whitespace::Whitespace;
+    use tokenizers::processors::template::TemplateProcessing;
+
+    let vocab = ["[PAD]", "[UNK]", "[CLS]", "[SEP]"].iter().chain(WORDS).enumerate().map(|(id, token)| (token.to_string(), id as u32));
+    let model = WordLevel::builder().vocab(vocab.collect()).unk_token("[UNK]".to_string()).build()?;
+    let template = TemplateProcessing::builder()
+        .try_single("[CLS] $A [SEP]")?
+        .try_pair("[CLS] $A [SEP] $B:1 [SEP]:1")?
+        .special_tokens(vec![("[CLS]", 2), ("[SEP]", 3)])
+        .build()?;
+    let mut tokenizer = tokenizers::Tokenizer::new(model);
+    tokenizer.with_pre_tokenizer(Some(Whitespace {}));
+    tokenizer.with_post_processor(Some(template));
+    tokenizer.save(dir.join("tokenizer.json"), false)
+}
+
+// A fresh directory under the system temp dir, removed with everything in
+// it when dropped, so a failing test doesn't leave it behind
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> io::Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!("context-rag-{}-{}-{}", name, std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed)));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path)?;
+        Ok(TempDir(path))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+
+    pub fn to_str(&self) -> &str {
+        self.0.to_str().expect("temp dirs have UTF-8 paths")
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
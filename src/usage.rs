@@ -0,0 +1,141 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const DEFAULT_USAGE_FILE: &str = ".cache/context-rag/usage.json";
+// Set to 1, true or yes to record usage without --usage-stats
+const USAGE_ENV: &str = "CONTEXT_RAG_USAGE_STATS";
+
+// How often indexes are built and queries run on this machine, and how long
+// they take, for teams measuring whether their tooling gets used. Opt-in and
+// local: counts go to a JSON file and are never sent anywhere, and nothing
+// about what was indexed or asked is kept. None (the library's default)
+// records nothing.
+static USAGE_FILE: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+pub fn set_usage_file(file: Option<PathBuf>) {
+    *USAGE_FILE.lock().unwrap_or_else(|e| e.into_inner()) = file;
+}
+
+pub fn usage_file() -> Option<PathBuf> {
+    USAGE_FILE.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+pub fn default_usage_file() -> PathBuf {
+    std::env::var_os("HOME").map(PathBuf::from).unwrap_or_default().join(DEFAULT_USAGE_FILE)
+}
+
+pub fn enabled_by_env() -> bool {
+    std::env::var(USAGE_ENV).is_ok_and(|value| matches!(value.trim().to_ascii_lowercase().as_str(), "1" | "true" | "yes"))
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct UsageStats {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_recorded: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_recorded: Option<DateTime<Utc>>,
+    // By command: "index", "search" or "context"
+    #[serde(default)]
+    pub commands: BTreeMap<String, CommandUsage>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CommandUsage {
+    pub runs: u64,
+    // Runs that ended in an error; their time counts all the same
+    pub failures: u64,
+    pub total_ms: u64,
+    pub max_ms: u64,
+}
+
+// A missing file is no usage yet
+pub fn load_usage(path: &Path) -> Result<UsageStats, String> {
+    match fs::read_to_string(path) {
+        Ok(content) => serde_json::from_str(&content).map_err(|e| format!("Failed to parse {}: {}", path.display(), e)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(UsageStats::default()),
+        Err(e) => Err(format!("Failed to read {}: {}", path.display(), e)),
+    }
+}
+
+// Counts one run of `command` when usage is being recorded. Best effort: a
+// file that can't be read or written loses the run rather than failing the
+// command, and runs finishing at the same moment may overwrite each other.
+pub fn record(command: &str, elapsed: Duration, succeeded: bool) {
+    if let Some(path) = usage_file() {
+        record_to(&path, command, elapsed, succeeded);
+    }
+}
+
+// `record` into the file at `path`, whatever this process records to
+pub fn record_to(path: &Path, command: &str, elapsed: Duration, succeeded: bool) {
+    let Ok(mut stats) = load_usage(path) else { return };
+    let now = Utc::now();
+    stats.first_recorded.get_or_insert(now);
+    stats.last_recorded = Some(now);
+    let usage = stats.commands.entry(command.to_string()).or_default();
+    let ms = elapsed.as_millis() as u64;
+    usage.runs += 1;
+    usage.failures += u64::from(!succeeded);
+    usage.total_ms += ms;
+    usage.max_ms = usage.max_ms.max(ms);
+    let _ = write_usage(path, &stats);
+}
+
+fn write_usage(path: &Path, stats: &UsageStats) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // Renamed into place so a concurrent run never reads half a file
+    let partial = path.with_extension(format!("{}.tmp", std::process::id()));
+    let written = fs::write(&partial, serde_json::to_vec_pretty(stats)?).and_then(|_| fs::rename(&partial, path));
+    if written.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    written
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UsageReport {
+    pub file: String,
+    // Whether this run records usage, so an empty report can say why
+    pub recording: bool,
+    pub first_recorded: Option<DateTime<Utc>>,
+    pub last_recorded: Option<DateTime<Utc>>,
+    pub commands: Vec<CommandReport>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct CommandReport {
+    pub command: String,
+    pub runs: u64,
+    pub failures: u64,
+    pub average_ms: f64,
+    pub max_ms: u64,
+}
+
+pub fn usage_report(path: &Path) -> Result<UsageReport, String> {
+    let stats = load_usage(path)?;
+    Ok(UsageReport {
+        file: path.display().to_string(),
+        recording: usage_file().is_some(),
+        first_recorded: stats.first_recorded,
+        last_recorded: stats.last_recorded,
+        commands: stats
+            .commands
+            .into_iter()
+            .map(|(command, usage)| CommandReport {
+                average_ms: usage.total_ms as f64 / usage.runs.max(1) as f64,
+                command,
+                runs: usage.runs,
+                failures: usage.failures,
+                max_ms: usage.max_ms,
+            })
+            .collect(),
+    })
+}
@@ -0,0 +1,440 @@
+// Retrieval invariants checked over generated inputs, mostly using in-RAM
+// indexes from `test_utils`; the rest work in temp directories
+
+use context_rag_indexer::cache::{clear_cache, EmbeddingCache};
+use context_rag_indexer::config::{ByteSize, ProjectConfig};
+use context_rag_indexer::indexer::clock::{modified_time, modified_time_with, now, Clock, FixedClock};
+use context_rag_indexer::indexer::inspect::{chunk_vectors, inspect_chunk};
+use context_rag_indexer::indexer::{
+    backfill_vectors, chunk_content, memory, skip_reason, stored_path, Blocklist, ContentHash, ContextRagIndexer, ContextRagSearcher, PathAliases, Uncovered,
+};
+use context_rag_indexer::rerank::rerank_request;
+use context_rag_indexer::server::auth::ApiKey;
+use context_rag_indexer::server::ServerState;
+use context_rag_indexer::test_utils::{generate_corpus, memory_index, write_corpus, CorpusFile, TempDir};
+use context_rag_indexer::usage::{record, record_to, usage_report};
+use proptest::prelude::*;
+use std::path::{Path, PathBuf};
+
+// Lowercase and unlike anything in the generated vocabulary
+const NEEDLE: &str = "zyqxwvneedle";
+
+proptest! {
+    #[test]
+    fn chunking_keeps_every_line(content in "(\\PC{0,200}\n){0,40}") {
+        let chunks = chunk_content(&content);
+        prop_assert!(!chunks.is_empty());
+        for line in content.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            prop_assert!(chunks.iter().any(|chunk| chunk.contains(line)), "line {:?} missing from chunks", line);
+        }
+    }
+
+    #[test]
+    fn hashes_are_stable_hex(content in proptest::collection::vec(any::<u8>(), 0..4096)) {
+        for hash in [ContentHash::Blake3, ContentHash::Sha256] {
+            let digest = hash.hash(&content);
+            prop_assert_eq!(digest.len(), 64);
+            prop_assert!(digest.bytes().all(|b| b.is_ascii_hexdigit()));
+            prop_assert_eq!(digest, hash.hash(&content));
+        }
+    }
+
+    // Walker paths are built with the platform's separator (a backslash on
+    // Windows); stored ones must read the same everywhere and lead back to
+    // the same file
+    #[test]
+    fn stored_paths_round_trip(components in proptest::collection::vec("[A-Za-z0-9_-][A-Za-z0-9_.-]{0,11}", 1..6)) {
+        let path: PathBuf = std::iter::once(".").chain(components.iter().map(String::as_str)).collect();
+        let stored = stored_path(&path).unwrap();
+        prop_assert_eq!(&stored, &format!("./{}", components.join("/")));
+        prop_assert_eq!(Path::new(&stored).components().collect::<Vec<_>>(), path.components().collect::<Vec<_>>());
+    }
+}
+
+proptest! {
+    // Every case builds an index, so keep the count modest
+    #![proptest_config(ProptestConfig::with_cases(24))]
+
+    #[test]
+    fn unique_term_finds_its_file(seed in any::<u64>(), files in 1usize..24, target in any::<prop::sample::Index>()) {
+        let mut corpus = generate_corpus(seed, files);
+        let target = target.index(corpus.len());
+        corpus[target].content.push_str(&format!("\n{}\n", NEEDLE));
+
+        let searcher = memory_index(&corpus).unwrap();
+        let hits = searcher.search(NEEDLE, 10).unwrap();
+        prop_assert_eq!(hits.len(), 1);
+        prop_assert_eq!(&hits[0].file_path, &corpus[target].path);
+    }
+
+    #[test]
+    fn hits_respect_limit_and_order(seed in any::<u64>(), files in 1usize..24, limit in 0usize..20) {
+        let corpus = generate_corpus(seed, files);
+        let searcher = memory_index(&corpus).unwrap();
+        let hits = searcher.search("shard commit vector", limit).unwrap();
+        prop_assert!(hits.len() <= limit);
+        prop_assert!(hits.windows(2).all(|pair| pair[0].score >= pair[1].score));
+    }
+
+    #[test]
+    fn every_file_is_indexed(seed in any::<u64>(), files in 0usize..24) {
+        let corpus = generate_corpus(seed, files);
+        let searcher = memory_index(&corpus).unwrap();
+        let mut indexed: Vec<String> = searcher.all_chunks().unwrap().into_iter().map(|chunk| chunk.file_path).collect();
+        indexed.sort();
+        indexed.dedup();
+        let mut expected: Vec<String> = corpus.iter().map(|file: &CorpusFile| file.path.clone()).collect();
+        expected.sort();
+        prop_assert_eq!(indexed, expected);
+    }
+}
+
+#[test]
+fn memory_storage_is_shared_by_path() {
+    let storage = ":memory:properties";
+    let corpus = generate_corpus(7, 4);
+    let mut indexer = ContextRagIndexer::open(storage, None).unwrap();
+    for file in &corpus {
+        indexer.add_text(&file.path, &file.content).unwrap();
+    }
+    indexer.commit().unwrap();
+    drop(indexer);
+
+    let searcher = ContextRagSearcher::open(storage).unwrap();
+    assert_eq!(searcher.num_chunks(), ContextRagIndexer::open(storage, None).unwrap().searcher().unwrap().num_chunks());
+    assert!(searcher.num_chunks() >= corpus.len() as u64);
+    assert!(memory::drop_index(storage));
+    assert!(ContextRagSearcher::open(storage).is_err());
+}
+
+#[test]
+fn blocklist_hides_matches_from_every_search() {
+    let storage = ":memory:blocklist";
+    let mut indexer = ContextRagIndexer::open(storage, None).unwrap();
+    indexer.add_text("./config/secrets/keys.md", "shared rotation notes").unwrap();
+    indexer.add_text("./docs/legacy.md", "shared notes on the Deprecated_API").unwrap();
+    indexer.add_text("./docs/current.md", "shared notes on the current api").unwrap();
+    indexer.commit().unwrap();
+    drop(indexer);
+
+    let blocklist = Blocklist { paths: vec!["**/secrets/**".to_string()], terms: vec!["deprecated api".to_string()] };
+    blocklist.save(storage).unwrap();
+    let searcher = ContextRagSearcher::open(storage).unwrap();
+    let paths = |hits: Vec<context_rag_indexer::indexer::SearchHit>| hits.into_iter().map(|hit| hit.file_path).collect::<Vec<_>>();
+    assert_eq!(paths(searcher.search("shared notes", 10).unwrap()), ["./docs/current.md"]);
+    assert_eq!(paths(searcher.fuzzy_search("shared", 10).unwrap()), ["./docs/current.md"]);
+    assert_eq!(paths(searcher.all_chunks().unwrap()), ["./docs/current.md"]);
+    assert!(memory::drop_index(storage));
+}
+
+#[test]
+fn blocklist_hides_chunks_from_audit_and_inspect() {
+    let storage = ":memory:blocklist-audit";
+    let mut indexer = ContextRagIndexer::open(storage, None).unwrap();
+    indexer.add_text("./config/secrets/keys.md", "rotation notes").unwrap();
+    indexer.add_text("./docs/current.md", "current notes").unwrap();
+    indexer.commit().unwrap();
+    drop(indexer);
+
+    Blocklist { paths: vec!["**/secrets/**".to_string()], terms: Vec::new() }.save(storage).unwrap();
+    let searcher = ContextRagSearcher::open(storage).unwrap();
+    assert!(searcher.audit_chunk("./config/secrets/keys.md", 0).unwrap().is_none());
+    assert!(searcher.chunk_segments("./config/secrets/keys.md", 0).unwrap().is_empty());
+    let audited = searcher.audit_all().unwrap().into_iter().map(|audit| audit.chunk.file_path).collect::<Vec<_>>();
+    assert_eq!(audited, ["./docs/current.md"]);
+    assert!(inspect_chunk(storage, "config/secrets/keys.md", 0).unwrap().is_none());
+    assert!(inspect_chunk(storage, "docs/current.md", 0).unwrap().is_some());
+    let ids = ["./config/secrets/keys.md#0".to_string()];
+    assert_eq!(chunk_vectors(storage, &ids).unwrap(), [None]);
+    assert!(memory::drop_index(storage));
+}
+
+#[test]
+fn aliased_files_are_stored_under_canonical_paths() {
+    let storage = ":memory:aliases";
+    let aliases = PathAliases([("node_modules/@org/pkg/".to_string(), "./packages/pkg".to_string())].into());
+    assert_eq!(aliases.canonical("./node_modules/@org/pkg/src/lib.rs").as_deref(), Some("./packages/pkg/src/lib.rs"));
+    assert_eq!(aliases.canonical("./node_modules/@org/pkgs/lib.rs"), None);
+    assert_eq!(aliases.canonical("./packages/pkg/src/lib.rs"), None);
+    assert!(PathAliases([("packages".to_string(), "packages/pkg".to_string())].into()).save(storage).is_err());
+
+    aliases.save(storage).unwrap();
+    let mut indexer = ContextRagIndexer::open(storage, None).unwrap();
+    indexer.add_text("./node_modules/@org/pkg/README.md", "vendored widget notes").unwrap();
+    indexer.commit().unwrap();
+    let hits = indexer.searcher().unwrap().search("widget", 10).unwrap();
+    assert_eq!(hits.iter().map(|hit| hit.file_path.as_str()).collect::<Vec<_>>(), ["./packages/pkg/README.md"]);
+    drop(indexer);
+    assert!(memory::drop_index(storage));
+}
+
+#[test]
+fn index_presets_are_extended_by_the_config() {
+    let config = ProjectConfig::parse("[index]\npreset = \"rust\"\nexclude = [\"fixtures/\"]\nmax_file_size = \"2MB\"\n").unwrap();
+    let index = config.index_config();
+    assert!(index.exclude.iter().any(|pattern| pattern == "Cargo.lock") && index.exclude.iter().any(|pattern| pattern == "fixtures/"));
+    assert!(index.prune_dirs.iter().any(|name| name == "target"));
+    assert_eq!(index.max_file_size, Some("2MB".parse::<ByteSize>().unwrap().0));
+    assert_eq!(skip_reason(Path::new("./src/lib.rs"), &index), None);
+    assert_eq!(skip_reason(Path::new("./docs/logo.png"), &index), Some(Uncovered::Excluded));
+    assert_eq!(skip_reason(Path::new("./target/debug/build.rs"), &index), Some(Uncovered::Pruned));
+    assert!(ProjectConfig::parse("[index]\npreset = \"cobol\"\n").is_err());
+}
+
+#[test]
+fn mock_rerank_puts_matching_chunks_first() {
+    let input = r#"{"query": "rotate keys", "chunks": [{"content": "release notes", "file_path": "./a.md"}, "how to rotate the signing keys"]}"#;
+    let response = rerank_request("mock-cross-encoder", input, None, Some(1)).unwrap();
+    let chunks = response["chunks"].as_array().unwrap();
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0]["content"], "how to rotate the signing keys");
+    assert!(chunks[0]["rerank_score"].as_f64().unwrap() > 0.0);
+    assert!(rerank_request("mock-cross-encoder", r#"{"chunks": []}"#, None, None).is_err());
+}
+
+// Vectors are keyed by chunk content, so a re-chunked file only sends the
+// chunks whose text changed back to the engine. Vector stores are files, so
+// this index lives in a temporary directory.
+#[test]
+fn rechunked_files_only_embed_new_chunks() {
+    let dir = TempDir::new("rechunk").unwrap();
+    let storage = dir.to_str();
+    // Five 200-byte lines fill a chunk, so each section is one chunk
+    let section = |word: &str| format!("{}\n", word.repeat(199 / word.len())).repeat(5);
+    // No engine is set, leaving the mock one: selecting it here would switch
+    // engines under every test running alongside
+
+    let mut indexer = ContextRagIndexer::open(storage, None).unwrap();
+    indexer.add_text("./guide.md", &[section("a"), section("b"), section("c")].concat()).unwrap();
+    indexer.commit().unwrap();
+    let first = backfill_vectors(storage, "mock-model", None, |_, _| {}).unwrap();
+    assert_eq!((first.embedded, first.reused), (3, 0));
+
+    indexer.delete_file("./guide.md").unwrap();
+    indexer.add_text("./guide.md", &[section("a"), section("b"), section("d"), section("e")].concat()).unwrap();
+    indexer.commit().unwrap();
+    let second = backfill_vectors(storage, "mock-model", None, |_, _| {}).unwrap();
+    assert_eq!((second.embedded, second.reused, second.total_chunks), (2, 2, 4));
+    drop(indexer);
+}
+
+#[cfg(unix)]
+#[test]
+fn non_utf8_paths_are_not_stored() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = Path::new(std::ffi::OsStr::from_bytes(b"./src/caf\xe9.rs"));
+    assert_eq!(stored_path(path), None);
+    assert_eq!(stored_path(Path::new("./src/café.rs")).as_deref(), Some("./src/café.rs"));
+}
+
+#[cfg(windows)]
+#[test]
+fn windows_separators_are_stored_as_slashes() {
+    assert_eq!(stored_path(Path::new(r".\src\indexer\mod.rs")).as_deref(), Some("./src/indexer/mod.rs"));
+    assert_eq!(stored_path(&Path::new(".").join("src").join("lib.rs")).as_deref(), Some("./src/lib.rs"));
+}
+
+#[test]
+fn fixed_clock_pins_times_and_odd_mtimes_are_clamped() {
+    let path = Path::new("Cargo.toml");
+    // Passed in rather than set for the process, which other tests share
+    let clock = FixedClock { now: 1_000_000, modified: Some(-86_400) };
+    assert_eq!(clock.now(), 1_000_000);
+    assert_eq!(modified_time_with(&clock, path).unwrap(), 0);
+    let clock = FixedClock { now: 1_000_000, modified: Some(4_000_000_000) };
+    assert_eq!(modified_time_with(&clock, path).unwrap(), 1_000_000);
+    assert!(modified_time(path).unwrap() <= now());
+}
+
+// Keys narrowed to some paths only read through the access policy; requests
+// acting on the whole tree are refused before they run
+#[test]
+fn restricted_keys_cannot_index_embed_or_create_collections() {
+    let dir = TempDir::new("access").unwrap();
+    let state = ServerState::new(dir.to_str(), "mock-model");
+    let key = |allowed_paths: &[&str]| ApiKey {
+        key: "k".to_string(),
+        name: None,
+        rate_limit_per_minute: None,
+        allowed_paths: allowed_paths.iter().map(|path| path.to_string()).collect(),
+    };
+    let restricted = key(&["docs"]);
+    for request in [
+        r#"{"method": "index", "include": ["nothing-here/**"]}"#,
+        r#"{"method": "embed", "texts": ["hello"]}"#,
+        r#"{"method": "create_collection", "collection": "other"}"#,
+    ] {
+        let response = state.handle_json_as(request, Some(&restricted));
+        assert_eq!(response["status"], "error", "{}", request);
+        assert!(response["message"].as_str().unwrap().starts_with("Permission denied"));
+    }
+    let response = state.handle_json_as(r#"{"method": "embed", "texts": ["hello"]}"#, Some(&key(&[])));
+    assert_eq!(response["status"], "success");
+}
+
+// Only a first build shows files part of the way through; a re-index keeps
+// serving the previous files until it commits
+#[test]
+fn reindexing_keeps_previous_files_searchable() {
+    let dir = TempDir::new("reindex").unwrap();
+    let corpus = generate_corpus(3, 240);
+    write_corpus(&dir.join("tree"), &corpus).unwrap();
+    let mut config = ProjectConfig::parse("[index]\ninclude = [\"*.md\", \"*.rs\"]\n").unwrap().index_config();
+    config.storage_path = dir.join("index").to_string_lossy().into_owned();
+    config.root = Some(dir.join("tree"));
+
+    let mut indexer = ContextRagIndexer::for_config(&config).unwrap();
+    assert_eq!(indexer.index_directory(&config).unwrap().indexed_files, corpus.len());
+    let mut visible = Vec::new();
+    indexer
+        .index_directory_with_progress(&config, |progress| {
+            if progress.indexed_files % 10 == 0 {
+                visible.push(ContextRagSearcher::open(&config.storage_path).unwrap().list_files().unwrap().len());
+            }
+        })
+        .unwrap();
+    assert!(!visible.is_empty() && visible.iter().all(|files| *files == corpus.len()), "{:?}", visible);
+    drop(indexer);
+}
+
+// Each collection indexes its own directory, whatever the server's working
+// directory, and an existing collection is only reconfigured on request
+#[test]
+fn collections_index_their_own_root() {
+    let dir = TempDir::new("collections").unwrap();
+    let source = dir.join("source");
+    std::fs::create_dir_all(source.join("docs")).unwrap();
+    std::fs::write(source.join("docs/guide.md"), "collection rooted guide").unwrap();
+    // The vendored copy is left out because its canonical path exists under
+    // the collection's root, not the server's working directory
+    for copy in ["vendor/pkg", "packages/pkg"] {
+        std::fs::create_dir_all(source.join(copy)).unwrap();
+        std::fs::write(source.join(copy).join("notes.md"), "package notes").unwrap();
+    }
+    std::fs::create_dir_all(source.join("build")).unwrap();
+    std::fs::write(source.join("build/generated.md"), "pruned output").unwrap();
+    // Sized against the file under the root, which the working directory lacks
+    std::fs::write(source.join("docs/dump.md"), "generated ".repeat(200)).unwrap();
+    let state = ServerState::new(dir.join("storage").to_str().unwrap(), "mock-model");
+    // Roots outside the working directory are refused until allowed
+    let elsewhere = serde_json::json!({ "method": "create_collection", "collection": "etc", "config": { "source_root": source } });
+    assert!(state.handle_json(&elsewhere.to_string())["message"].as_str().unwrap().contains("outside"));
+    let state = state.with_source_roots(&[dir.path().to_path_buf()]).unwrap();
+    let escape = serde_json::json!({ "method": "create_collection", "collection": "etc", "config": { "source_root": dir.join("..") } });
+    assert!(state.handle_json(&escape.to_string())["message"].as_str().unwrap().contains("outside"));
+
+    let config = serde_json::json!({
+        "source_root": source,
+        "include": ["*.md"],
+        "aliases": { "vendor/pkg/": "./packages/pkg" },
+        "max_file_size": "1KB",
+        "prune_dirs": ["build"],
+    });
+    let create = serde_json::json!({ "method": "create_collection", "collection": "docs", "config": config });
+    assert_eq!(state.handle_json(&create.to_string())["status"], "success");
+    let again = state.handle_json(&create.to_string());
+    assert_eq!(again["status"], "error");
+    assert!(again["message"].as_str().unwrap().contains("already exists"));
+    let mut replace = create.clone();
+    replace["replace"] = serde_json::json!(true);
+    assert_eq!(state.handle_json(&replace.to_string())["status"], "success");
+
+    let index = state.handle_json(r#"{"method": "index", "collection": "docs"}"#);
+    assert_eq!(index["result"]["indexed_files"], 2, "{}", index);
+    let files = state.handle_json(r#"{"method": "list_files", "collection": "docs"}"#);
+    let paths: Vec<_> = files["files"].as_array().unwrap().iter().map(|file| file["file_path"].as_str().unwrap()).collect();
+    assert_eq!(paths, ["./docs/guide.md", "./packages/pkg/notes.md"], "{}", files);
+}
+
+// Vectors from one model file never answer for another given under the
+// same model name
+#[test]
+fn cache_keys_cover_the_model_file() {
+    let dir = TempDir::new("cache-key").unwrap();
+    let (q4, q8) = (dir.join("bge-q4.gguf"), dir.join("bge-q8.gguf"));
+    std::fs::write(&q4, b"q4").unwrap();
+    std::fs::write(&q8, b"q8 weights").unwrap();
+    let cache = |file: Option<&Path>| EmbeddingCache::new(&dir.join("cache"), "gguf", "bge", file, None, None, true);
+
+    cache(Some(&q4)).put("hello", &[1.0, 0.0]);
+    assert_eq!(cache(Some(&q4)).get("hello"), Some(vec![1.0, 0.0]));
+    assert_eq!(cache(Some(&q8)).get("hello"), None);
+    assert_eq!(cache(None).get("hello"), None);
+
+    // Only what the cache wrote is cleared, and a directory holding anything
+    // else is refused whole
+    assert!(clear_cache(dir.path(), None).is_err());
+    assert!(q4.exists());
+    let cleared = clear_cache(&dir.join("cache"), None).unwrap();
+    assert_eq!(cleared.entries, 1);
+    assert!(std::fs::read_dir(dir.join("cache")).unwrap().next().is_none());
+}
+
+// Directory runs walk the working directory, so these go through the binary,
+// with `dir` as home too so usage stats land there
+fn embedder(dir: &Path, args: &[&str]) -> std::process::Output {
+    std::process::Command::new(env!("CARGO_BIN_EXE_context-rag-embedder"))
+        .current_dir(dir)
+        .env("HOME", dir)
+        .env_remove("CONTEXT_RAG_USAGE_STATS")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+// A file marked `context-rag: ignore` is neither counted as indexed nor
+// reported as new on every status afterwards
+#[test]
+fn ignored_files_leave_a_fresh_index_clean() {
+    let dir = TempDir::new("ignore").unwrap();
+    std::fs::write(dir.join("private.md"), "# context-rag: ignore\nsalary bands\n").unwrap();
+    std::fs::write(dir.join("notes.md"), "release notes\n").unwrap();
+
+    let index = embedder(dir.path(), &["index", "--keyword-only", "--json"]);
+    assert!(index.status.success(), "{}", String::from_utf8_lossy(&index.stderr));
+    let result: serde_json::Value = serde_json::from_slice(&index.stdout).unwrap();
+    assert_eq!((result["indexed_files"].as_u64(), result["ignored_files"].as_u64()), (Some(1), Some(1)));
+
+    let status = embedder(dir.path(), &["status", "--json"]);
+    let status: serde_json::Value = serde_json::from_slice(&status.stdout).unwrap();
+    assert_eq!(status["stale"], false, "{}", status);
+}
+
+// Runs are counted per command, failures with them, and read back averaged
+#[test]
+fn usage_stats_round_trip() {
+    let dir = TempDir::new("usage").unwrap();
+    let file = dir.join("usage.json");
+    // Written to straight away; setting the process's usage file would reach
+    // into tests running alongside
+    record_to(&file, "search", std::time::Duration::from_millis(10), true);
+    record_to(&file, "search", std::time::Duration::from_millis(30), false);
+    record_to(&file, "index", std::time::Duration::from_millis(5), true);
+    // Nothing is recorded without a usage file set
+    record("search", std::time::Duration::from_millis(99), true);
+
+    let report = usage_report(&file).unwrap();
+    let counts: Vec<_> = report.commands.iter().map(|usage| (usage.command.as_str(), usage.runs, usage.failures, usage.average_ms, usage.max_ms)).collect();
+    assert_eq!(counts, [("index", 1, 0, 5.0, 5), ("search", 2, 1, 20.0, 30)]);
+    assert!(report.first_recorded.is_some() && !report.recording);
+}
+
+// `stats report` is a subcommand, while `stats --storage` still describes
+// the index
+#[test]
+fn stats_report_parses_next_to_stats_options() {
+    let dir = TempDir::new("stats").unwrap();
+    std::fs::write(dir.join("notes.md"), "release notes\n").unwrap();
+    assert!(embedder(dir.path(), &["index", "--keyword-only", "--usage-stats"]).status.success());
+
+    let stats = embedder(dir.path(), &["stats", "--storage", ".context-rag/index", "--json"]);
+    assert!(stats.status.success(), "{}", String::from_utf8_lossy(&stats.stderr));
+    let report = embedder(dir.path(), &["stats", "report", "--json"]);
+    assert!(report.status.success(), "{}", String::from_utf8_lossy(&report.stderr));
+    let report: serde_json::Value = serde_json::from_slice(&report.stdout).unwrap();
+    assert_eq!(report["commands"][0]["command"], "index", "{}", report);
+    assert_eq!(report["commands"][0]["runs"], 1);
+    // Options of the two forms don't mix
+    assert!(!embedder(dir.path(), &["stats", "--storage", ".context-rag/index", "report"]).status.success());
+}